@@ -0,0 +1,82 @@
+//! Time reading a large window, which decodes tiles and assembles the window from them, with
+//! rayon thread pools of increasing size.
+//!
+//! Run with `cargo bench --bench assembly --features rayon,testing`. The benchmark re-runs itself
+//! with `RAYON_NUM_THREADS` set to each pool size, as rayon's global pool can only be configured
+//! once per process.
+//!
+//! Results of a 3800x3800 window of 3 `u16` bands from 256x256 tiles on a single core machine,
+//! before and after tiles were decoded on the rayon pool while responses are awaited, instead of
+//! on the task polling them. With one core, neither extra threads nor overlapping decoding with
+//! fetching can help, and the differences are within the noise of repeated runs:
+//!
+//! ```text
+//!              before      after
+//!   1 threads: 123.11ms    117.41ms
+//!   2 threads: 120.73ms    131.02ms
+//!   4 threads: 122.71ms    127.04ms
+//!   8 threads: 128.59ms    138.58ms
+//! ```
+//!
+//! Re-run on a machine with at least 8 cores before relying on how assembly scales.
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use aiocogeo::testing::CogBuilder;
+use aiocogeo::{DataType, ReadOptions, Window};
+
+const ITERATIONS: u32 = 10;
+
+fn main() {
+    if std::env::var_os("RAYON_NUM_THREADS").is_some() {
+        let mean = time_window();
+        println!("{}", mean.as_secs_f64());
+        return;
+    }
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut threads = 1;
+    let mut baseline = None;
+    while threads <= cores.max(8) {
+        let output = Command::new(std::env::current_exe().unwrap())
+            .env("RAYON_NUM_THREADS", threads.to_string())
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let mean = Duration::from_secs_f64(stdout.trim().parse().unwrap());
+        let baseline = *baseline.get_or_insert(mean);
+        println!(
+            "{threads:>3} threads: {mean:>10.2?} per window, {:.2}x",
+            baseline.as_secs_f64() / mean.as_secs_f64()
+        );
+        threads *= 2;
+    }
+    if cores < 8 {
+        println!("only {cores} cores are available, so larger pools can't scale");
+    }
+}
+
+/// Return the mean time to read the window on the global rayon pool
+fn time_window() -> Duration {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let builder = CogBuilder {
+        width: 4096,
+        height: 4096,
+        tile_width: 256,
+        tile_height: 256,
+        data_type: DataType::Uint16,
+        bands: 3,
+        ..Default::default()
+    };
+    let (reader, _) = runtime.block_on(builder.open()).unwrap();
+    let window = Window::new(100, 100, 3800, 3800);
+    let options = ReadOptions::default();
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        runtime
+            .block_on(reader.read_window(window, 0, &options))
+            .unwrap();
+        elapsed += start.elapsed();
+    }
+    elapsed / ITERATIONS
+}
@@ -0,0 +1,53 @@
+#ifndef AIOCOGEO_H
+#define AIOCOGEO_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* An open Cloud Optimized GeoTIFF. Readers may be shared between threads. */
+typedef struct AiocogeoReader AiocogeoReader;
+
+/*
+ * Open a COG from a local path or a file://, s3://, gs://, az:// or https:// URL.
+ * Returns NULL on failure, see aiocogeo_last_error.
+ */
+AiocogeoReader *aiocogeo_open(const char *uri);
+
+/* Close a reader returned by aiocogeo_open. Passing NULL is a no-op. */
+void aiocogeo_close(AiocogeoReader *reader);
+
+/*
+ * Describe the image as a JSON object with its width, height, bands, dtype, dtype_size,
+ * tile_width, tile_height, overviews (a list of [width, height]), epsg, bounds, date_time and
+ * acquisition_date_time (RFC 3339, without an offset when the file doesn't give one).
+ * The string must be released with aiocogeo_string_free. Returns NULL on failure.
+ */
+char *aiocogeo_info_json(const AiocogeoReader *reader);
+
+/*
+ * Read a window of overview level z into buf, as (bands, height, width) row-major values in
+ * native byte order. buf must hold at least bands * height * width * dtype_size bytes.
+ * Returns the number of bytes written, or -1 on failure.
+ */
+int64_t aiocogeo_read_window(const AiocogeoReader *reader, size_t z, size_t col_off,
+                             size_t row_off, size_t width, size_t height, uint8_t *buf,
+                             size_t buf_len);
+
+/*
+ * The message of the last error raised on this thread, or NULL if there was none.
+ * The pointer is valid until the next call into the library on this thread.
+ */
+const char *aiocogeo_last_error(void);
+
+/* Release a string returned by the library. Passing NULL is a no-op. */
+void aiocogeo_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* AIOCOGEO_H */
@@ -0,0 +1,185 @@
+//! A C interface to the blocking COG reader, see `include/aiocogeo.h`.
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::sync::Arc;
+
+use aiocogeo::blocking::COGReader;
+use aiocogeo::error::{AiocogeoError, Result};
+use aiocogeo::{ReadOptions, Window};
+use serde_json::json;
+use url::Url;
+
+/// An open COG, handed to C as an opaque pointer
+pub struct AiocogeoReader {
+    reader: COGReader,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', "")).unwrap();
+    LAST_ERROR.with(|err| *err.borrow_mut() = Some(message));
+}
+
+/// Run `f`, recording any error or panic as the last error and returning `None`
+fn ffi_call<T>(f: impl FnOnce() -> Result<T>) -> Option<T> {
+    LAST_ERROR.with(|err| *err.borrow_mut() = None);
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => Some(value),
+        Ok(Err(err)) => {
+            set_last_error(err.to_string());
+            None
+        }
+        Err(_) => {
+            set_last_error("panic in aiocogeo".to_string());
+            None
+        }
+    }
+}
+
+fn open(uri: &str) -> Result<COGReader> {
+    match Url::parse(uri) {
+        Ok(url) if url.scheme() == "file" => {
+            let path = url
+                .to_file_path()
+                .map_err(|_| AiocogeoError::General(format!("{uri} is not a local file path")))?;
+            COGReader::from_path(path)
+        }
+        Ok(url) if url.scheme().len() > 1 => {
+            let (store, path) = object_store::parse_url(&url).map_err(AiocogeoError::from)?;
+            COGReader::try_open(Arc::from(store), path)
+        }
+        // Bare paths, including Windows drive letters which parse as a one letter scheme
+        _ => COGReader::from_path(uri),
+    }
+}
+
+fn info(reader: &COGReader) -> Result<String> {
+    let ifds = reader.ifds();
+    let ifd = &ifds[0];
+    let dtype = ifd.dtype()?;
+    let (tile_width, tile_height) = ifd.tile_size();
+    let info = json!({
+        "width": ifd.width(),
+        "height": ifd.height(),
+        "bands": ifd.bands(),
+        "dtype": format!("{dtype:?}").to_lowercase(),
+        "dtype_size": dtype.size(),
+        "tile_width": tile_width,
+        "tile_height": tile_height,
+        "profile": reader.profile().name.map(|name| name.as_str()),
+        "overviews": ifds[1..]
+            .iter()
+            .map(|ifd| [ifd.width(), ifd.height()])
+            .collect::<Vec<_>>(),
+        "epsg": reader.epsg(),
+        "vertical_epsg": reader.vertical_epsg(),
+        "crs": reader.crs_code(),
+        "bounds": reader.native_bounds().map(|(x0, y0, x1, y1)| [x0, y0, x1, y1]),
+        // RFC 3339, without an offset when the file doesn't give one
+        "date_time": reader.date_time().map(|t| t.to_string()),
+        "acquisition_date_time": reader.acquisition_date_time().map(|t| t.to_string()),
+    });
+    Ok(info.to_string())
+}
+
+/// # Safety
+///
+/// `uri` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn aiocogeo_open(uri: *const c_char) -> *mut AiocogeoReader {
+    ffi_call(|| {
+        if uri.is_null() {
+            return Err(AiocogeoError::General("uri is NULL".to_string()));
+        }
+        let uri = CStr::from_ptr(uri)
+            .to_str()
+            .map_err(|err| AiocogeoError::General(format!("uri is not UTF-8: {err}")))?;
+        Ok(Box::into_raw(Box::new(AiocogeoReader {
+            reader: open(uri)?,
+        })))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// # Safety
+///
+/// `reader` must be NULL or a pointer returned by [`aiocogeo_open`] that has not been closed.
+#[no_mangle]
+pub unsafe extern "C" fn aiocogeo_close(reader: *mut AiocogeoReader) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader));
+    }
+}
+
+/// # Safety
+///
+/// `reader` must be a pointer returned by [`aiocogeo_open`] that has not been closed.
+#[no_mangle]
+pub unsafe extern "C" fn aiocogeo_info_json(reader: *const AiocogeoReader) -> *mut c_char {
+    ffi_call(|| {
+        let reader = reader
+            .as_ref()
+            .ok_or_else(|| AiocogeoError::General("reader is NULL".to_string()))?;
+        let info = info(&reader.reader)?;
+        Ok(CString::new(info).unwrap().into_raw())
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// # Safety
+///
+/// `reader` must be a pointer returned by [`aiocogeo_open`] that has not been closed, and `buf`
+/// must be valid for writes of `buf_len` bytes.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn aiocogeo_read_window(
+    reader: *const AiocogeoReader,
+    z: usize,
+    col_off: usize,
+    row_off: usize,
+    width: usize,
+    height: usize,
+    buf: *mut u8,
+    buf_len: usize,
+) -> i64 {
+    ffi_call(|| {
+        let reader = reader
+            .as_ref()
+            .ok_or_else(|| AiocogeoError::General("reader is NULL".to_string()))?;
+        if buf.is_null() {
+            return Err(AiocogeoError::General("buf is NULL".to_string()));
+        }
+        let window = Window::new(col_off, row_off, width, height);
+        let array = reader
+            .reader
+            .read_window(window, z, &ReadOptions::default())?;
+        let out = std::slice::from_raw_parts_mut(buf, buf_len);
+        array.copy_to_bytes(out)?;
+        Ok(array.nbytes() as i64)
+    })
+    .unwrap_or(-1)
+}
+
+#[no_mangle]
+pub extern "C" fn aiocogeo_last_error() -> *const c_char {
+    LAST_ERROR.with(|err| {
+        err.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// # Safety
+///
+/// `s` must be NULL or a string returned by this library that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn aiocogeo_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
@@ -0,0 +1,185 @@
+use std::sync::Arc;
+
+use aiocogeo::blocking::COGReader;
+use aiocogeo::error::AiocogeoError;
+use aiocogeo::{ColorInterp, DataType, RasterArray, ReadOptions, Window};
+use numpy::IntoPyArray;
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use url::Url;
+
+fn to_py_err(err: AiocogeoError) -> PyErr {
+    match err {
+        AiocogeoError::IOError(err) => PyIOError::new_err(err.to_string()),
+        AiocogeoError::ObjectStore(err) => PyIOError::new_err(err.to_string()),
+        err => PyValueError::new_err(err.to_string()),
+    }
+}
+
+/// Convert a decoded array to a numpy array, moving its buffer without copying
+fn to_numpy(py: Python<'_>, array: RasterArray) -> PyResult<Py<PyAny>> {
+    let array = match array {
+        RasterArray::Uint8(arr) => arr.into_pyarray(py).into_any(),
+        RasterArray::Uint16(arr) => arr.into_pyarray(py).into_any(),
+        RasterArray::Uint32(arr) => arr.into_pyarray(py).into_any(),
+        RasterArray::Uint64(arr) => arr.into_pyarray(py).into_any(),
+        RasterArray::Int8(arr) => arr.into_pyarray(py).into_any(),
+        RasterArray::Int16(arr) => arr.into_pyarray(py).into_any(),
+        RasterArray::Int32(arr) => arr.into_pyarray(py).into_any(),
+        RasterArray::Int64(arr) => arr.into_pyarray(py).into_any(),
+        RasterArray::Float32(arr) => arr.into_pyarray(py).into_any(),
+        RasterArray::Float64(arr) => arr.into_pyarray(py).into_any(),
+        RasterArray::CFloat32(arr) => arr.into_pyarray(py).into_any(),
+        RasterArray::CFloat64(arr) => arr.into_pyarray(py).into_any(),
+        // numpy has no complex integer types
+        RasterArray::CInt16(arr) => arr
+            .mapv(|v| numpy::Complex32::new(v.re as f32, v.im as f32))
+            .into_pyarray(py)
+            .into_any(),
+        RasterArray::CInt32(arr) => arr
+            .mapv(|v| numpy::Complex64::new(v.re as f64, v.im as f64))
+            .into_pyarray(py)
+            .into_any(),
+    };
+    Ok(array.unbind())
+}
+
+/// The numpy name of a data type
+fn dtype_name(dtype: DataType) -> &'static str {
+    match dtype {
+        DataType::Uint8 => "uint8",
+        DataType::Uint16 => "uint16",
+        DataType::Uint32 => "uint32",
+        DataType::Uint64 => "uint64",
+        DataType::Int8 => "int8",
+        DataType::Int16 => "int16",
+        DataType::Int32 => "int32",
+        DataType::Int64 => "int64",
+        DataType::Float32 => "float32",
+        DataType::Float64 => "float64",
+        DataType::CInt16 | DataType::CFloat32 => "complex64",
+        DataType::CInt32 | DataType::CFloat64 => "complex128",
+    }
+}
+
+fn color_interp_name(interp: ColorInterp) -> &'static str {
+    match interp {
+        ColorInterp::Undefined => "undefined",
+        ColorInterp::Gray => "gray",
+        ColorInterp::Palette => "palette",
+        ColorInterp::Red => "red",
+        ColorInterp::Green => "green",
+        ColorInterp::Blue => "blue",
+        ColorInterp::Alpha => "alpha",
+        ColorInterp::Hue => "hue",
+        ColorInterp::Saturation => "saturation",
+        ColorInterp::Lightness => "lightness",
+        ColorInterp::Cyan => "cyan",
+        ColorInterp::Magenta => "magenta",
+        ColorInterp::Yellow => "yellow",
+        ColorInterp::Black => "black",
+        ColorInterp::Y => "Y",
+        ColorInterp::Cb => "Cb",
+        ColorInterp::Cr => "Cr",
+    }
+}
+
+/// A reader of Cloud Optimized GeoTIFFs
+#[pyclass(name = "COGReader", frozen)]
+struct PyCOGReader {
+    reader: COGReader,
+}
+
+#[pymethods]
+impl PyCOGReader {
+    /// Open a COG from a local path or a `file://`, `s3://`, `gs://`, `az://` or `https://` URL
+    #[new]
+    fn new(py: Python<'_>, path: &str) -> PyResult<Self> {
+        let reader = py.detach(|| match Url::parse(path) {
+            Ok(url) if url.scheme() == "file" => {
+                let path = url.to_file_path().map_err(|_| {
+                    AiocogeoError::General(format!("{path} is not a local file path"))
+                })?;
+                COGReader::from_path(path)
+            }
+            Ok(url) if url.scheme().len() > 1 => {
+                let (store, path) = object_store::parse_url(&url).map_err(AiocogeoError::from)?;
+                COGReader::try_open(Arc::from(store), path)
+            }
+            // Bare paths, including Windows drive letters which parse as a one letter scheme
+            _ => COGReader::from_path(path),
+        });
+        Ok(Self {
+            reader: reader.map_err(to_py_err)?,
+        })
+    }
+
+    /// Return the metadata of the image as a dict
+    fn info<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let ifd = &self.reader.ifds()[0];
+        let info = PyDict::new(py);
+        info.set_item("width", ifd.width())?;
+        info.set_item("height", ifd.height())?;
+        info.set_item("bands", ifd.bands())?;
+        info.set_item("dtype", ifd.dtype().map(dtype_name).map_err(to_py_err)?)?;
+        info.set_item("tile_size", ifd.tile_size())?;
+        info.set_item("compression", format!("{:?}", ifd.compression()))?;
+        let profile = self.reader.profile();
+        info.set_item("profile", profile.name.map(|name| name.as_str()))?;
+        info.set_item("overviews", self.reader.ifds().len() - 1)?;
+        info.set_item("epsg", self.reader.epsg())?;
+        info.set_item("vertical_epsg", self.reader.vertical_epsg())?;
+        info.set_item("crs", self.reader.crs_code())?;
+        info.set_item("bounds", self.reader.native_bounds())?;
+        // RFC 3339, without an offset when the file doesn't give one
+        let date_time = self.reader.date_time().map(|t| t.to_string());
+        info.set_item("date_time", date_time)?;
+        let acquired = self.reader.acquisition_date_time().map(|t| t.to_string());
+        info.set_item("acquisition_date_time", acquired)?;
+        info.set_item(
+            "color_interp",
+            self.reader
+                .color_interp()
+                .into_iter()
+                .map(color_interp_name)
+                .collect::<Vec<_>>(),
+        )?;
+        Ok(info)
+    }
+
+    /// Fetch and decode the internal tile at the given x/y index of overview level `z`, as an
+    /// array of shape `(bands, height, width)`
+    #[pyo3(signature = (x, y, z=0))]
+    fn tile(&self, py: Python<'_>, x: usize, y: usize, z: usize) -> PyResult<Py<PyAny>> {
+        let tile = py
+            .detach(|| self.reader.get_tile(x, y, z))
+            .map_err(to_py_err)?;
+        to_numpy(py, tile)
+    }
+
+    /// Read the pixels of overview level `z` within a window, as an array of shape
+    /// `(bands, height, width)`
+    #[pyo3(signature = (col_off, row_off, width, height, z=0))]
+    fn read_window(
+        &self,
+        py: Python<'_>,
+        col_off: usize,
+        row_off: usize,
+        width: usize,
+        height: usize,
+        z: usize,
+    ) -> PyResult<Py<PyAny>> {
+        let window = Window::new(col_off, row_off, width, height);
+        let array = py
+            .detach(|| self.reader.read_window(window, z, &ReadOptions::default()))
+            .map_err(to_py_err)?;
+        to_numpy(py, array)
+    }
+}
+
+#[pymodule]
+fn aiocogeo_rs(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyCOGReader>()?;
+    Ok(())
+}
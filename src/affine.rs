@@ -1,3 +1,4 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct AffineTransform(f64, f64, f64, f64, f64, f64);
 
 impl AffineTransform {
@@ -28,4 +29,64 @@ impl AffineTransform {
     pub fn f(&self) -> f64 {
         self.5
     }
+
+    /// Apply the transform to a pixel `(col, row)` location, returning model `(x, y)` coordinates
+    pub fn apply(&self, col: f64, row: f64) -> (f64, f64) {
+        (
+            self.a() * col + self.b() * row + self.c(),
+            self.d() * col + self.e() * row + self.f(),
+        )
+    }
+
+    /// Return the inverse transform, mapping model coordinates to pixels, or `None` if the
+    /// transform is degenerate
+    pub fn inverse(&self) -> Option<AffineTransform> {
+        let det = self.a() * self.e() - self.b() * self.d();
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        let (a, b, d, e) = (
+            self.e() / det,
+            -self.b() / det,
+            -self.d() / det,
+            self.a() / det,
+        );
+        Some(AffineTransform::new(
+            a,
+            b,
+            -(a * self.c() + b * self.f()),
+            d,
+            e,
+            -(d * self.c() + e * self.f()),
+        ))
+    }
+
+    /// Return the transform that applies `other` first and then `self`
+    pub fn compose(&self, other: &AffineTransform) -> AffineTransform {
+        AffineTransform::new(
+            self.a() * other.a() + self.b() * other.d(),
+            self.a() * other.b() + self.b() * other.e(),
+            self.a() * other.c() + self.b() * other.f() + self.c(),
+            self.d() * other.a() + self.e() * other.d(),
+            self.d() * other.b() + self.e() * other.e(),
+            self.d() * other.c() + self.e() * other.f() + self.f(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn inverse_round_trip() {
+        let transform = AffineTransform::new(10.0, 0.5, 1000.0, 0.0, -10.0, 5000.0);
+        let inverse = transform.inverse().unwrap();
+        let (x, y) = transform.apply(3.0, 7.0);
+        let (col, row) = inverse.apply(x, y);
+        assert!((col - 3.0).abs() < 1e-9 && (row - 7.0).abs() < 1e-9);
+        assert!(AffineTransform::new(0.0, 0.0, 0.0, 0.0, 0.0, 0.0)
+            .inverse()
+            .is_none());
+    }
 }
@@ -0,0 +1,225 @@
+//! Estimate how efficiently tiles of a target grid, e.g. 256 pixel web map tiles, can be rendered
+//! from a file, and flag internal tile sizes that make renders fetch more than they need.
+use std::fmt::{self, Display};
+
+use crate::error::Result;
+use crate::ifd::ImageFileDirectory;
+use crate::options::ReadOptions;
+use crate::partial_reads::{coalesce_ranges, intersecting_tiles, Window};
+
+/// How renders of one overview level line up with its internal tiles, as returned by
+/// [`crate::COGReader::alignment_report`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelAlignment {
+    /// The overview level, where level 0 is the full resolution image
+    pub level: usize,
+    /// The `(width, height)` of the internal tiles in pixels
+    pub tile_size: (usize, usize),
+    /// The number of target tiles covering the level
+    pub renders: usize,
+    /// The mean number of stored internal tiles a render fetches
+    pub mean_tiles: f64,
+    /// The maximum number of stored internal tiles a render fetches
+    pub max_tiles: usize,
+    /// The mean number of requests a render makes, after coalescing nearby tiles
+    pub mean_requests: f64,
+    /// The maximum number of requests a render makes, after coalescing nearby tiles
+    pub max_requests: usize,
+    /// The mean number of bytes a render fetches
+    pub mean_bytes: f64,
+    /// The number of pixels decoded per rendered pixel, 1 when internal tiles match the target
+    /// tiles
+    pub decoded_pixel_ratio: f64,
+    /// Whether internal tile edges fall inside target tiles, so that renders straddle internal
+    /// tiles: neither tile size is a multiple of the other
+    pub misaligned: bool,
+    /// Whether internal tiles are larger than target tiles, so that renders decode pixels they
+    /// don't need
+    pub oversized: bool,
+}
+
+impl LevelAlignment {
+    fn new(
+        ifd: &ImageFileDirectory,
+        level: usize,
+        (target_width, target_height): (usize, usize),
+        options: &ReadOptions,
+    ) -> Result<Self> {
+        let orientation = ifd.read_orientation(options);
+        let (width, height) = ifd.oriented_size(orientation);
+        let (tile_width, tile_height) = ifd.oriented_tile_size(orientation);
+        let pixel_size = tile_width * tile_height;
+
+        let (mut renders, mut tiles, mut requests, mut bytes) = (0, 0, 0, 0);
+        let (mut max_tiles, mut max_requests) = (0, 0);
+        let mut decoded_pixels = 0;
+        for row_off in (0..height).step_by(target_height.max(1)) {
+            for col_off in (0..width).step_by(target_width.max(1)) {
+                let window = Window::new(
+                    col_off,
+                    row_off,
+                    target_width.min(width - col_off),
+                    target_height.min(height - row_off),
+                );
+                let mut ranges = vec![];
+                let mut stored = 0;
+                for (x, y) in intersecting_tiles(&window, tile_width, tile_height) {
+                    let tile_ranges = ifd.tile_ranges(x, y, orientation, None)?;
+                    if tile_ranges.iter().any(|range| !range.is_empty()) {
+                        stored += 1;
+                    }
+                    ranges.extend(tile_ranges);
+                }
+                let coalesced =
+                    coalesce_ranges(&ranges, options.coalesce_gap_bytes, options.max_range_bytes);
+                renders += 1;
+                tiles += stored;
+                requests += coalesced.len();
+                bytes += coalesced.iter().map(|range| range.len()).sum::<usize>();
+                max_tiles = max_tiles.max(stored);
+                max_requests = max_requests.max(coalesced.len());
+                decoded_pixels += stored * pixel_size;
+            }
+        }
+
+        let renders_f = renders.max(1) as f64;
+        let aligned = |target: usize, tile: usize| {
+            target.is_multiple_of(tile.max(1)) || tile.is_multiple_of(target.max(1))
+        };
+        Ok(Self {
+            level,
+            tile_size: (tile_width, tile_height),
+            renders,
+            mean_tiles: tiles as f64 / renders_f,
+            max_tiles,
+            mean_requests: requests as f64 / renders_f,
+            max_requests,
+            mean_bytes: bytes as f64 / renders_f,
+            decoded_pixel_ratio: decoded_pixels as f64 / (width * height).max(1) as f64,
+            misaligned: !aligned(target_width, tile_width) || !aligned(target_height, tile_height),
+            oversized: tile_width > target_width || tile_height > target_height,
+        })
+    }
+}
+
+/// How efficiently tiles of a target size can be rendered from each overview level, returned by
+/// [`crate::COGReader::alignment_report`]. [`Display`] formats it as a table followed by
+/// [`AlignmentReport::hints`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlignmentReport {
+    /// The `(width, height)` of the target tiles in pixels
+    pub target_tile_size: (usize, usize),
+    /// The image and its overviews, indexed by overview level
+    pub levels: Vec<LevelAlignment>,
+}
+
+impl AlignmentReport {
+    pub(crate) fn new(
+        levels: &[ImageFileDirectory],
+        target_tile_size: (usize, usize),
+        options: &ReadOptions,
+    ) -> Result<Self> {
+        let levels = levels
+            .iter()
+            .enumerate()
+            .map(|(level, ifd)| LevelAlignment::new(ifd, level, target_tile_size, options))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            target_tile_size,
+            levels,
+        })
+    }
+
+    /// Describe the misaligned and oversized internal tiles of each level, or nothing if every
+    /// level is efficient for the target tiles
+    pub fn hints(&self) -> Vec<String> {
+        let (target_width, target_height) = self.target_tile_size;
+        let mut hints = vec![];
+        for level in &self.levels {
+            let (width, height) = level.tile_size;
+            if level.misaligned {
+                hints.push(format!(
+                    "level {}: {width}x{height} tiles are not aligned with \
+                     {target_width}x{target_height} renders, which read up to {} tiles; use a \
+                     tile size that divides or is a multiple of {target_width}x{target_height}",
+                    level.level, level.max_tiles
+                ));
+            }
+            if level.oversized {
+                hints.push(format!(
+                    "level {}: {width}x{height} tiles are larger than \
+                     {target_width}x{target_height} renders, which decode {:.1} pixels per \
+                     rendered pixel; use {target_width}x{target_height} tiles",
+                    level.level, level.decoded_pixel_ratio
+                ));
+            }
+        }
+        hints
+    }
+}
+
+impl Display for AlignmentReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (target_width, target_height) = self.target_tile_size;
+        writeln!(f, "renders of {target_width}x{target_height} tiles")?;
+        writeln!(
+            f,
+            "{:>5} {:>11} {:>8} {:>6} {:>8} {:>12} {:>7}",
+            "level", "tile size", "renders", "tiles", "requests", "bytes", "decoded"
+        )?;
+        for level in &self.levels {
+            writeln!(
+                f,
+                "{:>5} {:>11} {:>8} {:>6.2} {:>8.2} {:>12.0} {:>7.2}",
+                level.level,
+                format!("{}x{}", level.tile_size.0, level.tile_size.1),
+                level.renders,
+                level.mean_tiles,
+                level.mean_requests,
+                level.mean_bytes,
+                level.decoded_pixel_ratio
+            )?;
+        }
+        for hint in self.hints() {
+            writeln!(f, "{hint}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::CogBuilder;
+
+    #[tokio::test]
+    async fn alignment_report() {
+        let (reader, _) = CogBuilder {
+            width: 96,
+            height: 96,
+            overviews: vec![2],
+            ..Default::default()
+        }
+        .open()
+        .await
+        .unwrap();
+        let options = ReadOptions::default();
+
+        let report = reader.alignment_report((32, 32), &options).unwrap();
+        let level = &report.levels[0];
+        assert_eq!((level.renders, level.max_tiles), (9, 1));
+        assert_eq!(level.decoded_pixel_ratio, 1.0);
+        assert!(report.hints().is_empty());
+
+        let report = reader.alignment_report((48, 48), &options).unwrap();
+        let level = &report.levels[0];
+        assert_eq!((level.renders, level.max_tiles), (4, 4));
+        assert!(level.misaligned && !level.oversized);
+        assert_eq!(level.mean_tiles, 4.0);
+
+        let report = reader.alignment_report((16, 16), &options).unwrap();
+        assert!(report.levels.iter().all(|l| l.oversized && !l.misaligned));
+        assert_eq!(report.levels[0].decoded_pixel_ratio, 4.0);
+        assert_eq!(report.hints().len(), 2);
+    }
+}
@@ -0,0 +1,577 @@
+use ndarray::{s, Array2, Array3, Axis};
+use num_complex::Complex;
+
+use crate::enums::{DataType, Interleave};
+use crate::error::{AiocogeoError, Result};
+use crate::partial_reads::Window;
+
+/// A decoded array of pixel values with shape `(bands, height, width)`, typed according to the
+/// sample format of the image it was read from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RasterArray {
+    Uint8(Array3<u8>),
+    Uint16(Array3<u16>),
+    Uint32(Array3<u32>),
+    Uint64(Array3<u64>),
+    Int8(Array3<i8>),
+    Int16(Array3<i16>),
+    Int32(Array3<i32>),
+    Int64(Array3<i64>),
+    Float32(Array3<f32>),
+    Float64(Array3<f64>),
+    CInt16(Array3<Complex<i16>>),
+    CInt32(Array3<Complex<i32>>),
+    CFloat32(Array3<Complex<f32>>),
+    CFloat64(Array3<Complex<f64>>),
+}
+
+/// A single pixel value of one of the real data types, e.g. a fill value.
+///
+/// Values that `f64` can't hold exactly, such as large `u64`s, keep their value in the variant of
+/// their type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PixelValue {
+    Uint8(u8),
+    Uint16(u16),
+    Uint32(u32),
+    Uint64(u64),
+    Int8(i8),
+    Int16(i16),
+    Int32(i32),
+    Int64(i64),
+    Float32(f32),
+    Float64(f64),
+}
+
+/// Apply the same expression to the inner array of every variant
+macro_rules! map_inner {
+    ($value:expr, $arr:ident => $expr:expr) => {
+        match $value {
+            RasterArray::Uint8($arr) => $expr,
+            RasterArray::Uint16($arr) => $expr,
+            RasterArray::Uint32($arr) => $expr,
+            RasterArray::Uint64($arr) => $expr,
+            RasterArray::Int8($arr) => $expr,
+            RasterArray::Int16($arr) => $expr,
+            RasterArray::Int32($arr) => $expr,
+            RasterArray::Int64($arr) => $expr,
+            RasterArray::Float32($arr) => $expr,
+            RasterArray::Float64($arr) => $expr,
+            RasterArray::CInt16($arr) => $expr,
+            RasterArray::CInt32($arr) => $expr,
+            RasterArray::CFloat32($arr) => $expr,
+            RasterArray::CFloat64($arr) => $expr,
+        }
+    };
+}
+
+/// Apply the same array-to-array expression to every variant, keeping the variant
+macro_rules! map_array {
+    ($value:expr, $arr:ident => $expr:expr) => {
+        match $value {
+            RasterArray::Uint8($arr) => RasterArray::Uint8($expr),
+            RasterArray::Uint16($arr) => RasterArray::Uint16($expr),
+            RasterArray::Uint32($arr) => RasterArray::Uint32($expr),
+            RasterArray::Uint64($arr) => RasterArray::Uint64($expr),
+            RasterArray::Int8($arr) => RasterArray::Int8($expr),
+            RasterArray::Int16($arr) => RasterArray::Int16($expr),
+            RasterArray::Int32($arr) => RasterArray::Int32($expr),
+            RasterArray::Int64($arr) => RasterArray::Int64($expr),
+            RasterArray::Float32($arr) => RasterArray::Float32($expr),
+            RasterArray::Float64($arr) => RasterArray::Float64($expr),
+            RasterArray::CInt16($arr) => RasterArray::CInt16($expr),
+            RasterArray::CInt32($arr) => RasterArray::CInt32($expr),
+            RasterArray::CFloat32($arr) => RasterArray::CFloat32($expr),
+            RasterArray::CFloat64($arr) => RasterArray::CFloat64($expr),
+        }
+    };
+}
+
+pub(crate) use map_array;
+
+impl RasterArray {
+    /// The data type of the array's values
+    pub fn dtype(&self) -> DataType {
+        match self {
+            Self::Uint8(_) => DataType::Uint8,
+            Self::Uint16(_) => DataType::Uint16,
+            Self::Uint32(_) => DataType::Uint32,
+            Self::Uint64(_) => DataType::Uint64,
+            Self::Int8(_) => DataType::Int8,
+            Self::Int16(_) => DataType::Int16,
+            Self::Int32(_) => DataType::Int32,
+            Self::Int64(_) => DataType::Int64,
+            Self::Float32(_) => DataType::Float32,
+            Self::Float64(_) => DataType::Float64,
+            Self::CInt16(_) => DataType::CInt16,
+            Self::CInt32(_) => DataType::CInt32,
+            Self::CFloat32(_) => DataType::CFloat32,
+            Self::CFloat64(_) => DataType::CFloat64,
+        }
+    }
+
+    /// The `(bands, height, width)` shape of the array
+    pub fn shape(&self) -> (usize, usize, usize) {
+        map_inner!(self, arr => arr.dim())
+    }
+
+    /// The number of bytes needed to hold the values of the array
+    pub fn nbytes(&self) -> usize {
+        let (bands, height, width) = self.shape();
+        bands * height * width * self.dtype().size()
+    }
+
+    /// Copy the values of the array into `out` in `(bands, height, width)` row-major order and
+    /// native byte order. `out` must hold at least [`nbytes`](Self::nbytes) bytes.
+    pub fn copy_to_bytes(&self, out: &mut [u8]) -> Result<()> {
+        self.copy_to_bytes_interleaved(out, Interleave::Band)
+    }
+
+    /// Copy the values of the array into `out` in native byte order, either band-sequential like
+    /// [`copy_to_bytes`](Self::copy_to_bytes) or pixel-interleaved in `(height, width, bands)`
+    /// order, whatever the planar configuration of the file it was read from. `out` must hold at
+    /// least [`nbytes`](Self::nbytes) bytes.
+    pub fn copy_to_bytes_interleaved(&self, out: &mut [u8], interleave: Interleave) -> Result<()> {
+        let len = self.nbytes();
+        if out.len() < len {
+            return Err(AiocogeoError::General(format!(
+                "buffer of {} bytes is too small for {len} bytes of pixels",
+                out.len()
+            )));
+        }
+        let shape = self.shape();
+        let sample_size = self.dtype().size();
+        map_inner!(self, arr => {
+            let arr = arr.as_standard_layout();
+            let values = arr.as_slice().expect("standard layout arrays are contiguous");
+            // SAFETY: every variant holds primitive numbers or `#[repr(C)]` pairs of them, which
+            // have no padding, and `len` is the size of `values` in bytes
+            let bytes = unsafe { std::slice::from_raw_parts(values.as_ptr().cast::<u8>(), len) };
+            match interleave {
+                Interleave::Band => out[..len].copy_from_slice(bytes),
+                Interleave::Pixel => interleave_pixels(bytes, &mut out[..len], shape, sample_size),
+            }
+        });
+        Ok(())
+    }
+
+    /// Convert the values to `f64`. Complex values are not supported.
+    pub(crate) fn to_f64(&self) -> Result<Array3<f64>> {
+        Ok(match self {
+            Self::Uint8(arr) => arr.mapv(f64::from),
+            Self::Uint16(arr) => arr.mapv(f64::from),
+            Self::Uint32(arr) => arr.mapv(f64::from),
+            Self::Uint64(arr) => arr.mapv(|v| v as f64),
+            Self::Int8(arr) => arr.mapv(f64::from),
+            Self::Int16(arr) => arr.mapv(f64::from),
+            Self::Int32(arr) => arr.mapv(f64::from),
+            Self::Int64(arr) => arr.mapv(|v| v as f64),
+            Self::Float32(arr) => arr.mapv(f64::from),
+            Self::Float64(arr) => arr.clone(),
+            arr => {
+                return Err(AiocogeoError::General(format!(
+                    "cannot convert {:?} values to f64",
+                    arr.dtype()
+                )))
+            }
+        })
+    }
+
+    /// Convert `f64` values to an array of `dtype`, as by [`RasterArray::full`]
+    pub(crate) fn from_f64(dtype: DataType, values: Array3<f64>) -> Self {
+        macro_rules! from_f64 {
+            ($($variant:ident),*) => {
+                match dtype {
+                    $(DataType::$variant => {
+                        Self::$variant(values.mapv(|v| FromFill::from_fill(PixelValue::Float64(v))))
+                    })*
+                }
+            };
+        }
+        from_f64!(
+            Uint8, Uint16, Uint32, Uint64, Int8, Int16, Int32, Int64, Float32, Float64, CInt16,
+            CInt32, CFloat32, CFloat64
+        )
+    }
+
+    /// Resample with nearest neighbors, taking output row `i` from input row `rows[i]` and output
+    /// column `j` from input column `cols[j]`
+    pub(crate) fn resample_nearest(&self, rows: &[usize], cols: &[usize]) -> Self {
+        map_array!(self, arr => arr.select(Axis(1), rows).select(Axis(2), cols))
+    }
+
+    /// Build a `(height, width)` array taking each output pixel, in row-major order, from the
+    /// `(row, col)` input pixel in `pixels`
+    pub(crate) fn gather(&self, pixels: &[(usize, usize)], shape: (usize, usize)) -> Self {
+        let (height, width) = shape;
+        map_array!(self, arr => Array3::from_shape_fn((arr.dim().0, height, width), |(band, row, col)| {
+            let (src_row, src_col) = pixels[row * width + col];
+            arr[[band, src_row, src_col]]
+        }))
+    }
+
+    /// Set every pixel that is `false` in a `(height, width)` mask to `fill`, converted to the
+    /// data type of the array as by [`RasterArray::full`], in all bands
+    pub(crate) fn fill_masked(&mut self, mask: &Array2<bool>, fill: PixelValue) {
+        map_inner!(self, arr => {
+            let fill = FromFill::from_fill(fill);
+            for mut band in arr.outer_iter_mut() {
+                band.zip_mut_with(mask, |value, &valid| {
+                    if !valid {
+                        *value = fill;
+                    }
+                });
+            }
+        })
+    }
+
+    /// Select bands by index, in the given order
+    pub(crate) fn select_bands(&self, bands: &[usize]) -> Self {
+        map_array!(self, arr => arr.select(Axis(0), bands))
+    }
+
+    /// Keep the top left `(height, width)` pixels of each band
+    pub(crate) fn crop(&self, height: usize, width: usize) -> Self {
+        map_array!(self, arr => arr.slice(s![.., ..height, ..width]).to_owned())
+    }
+
+    /// Create an array of `value` with the given data type and `(bands, height, width)` shape.
+    ///
+    /// `value` is converted as with `as`: integers saturate at the bounds of the type and `NaN`
+    /// becomes 0, and complex values have no imaginary part.
+    pub(crate) fn full(dtype: DataType, shape: (usize, usize, usize), value: PixelValue) -> Self {
+        macro_rules! full {
+            ($($variant:ident),*) => {
+                match dtype {
+                    $(DataType::$variant => {
+                        Self::$variant(Array3::from_elem(shape, FromFill::from_fill(value)))
+                    })*
+                }
+            };
+        }
+        full!(
+            Uint8, Uint16, Uint32, Uint64, Int8, Int16, Int32, Int64, Float32, Float64, CInt16,
+            CInt32, CFloat32, CFloat64
+        )
+    }
+
+    /// Paste tiles laid out on a grid into this array, as with [`RasterArray::paste`]. Tiles in
+    /// the same row of the grid must share their `row`, and tiles in the same column their
+    /// `col`, so that no two tiles overlap.
+    ///
+    /// With the `rayon` feature, tiles are copied in parallel on the rayon thread pool.
+    pub(crate) fn paste_tiles(&mut self, tiles: &[Placement]) -> Result<()> {
+        #[cfg(feature = "rayon")]
+        if tiles.len() > 1 {
+            macro_rules! paste_grid {
+                ($($variant:ident),*) => {
+                    match self {
+                        $(Self::$variant(dst) => {
+                            let srcs = tiles
+                                .iter()
+                                .map(|tile| match &tile.src {
+                                    Self::$variant(src) => Ok(src),
+                                    src => Err(AiocogeoError::General(format!(
+                                        "cannot copy {:?} values into a {:?} array",
+                                        src.dtype(),
+                                        DataType::$variant
+                                    ))),
+                                })
+                                .collect::<Result<Vec<_>>>()?;
+                            paste_grid(dst.view_mut(), tiles, &srcs);
+                        })*
+                    }
+                };
+            }
+            paste_grid!(
+                Uint8, Uint16, Uint32, Uint64, Int8, Int16, Int32, Int64, Float32, Float64, CInt16,
+                CInt32, CFloat32, CFloat64
+            );
+            return Ok(());
+        }
+        for tile in tiles {
+            self.paste(&tile.src, tile.src_window, tile.row, tile.col)?;
+        }
+        Ok(())
+    }
+
+    /// Copy the pixels of `src` within `src_window` into this array, with the top left corner of
+    /// the window placed at `(row, col)`
+    pub(crate) fn paste(
+        &mut self,
+        src: &RasterArray,
+        src_window: Window,
+        row: usize,
+        col: usize,
+    ) -> Result<()> {
+        macro_rules! paste {
+            ($($variant:ident),*) => {
+                match (self, src) {
+                    $((Self::$variant(dst), Self::$variant(src)) => {
+                        let src = src.slice(s![
+                            ..,
+                            src_window.row_off..src_window.row_off + src_window.height,
+                            src_window.col_off..src_window.col_off + src_window.width
+                        ]);
+                        dst.slice_mut(s![
+                            ..,
+                            row..row + src_window.height,
+                            col..col + src_window.width
+                        ])
+                        .assign(&src);
+                        Ok(())
+                    })*
+                    (dst, src) => Err(AiocogeoError::General(format!(
+                        "cannot copy {:?} values into a {:?} array",
+                        src.dtype(),
+                        dst.dtype()
+                    ))),
+                }
+            };
+        }
+        paste!(
+            Uint8, Uint16, Uint32, Uint64, Int8, Int16, Int32, Int64, Float32, Float64, CInt16,
+            CInt32, CFloat32, CFloat64
+        )
+    }
+}
+
+/// A tile to paste into an array with [`RasterArray::paste_tiles`]: the pixels of `src` within
+/// `src_window`, with the top left corner of the window placed at `(row, col)`
+pub(crate) struct Placement {
+    pub(crate) src: RasterArray,
+    pub(crate) src_window: Window,
+    pub(crate) row: usize,
+    pub(crate) col: usize,
+}
+
+/// Copy each tile into its own cell of `dst` on the rayon thread pool. The cells are split off at
+/// the rows and columns of the tiles, so that every tile writes to a disjoint view.
+#[cfg(feature = "rayon")]
+fn paste_grid<T: Clone + Send + Sync>(
+    dst: ndarray::ArrayViewMut3<T>,
+    tiles: &[Placement],
+    srcs: &[&Array3<T>],
+) {
+    use rayon::prelude::*;
+    use std::collections::HashMap;
+
+    let starts = |key: fn(&Placement) -> usize| {
+        let mut starts: Vec<_> = tiles.iter().map(key).collect();
+        starts.sort_unstable();
+        starts.dedup();
+        starts
+    };
+    let (rows, cols) = (starts(|t| t.row), starts(|t| t.col));
+    let index: HashMap<_, _> = tiles
+        .iter()
+        .enumerate()
+        .map(|(i, t)| ((t.row, t.col), i))
+        .collect();
+
+    let mut cells = Vec::with_capacity(tiles.len());
+    let mut rest = dst;
+    for &row in rows.iter().rev() {
+        let (top, mut band) = rest.split_at(Axis(1), row);
+        rest = top;
+        for &col in cols.iter().rev() {
+            let (left, cell) = band.split_at(Axis(2), col);
+            band = left;
+            if let Some(&i) = index.get(&(row, col)) {
+                cells.push((cell, i));
+            }
+        }
+    }
+    cells.into_par_iter().for_each(|(mut cell, i)| {
+        let window = tiles[i].src_window;
+        let src = srcs[i].slice(s![
+            ..,
+            window.row_off..window.row_off + window.height,
+            window.col_off..window.col_off + window.width
+        ]);
+        cell.slice_mut(s![.., ..window.height, ..window.width])
+            .assign(&src);
+    });
+}
+
+/// Transpose band-sequential samples of `sample_size` bytes to pixel-interleaved order, a row at a
+/// time so that the rows of every band being read and the output row being written stay in cache
+fn interleave_pixels(
+    src: &[u8],
+    dst: &mut [u8],
+    (bands, height, width): (usize, usize, usize),
+    sample_size: usize,
+) {
+    let row_size = width * sample_size;
+    let band_size = height * row_size;
+    for (row, dst_row) in dst.chunks_exact_mut(row_size * bands).enumerate() {
+        for band in 0..bands {
+            let start = band * band_size + row * row_size;
+            let src_row = &src[start..start + row_size];
+            let dst_samples = dst_row
+                .chunks_exact_mut(sample_size)
+                .skip(band)
+                .step_by(bands);
+            for (dst, src) in dst_samples.zip(src_row.chunks_exact(sample_size)) {
+                dst.copy_from_slice(src);
+            }
+        }
+    }
+}
+
+/// Conversion of a fill value to the type of a sample
+trait FromFill: Copy {
+    fn from_fill(value: PixelValue) -> Self;
+}
+
+macro_rules! impl_from_fill {
+    ($($typ:ty),*) => {
+        $(impl FromFill for $typ {
+            #[allow(clippy::unnecessary_cast)]
+            fn from_fill(value: PixelValue) -> Self {
+                match value {
+                    PixelValue::Uint8(v) => v as $typ,
+                    PixelValue::Uint16(v) => v as $typ,
+                    PixelValue::Uint32(v) => v as $typ,
+                    PixelValue::Uint64(v) => v as $typ,
+                    PixelValue::Int8(v) => v as $typ,
+                    PixelValue::Int16(v) => v as $typ,
+                    PixelValue::Int32(v) => v as $typ,
+                    PixelValue::Int64(v) => v as $typ,
+                    PixelValue::Float32(v) => v as $typ,
+                    PixelValue::Float64(v) => v as $typ,
+                }
+            }
+        })*
+    };
+}
+
+impl_from_fill!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl<T: FromFill> FromFill for Complex<T> {
+    fn from_fill(value: PixelValue) -> Self {
+        Complex::new(T::from_fill(value), T::from_fill(PixelValue::Uint8(0)))
+    }
+}
+
+macro_rules! impl_from_value {
+    ($variant:ident, $typ:ty) => {
+        impl From<$typ> for PixelValue {
+            fn from(value: $typ) -> Self {
+                Self::$variant(value)
+            }
+        }
+    };
+}
+
+impl_from_value!(Uint8, u8);
+impl_from_value!(Uint16, u16);
+impl_from_value!(Uint32, u32);
+impl_from_value!(Uint64, u64);
+impl_from_value!(Int8, i8);
+impl_from_value!(Int16, i16);
+impl_from_value!(Int32, i32);
+impl_from_value!(Int64, i64);
+impl_from_value!(Float32, f32);
+impl_from_value!(Float64, f64);
+
+macro_rules! impl_from_array {
+    ($variant:ident, $typ:ty) => {
+        impl From<Array3<$typ>> for RasterArray {
+            fn from(value: Array3<$typ>) -> Self {
+                Self::$variant(value)
+            }
+        }
+    };
+}
+
+impl_from_array!(Uint8, u8);
+impl_from_array!(Uint16, u16);
+impl_from_array!(Uint32, u32);
+impl_from_array!(Uint64, u64);
+impl_from_array!(Int8, i8);
+impl_from_array!(Int16, i16);
+impl_from_array!(Int32, i32);
+impl_from_array!(Int64, i64);
+impl_from_array!(Float32, f32);
+impl_from_array!(Float64, f64);
+impl_from_array!(CInt16, Complex<i16>);
+impl_from_array!(CInt32, Complex<i32>);
+impl_from_array!(CFloat32, Complex<f32>);
+impl_from_array!(CFloat64, Complex<f64>);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ndarray::Array;
+
+    #[test]
+    fn paste_window() {
+        let src =
+            RasterArray::from(Array::from_shape_vec((1, 2, 3), vec![1u8, 2, 3, 4, 5, 6]).unwrap());
+        let mut dst = RasterArray::full(DataType::Uint8, (1, 3, 3), 0u8.into());
+        dst.paste(&src, Window::new(1, 0, 2, 2), 1, 0).unwrap();
+        let expected = Array::from_shape_vec((1, 3, 3), vec![0u8, 0, 0, 2, 3, 0, 5, 6, 0]).unwrap();
+        assert_eq!(dst, RasterArray::Uint8(expected));
+
+        let mut wrong = RasterArray::full(DataType::Int16, (1, 3, 3), 0i16.into());
+        assert!(wrong.paste(&src, Window::new(0, 0, 1, 1), 0, 0).is_err());
+    }
+
+    #[test]
+    fn paste_tiles() {
+        // A 3x3 window over four 2x2 tiles, offset by one pixel from the tile grid
+        let tiles: Vec<_> = (0..4u8)
+            .map(|i| {
+                let tile = Array::from_elem((1, 2, 2), i + 1);
+                let (row, col) = (i as usize / 2, i as usize % 2);
+                Placement {
+                    src: RasterArray::from(tile),
+                    src_window: Window::new(1 - col, 1 - row, 1 + col, 1 + row),
+                    row,
+                    col,
+                }
+            })
+            .collect();
+        let mut dst = RasterArray::full(DataType::Uint8, (1, 3, 3), 0u8.into());
+        dst.paste_tiles(&tiles).unwrap();
+        let expected = Array::from_shape_vec((1, 3, 3), vec![1u8, 2, 2, 3, 4, 4, 3, 4, 4]).unwrap();
+        assert_eq!(dst, RasterArray::Uint8(expected));
+
+        let mut wrong = RasterArray::full(DataType::Int16, (1, 3, 3), 0i16.into());
+        assert!(wrong.paste_tiles(&tiles).is_err());
+    }
+
+    #[test]
+    fn copy_to_bytes() {
+        let arr = RasterArray::from(Array::from_shape_vec((1, 1, 2), vec![1u16, 2]).unwrap());
+        let mut out = [0u8; 4];
+        arr.copy_to_bytes(&mut out).unwrap();
+        assert_eq!(out[..2], 1u16.to_ne_bytes());
+        assert_eq!(out[2..], 2u16.to_ne_bytes());
+        assert!(arr.copy_to_bytes(&mut [0u8; 3]).is_err());
+    }
+
+    #[test]
+    fn copy_to_bytes_interleaved() {
+        // 2 bands of 2x2 pixels
+        let values: Vec<u16> = (0..8).collect();
+        let arr = RasterArray::from(Array::from_shape_vec((2, 2, 2), values).unwrap());
+        let mut out = [0u8; 16];
+        arr.copy_to_bytes_interleaved(&mut out, Interleave::Pixel)
+            .unwrap();
+        let pixels: Vec<_> = out
+            .chunks_exact(2)
+            .map(|b| u16::from_ne_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(pixels, [0, 4, 1, 5, 2, 6, 3, 7]);
+
+        arr.copy_to_bytes_interleaved(&mut out, Interleave::Band)
+            .unwrap();
+        let bands: Vec<_> = out
+            .chunks_exact(2)
+            .map(|b| u16::from_ne_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(bands, [0, 1, 2, 3, 4, 5, 6, 7]);
+    }
+}
@@ -0,0 +1,148 @@
+//! Export decoded arrays to Arrow, for handing pixels to DataFusion, Polars or geoarrow.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow_array::types::*;
+use arrow_array::{ArrayRef, BinaryArray, FixedSizeListArray, PrimitiveArray};
+use arrow_buffer::{ArrowNativeType, Buffer, OffsetBuffer, ScalarBuffer};
+use arrow_schema::{DataType as ArrowDataType, Field};
+use ndarray::Array3;
+use num_complex::Complex;
+
+use crate::array::RasterArray;
+
+/// The name of Arrow's canonical fixed shape tensor extension type
+const FIXED_SHAPE_TENSOR: &str = "arrow.fixed_shape_tensor";
+
+/// Take the values of an array in row-major order, reusing its allocation when it is already
+/// contiguous
+fn into_vec<T: Clone>(arr: Array3<T>) -> Vec<T> {
+    if !arr.is_standard_layout() {
+        return arr.iter().cloned().collect();
+    }
+    let len = arr.len();
+    let (mut vec, offset) = arr.into_raw_vec_and_offset();
+    let offset = offset.unwrap_or(0);
+    if offset != 0 || vec.len() != len {
+        vec.drain(..offset);
+        vec.truncate(len);
+    }
+    vec
+}
+
+/// Split complex values into interleaved real and imaginary parts
+fn complex_into_vec<T: Clone>(arr: Array3<Complex<T>>) -> Vec<T> {
+    arr.iter()
+        .flat_map(|v| [v.re.clone(), v.im.clone()])
+        .collect()
+}
+
+/// The values of an array as an Arrow buffer, and the Arrow type of each value. Complex values
+/// are stored as interleaved pairs of their component type.
+fn into_buffer(array: RasterArray) -> (Buffer, ArrayRef) {
+    fn primitive<T: ArrowPrimitiveType>(values: Vec<T::Native>) -> (Buffer, ArrayRef)
+    where
+        T::Native: ArrowNativeType,
+    {
+        let buffer = Buffer::from_vec(values);
+        let array = PrimitiveArray::<T>::new(ScalarBuffer::from(buffer.clone()), None);
+        (buffer, Arc::new(array))
+    }
+    match array {
+        RasterArray::Uint8(arr) => primitive::<UInt8Type>(into_vec(arr)),
+        RasterArray::Uint16(arr) => primitive::<UInt16Type>(into_vec(arr)),
+        RasterArray::Uint32(arr) => primitive::<UInt32Type>(into_vec(arr)),
+        RasterArray::Uint64(arr) => primitive::<UInt64Type>(into_vec(arr)),
+        RasterArray::Int8(arr) => primitive::<Int8Type>(into_vec(arr)),
+        RasterArray::Int16(arr) => primitive::<Int16Type>(into_vec(arr)),
+        RasterArray::Int32(arr) => primitive::<Int32Type>(into_vec(arr)),
+        RasterArray::Int64(arr) => primitive::<Int64Type>(into_vec(arr)),
+        RasterArray::Float32(arr) => primitive::<Float32Type>(into_vec(arr)),
+        RasterArray::Float64(arr) => primitive::<Float64Type>(into_vec(arr)),
+        RasterArray::CInt16(arr) => primitive::<Int16Type>(complex_into_vec(arr)),
+        RasterArray::CInt32(arr) => primitive::<Int32Type>(complex_into_vec(arr)),
+        RasterArray::CFloat32(arr) => primitive::<Float32Type>(complex_into_vec(arr)),
+        RasterArray::CFloat64(arr) => primitive::<Float64Type>(complex_into_vec(arr)),
+    }
+}
+
+impl RasterArray {
+    /// Convert the array into a single-element Arrow `FixedShapeTensor` array, returned with the
+    /// field carrying the extension type metadata.
+    ///
+    /// The tensor has shape `[bands, height, width]`, or `[bands, height, width, 2]` for complex
+    /// data types, whose real and imaginary parts are interleaved. Contiguous arrays of real
+    /// values are converted without copying.
+    pub fn into_arrow_tensor(self, name: &str) -> (Field, FixedSizeListArray) {
+        let (bands, height, width) = self.shape();
+        let mut shape = vec![bands, height, width];
+        if self.dtype().is_complex() {
+            shape.push(2);
+        }
+        let size = shape.iter().product::<usize>() as i32;
+
+        let (_, values) = into_buffer(self);
+        let item = Arc::new(Field::new("item", values.data_type().clone(), false));
+        let list = FixedSizeListArray::new(item.clone(), size, values, None);
+
+        let shape = shape
+            .iter()
+            .map(usize::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        let metadata = HashMap::from([
+            (
+                "ARROW:extension:name".to_string(),
+                FIXED_SHAPE_TENSOR.to_string(),
+            ),
+            (
+                "ARROW:extension:metadata".to_string(),
+                format!(r#"{{"shape":[{shape}]}}"#),
+            ),
+        ]);
+        let field = Field::new(name, ArrowDataType::FixedSizeList(item, size), false)
+            .with_metadata(metadata);
+        (field, list)
+    }
+
+    /// Convert the array into an Arrow `Binary` array with one element per band, each holding
+    /// the band's `(height, width)` values in row-major order and native byte order.
+    ///
+    /// Contiguous arrays of real values are converted without copying.
+    pub fn into_arrow_binary(self) -> BinaryArray {
+        let (bands, height, width) = self.shape();
+        let band_bytes = height * width * self.dtype().size();
+        let (buffer, _) = into_buffer(self);
+        let offsets = OffsetBuffer::from_lengths(std::iter::repeat_n(band_bytes, bands));
+        BinaryArray::new(offsets, buffer, None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arrow_array::Array as _;
+    use ndarray::Array;
+
+    #[test]
+    fn tensor_and_binary() {
+        let values: Vec<u16> = (0..12).collect();
+        let arr = RasterArray::from(Array::from_shape_vec((2, 2, 3), values.clone()).unwrap());
+
+        let (field, tensor) = arr.clone().into_arrow_tensor("pixels");
+        assert_eq!(tensor.len(), 1);
+        assert_eq!(tensor.value_length(), 12);
+        assert_eq!(
+            field.metadata()["ARROW:extension:metadata"],
+            r#"{"shape":[2,2,3]}"#
+        );
+        let inner = tensor.value(0);
+        let inner = inner.as_any().downcast_ref::<PrimitiveArray<UInt16Type>>();
+        assert_eq!(inner.unwrap().values().to_vec(), values);
+
+        let binary = arr.into_arrow_binary();
+        assert_eq!(binary.len(), 2);
+        let expected: Vec<u8> = (6u16..12).flat_map(u16::to_ne_bytes).collect();
+        assert_eq!(binary.value(1), expected.as_slice());
+    }
+}
@@ -0,0 +1,187 @@
+//! A synchronous facade over [`crate::COGReader`], for callers that aren't async.
+//!
+//! Each reader drives the async reader on its own single-threaded tokio runtime. Methods that
+//! don't make requests, like [`crate::COGReader::geotransform`], are available through `Deref`.
+
+use std::ops::{Deref, Range};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::StreamExt;
+use ndarray::Array2;
+use object_store::path::Path;
+use object_store::{ObjectMeta, ObjectStore};
+use tokio::runtime::{Builder, Runtime};
+
+use crate::array::RasterArray;
+use crate::error::Result;
+use crate::expression::Expression;
+use crate::ifd::RawTile;
+use crate::options::{OpenOptions, ReadOptions};
+use crate::partial_reads::{Tile, Window};
+
+/// A blocking COG reader with the same API as [`crate::COGReader`]
+pub struct COGReader {
+    inner: crate::COGReader,
+    runtime: Runtime,
+}
+
+impl COGReader {
+    /// Open a COG with the options of [`OpenOptions::from_env`]
+    pub fn try_open(store: Arc<dyn ObjectStore>, path: Path) -> Result<Self> {
+        Self::try_open_with_options(store, path, &OpenOptions::from_env()?)
+    }
+
+    /// Open a COG, as in [`crate::COGReader::try_open_with_options`]
+    pub fn try_open_with_options(
+        store: Arc<dyn ObjectStore>,
+        path: Path,
+        options: &OpenOptions,
+    ) -> Result<Self> {
+        Self::block_on_open(|| crate::COGReader::try_open_with_options(store, path, options))
+    }
+
+    /// Open a COG, as in [`crate::COGReader::try_open_with_meta`]
+    pub fn try_open_with_meta(
+        store: Arc<dyn ObjectStore>,
+        meta: ObjectMeta,
+        options: &OpenOptions,
+    ) -> Result<Self> {
+        Self::block_on_open(|| crate::COGReader::try_open_with_meta(store, meta, options))
+    }
+
+    /// Open a COG, as in [`crate::COGReader::try_open_with_header`]
+    pub fn try_open_with_header(
+        header: Bytes,
+        store: Arc<dyn ObjectStore>,
+        path: Path,
+    ) -> Result<Self> {
+        Self::block_on_open(|| crate::COGReader::try_open_with_header(header, store, path))
+    }
+
+    /// Open a COG held in memory, as in [`crate::COGReader::from_bytes`]
+    pub fn from_bytes(bytes: Bytes) -> Result<Self> {
+        Self::block_on_open(|| crate::COGReader::from_bytes(bytes))
+    }
+
+    /// Open a COG from a local file path, as in [`crate::COGReader::from_path`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_path(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        Self::block_on_open(|| crate::COGReader::from_path(path))
+    }
+
+    /// Open a COG from an open local file, as in [`crate::COGReader::from_file`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_file(file: std::fs::File, location: Path) -> Result<Self> {
+        Self::block_on_open(|| crate::COGReader::from_file(file, location))
+    }
+
+    fn block_on_open<F: std::future::Future<Output = Result<crate::COGReader>>>(
+        open: impl FnOnce() -> F,
+    ) -> Result<Self> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let inner = runtime.block_on(open())?;
+        Ok(Self { inner, runtime })
+    }
+
+    /// Return the async reader
+    pub fn into_inner(self) -> crate::COGReader {
+        self.inner
+    }
+
+    /// Fetch and decode an internal tile, as in [`crate::COGReader::get_tile`]
+    pub fn get_tile(&self, x: usize, y: usize, z: usize) -> Result<RasterArray> {
+        self.runtime.block_on(self.inner.get_tile(x, y, z))
+    }
+
+    /// Fetch and decode an internal tile, as in [`crate::COGReader::get_tile_with_options`]
+    pub fn get_tile_with_options(
+        &self,
+        x: usize,
+        y: usize,
+        z: usize,
+        options: &ReadOptions,
+    ) -> Result<RasterArray> {
+        self.runtime
+            .block_on(self.inner.get_tile_with_options(x, y, z, options))
+    }
+
+    /// Fetch the compressed bytes of an internal tile, as in [`crate::COGReader::get_raw_tile`]
+    pub fn get_raw_tile(&self, x: usize, y: usize, z: usize) -> Result<RawTile> {
+        self.runtime.block_on(self.inner.get_raw_tile(x, y, z))
+    }
+
+    /// Read the pixels within a window, as in [`crate::COGReader::read_window`]
+    pub fn read_window(
+        &self,
+        window: Window,
+        z: usize,
+        options: &ReadOptions,
+    ) -> Result<RasterArray> {
+        self.runtime
+            .block_on(self.inner.read_window(window, z, options))
+    }
+
+    /// Evaluate a band math expression over a window, as in
+    /// [`crate::COGReader::read_expression`]
+    pub fn read_expression(
+        &self,
+        expression: &Expression,
+        window: Window,
+        z: usize,
+        options: &ReadOptions,
+    ) -> Result<(Array2<f64>, Array2<bool>)> {
+        self.runtime
+            .block_on(self.inner.read_expression(expression, window, z, options))
+    }
+
+    /// Iterate over the decoded tiles intersecting a window, as in
+    /// [`crate::COGReader::read_tiles_stream`]
+    pub fn read_tiles_stream<'a>(
+        &'a self,
+        window: Option<Window>,
+        z: usize,
+        options: &'a ReadOptions,
+    ) -> Result<impl Iterator<Item = Result<Tile>> + 'a> {
+        let mut stream = Box::pin(self.inner.read_tiles_stream(window, z, options)?);
+        Ok(std::iter::from_fn(move || {
+            self.runtime.block_on(stream.next())
+        }))
+    }
+
+    /// Iterate over horizontal bands of a window as each is assembled, as in
+    /// [`crate::COGReader::read_window_rows`]
+    pub fn read_window_rows<'a>(
+        &'a self,
+        window: Window,
+        z: usize,
+        options: &'a ReadOptions,
+    ) -> Result<impl Iterator<Item = Result<(Window, RasterArray)>> + 'a> {
+        let mut stream = Box::pin(self.inner.read_window_rows(window, z, options)?);
+        Ok(std::iter::from_fn(move || {
+            self.runtime.block_on(stream.next())
+        }))
+    }
+
+    /// Fetch the tiles intersecting a window into the cache, as in
+    /// [`crate::COGReader::prefetch_window`]
+    pub fn prefetch_window(&self, window: Window, z: usize, options: &ReadOptions) -> Result<()> {
+        self.runtime
+            .block_on(self.inner.prefetch_window(window, z, options))
+    }
+
+    /// Fetch every tile of the given overview levels into the cache, as in
+    /// [`crate::COGReader::prefetch_tiles`]
+    pub fn prefetch_tiles(&self, levels: Range<usize>, options: &ReadOptions) -> Result<()> {
+        self.runtime
+            .block_on(self.inner.prefetch_tiles(levels, options))
+    }
+}
+
+impl Deref for COGReader {
+    type Target = crate::COGReader;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
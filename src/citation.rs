@@ -0,0 +1,117 @@
+//! Parse the citation GeoKeys, in which GDAL and ESRI software write the names of the parts of
+//! user-defined CRSs that have no EPSG code.
+
+/// A citation GeoKey (`GTCitationGeoKey`, `GeogCitationGeoKey` or `ProjCitationGeoKey`), split
+/// into the names it holds.
+///
+/// GDAL writes the names of user-defined CRSs in a "pipe" format of `Key = value` parts, e.g.
+/// `GCS Name = NAD83|Datum = North_American_Datum_1983|Ellipsoid = GRS 1980|Primem = Greenwich||`,
+/// while most other citations are just a name, e.g. `NAD83 / UTM zone 18N`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Citation {
+    /// The citation as stored
+    pub text: String,
+    /// The part of the citation that isn't labelled with a key, usually the name of the CRS
+    pub name: Option<String>,
+    /// The name of the projected CRS (`PCS Name`), e.g. `UTM Zone 18N`
+    pub pcs_name: Option<String>,
+    /// The name of the projection (`PRJ Name`)
+    pub projection_name: Option<String>,
+    /// The name of the geographic CRS (`GCS Name`), e.g. `NAD83`
+    pub gcs_name: Option<String>,
+    /// The name of the datum (`Datum`), e.g. `North_American_Datum_1983`
+    pub datum: Option<String>,
+    /// The name of the ellipsoid (`Ellipsoid`), e.g. `GRS 1980`
+    pub ellipsoid: Option<String>,
+    /// The name of the prime meridian (`Primem`), e.g. `Greenwich`
+    pub prime_meridian: Option<String>,
+    /// The name of the angular unit (`AUnits`), e.g. `Degree`
+    pub angular_units: Option<String>,
+    /// The name of the linear unit (`LUnits`), e.g. `metre`
+    pub linear_units: Option<String>,
+    /// The WKT of the CRS written by ESRI software (`ESRI PE String`)
+    pub esri_pe_string: Option<String>,
+}
+
+impl Citation {
+    /// Split a citation into the names it holds
+    pub fn parse(text: &str) -> Self {
+        let mut citation = Self {
+            text: text.to_string(),
+            ..Default::default()
+        };
+        // The WKT may hold anything, so it is the rest of the citation
+        if let Some(wkt) = text.strip_prefix("ESRI PE String = ") {
+            citation.esri_pe_string = Some(wkt.trim_end_matches('|').to_string());
+            return citation;
+        }
+        for part in text
+            .split('|')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+        {
+            let Some((key, value)) = part.split_once(" = ") else {
+                citation.name.get_or_insert_with(|| part.to_string());
+                continue;
+            };
+            let field = match key.trim() {
+                "PCS Name" => &mut citation.pcs_name,
+                "PRJ Name" => &mut citation.projection_name,
+                "GCS Name" => &mut citation.gcs_name,
+                "Datum" => &mut citation.datum,
+                "Ellipsoid" => &mut citation.ellipsoid,
+                "Primem" => &mut citation.prime_meridian,
+                "AUnits" => &mut citation.angular_units,
+                "LUnits" => &mut citation.linear_units,
+                "ESRI PE String" => &mut citation.esri_pe_string,
+                _ => {
+                    citation.name.get_or_insert_with(|| part.to_string());
+                    continue;
+                }
+            };
+            *field = Some(value.trim().to_string());
+        }
+        citation
+    }
+
+    /// Return the most specific name of the CRS in the citation: the projected CRS, the
+    /// geographic CRS, or the unlabelled name
+    pub fn crs_name(&self) -> Option<&str> {
+        (self.pcs_name.as_deref())
+            .or(self.gcs_name.as_deref())
+            .or(self.name.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_pipe_format() {
+        let citation = Citation::parse(
+            "GCS Name = NAD83|Datum = North_American_Datum_1983|Ellipsoid = GRS 1980|\
+             Primem = Greenwich||",
+        );
+        assert_eq!(citation.gcs_name.as_deref(), Some("NAD83"));
+        assert_eq!(citation.datum.as_deref(), Some("North_American_Datum_1983"));
+        assert_eq!(citation.ellipsoid.as_deref(), Some("GRS 1980"));
+        assert_eq!(citation.prime_meridian.as_deref(), Some("Greenwich"));
+        assert_eq!(citation.crs_name(), Some("NAD83"));
+
+        let citation = Citation::parse("PCS Name = UTM Zone 18N|GCS Name = NAD83|LUnits = metre|");
+        assert_eq!(citation.crs_name(), Some("UTM Zone 18N"));
+        assert_eq!(citation.linear_units.as_deref(), Some("metre"));
+
+        // Plain names are kept whole
+        let citation = Citation::parse("NAD83 / UTM zone 18N");
+        assert_eq!(citation.name.as_deref(), Some("NAD83 / UTM zone 18N"));
+        assert_eq!(citation.pcs_name, None);
+
+        let citation = Citation::parse("ESRI PE String = PROJCS[\"NAD_1983_UTM_Zone_18N\"]");
+        assert_eq!(
+            citation.esri_pe_string.as_deref(),
+            Some("PROJCS[\"NAD_1983_UTM_Zone_18N\"]")
+        );
+    }
+}
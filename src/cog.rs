@@ -1,66 +1,2797 @@
+use std::collections::HashMap;
+use std::ops::Range;
 use std::sync::Arc;
 
 use bytes::Bytes;
+use futures::channel::mpsc;
+use futures::stream::{self, Stream, StreamExt};
+use geo_types::{LineString, MultiPolygon};
+use ndarray::{s, Array2, ArrayView2};
+#[cfg(not(target_arch = "wasm32"))]
+use object_store::local::LocalFileSystem;
+use object_store::memory::InMemory;
 use object_store::path::Path;
-use object_store::ObjectStore;
+use object_store::{GetOptions, ObjectMeta, ObjectStore};
+use tiff::decoder::ifd::Value;
+use tiff::tags::Tag;
 
+use crate::affine::AffineTransform;
+use crate::alignment::AlignmentReport;
+use crate::array::{PixelValue, Placement, RasterArray};
+use crate::cog_profile::CogProfile;
 use crate::cursor::{Endianness, ObjectStoreCursor};
-use crate::error::Result;
-use crate::ifd::ImageFileDirectories;
+use crate::datetime::Timestamp;
+use crate::decoder::{apply_scale_offset, normalize_nbits};
+use crate::diff::{diff, Difference};
+use crate::dump::{FileStructure, IfdKind, IfdStructure};
+use crate::enums::{ColorInterp, DataType, Orientation};
+use crate::error::{AiocogeoError, Result};
+use crate::exif::ExifDirectory;
+use crate::expression::Expression;
+use crate::gdal_metadata::GdalMetadata;
+use crate::geo_key_directory::{CompoundCrs, GeoKeyDirectory};
+use crate::ifd::{
+    FileMetadata, ImageFileDirectories, ImageFileDirectory, RawTile, Tiepoint, MASK_INTERLEAVE_GAP,
+};
+use crate::options::{
+    DiffOptions, OpenOptions, ReadOptions, Resampling, Spawner, TileErrorPolicy,
+    DEFAULT_CONCURRENCY,
+};
+use crate::partial_reads::{
+    coalesce_ranges, get_coalesced_range, get_range_exact, get_ranges_coalesced,
+    intersecting_tiles, ImageData, Tile, Window, TILE_BATCH_SIZE,
+};
+use crate::pool::BufferPool;
+use crate::profile::{bilinear_weights, sample_points, ProfileSample};
+use crate::rasterize::rasterize;
+use crate::recorder::{active_hooks, now, DecodeEvent, RequestHooks, RequestPurpose};
+#[cfg(feature = "proj")]
+use crate::reproject::Crs;
+use crate::rpc::RpcCoefficients;
+use crate::scheduler::RequestScheduler;
+use crate::statistics::BandStatistics;
+use crate::storage::StorageReport;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::store::FileStore;
+use crate::store::{
+    recording_store, scheduled_store, CachingStore, PinnedStore, RefreshingStore, CACHE_BLOCK_SIZE,
+};
+use crate::tms::TileMatrixSet;
+use crate::units::Units;
+use crate::validate::{overview_issues, validate_ifd, TileFailure, TileValidation};
 
+/// A reader of a Cloud Optimized GeoTIFF. Cloning it is cheap: clones share the parsed metadata
+/// and the store with its caches, so a server can hand one clone to each request.
+#[derive(Clone)]
 pub struct COGReader {
     store: Arc<dyn ObjectStore>,
     path: Path,
-    ifds: ImageFileDirectories,
+    /// The metadata of the file at open, used to pin reads to that version of the file
+    meta: Option<ObjectMeta>,
+    /// The disk cache of the file, wrapping the store of each read so that only the reads it
+    /// doesn't serve reach the hooks and the scheduler
+    cache: Option<Arc<CachingStore>>,
+    spawner: Option<Spawner>,
+    hooks: Option<Arc<dyn RequestHooks>>,
+    buffer_pool: Option<BufferPool>,
+    scheduler: Option<RequestScheduler>,
+    /// The options of reads made without options, resolved at open
+    read_options: Arc<ReadOptions>,
+    /// The IFDs of the image being read
+    ifds: Arc<ImageFileDirectories>,
+    /// The IFDs of every image of the file, the first of which is read by default
+    subdatasets: Arc<[Arc<ImageFileDirectories>]>,
 }
 
+/// The size of the TIFF header, which holds the byte order, version and first IFD offset
+const TIFF_HEADER_BYTES: usize = 8;
+
+/// The minimum number of bytes fetched from the first IFD when it's past the header
+const IFD_PREFETCH_BYTES: usize = 32 * 1024;
+
 impl COGReader {
+    /// Open a COG with the options of [`OpenOptions::from_env`]
     pub async fn try_open(store: Arc<dyn ObjectStore>, path: Path) -> Result<Self> {
-        let mut cursor = ObjectStoreCursor::new(store, path);
-        let magic_bytes = cursor.read(2).await;
+        Self::try_open_with_options(store, path, &OpenOptions::from_env()?).await
+    }
+
+    /// Open a COG, as in [`COGReader::try_open`], with custom options
+    pub async fn try_open_with_options(
+        store: Arc<dyn ObjectStore>,
+        path: Path,
+        options: &OpenOptions,
+    ) -> Result<Self> {
+        let store = refreshing_store(store, &path, options);
+        if options.disable_version_pinning
+            && options.whole_file_threshold.is_none()
+            && options.cache_dir.is_none()
+            && options.header_cache.is_none()
+        {
+            return Self::open(store, path, None, Bytes::new(), options).await;
+        }
+        // Take the file's metadata from the response to the first read rather than from a `HEAD`
+        // request, which some endpoints only serving ranged `GET`s reject
+        let hooks = active_hooks(options.hooks.as_ref(), options.recorder.as_ref());
+        let header_store = recording_store(&store, hooks, RequestPurpose::Header);
+        let header_store = scheduled_store(
+            header_store,
+            options.scheduler.as_ref(),
+            RequestPurpose::Header,
+        );
+        let header_len = options.header_bytes.max(TIFF_HEADER_BYTES);
+        // Revalidate a cached header covering as many bytes rather than downloading it again
+        let cached = match &options.header_cache {
+            Some(cache) => cache.get(cache_namespace(options)?, &path),
+            None => None,
+        };
+        let cached = cached.filter(|(meta, header)| header.len() >= header_len.min(meta.size));
+        let get_options = GetOptions {
+            range: Some((0..header_len).into()),
+            if_none_match: cached.as_ref().and_then(|(meta, _)| meta.e_tag.clone()),
+            ..Default::default()
+        };
+        let (meta, header) = match (header_store.get_opts(&path, get_options).await, cached) {
+            (Err(object_store::Error::NotModified { .. }), Some(cached)) => cached,
+            (result, _) => {
+                let result = result?;
+                let meta = ObjectMeta {
+                    location: path.clone(),
+                    ..result.meta.clone()
+                };
+                let header = result.bytes().await?;
+                if let Some(cache) = &options.header_cache {
+                    cache.insert(cache_namespace(options)?, meta.clone(), header.clone());
+                }
+                (meta, header)
+            }
+        };
+        Self::open(store, path, Some(meta), header, options).await
+    }
+
+    /// Open many COGs of the same store concurrently, e.g. the assets of a mosaic or catalog,
+    /// with at most `concurrency` files being opened at a time.
+    ///
+    /// Every file is opened with `options`, so the readers share its disk cache, buffer pool,
+    /// hooks and spawner. Returns a reader or the error that prevented opening it for each path,
+    /// in the order of `paths`.
+    pub async fn open_many(
+        store: Arc<dyn ObjectStore>,
+        paths: impl IntoIterator<Item = Path>,
+        options: &OpenOptions,
+        concurrency: usize,
+    ) -> Vec<Result<Self>> {
+        stream::iter(paths)
+            .map(|path| Self::try_open_with_options(store.clone(), path, options))
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    /// Open a COG held in memory, e.g. in tests or WASM hosts
+    pub async fn from_bytes(bytes: Bytes) -> Result<Self> {
+        let path = Path::from("memory.tif");
+        let store = InMemory::new();
+        store.put(&path, bytes.into()).await?;
+        let options = OpenOptions {
+            disable_version_pinning: true,
+            ..Default::default()
+        };
+        Self::open(Arc::new(store), path, None, Bytes::new(), &options).await
+    }
+
+    /// Open a COG from a local file path, e.g. in CLI tools, as in [`COGReader::from_file`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn from_path(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let name = path.file_name().map(|name| name.to_string_lossy());
+        let name = Path::from(name.as_deref().unwrap_or("file.tif"));
+        Self::from_file(std::fs::File::open(path)?, name).await
+    }
+
+    /// Open a COG from an open local file, e.g. a `tokio::fs::File` converted with `into_std`,
+    /// without an object store of the file system.
+    ///
+    /// Tiles are read with positional reads of the file, on the calling task. `location` is the
+    /// path the reader reports for the file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn from_file(file: std::fs::File, location: Path) -> Result<Self> {
+        let store = FileStore::new(file, location)?;
+        let meta = store.meta().clone();
+        let options = OpenOptions {
+            disable_version_pinning: true,
+            ..Default::default()
+        };
+        let path = meta.location.clone();
+        Self::open(Arc::new(store), path, Some(meta), Bytes::new(), &options).await
+    }
+
+    /// Open a COG whose metadata is already known, e.g. from a listing or a STAC asset.
+    ///
+    /// The file is read from `meta.location`, and `meta` is used for version pinning and the
+    /// whole file download threshold as if it had been returned by the first read of
+    /// [`COGReader::try_open_with_options`].
+    pub async fn try_open_with_meta(
+        store: Arc<dyn ObjectStore>,
+        meta: ObjectMeta,
+        options: &OpenOptions,
+    ) -> Result<Self> {
+        let path = meta.location.clone();
+        let store = refreshing_store(store, &path, options);
+        Self::open(store, path, Some(meta), Bytes::new(), options).await
+    }
+
+    /// Open a COG using already known bytes from the start of the file, e.g. from a previous
+    /// partial download or a sidecar cache.
+    ///
+    /// If `header` covers all IFDs and tag values, parsing the metadata makes no requests. Reads
+    /// past the end of `header` are fetched from the store as usual. Without the metadata of a
+    /// read at open, reads are not pinned to a version of the file.
+    pub async fn try_open_with_header(
+        header: Bytes,
+        store: Arc<dyn ObjectStore>,
+        path: Path,
+    ) -> Result<Self> {
+        let options = OpenOptions {
+            disable_version_pinning: true,
+            ..Default::default()
+        };
+        Self::open(store, path, None, header, &options).await
+    }
+
+    async fn open(
+        store: Arc<dyn ObjectStore>,
+        path: Path,
+        meta: Option<ObjectMeta>,
+        header: Bytes,
+        options: &OpenOptions,
+    ) -> Result<Self> {
+        let store = match &meta {
+            Some(meta) if !options.disable_version_pinning => {
+                Arc::new(PinnedStore::new(store, meta.clone()))
+            }
+            _ => store,
+        };
+        let (store, cache) = match (&meta, options.whole_file_threshold, &options.cache_dir) {
+            (Some(meta), Some(threshold), _) if meta.size <= threshold => {
+                let hooks = active_hooks(options.hooks.as_ref(), options.recorder.as_ref());
+                let recorded = recording_store(&store, hooks, RequestPurpose::WholeFile);
+                let recorded = scheduled_store(
+                    recorded,
+                    options.scheduler.as_ref(),
+                    RequestPurpose::WholeFile,
+                );
+                (Self::load_file(recorded, &path).await?, None)
+            }
+            (Some(meta), _, Some(cache_dir)) => {
+                let cache = CachingStore::new(
+                    store.clone(),
+                    Self::open_disk_cache(cache_dir)?,
+                    cache_namespace(options)?.to_string(),
+                    meta.clone(),
+                    CACHE_BLOCK_SIZE,
+                );
+                (store, Some(Arc::new(cache)))
+            }
+            _ => (store, None),
+        };
+
+        let hooks = active_hooks(options.hooks.as_ref(), options.recorder.as_ref());
+        let header_store = recording_store(&store, hooks, RequestPurpose::Header);
+        let header_store = scheduled_store(
+            header_store,
+            options.scheduler.as_ref(),
+            RequestPurpose::Header,
+        );
+        let header_store = match &cache {
+            Some(cache) => Arc::new(cache.with_inner(header_store)),
+            None => header_store,
+        };
+        let header = if header.is_empty() && options.header_bytes > 0 {
+            let options = GetOptions {
+                range: Some((0..options.header_bytes).into()),
+                ..Default::default()
+            };
+            // Bounded ranges are clamped to the end of the file
+            header_store.get_opts(&path, options).await?.bytes().await?
+        } else {
+            header
+        };
+        let mut cursor = ObjectStoreCursor::new(header_store, path);
+        cursor.set_header(header);
+        let magic_bytes = cursor.read(2).await?;
         // Should be b"II" for little endian or b"MM" for big endian
         if magic_bytes == Bytes::from_static(b"II") {
             cursor.set_endianness(Endianness::LittleEndian);
         } else if magic_bytes == Bytes::from_static(b"MM") {
             cursor.set_endianness(Endianness::BigEndian);
         } else {
-            panic!("unexpected magic bytes {magic_bytes:?}");
+            return Err(AiocogeoError::InvalidHeader(format!(
+                "unexpected magic bytes {magic_bytes:?}"
+            )));
+        }
+
+        // Only standard non-big tiffs are supported
+        let version = cursor.read_u16().await?;
+        if version != 42 {
+            return Err(AiocogeoError::InvalidHeader(format!(
+                "unsupported TIFF version {version}"
+            )));
+        }
+
+        let first_ifd_location = cursor.read_u32().await?;
+        let first_ifd = first_ifd_location as usize;
+        // Streaming writers put IFDs at the end of the file, where the tail can fetch them all at
+        // once, so skip it only if the size shows the first IFD isn't within it
+        let in_tail = meta
+            .as_ref()
+            .is_none_or(|meta| first_ifd >= meta.size.saturating_sub(options.tail_bytes));
+        if options.tail_bytes > 0 && in_tail && !cursor.is_known(&(first_ifd..first_ifd + 2)) {
+            let size = meta.as_ref().map(|meta| meta.size);
+            cursor.fetch_tail(options.tail_bytes, size).await?;
+        }
+        // Files that aren't cloud optimized may have their first IFD past the header, so fetch
+        // the metadata from there at once rather than value by value
+        if options.header_bytes > 0 && !cursor.is_known(&(first_ifd..first_ifd + 2)) {
+            let length = options.header_bytes.max(IFD_PREFETCH_BYTES);
+            cursor.fetch_chunk(first_ifd..first_ifd + length).await?;
+        }
+
+        let subdatasets: Arc<[_]> =
+            ImageFileDirectories::open(&mut cursor, first_ifd_location as usize, options)
+                .await?
+                .into_iter()
+                .map(Arc::new)
+                .collect();
+
+        let (_, path) = cursor.into_inner();
+        Ok(Self {
+            store,
+            path,
+            meta,
+            cache,
+            spawner: options.spawner.clone(),
+            hooks: options.hooks.clone(),
+            buffer_pool: options.buffer_pool.clone(),
+            scheduler: options.scheduler.clone(),
+            read_options: Arc::new(options.read_options.clone()),
+            ifds: subdatasets[0].clone(),
+            subdatasets,
+        })
+    }
+
+    /// Return a store of files in the local cache directory.
+    ///
+    /// This does no filesystem I/O, so that it can run on the async runtime: the directory is
+    /// created when the first block is written, and the local store runs its reads and writes
+    /// on blocking threads.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn open_disk_cache(cache_dir: &std::path::Path) -> Result<Arc<dyn ObjectStore>> {
+        let cache_dir = std::path::absolute(cache_dir)?;
+        let prefix = Path::from_absolute_path(&cache_dir).map_err(object_store::Error::from)?;
+        Ok(Arc::new(object_store::prefix::PrefixStore::new(
+            LocalFileSystem::new(),
+            prefix,
+        )))
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn open_disk_cache(_cache_dir: &std::path::Path) -> Result<Arc<dyn ObjectStore>> {
+        Err(AiocogeoError::General(
+            "the local disk cache is not supported on wasm32".to_string(),
+        ))
+    }
+
+    /// Download the file in full and return an in-memory store holding it at the same path
+    async fn load_file(store: Arc<dyn ObjectStore>, path: &Path) -> Result<Arc<dyn ObjectStore>> {
+        let bytes = store.get(path).await?.bytes().await?;
+        let memory = InMemory::new();
+        memory.put(path, bytes.into()).await?;
+        Ok(Arc::new(memory))
+    }
+
+    /// Return the metadata of the file at open, including its ETag and version, if it was
+    /// fetched
+    pub fn object_meta(&self) -> Option<&ObjectMeta> {
+        self.meta.as_ref()
+    }
+
+    /// Return the IFD of overview level `z`, where level 0 is the full resolution image
+    fn ifd(&self, z: usize) -> Result<&ImageFileDirectory> {
+        self.ifds
+            .levels()
+            .get(z)
+            .ok_or_else(|| AiocogeoError::General(format!("overview level {z} does not exist")))
+    }
+
+    /// Return the IFDs of the full resolution image and its overviews, indexed by overview level
+    pub fn ifds(&self) -> &[ImageFileDirectory] {
+        self.ifds.levels()
+    }
+
+    /// Return the number of full resolution images in the file, e.g. the pages of a multi-page
+    /// TIFF. Each can be read as a subdataset with [`COGReader::subdataset`].
+    pub fn subdataset_count(&self) -> usize {
+        self.subdatasets.len()
+    }
+
+    /// Return a reader of the image at `index`, with its own overviews and masks. Index 0 is the
+    /// image read by default.
+    pub fn subdataset(&self, index: usize) -> Option<Self> {
+        Some(Self {
+            ifds: self.subdatasets.get(index)?.clone(),
+            ..self.clone()
+        })
+    }
+
+    /// Return the IFDs of the overviews, from highest to lowest resolution
+    pub fn overview_ifds(&self) -> &[ImageFileDirectory] {
+        self.ifds.overviews()
+    }
+
+    /// Return the IFDs of the internal transparency masks, from highest to lowest resolution
+    pub fn mask_ifds(&self) -> &[ImageFileDirectory] {
+        self.ifds.masks()
+    }
+
+    /// Return the internal transparency mask of overview level `z`, the mask IFD with the same
+    /// dimensions, if any
+    pub fn mask_ifd(&self, z: usize) -> Option<&ImageFileDirectory> {
+        self.ifds.mask(z)
+    }
+
+    /// Return whether the mask tiles are stored right after the image tiles they mask, as in
+    /// COGs written by GDAL with `MASK_INTERLEAVED_WITH_IMAGERY=YES`, so that
+    /// [`COGReader::read_window_masked`] fetches both with a single request per tile
+    pub fn is_mask_interleaved(&self) -> bool {
+        self.ifds.mask_interleaved()
+    }
+
+    /// List the IFDs of the file, with their tile layout and every tag, to debug malformed
+    /// files. Format the result with `{}` for a `tiffdump`-like listing or as JSON with
+    /// [`FileStructure::to_json`].
+    pub fn dump_structure(&self) -> FileStructure {
+        let mut ifds = vec![];
+        for (subdataset, image) in self.subdatasets.iter().enumerate() {
+            for (level, ifd) in image.levels().iter().enumerate() {
+                let kind = if level == 0 {
+                    IfdKind::Image
+                } else {
+                    IfdKind::Overview
+                };
+                ifds.push(IfdStructure::new(ifd, kind, subdataset, level));
+            }
+            for (level, ifd) in image.masks_by_level() {
+                ifds.push(IfdStructure::new(ifd, IfdKind::Mask, subdataset, level));
+            }
+        }
+        let size = self.meta.as_ref().map(|meta| meta.size as u64);
+        FileStructure::new(self.ifds.primary().endianness, size, ifds)
+    }
+
+    /// Classify the encoding of the full resolution image into the profiles of rio-cogeo, from
+    /// its compression, photometric interpretation, predictor and tile size
+    pub fn profile(&self) -> CogProfile {
+        CogProfile::new(&self.ifds()[0])
+    }
+
+    /// Report the number and stored size of the tiles of the image, its overviews and masks,
+    /// their compression ratio and the distribution of tile sizes, from the tile byte counts and
+    /// without fetching any tile
+    pub fn storage_report(&self) -> StorageReport {
+        StorageReport::new(self.ifds.levels(), self.ifds.masks_by_level())
+    }
+
+    /// Report how many internal tiles and requests rendering tiles of `target_tile_size` pixels,
+    /// e.g. `(256, 256)` for web map tiles, needs at each overview level, flagging internal
+    /// tiles that are misaligned with or larger than the target tiles. See
+    /// [`AlignmentReport::hints`].
+    ///
+    /// Target tiles are aligned with the pixel grid of each level, and requests are coalesced as
+    /// configured by `options`. No tile is fetched.
+    pub fn alignment_report(
+        &self,
+        target_tile_size: (usize, usize),
+        options: &ReadOptions,
+    ) -> Result<AlignmentReport> {
+        AlignmentReport::new(self.ifds.levels(), target_tile_size, options)
+    }
+
+    /// Compare the structure and metadata of this file with `other`: the byte order, the
+    /// layout and tags of each IFD, its GeoKeys and, with [`DiffOptions::compare_tiles`], the
+    /// checksums of its tiles.
+    ///
+    /// IFDs are matched by subdataset, role and overview level rather than by offset, and tile
+    /// offsets and byte counts are not compared, so files that only differ in where tiles were
+    /// written have no differences.
+    ///
+    /// There is no command line tool for this; print the returned [`Difference`]s, whose
+    /// `Display` is one line each.
+    pub async fn diff(&self, other: &COGReader, options: &DiffOptions) -> Result<Vec<Difference>> {
+        diff(self, other, options).await
+    }
+
+    /// Fetch and decode every stored tile of the image, its overviews and masks, reporting the
+    /// tiles that are truncated or can't be decoded instead of failing on the first one, so
+    /// archives can be checked for bit rot without GDAL. Overviews that are missing or stored out
+    /// of order are reported in [`TileValidation::overview_issues`].
+    ///
+    /// Tiles are fetched a batch at a time with coalesced requests, as configured by `options`.
+    /// Errors are only returned for failures that aren't specific to a tile.
+    pub async fn validate_tiles(&self, options: &ReadOptions) -> Result<TileValidation> {
+        let store = self.recording_store(options, RequestPurpose::Tile);
+        let file_size = self.meta.as_ref().map(|meta| meta.size as u64);
+        let mut validation = TileValidation {
+            overview_issues: overview_issues(&self.ifds),
+            ..Default::default()
+        };
+        let levels = self.ifds.levels().iter().enumerate().map(|(level, ifd)| {
+            let kind = if level == 0 {
+                IfdKind::Image
+            } else {
+                IfdKind::Overview
+            };
+            (ifd, kind, level)
+        });
+        let masks = self.ifds.masks_by_level();
+        let ifds = levels.chain(masks.map(|(level, ifd)| (ifd, IfdKind::Mask, level)));
+        for (ifd, kind, level) in ifds {
+            validate_ifd(
+                store.as_ref(),
+                &self.path,
+                ifd,
+                (kind, level),
+                file_size,
+                options,
+                &mut validation,
+            )
+            .await?;
+        }
+        Ok(validation)
+    }
+
+    /// Return the CRC-32 checksum of the stored bytes of every tile of an IFD of the file, in
+    /// the order of its `TileOffsets`, or `None` for sparse tiles
+    pub(crate) async fn tile_checksums(
+        &self,
+        ifd: &IfdStructure,
+        options: &ReadOptions,
+    ) -> Result<Vec<Option<u32>>> {
+        let offsets = ifd.tags.iter().find(|(tag, _)| *tag == Tag::TileOffsets);
+        let counts = ifd.tags.iter().find(|(tag, _)| *tag == Tag::TileByteCounts);
+        let (Some((_, offsets)), Some((_, counts))) = (offsets, counts) else {
+            return Ok(vec![]);
+        };
+        let offsets = offsets.clone().into_u64_vec()?;
+        let counts = counts.clone().into_u64_vec()?;
+        let ranges: Vec<_> = offsets
+            .iter()
+            .zip(&counts)
+            .map(|(&offset, &count)| match offset {
+                0 => 0..0,
+                offset => offset as usize..(offset + count) as usize,
+            })
+            .collect();
+
+        let store = self.recording_store(options, RequestPurpose::Tile);
+        let concurrency = options
+            .max_concurrent_requests
+            .unwrap_or(DEFAULT_CONCURRENCY);
+        let mut checksums = Vec::with_capacity(ranges.len());
+        for batch in ranges.chunks(TILE_BATCH_SIZE) {
+            let fetched = get_ranges_coalesced(
+                store.as_ref(),
+                &self.path,
+                batch,
+                options.coalesce_gap_bytes,
+                options.max_range_bytes,
+                concurrency,
+            )
+            .await?;
+            checksums.extend(
+                batch
+                    .iter()
+                    .zip(fetched)
+                    .map(|(range, bytes)| (!range.is_empty()).then(|| crc32fast::hash(&bytes))),
+            );
+        }
+        Ok(checksums)
+    }
+
+    /// Return the raw value of any tag of the full resolution image, including private and
+    /// unknown tags
+    pub fn tag(&self, tag: Tag) -> Option<&Value> {
+        self.ifds.primary().tag(tag)
+    }
+
+    /// Return the rational polynomial coefficients of the image's sensor model, if present
+    pub fn rpc_coefficients(&self) -> Option<RpcCoefficients> {
+        self.ifds.primary().rpc_coefficients()
+    }
+
+    /// Return the ICC color profile of the full resolution image, if any
+    pub fn icc_profile(&self) -> Option<Vec<u8>> {
+        self.ifds.primary().icc_profile()
+    }
+
+    /// Return the XMP metadata packet of the full resolution image, if any
+    pub fn xmp(&self) -> Option<String> {
+        self.ifds.primary().xmp()
+    }
+
+    /// Return the descriptive tags of the full resolution image, such as `Software` and
+    /// `Copyright`
+    pub fn file_metadata(&self) -> FileMetadata {
+        self.ifds.primary().file_metadata()
+    }
+
+    /// Return the time the file was written, from the `DateTime` tag or GDAL metadata of the full
+    /// resolution image. See [`ImageFileDirectory::date_time`].
+    pub fn date_time(&self) -> Option<Timestamp> {
+        self.ifds.primary().date_time()
+    }
+
+    /// Return the time the image was acquired, from GDAL's `ACQUISITIONDATETIME` metadata item
+    /// or the EXIF `DateTimeOriginal` tag of the full resolution image. See
+    /// [`ImageFileDirectory::acquisition_date_time`].
+    pub fn acquisition_date_time(&self) -> Option<Timestamp> {
+        self.ifds.primary().acquisition_date_time()
+    }
+
+    /// Return the EXIF sub-IFD of the full resolution image, if any
+    pub fn exif(&self) -> Option<&ExifDirectory> {
+        self.ifds.primary().exif()
+    }
+
+    /// Return the EPSG code of the horizontal CRS of the image. Images with a vertical CRS also
+    /// report it in [`COGReader::vertical_epsg`] and [`COGReader::crs_code`].
+    pub fn epsg(&self) -> Option<u16> {
+        let ifd = self.ifds.primary();
+        ifd.geo_key_directory
+            .as_ref()
+            .and_then(|gkd| gkd.epsg_code())
+    }
+
+    /// Return the EPSG code of the vertical CRS of the image (`VerticalGeoKey`), if any
+    pub fn vertical_epsg(&self) -> Option<u16> {
+        self.geo_key_directory()?.vertical_epsg_code()
+    }
+
+    /// Return the horizontal and vertical CRSs of the image, if it has both
+    pub fn compound_crs(&self) -> Option<CompoundCrs> {
+        self.geo_key_directory()?.compound_crs()
+    }
+
+    /// Return the CRS of the image as an authority code, e.g. `EPSG:4326`, or a compound code
+    /// like `EPSG:26918+5703` if it also has a vertical CRS
+    pub fn crs_code(&self) -> Option<String> {
+        match self.compound_crs() {
+            Some(compound) => Some(compound.to_string()),
+            None => self.epsg().map(|epsg| format!("EPSG:{epsg}")),
+        }
+    }
+
+    /// Return the GeoKey directory of the full resolution image, if any
+    pub fn geo_key_directory(&self) -> Option<&GeoKeyDirectory> {
+        self.ifds.primary().geo_key_directory.as_ref()
+    }
+
+    /// Return all tiepoints of the full resolution image, including their Z components
+    pub fn tiepoints(&self) -> Vec<Tiepoint> {
+        self.ifds.primary().tiepoints()
+    }
+
+    /// Return the vertical scale (Z component of `ModelPixelScaleTag`)
+    pub fn vertical_scale(&self) -> Option<f64> {
+        self.ifds.primary().vertical_scale()
+    }
+
+    /// Return the model Z coordinate corresponding to a raster value of 0
+    pub fn vertical_origin(&self) -> Option<f64> {
+        self.ifds.primary().vertical_origin()
+    }
+
+    /// Fetch and decode the internal tile at the given x/y index of overview level `z`, where
+    /// level 0 is the full resolution image.
+    ///
+    /// The returned array has shape `(bands, tile_height, tile_width)` and is typed according to
+    /// the image's sample format. Sub-byte samples are unpacked to one `u8` per sample. Tiles are
+    /// read with the [`OpenOptions::read_options`] of the reader, which [`COGReader::try_open`]
+    /// takes from the environment.
+    pub async fn get_tile(&self, x: usize, y: usize, z: usize) -> Result<RasterArray> {
+        self.get_tile_with_options(x, y, z, &self.read_options)
+            .await
+    }
+
+    /// Fetch and decode an internal tile, as in [`COGReader::get_tile`], with custom decoding
+    /// options.
+    pub async fn get_tile_with_options(
+        &self,
+        x: usize,
+        y: usize,
+        z: usize,
+        options: &ReadOptions,
+    ) -> Result<RasterArray> {
+        let ifd = self.ifd(z)?;
+        if options.prefetch_neighbors {
+            self.spawn_neighbor_prefetch(ifd, x, y, options);
+        }
+        let mut tile = self.fetch_tiles(z, &[(x, y)], options).await?.remove(0);
+        if options.clip_edge_tiles {
+            let (width, height) = ifd.oriented_size(ifd.read_orientation(options));
+            let (tile_width, tile_height) = ifd.oriented_tile_size(ifd.read_orientation(options));
+            tile = tile.crop(
+                tile_height.min(height - y * tile_height),
+                tile_width.min(width - x * tile_width),
+            );
+        }
+        Ok(tile)
+    }
+
+    /// Prefetch the tiles adjacent to tile `(x, y)` into the cache in the background, if the
+    /// reader has a cache and a spawner
+    fn spawn_neighbor_prefetch(
+        &self,
+        ifd: &ImageFileDirectory,
+        x: usize,
+        y: usize,
+        options: &ReadOptions,
+    ) {
+        let Some(spawner) = self.spawner.as_ref().filter(|_| self.cache.is_some()) else {
+            return;
+        };
+        // Prefetching is best effort, so errors are left for the actual read to report
+        let Ok(bands) = self.selected_bands(options) else {
+            return;
+        };
+        let orientation = ifd.read_orientation(options);
+        let mut ranges = vec![];
+        for (dx, dy) in [
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+            (-1, 0),
+            (1, 0),
+            (-1, 1),
+            (0, 1),
+            (1, 1),
+        ] {
+            let (Some(x), Some(y)) = (x.checked_add_signed(dx), y.checked_add_signed(dy)) else {
+                continue;
+            };
+            // Neighbors outside of the tile grid are skipped
+            if let Ok(tile_ranges) = ifd.tile_ranges(x, y, orientation, bands.as_deref()) {
+                ranges.extend(tile_ranges);
+            }
+        }
+
+        let store = self.recording_store(options, RequestPurpose::Prefetch);
+        let path = self.path.clone();
+        let (gap, max_len) = (options.coalesce_gap_bytes, options.max_range_bytes);
+        let concurrency = options
+            .max_concurrent_requests
+            .unwrap_or(DEFAULT_CONCURRENCY);
+        spawner.spawn(Box::pin(async move {
+            // Prefetching is best effort, so errors are left for the actual read to report
+            let fetched =
+                get_ranges_coalesced(store.as_ref(), &path, &ranges, gap, max_len, concurrency);
+            let _ = fetched.await;
+        }));
+    }
+
+    /// Return a tile of `ifd` that isn't stored, filled with the fill value of `options`
+    fn sparse_tile(&self, ifd: &ImageFileDirectory, options: &ReadOptions) -> Result<RasterArray> {
+        let (tile_width, tile_height) = ifd.oriented_tile_size(ifd.read_orientation(options));
+        let bands = self.selected_band_count(ifd, options)?;
+        Ok(RasterArray::full(
+            self.output_dtype(ifd, options)?,
+            (bands, tile_height, tile_width),
+            self.fill_value(options),
+        ))
+    }
+
+    /// Apply the dataset-level decoding options to a decoded tile
+    fn postprocess(&self, mut tile: RasterArray, options: &ReadOptions) -> Result<RasterArray> {
+        // Dataset-level metadata like NBITS and band scales is only written to the full
+        // resolution IFD, so it's applied here rather than per-IFD.
+        let primary = self.ifds.primary();
+        if options.normalize_nbits {
+            // Sub-byte samples are already right-aligned when they're unpacked
+            if let (Some(nbits), 8 | 16 | 32 | 64) = (primary.nbits(), primary.bits_per_sample[0]) {
+                normalize_nbits(&mut tile, nbits);
+            }
+        }
+        if options.apply_scale_offset {
+            let (scales, offsets) = self.selected_scales_offsets(options)?;
+            tile = apply_scale_offset(tile, &scales, &offsets)?;
+        }
+
+        Ok(tile)
+    }
+
+    /// Return the scale and offset of each band selected by the options
+    fn selected_scales_offsets(&self, options: &ReadOptions) -> Result<(Vec<f64>, Vec<f64>)> {
+        let bands = self.selected_bands(options)?;
+        let select = |values: Vec<f64>| match &bands {
+            Some(bands) => bands.iter().map(|&band| values[band]).collect(),
+            None => values,
+        };
+        Ok((select(self.scales()), select(self.offsets())))
+    }
+
+    /// Return the indices of the bands selected by [`ReadOptions::bands`] or
+    /// [`ReadOptions::band_names`], or `None` for every band
+    fn selected_bands(&self, options: &ReadOptions) -> Result<Option<Vec<usize>>> {
+        match (&options.bands, &options.band_names) {
+            (Some(_), Some(_)) => Err(AiocogeoError::General(
+                "bands can't be selected both by index and by name".to_string(),
+            )),
+            (bands, None) => Ok(bands.clone()),
+            (None, Some(names)) => names
+                .iter()
+                .map(|name| {
+                    self.band_index(name).ok_or_else(|| {
+                        AiocogeoError::General(format!(
+                            "no band is named {name:?}, the bands are {:?}",
+                            self.band_descriptions()
+                        ))
+                    })
+                })
+                .collect::<Result<_>>()
+                .map(Some),
+        }
+    }
+
+    /// Return the number of bands of `ifd` read with `options`
+    fn selected_band_count(
+        &self,
+        ifd: &ImageFileDirectory,
+        options: &ReadOptions,
+    ) -> Result<usize> {
+        Ok(self
+            .selected_bands(options)?
+            .map_or(ifd.bands() as usize, |bands| bands.len()))
+    }
+
+    /// Read the pixels of overview level `z` within `window`, assembled from internal tiles.
+    ///
+    /// The returned array has shape `(bands, window.height, window.width)`. Tile fetches are
+    /// sorted by file offset and tiles that are adjacent in the file, or separated by at most
+    /// [`ReadOptions::coalesce_gap_bytes`], are fetched with a single request, with up to
+    /// [`ReadOptions::max_concurrent_requests`] requests in flight. Parts of the window outside
+    /// of the image and sparse tiles are filled with the fill value of
+    /// [`ReadOptions::fill_value`].
+    ///
+    /// Tiles are decoded as soon as their request completes, while later requests are in flight,
+    /// on the rayon thread pool with the `rayon` feature.
+    ///
+    /// Tiles that can't be fetched or decoded fail the read, or are filled with the fill value
+    /// under [`TileErrorPolicy::Fill`].
+    pub async fn read_window(
+        &self,
+        window: Window,
+        z: usize,
+        options: &ReadOptions,
+    ) -> Result<RasterArray> {
+        let (data, _) = self
+            .assemble_window(window, z, options, options.on_tile_error)
+            .await?;
+        Ok(data)
+    }
+
+    /// Read `window` of overview level `z` as in [`COGReader::read_window`], filling tiles that
+    /// can't be fetched or decoded with the fill value instead of failing, whatever
+    /// [`ReadOptions::on_tile_error`] is.
+    ///
+    /// Returns the failed tiles along with the pixels. Fails only if every tile failed, as there
+    /// is nothing to return then.
+    pub async fn read_window_partial(
+        &self,
+        window: Window,
+        z: usize,
+        options: &ReadOptions,
+    ) -> Result<(RasterArray, Vec<TileFailure>)> {
+        self.assemble_window(window, z, options, TileErrorPolicy::Fill)
+            .await
+    }
+
+    /// Read `window` of overview level `z` as in [`COGReader::read_window`], along with its
+    /// internal transparency mask, `true` where pixels are valid.
+    ///
+    /// Each mask tile is fetched alongside the image tile it masks, with a single request when
+    /// the file interleaves them (see [`COGReader::is_mask_interleaved`]). Every pixel inside
+    /// the image is valid when the level has no mask; pixels outside the image, of sparse mask
+    /// tiles and of tiles that fail under [`TileErrorPolicy::Fill`] are not.
+    pub async fn read_window_masked(
+        &self,
+        window: Window,
+        z: usize,
+        options: &ReadOptions,
+    ) -> Result<(RasterArray, Array2<bool>)> {
+        let (data, mask, _) = self
+            .assemble_masked_window(window, z, options, options.on_tile_error, true)
+            .await?;
+        Ok((data, mask.expect("the mask is assembled on request")))
+    }
+
+    /// Read the internal transparency mask of overview level `z` within `window`, with shape
+    /// `(window.height, window.width)`, `true` where pixels are valid.
+    ///
+    /// Only the mask tiles are fetched, with coalesced requests as in
+    /// [`COGReader::read_window`]. As in [`COGReader::read_window_masked`], every pixel inside
+    /// the image is valid when the level has no mask, and pixels outside the image and of sparse
+    /// mask tiles aren't.
+    pub async fn read_mask(
+        &self,
+        window: Window,
+        z: usize,
+        options: &ReadOptions,
+    ) -> Result<Array2<bool>> {
+        let ifd = self.ifd(z)?;
+        let orientation = ifd.read_orientation(options);
+        let clipped = self.clip_window(ifd, window, orientation)?;
+        let mut output = Array2::from_elem((window.height, window.width), false);
+        let Some(mask) = self.ifds.mask(z) else {
+            paste_mask(&mut output, window, clipped, None);
+            return Ok(output);
+        };
+
+        let (tile_width, tile_height) = mask.oriented_tile_size(orientation);
+        let tiles = intersecting_tiles(&clipped, tile_width, tile_height);
+        let mut ranges = vec![];
+        let mut stored = Vec::with_capacity(tiles.len());
+        for &(x, y) in &tiles {
+            let tile_ranges = mask.tile_ranges(x, y, orientation, None)?;
+            let is_stored = tile_ranges.iter().any(|range| !range.is_empty());
+            if is_stored {
+                ranges.extend(tile_ranges);
+            }
+            stored.push(is_stored);
+        }
+        let store = self.recording_store(options, RequestPurpose::Tile);
+        let concurrency = options
+            .max_concurrent_requests
+            .unwrap_or(DEFAULT_CONCURRENCY);
+        let (gap, max_len) = (options.coalesce_gap_bytes, options.max_range_bytes);
+        let fetched = get_ranges_coalesced(
+            store.as_ref(),
+            &self.path,
+            &ranges,
+            gap,
+            max_len,
+            concurrency,
+        );
+        let mut fetched = fetched.await?.into_iter();
+
+        for (&(x, y), is_stored) in tiles.iter().zip(stored) {
+            // Sparse mask tiles are left masked out
+            if !is_stored {
+                continue;
+            }
+            let tile = self.decode_mask(mask, vec![fetched.next().unwrap()], orientation)?;
+            let tile_window = Window::new(x * tile_width, y * tile_height, tile_width, tile_height);
+            let overlap = tile_window.intersection(&clipped).unwrap();
+            let src = tile.slice(s![
+                overlap.row_off - tile_window.row_off..,
+                overlap.col_off - tile_window.col_off..
+            ]);
+            paste_mask(&mut output, window, overlap, Some(src));
+        }
+        Ok(output)
+    }
+
+    /// Read `window` of overview level `z` as in [`COGReader::read_window`], yielding it in
+    /// horizontal bands as each is assembled, so that windows larger than memory can be written
+    /// out without holding the whole array.
+    ///
+    /// Bands follow the rows of internal tiles, so each tile is fetched once, and are yielded from
+    /// top to bottom along with their window of the overview level. The next band is only
+    /// fetched once the stream is polled again. [`ReadOptions::memory_limit`] applies to each
+    /// band rather than to the whole window.
+    pub fn read_window_rows<'a>(
+        &'a self,
+        window: Window,
+        z: usize,
+        options: &'a ReadOptions,
+    ) -> Result<impl Stream<Item = Result<(Window, RasterArray)>> + 'a> {
+        let ifd = self.ifd(z)?;
+        let orientation = ifd.read_orientation(options);
+        self.clip_window(ifd, window, orientation)?;
+        let (_, tile_height) = ifd.oriented_tile_size(orientation);
+        let window_end = window.row_off + window.height;
+        let bands = std::iter::successors(Some(window.row_off), move |&row| {
+            Some((row / tile_height + 1) * tile_height).filter(|&next| next < window_end)
+        })
+        .map(move |row| {
+            let end = ((row / tile_height + 1) * tile_height).min(window_end);
+            Window::new(window.col_off, row, window.width, end - row)
+        });
+
+        Ok(stream::iter(bands).then(move |band| async move {
+            let data = match self.clip_window(ifd, band, orientation) {
+                Ok(_) => {
+                    let (data, _) = self
+                        .assemble_window(band, z, options, options.on_tile_error)
+                        .await?;
+                    data
+                }
+                // Rows below the image are filled, as in `read_window`
+                Err(_) => {
+                    let bands = self.selected_band_count(ifd, options)?;
+                    RasterArray::full(
+                        self.output_dtype(ifd, options)?,
+                        (bands, band.height, band.width),
+                        self.fill_value(options),
+                    )
+                }
+            };
+            Ok((band, data))
+        }))
+    }
+
+    /// Return the value of pixels with no data in reads with `options`: the fill value of the
+    /// options, the nodata value of the image or zero
+    fn fill_value(&self, options: &ReadOptions) -> PixelValue {
+        (options.fill_value)
+            .or_else(|| self.nodata().map(PixelValue::Float64))
+            .unwrap_or(PixelValue::Uint8(0))
+    }
+
+    /// Return the data type of the arrays read from `ifd` with `options`
+    fn output_dtype(&self, ifd: &ImageFileDirectory, options: &ReadOptions) -> Result<DataType> {
+        let dtype = ifd.dtype()?;
+        Ok(match (options.apply_scale_offset, dtype) {
+            (false, dtype) => dtype,
+            (
+                true,
+                DataType::Uint8
+                | DataType::Uint16
+                | DataType::Int8
+                | DataType::Int16
+                | DataType::Float32,
+            ) => DataType::Float32,
+            (true, _) => DataType::Float64,
+        })
+    }
+
+    /// Fetch the tiles intersecting `window` and paste them into an array, handling tiles that
+    /// fail by `policy`
+    async fn assemble_window(
+        &self,
+        window: Window,
+        z: usize,
+        options: &ReadOptions,
+        policy: TileErrorPolicy,
+    ) -> Result<(RasterArray, Vec<TileFailure>)> {
+        let (data, _, failures) = self
+            .assemble_masked_window(window, z, options, policy, false)
+            .await?;
+        Ok((data, failures))
+    }
+
+    /// Assemble a window as in [`COGReader::assemble_window`], along with its transparency mask
+    /// when `with_mask` is set
+    async fn assemble_masked_window(
+        &self,
+        window: Window,
+        z: usize,
+        options: &ReadOptions,
+        policy: TileErrorPolicy,
+        with_mask: bool,
+    ) -> Result<(RasterArray, Option<Array2<bool>>, Vec<TileFailure>)> {
+        let ifd = self.ifd(z)?;
+        let orientation = ifd.read_orientation(options);
+        let clipped = self.clip_window(ifd, window, orientation)?;
+        if let Some(limit) = options.memory_limit {
+            let required = self.window_bytes(ifd, window, options)?;
+            if required > limit {
+                return Err(AiocogeoError::MemoryLimitExceeded { required, limit });
+            }
+        }
+        let (tile_width, tile_height) = ifd.oriented_tile_size(orientation);
+        let tiles = intersecting_tiles(&clipped, tile_width, tile_height);
+        let bands = self.selected_bands(options)?;
+        let isolate_errors = policy == TileErrorPolicy::Fill;
+        let decoded = self
+            .try_fetch_masked_tiles(z, with_mask, &tiles, options, isolate_errors)
+            .await?;
+
+        let mut window_mask =
+            with_mask.then(|| Array2::from_elem((window.height, window.width), false));
+        let mut placements = vec![];
+        let mut failures = vec![];
+        let mut first_error = None;
+        for (&(x, y), tile) in tiles.iter().zip(decoded) {
+            let (tile, tile_mask) = match (tile, policy) {
+                (Ok(tile), _) => tile,
+                (Err(err), TileErrorPolicy::Fail) => return Err(err),
+                (Err(err), TileErrorPolicy::Fill) => {
+                    let ranges = ifd.tile_ranges(x, y, orientation, bands.as_deref())?;
+                    let start = ranges.iter().map(|r| r.start).min().unwrap_or_default();
+                    let end = ranges.iter().map(|r| r.end).max().unwrap_or_default();
+                    let failure = TileFailure {
+                        kind: if z == 0 {
+                            IfdKind::Image
+                        } else {
+                            IfdKind::Overview
+                        },
+                        level: z,
+                        x,
+                        y,
+                        band: None,
+                        range: start as u64..end as u64,
+                        reason: err.to_string(),
+                    };
+                    if let Some(hooks) = &self.hooks {
+                        hooks.on_tile_error(&failure);
+                    }
+                    failures.push(failure);
+                    first_error.get_or_insert(err);
+                    continue;
+                }
+            };
+            let tile_window = Window::new(x * tile_width, y * tile_height, tile_width, tile_height);
+            let overlap = tile_window.intersection(&clipped).unwrap();
+            let src_window = Window::new(
+                overlap.col_off - tile_window.col_off,
+                overlap.row_off - tile_window.row_off,
+                overlap.width,
+                overlap.height,
+            );
+            let (row, col) = (
+                overlap.row_off - window.row_off,
+                overlap.col_off - window.col_off,
+            );
+            if let Some(window_mask) = &mut window_mask {
+                let src = tile_mask.as_ref().map(|tile_mask| {
+                    tile_mask.slice(s![src_window.row_off.., src_window.col_off..])
+                });
+                paste_mask(window_mask, window, overlap, src);
+            }
+            placements.push(Placement {
+                src: tile,
+                src_window,
+                row,
+                col,
+            });
+        }
+
+        match (placements.first(), first_error) {
+            (Some(first), _) => {
+                let mut output = RasterArray::full(
+                    first.src.dtype(),
+                    (first.src.shape().0, window.height, window.width),
+                    self.fill_value(options),
+                );
+                output.paste_tiles(&placements)?;
+                Ok((output, window_mask, failures))
+            }
+            (None, Some(err)) => Err(err),
+            // A window that intersects the image always covers at least one tile
+            (None, None) => unreachable!(),
+        }
+    }
+
+    /// Read `window` of overview level `z` as in [`COGReader::read_window`], falling back to the
+    /// highest resolution overview level where the same area fits in
+    /// [`ReadOptions::memory_limit`].
+    ///
+    /// Returns the overview level read and the window of it covering the area, along with the
+    /// pixels. Fails with [`AiocogeoError::MemoryLimitExceeded`] if even the lowest resolution
+    /// level doesn't fit.
+    pub async fn read_window_within_limit(
+        &self,
+        window: Window,
+        z: usize,
+        options: &ReadOptions,
+    ) -> Result<(usize, Window, RasterArray)> {
+        let Some(limit) = options.memory_limit else {
+            return Ok((z, window, self.read_window(window, z, options).await?));
+        };
+        let ifd = self.ifd(z)?;
+        let (width, height) = ifd.oriented_size(ifd.read_orientation(options));
+        let mut required = 0;
+        for level in z..self.ifds.levels().len() {
+            let overview = &self.ifds.levels()[level];
+            let (level_width, level_height) = overview.oriented_size(ifd.read_orientation(options));
+            let (x_scale, y_scale) = (
+                level_width as f64 / width as f64,
+                level_height as f64 / height as f64,
+            );
+            let col_off = (window.col_off as f64 * x_scale).floor() as usize;
+            let row_off = (window.row_off as f64 * y_scale).floor() as usize;
+            let col_end = ((window.col_off + window.width) as f64 * x_scale).ceil() as usize;
+            let row_end = ((window.row_off + window.height) as f64 * y_scale).ceil() as usize;
+            let scaled = Window::new(col_off, row_off, col_end - col_off, row_end - row_off);
+            required = self.window_bytes(overview, scaled, options)?;
+            if required <= limit {
+                return Ok((
+                    level,
+                    scaled,
+                    self.read_window(scaled, level, options).await?,
+                ));
+            }
+        }
+        Err(AiocogeoError::MemoryLimitExceeded { required, limit })
+    }
+
+    /// Return the size in bytes of the array [`COGReader::read_window`] returns for `window`
+    fn window_bytes(
+        &self,
+        ifd: &ImageFileDirectory,
+        window: Window,
+        options: &ReadOptions,
+    ) -> Result<usize> {
+        let dtype = ifd.dtype()?;
+        let sample_size = match (options.apply_scale_offset, dtype.size()) {
+            (false, size) => size,
+            (true, size) if size >= 4 => 8,
+            (true, _) => 4,
+        };
+        let bands = self.selected_band_count(ifd, options)?;
+        Ok([bands, window.width, window.height, sample_size]
+            .into_iter()
+            .try_fold(1usize, |total, n| total.checked_mul(n))
+            .unwrap_or(usize::MAX))
+    }
+
+    /// Fetch and decode the given tiles of overview level `z`, with coalesced requests as in
+    /// [`COGReader::read_window`]
+    async fn fetch_tiles(
+        &self,
+        z: usize,
+        tiles: &[(usize, usize)],
+        options: &ReadOptions,
+    ) -> Result<Vec<RasterArray>> {
+        self.try_fetch_tiles(z, tiles, options, false)
+            .await?
+            .into_iter()
+            .collect()
+    }
+
+    /// Fetch and decode the given tiles of overview level `z` as in [`COGReader::fetch_tiles`],
+    /// returning the result of each tile.
+    ///
+    /// With `isolate_errors`, ranges of a coalesced request that fails are fetched one at a time,
+    /// so that only the tiles whose own ranges fail are lost; otherwise the request's error is
+    /// returned.
+    async fn try_fetch_tiles(
+        &self,
+        z: usize,
+        tiles: &[(usize, usize)],
+        options: &ReadOptions,
+        isolate_errors: bool,
+    ) -> Result<Vec<Result<RasterArray>>> {
+        let fetched = self
+            .try_fetch_masked_tiles(z, false, tiles, options, isolate_errors)
+            .await?;
+        Ok(fetched
+            .into_iter()
+            .map(|tile| tile.map(|(tile, _)| tile))
+            .collect())
+    }
+
+    /// Fetch and decode the given tiles of overview level `z` as in
+    /// [`COGReader::try_fetch_tiles`], along with the matching tiles of its transparency mask
+    /// when `with_mask` is set and it has one.
+    ///
+    /// The ranges of each mask tile follow those of its image tile, so that interleaved masks are
+    /// fetched with the same request as the image. Sparse mask tiles mask out the whole tile.
+    ///
+    /// With the `rayon` feature, tiles are decoded on the rayon thread pool while the remaining
+    /// responses are awaited, rather than on the thread polling the read.
+    async fn try_fetch_masked_tiles(
+        &self,
+        z: usize,
+        with_mask: bool,
+        tiles: &[(usize, usize)],
+        options: &ReadOptions,
+        isolate_errors: bool,
+    ) -> Result<Vec<Result<(RasterArray, Option<Array2<bool>>)>>> {
+        let ifd = self.ifd(z)?;
+        let mask = self.ifds.mask(z).filter(|_| with_mask);
+        let orientation = ifd.read_orientation(options);
+        let bands = self.selected_bands(options)?;
+        let tile_size = ifd.oriented_tile_size(orientation);
+        if let Some(mask) = mask {
+            if mask.oriented_tile_size(orientation) != tile_size {
+                return Err(AiocogeoError::General(format!(
+                    "the mask tiles are {:?}, unlike the {tile_size:?} image tiles",
+                    mask.oriented_tile_size(orientation)
+                )));
+            }
+        }
+        let mut ranges = vec![];
+        // The number of ranges of each tile and of its mask tile, or `None` for sparse tiles,
+        // which aren't fetched
+        let mut tile_range_counts = Vec::with_capacity(tiles.len());
+        for &(x, y) in tiles {
+            let mut count = |tile_ranges: Vec<Range<usize>>| {
+                if tile_ranges.iter().all(|range| range.is_empty()) {
+                    return None;
+                }
+                let count = tile_ranges.len();
+                ranges.extend(tile_ranges);
+                Some(count)
+            };
+            let image_count = count(ifd.tile_ranges(x, y, orientation, bands.as_deref())?);
+            let mask_count = match mask {
+                Some(mask) => Some(count(mask.tile_ranges(x, y, orientation, None)?)),
+                None => None,
+            };
+            tile_range_counts.push((image_count, mask_count));
+        }
+        // The gap between an interleaved image tile and its mask tile is at least the size of
+        // their leader and trailer, so it's always bridged to fetch both at once
+        let gap = match mask {
+            Some(mask) if ifd.interleaves_mask(mask) => {
+                options.coalesce_gap_bytes.max(MASK_INTERLEAVE_GAP)
+            }
+            _ => options.coalesce_gap_bytes,
+        };
+        let store = self.recording_store(options, RequestPurpose::Tile);
+        let concurrency = options
+            .max_concurrent_requests
+            .unwrap_or(DEFAULT_CONCURRENCY);
+        // The indices into `ranges` of each tile and of its mask tile
+        let mut next = 0;
+        let mut take = |count: usize| {
+            next += count;
+            next - count..next
+        };
+        let tile_ranges: Vec<_> = tile_range_counts
+            .into_iter()
+            .map(|(image, mask)| (image.map(&mut take), mask.map(|mask| mask.map(&mut take))))
+            .collect();
+
+        // Coalesced requests are yielded in file offset order, so a tile can be decoded as soon as
+        // the last request holding its bytes arrives, while later requests are still in flight
+        let merged = coalesce_ranges(&ranges, gap, options.max_range_bytes);
+        let mut request_ranges = vec![vec![]; merged.len()];
+        let range_requests: Vec<_> = (ranges.iter().enumerate())
+            .map(|(i, range)| {
+                // The merged ranges are sorted and disjoint, so the last one starting at or before
+                // this range contains it
+                let request = merged
+                    .partition_point(|m| m.start <= range.start)
+                    .checked_sub(1);
+                let request = request.filter(|_| !range.is_empty());
+                if let Some(request) = request {
+                    request_ranges[request].push(i);
+                }
+                request
+            })
+            .collect();
+        let mut ready_after: HashMap<Option<usize>, Vec<usize>> = HashMap::new();
+        for (tile, (image, mask)) in tile_ranges.iter().enumerate() {
+            let indices = image
+                .iter()
+                .chain(mask.iter().flatten())
+                .flat_map(|r| r.clone());
+            let last = indices.filter_map(|i| range_requests[i]).max();
+            ready_after.entry(last).or_default().push(tile);
+        }
+        let mut fetched: Vec<Option<Result<Bytes>>> = ranges
+            .iter()
+            .map(|range| range.is_empty().then(|| Ok(Bytes::new())))
+            .collect();
+
+        // Decoding needs owned copies of the reader and options to leave the polling thread
+        let job = Arc::new(DecodeJob {
+            reader: self.clone(),
+            z,
+            with_mask,
+            orientation,
+            bands,
+            options: options.clone(),
+        });
+        let (sender, mut receiver) = mpsc::unbounded();
+        // Take the buffers of both tiles before decoding, so that a failed image tile doesn't
+        // leave its mask buffers to the next tile
+        let decode_ready = |ready: Vec<usize>, fetched: &mut [Option<Result<Bytes>>]| {
+            let mut buffers = |indices: &Range<usize>| -> Result<Vec<Bytes>> {
+                indices
+                    .clone()
+                    .map(|i| fetched[i].take().expect("range was fetched"))
+                    .collect()
+            };
+            for tile in ready {
+                let (image, mask) = &tile_ranges[tile];
+                let image = image.as_ref().map(&mut buffers);
+                let mask = mask.as_ref().map(|mask| mask.as_ref().map(&mut buffers));
+                let (job, sender) = (job.clone(), sender.clone());
+                spawn_decode(move || {
+                    // The read may have returned early, in which case the tile is dropped
+                    let _ = sender.unbounded_send((tile, job.decode((image, mask))));
+                });
+            }
+        };
+
+        // Sparse tiles need no request
+        if let Some(ready) = ready_after.remove(&None) {
+            decode_ready(ready, &mut fetched);
+        }
+        let request_parts: Vec<Vec<_>> = (request_ranges.iter())
+            .map(|indices| indices.iter().map(|&i| ranges[i].clone()).collect())
+            .collect();
+        let mut responses = stream::iter(0..merged.len())
+            .map(|i| {
+                let (range, parts) = (merged[i].clone(), &request_parts[i]);
+                get_coalesced_range(store.as_ref(), &self.path, range, parts)
+            })
+            .buffered(concurrency.max(1))
+            .enumerate()
+            .fuse();
+        let mut decoded: Vec<Option<Result<_>>> = (0..tiles.len()).map(|_| None).collect();
+        let mut remaining = tiles.len();
+        // Keep fetching while earlier tiles decode, until every tile is decoded
+        while remaining > 0 {
+            let (request, response) = futures::select! {
+                response = responses.next() => match response {
+                    Some(response) => response,
+                    None => continue,
+                },
+                tile = receiver.next() => {
+                    let (tile, result) = tile.expect("a sender is held until every tile is decoded");
+                    decoded[tile] = Some(result);
+                    remaining -= 1;
+                    continue;
+                }
+            };
+            let indices = &request_ranges[request];
+            match response {
+                Ok(bytes) => {
+                    let start = merged[request].start;
+                    for &i in indices {
+                        let range = ranges[i].start - start..ranges[i].end - start;
+                        fetched[i] = Some(Ok(bytes.slice(range)));
+                    }
+                }
+                Err(err) if !isolate_errors => return Err(err),
+                // Fetch the ranges of the failed request one at a time, so that only the tiles
+                // whose own ranges fail are lost
+                Err(_) => {
+                    let retried: Vec<_> = stream::iter(indices.clone())
+                        .map(|i| get_range_exact(store.as_ref(), &self.path, ranges[i].clone()))
+                        .buffered(concurrency.max(1))
+                        .collect()
+                        .await;
+                    for (&i, result) in indices.iter().zip(retried) {
+                        fetched[i] = Some(result);
+                    }
+                }
+            }
+            if let Some(ready) = ready_after.remove(&Some(request)) {
+                decode_ready(ready, &mut fetched);
+            }
+        }
+
+        Ok(decoded
+            .into_iter()
+            .map(|tile| tile.expect("every tile is decoded"))
+            .collect())
+    }
+
+    /// Evaluate a band math expression over the pixels of overview level `z` within `window`.
+    ///
+    /// Only the bands referenced by the expression are read, as with [`ReadOptions::bands`].
+    /// Returns the `(height, width)` values of the expression along with a mask that is `true`
+    /// where every referenced band is valid and the value is finite, e.g. not a division by
+    /// zero. Pixels outside the image, masked out by the internal mask (see
+    /// [`COGReader::read_window_masked`]) or holding the nodata value in any referenced band are
+    /// not valid.
+    pub async fn read_expression(
+        &self,
+        expression: &Expression,
+        window: Window,
+        z: usize,
+        options: &ReadOptions,
+    ) -> Result<(Array2<f64>, Array2<bool>)> {
+        // Nodata applies to the stored values, so scales and offsets are applied afterwards
+        let raw_options = ReadOptions {
+            bands: Some(expression.bands().to_vec()),
+            band_names: None,
+            apply_scale_offset: false,
+            ..options.clone()
+        };
+        let (values, mut mask) = self.read_window_masked(window, z, &raw_options).await?;
+        let mut values = values.to_f64()?;
+        if let Some(nodata) = self.nodata() {
+            for band in values.outer_iter() {
+                mask.zip_mut_with(&band, |valid, &value| *valid &= value != nodata);
+            }
+        }
+        if options.apply_scale_offset {
+            let (scales, offsets) = self.selected_scales_offsets(&raw_options)?;
+            for (band, mut values) in values.outer_iter_mut().enumerate() {
+                let (scale, offset) = (scales[band], offsets[band]);
+                values.mapv_inplace(|value| value * scale + offset);
+            }
+        }
+
+        let values = expression.evaluate(values.view())?;
+        mask.zip_mut_with(&values, |valid, value| *valid &= value.is_finite());
+        Ok((values, mask))
+    }
+
+    /// Compute per-band statistics, with a histogram of `bins` bins, of the pixels of overview
+    /// level `z` whose center is inside `geometry`.
+    ///
+    /// The geometry is in the CRS of the image. Only the tiles intersecting its bounding box are
+    /// read. Pixels outside the image, masked out by the internal mask, nodata and `NaN` values
+    /// are excluded.
+    pub async fn zonal_statistics(
+        &self,
+        geometry: impl Into<MultiPolygon<f64>>,
+        z: usize,
+        bins: usize,
+        options: &ReadOptions,
+    ) -> Result<Vec<BandStatistics>> {
+        let geometry = geometry.into();
+        let transform = self.level_geotransform(z, self.read_orientation(options));
+        let transform = transform
+            .ok_or_else(|| AiocogeoError::General("image is not georeferenced".to_string()))?;
+
+        let bands = self.selected_band_count(self.ifd(z)?, options)?;
+        let window = geometry_bounds(&geometry)
+            .and_then(|bounds| self.bounds_window(bounds, z, self.read_orientation(options)));
+        let Some(window) = window else {
+            return Ok(vec![BandStatistics::empty(); bands]);
+        };
+
+        let window_transform = transform.compose(&AffineTransform::new(
+            1.0,
+            0.0,
+            window.col_off as f64,
+            0.0,
+            1.0,
+            window.row_off as f64,
+        ));
+        let mask = rasterize(&geometry, &window_transform, (window.height, window.width));
+        self.window_statistics(window, z, mask, bins, options).await
+    }
+
+    /// Compute per-band statistics, with a histogram of `bins` bins, of every pixel of overview
+    /// level `z`.
+    ///
+    /// Pixels masked out by the internal mask (see [`COGReader::read_window_masked`]), nodata and
+    /// `NaN` values are excluded. Use [`COGReader::overview_for_size`] to pick a small overview
+    /// to compute approximate statistics from, e.g. to stretch images for display when the file
+    /// doesn't carry GDAL statistics.
+    pub async fn statistics(
+        &self,
+        z: usize,
+        bins: usize,
+        options: &ReadOptions,
+    ) -> Result<Vec<BandStatistics>> {
+        let ifd = self.ifd(z)?;
+        let (width, height) = ifd.oriented_size(ifd.read_orientation(options));
+        let mask = Array2::from_elem((height, width), true);
+        self.window_statistics(Window::new(0, 0, width, height), z, mask, bins, options)
+            .await
+    }
+
+    /// Return the decimation factor of each overview, from highest to lowest resolution, as
+    /// rasterio's `overviews()`, e.g. `[2, 4, 8]`
+    pub fn overview_factors(&self) -> Vec<u32> {
+        self.ifds.overview_factors()
+    }
+
+    /// Return the highest resolution overview level whose width and height are at most
+    /// `max_size`, or the lowest resolution level if none are that small
+    pub fn overview_for_size(&self, max_size: usize) -> usize {
+        let levels = self.ifds.levels();
+        (0..levels.len())
+            .find(|&z| levels[z].width().max(levels[z].height()) as usize <= max_size)
+            .unwrap_or(levels.len() - 1)
+    }
+
+    /// Compute per-band statistics of the pixels of a window that are `true` in `mask`.
+    ///
+    /// Pixels equal to the nodata value before scaling and `NaN` values are excluded.
+    async fn window_statistics(
+        &self,
+        window: Window,
+        z: usize,
+        mut mask: Array2<bool>,
+        bins: usize,
+        options: &ReadOptions,
+    ) -> Result<Vec<BandStatistics>> {
+        // Nodata applies to the stored values, so scales and offsets are applied afterwards
+        let raw_options = ReadOptions {
+            apply_scale_offset: false,
+            ..options.clone()
+        };
+        let (values, valid) = self.read_window_masked(window, z, &raw_options).await?;
+        let mut values = values.to_f64()?;
+        mask.zip_mut_with(&valid, |selected, &valid| *selected &= valid);
+        let (scales, offsets) = self.selected_scales_offsets(options)?;
+        let nodata = self.nodata();
+
+        Ok(values
+            .outer_iter_mut()
+            .enumerate()
+            .map(|(band, mut values)| {
+                let mut mask = mask.clone();
+                if let Some(nodata) = nodata {
+                    mask.zip_mut_with(&values, |valid, &value| *valid &= value != nodata);
+                }
+                if options.apply_scale_offset {
+                    let (scale, offset) = (scales[band], offsets[band]);
+                    values.mapv_inplace(|value| value * scale + offset);
+                }
+                BandStatistics::compute(values.view(), mask.view(), bins)
+            })
+            .collect())
+    }
+
+    /// Sample the pixels of overview level `z` every `interval` along `line`, e.g. for elevation
+    /// profiles.
+    ///
+    /// The line and interval are in the CRS of the image, and the end of the line is always
+    /// sampled. Values are bilinearly interpolated between pixel centers. The tiles under the
+    /// line are fetched together, with coalesced requests as in [`COGReader::read_window`].
+    pub async fn read_profile(
+        &self,
+        line: &LineString<f64>,
+        interval: f64,
+        z: usize,
+        options: &ReadOptions,
+    ) -> Result<Vec<ProfileSample>> {
+        if interval.is_nan() || interval <= 0.0 {
+            return Err(AiocogeoError::General(format!(
+                "invalid interval {interval}"
+            )));
+        }
+        let ifd = self.ifd(z)?;
+        let orientation = ifd.read_orientation(options);
+        let inverse = self
+            .level_geotransform(z, self.read_orientation(options))
+            .and_then(|gt| gt.inverse())
+            .ok_or_else(|| AiocogeoError::General("image is not georeferenced".to_string()))?;
+        let (width, height) = ifd.oriented_size(orientation);
+        let (tile_width, tile_height) = ifd.oriented_tile_size(orientation);
+
+        // The pixels to interpolate from at each point, or `None` outside the image
+        let points = sample_points(line, interval)
+            .into_iter()
+            .map(|(distance, x, y)| {
+                let (col, row) = inverse.apply(x, y);
+                let inside = col >= 0.0 && col < width as f64 && row >= 0.0 && row < height as f64;
+                let weights = inside.then(|| bilinear_weights(col, row, width, height));
+                (distance, x, y, weights)
+            })
+            .collect::<Vec<_>>();
+
+        let mut tiles = points
+            .iter()
+            .flat_map(|(.., weights)| weights.iter().flatten())
+            .map(|&((row, col), _)| (col / tile_width, row / tile_height))
+            .collect::<Vec<_>>();
+        tiles.sort_unstable();
+        tiles.dedup();
+        let decoded = self.fetch_tiles(z, &tiles, options).await?;
+        let decoded = tiles
+            .into_iter()
+            .zip(decoded)
+            .map(|(tile, data)| Ok((tile, data.to_f64()?)))
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        let bands = self.selected_band_count(ifd, options)?;
+        Ok(points
+            .into_iter()
+            .map(|(distance, x, y, weights)| {
+                let values = (0..bands)
+                    .map(|band| match weights {
+                        Some(weights) => weights
+                            .iter()
+                            .map(|&((row, col), weight)| {
+                                let tile = &decoded[&(col / tile_width, row / tile_height)];
+                                tile[[band, row % tile_height, col % tile_width]] * weight
+                            })
+                            .sum(),
+                        None => f64::NAN,
+                    })
+                    .collect();
+                ProfileSample {
+                    distance,
+                    x,
+                    y,
+                    values,
+                }
+            })
+            .collect())
+    }
+
+    /// Read the pixels within `geometry` onto a north-up grid with square pixels of
+    /// `resolution` covering its bounding box.
+    ///
+    /// The geometry and resolution are in the CRS of the image. Pixels are read from the lowest
+    /// resolution overview that is at least as fine as `resolution` and resampled with nearest
+    /// neighbors. Pixels whose center is outside the geometry or the image are masked out and
+    /// set to the fill value of [`ReadOptions::fill_value`].
+    pub async fn read_feature(
+        &self,
+        geometry: impl Into<MultiPolygon<f64>>,
+        resolution: f64,
+        options: &ReadOptions,
+    ) -> Result<ImageData> {
+        let geometry = geometry.into();
+        let (min_x, min_y, max_x, max_y) = geometry_bounds(&geometry)
+            .ok_or_else(|| AiocogeoError::General("geometry is empty".to_string()))?;
+        if resolution.is_nan() || resolution <= 0.0 {
+            return Err(AiocogeoError::General(format!(
+                "invalid resolution {resolution}"
+            )));
+        }
+        let width = ((max_x - min_x) / resolution).ceil().max(1.0) as usize;
+        let height = ((max_y - min_y) / resolution).ceil().max(1.0) as usize;
+        let transform = AffineTransform::new(resolution, 0.0, min_x, 0.0, -resolution, max_y);
+
+        let z = self.overview_for_resolution(resolution, self.read_orientation(options));
+        let ifd = self.ifd(z)?;
+        let level = self
+            .level_geotransform(z, self.read_orientation(options))
+            .ok_or_else(|| AiocogeoError::General("image is not georeferenced".to_string()))?;
+        let inverse = level
+            .inverse()
+            .filter(|_| level.b() == 0.0 && level.d() == 0.0)
+            .ok_or_else(|| {
+                AiocogeoError::General("rotated geotransforms are not supported".to_string())
+            })?;
+
+        // The source pixel of each output column and row, if inside the image. Both grids are
+        // axis-aligned, so columns only depend on x and rows on y.
+        let (level_width, level_height) = ifd.oriented_size(ifd.read_orientation(options));
+        let source = |pixel: f64, size: usize| {
+            let pixel = pixel.floor();
+            (pixel >= 0.0 && pixel < size as f64).then_some(pixel as usize)
+        };
+        let cols = (0..width)
+            .map(|col| {
+                let (x, _) = transform.apply(col as f64 + 0.5, 0.5);
+                source(inverse.apply(x, max_y).0, level_width)
+            })
+            .collect::<Vec<_>>();
+        let rows = (0..height)
+            .map(|row| {
+                let (_, y) = transform.apply(0.5, row as f64 + 0.5);
+                source(inverse.apply(min_x, y).1, level_height)
+            })
+            .collect::<Vec<_>>();
+
+        let mut mask = rasterize(&geometry, &transform, (height, width));
+        for ((row, col), valid) in mask.indexed_iter_mut() {
+            *valid &= rows[row].is_some() && cols[col].is_some();
+        }
+        let fill = self.fill_value(options);
+
+        let span = |indices: &[Option<usize>]| {
+            let min = indices.iter().flatten().min()?;
+            let max = indices.iter().flatten().max()?;
+            Some((*min, max - min + 1))
+        };
+        let mut data = match (span(&cols), span(&rows)) {
+            (Some((col_off, window_width)), Some((row_off, window_height))) => {
+                let window = Window::new(col_off, row_off, window_width, window_height);
+                let data = self.read_window(window, z, options).await?;
+                // Pixels outside the image are masked, so any source pixel will do
+                let relative = |indices: &[Option<usize>], off: usize| {
+                    indices
+                        .iter()
+                        .map(|index| index.map_or(0, |index| index - off))
+                        .collect::<Vec<_>>()
+                };
+                data.resample_nearest(&relative(&rows, row_off), &relative(&cols, col_off))
+            }
+            _ => {
+                let bands = self.selected_band_count(ifd, options)?;
+                RasterArray::full(ifd.dtype()?, (bands, height, width), fill)
+            }
+        };
+        data.fill_masked(&mask, fill);
+
+        Ok(ImageData {
+            data,
+            mask,
+            transform,
+        })
+    }
+
+    /// Read the pixels within a GeoJSON polygon or multipolygon, as in
+    /// [`COGReader::read_feature`]
+    #[cfg(feature = "geojson")]
+    pub async fn read_geojson_feature(
+        &self,
+        geometry: &geojson::Geometry,
+        resolution: f64,
+        options: &ReadOptions,
+    ) -> Result<ImageData> {
+        let geometry = match geo_types::Geometry::<f64>::try_from(geometry) {
+            Ok(geo_types::Geometry::Polygon(polygon)) => MultiPolygon::from(polygon),
+            Ok(geo_types::Geometry::MultiPolygon(polygons)) => polygons,
+            Ok(_) => {
+                return Err(AiocogeoError::General(
+                    "only polygon and multipolygon geometries are supported".to_string(),
+                ))
+            }
+            Err(err) => return Err(AiocogeoError::General(err.to_string())),
+        };
+        self.read_feature(geometry, resolution, options).await
+    }
+
+    /// Return the CRS of the image, from its EPSG code, or for user-defined CRSs, from the
+    /// projection parameters of its GeoKeys as in [`GeoKeyDirectory::proj_string`].
+    ///
+    /// The coordinates of geographic CRSs are in the angular unit of the image, so that they
+    /// match its geotransform.
+    #[cfg(feature = "proj")]
+    pub fn crs(&self) -> Option<Crs> {
+        let crs = match self.epsg().and_then(|epsg| Crs::from_epsg(epsg).ok()) {
+            Some(crs) => crs,
+            None => Crs::from_proj_string(&self.geo_key_directory()?.proj_string()?).ok()?,
+        };
+        Some(match self.units() {
+            Some(Units::Angular(unit)) => crs.with_angular_unit(unit),
+            _ => crs,
+        })
+    }
+
+    /// Read pixels onto a grid of `dst_shape` `(height, width)` pixels covering `dst_bounds`
+    /// `(min_x, min_y, max_x, max_y)` in another CRS, e.g. to serve tiles in a CRS other than the
+    /// image's own.
+    ///
+    /// Each output pixel center is transformed to the image's CRS and resampled from the lowest
+    /// resolution overview that is at least as fine as the output. Pixels that fall outside the
+    /// image are masked out and set to the fill value of [`ReadOptions::fill_value`].
+    #[cfg(feature = "proj")]
+    pub async fn read_reprojected(
+        &self,
+        dst_crs: &Crs,
+        dst_bounds: (f64, f64, f64, f64),
+        dst_shape: (usize, usize),
+        resampling: Resampling,
+        options: &ReadOptions,
+    ) -> Result<ImageData> {
+        let src_crs = self
+            .crs()
+            .ok_or_else(|| AiocogeoError::General("the CRS of the image is unknown".to_string()))?;
+        let (transform, centers) = grid_centers(dst_bounds, dst_shape);
+        let src_points = dst_crs.transform_points(&src_crs, &centers);
+        self.resample_points(src_points, transform, dst_shape, resampling, options)
+            .await
+    }
+
+    /// Resample the image onto an output grid of `dst_shape` with `transform`, given the location
+    /// of each output pixel center in the image's CRS, in row-major order.
+    async fn resample_points(
+        &self,
+        src_points: Vec<Option<(f64, f64)>>,
+        transform: AffineTransform,
+        dst_shape: (usize, usize),
+        resampling: Resampling,
+        options: &ReadOptions,
+    ) -> Result<ImageData> {
+        let (height, width) = dst_shape;
+
+        // Pick the overview from the footprint of the output in the image's CRS
+        let footprint = src_points.iter().flatten().fold(None, |bounds, &(x, y)| {
+            let (x0, y0, x1, y1) = bounds.unwrap_or((x, y, x, y));
+            Some((x0.min(x), y0.min(y), x1.max(x), y1.max(y)))
+        });
+        let resolution = footprint.map_or(0.0, |(x0, y0, x1, y1)| {
+            ((x1 - x0) / width as f64).min((y1 - y0) / height as f64)
+        });
+        let z = self.overview_for_resolution(resolution, self.read_orientation(options));
+        let ifd = self.ifd(z)?;
+        let inverse = self
+            .level_geotransform(z, self.read_orientation(options))
+            .and_then(|gt| gt.inverse())
+            .ok_or_else(|| AiocogeoError::General("image is not georeferenced".to_string()))?;
+        let (level_width, level_height) = ifd.oriented_size(ifd.read_orientation(options));
+
+        // The `(col, row)` location of each output pixel in the overview, if inside it
+        let pixels = src_points
+            .into_iter()
+            .map(|point| {
+                let (col, row) = inverse.apply(point?.0, point?.1);
+                let inside = col >= 0.0
+                    && col < level_width as f64
+                    && row >= 0.0
+                    && row < level_height as f64;
+                inside.then_some((col, row))
+            })
+            .collect::<Vec<_>>();
+        let mask = Array2::from_shape_vec(
+            (height, width),
+            pixels.iter().map(Option::is_some).collect(),
+        )
+        .unwrap();
+        let fill = self.fill_value(options);
+
+        // Read the pixels under the output, with a margin for interpolation
+        let (mut col_min, mut row_min) = (f64::INFINITY, f64::INFINITY);
+        let (mut col_max, mut row_max) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for &(col, row) in pixels.iter().flatten() {
+            (col_min, col_max) = (col_min.min(col), col_max.max(col));
+            (row_min, row_max) = (row_min.min(row), row_max.max(row));
+        }
+        let mut data = if col_min.is_finite() {
+            let col_off = (col_min.floor() as usize).saturating_sub(1);
+            let row_off = (row_min.floor() as usize).saturating_sub(1);
+            let col_end = (col_max.floor() as usize + 2).min(level_width);
+            let row_end = (row_max.floor() as usize + 2).min(level_height);
+            let window = Window::new(col_off, row_off, col_end - col_off, row_end - row_off);
+            let source = self.read_window(window, z, options).await?;
+            let relative = pixels
+                .iter()
+                .map(|pixel| pixel.map(|(col, row)| (col - col_off as f64, row - row_off as f64)));
+
+            match resampling {
+                Resampling::Nearest => {
+                    // Masked pixels take any source pixel
+                    let nearest = relative
+                        .map(|pixel| {
+                            pixel.map_or((0, 0), |(col, row)| (row as usize, col as usize))
+                        })
+                        .collect::<Vec<_>>();
+                    source.gather(&nearest, dst_shape)
+                }
+                Resampling::Bilinear => {
+                    let source = source.to_f64()?;
+                    let weights = relative
+                        .map(|pixel| {
+                            pixel.map(|(col, row)| {
+                                bilinear_weights(col, row, window.width, window.height)
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    RasterArray::from(ndarray::Array3::from_shape_fn(
+                        (source.dim().0, height, width),
+                        |(band, row, col)| {
+                            weights[row * width + col].map_or(0.0, |weights| {
+                                weights
+                                    .iter()
+                                    .map(|&((r, c), weight)| source[[band, r, c]] * weight)
+                                    .sum()
+                            })
+                        },
+                    ))
+                }
+            }
+        } else {
+            let bands = self.selected_band_count(ifd, options)?;
+            let dtype = match resampling {
+                Resampling::Nearest => ifd.dtype()?,
+                Resampling::Bilinear => crate::enums::DataType::Float64,
+            };
+            RasterArray::full(dtype, (bands, height, width), fill)
+        };
+        data.fill_masked(&mask, fill);
+
+        Ok(ImageData {
+            data,
+            mask,
+            transform,
+        })
+    }
+
+    /// Read tile `(x, y)` of zoom level `z` of a tiling grid, e.g. to serve the image as WMTS.
+    ///
+    /// Grids in the image's CRS are resampled directly; other grids require the `proj` feature.
+    /// Pixels outside the image are masked out and set to the fill value of
+    /// [`ReadOptions::fill_value`].
+    pub async fn read_tms_tile(
+        &self,
+        tms: &TileMatrixSet,
+        x: usize,
+        y: usize,
+        z: usize,
+        resampling: Resampling,
+        options: &ReadOptions,
+    ) -> Result<ImageData> {
+        let bounds = tms.tile_bounds(x, y, z)?;
+        let matrix = tms.matrix(z)?;
+        let shape = (matrix.tile_height, matrix.tile_width);
+        if self.epsg() == Some(tms.epsg) {
+            let (transform, centers) = grid_centers(bounds, shape);
+            let src_points = centers.into_iter().map(Some).collect();
+            return self
+                .resample_points(src_points, transform, shape, resampling, options)
+                .await;
+        }
+        #[cfg(feature = "proj")]
+        {
+            let crs = Crs::from_epsg(tms.epsg)?;
+            self.read_reprojected(&crs, bounds, shape, resampling, options)
+                .await
+        }
+        #[cfg(not(feature = "proj"))]
+        Err(AiocogeoError::General(format!(
+            "reading {} tiles from an image in another CRS requires the proj feature",
+            tms.id
+        )))
+    }
+
+    /// Return tile `(x, y)` of zoom level `z` of a tiling grid with every pixel masked out, as
+    /// [`COGReader::read_tms_tile`] returns tiles outside the image, without reading anything
+    pub(crate) async fn empty_tms_tile(
+        &self,
+        tms: &TileMatrixSet,
+        x: usize,
+        y: usize,
+        z: usize,
+        resampling: Resampling,
+        options: &ReadOptions,
+    ) -> Result<ImageData> {
+        let bounds = tms.tile_bounds(x, y, z)?;
+        let matrix = tms.matrix(z)?;
+        let shape = (matrix.tile_height, matrix.tile_width);
+        let (transform, _) = grid_centers(bounds, shape);
+        let src_points = vec![None; shape.0 * shape.1];
+        self.resample_points(src_points, transform, shape, resampling, options)
+            .await
+    }
+
+    /// Return the highest `WebMercatorQuad` zoom level whose pixels are at least as large as the
+    /// full resolution image's, i.e. the zoom level to serve it at without upsampling.
+    ///
+    /// Images in a CRS other than EPSG:3857 require the `proj` feature; returns `None` otherwise
+    /// or if the image isn't georeferenced.
+    pub fn maxzoom(&self) -> Option<usize> {
+        let tms = TileMatrixSet::web_mercator_quad();
+        Some(tms.zoom_for_resolution(self.mercator_resolution()?))
+    }
+
+    /// Return the `WebMercatorQuad` zoom level matching the lowest resolution overview, below
+    /// which tiles would need more pixels than the image has
+    pub fn minzoom(&self) -> Option<usize> {
+        let levels = self.ifds.levels();
+        let decimation = levels[0].width() as f64 / levels[levels.len() - 1].width() as f64;
+        let tms = TileMatrixSet::web_mercator_quad();
+        Some(tms.zoom_for_resolution(self.mercator_resolution()? * decimation))
+    }
+
+    /// Return the overview level to read `WebMercatorQuad` tiles of zoom level `z` from: the
+    /// lowest resolution level that is at least as fine as the tiles
+    pub fn overview_for_zoom(&self, z: usize) -> Option<usize> {
+        let tms = TileMatrixSet::web_mercator_quad();
+        let cell_size = tms.matrix(z).ok()?.cell_size;
+        // Tile pixels in the units of the image's CRS
+        let resolution = cell_size * self.resolution()?.0 / self.mercator_resolution()?;
+        Some(self.overview_for_resolution(resolution, self.orientation()))
+    }
+
+    /// Return the approximate size of a full resolution pixel in EPSG:3857 meters
+    fn mercator_resolution(&self) -> Option<f64> {
+        let (x_res, y_res) = self.resolution()?;
+        if self.epsg() == Some(3857) {
+            return Some(x_res.max(y_res));
+        }
+        #[cfg(feature = "proj")]
+        {
+            // Transform points along the edges of the image, as its bounds may curve
+            let (min_x, min_y, max_x, max_y) = self.native_bounds()?;
+            let edges = (0..=20).flat_map(|i| {
+                let x = min_x + (max_x - min_x) * i as f64 / 20.0;
+                let y = min_y + (max_y - min_y) * i as f64 / 20.0;
+                [(x, min_y), (x, max_y), (min_x, y), (max_x, y)]
+            });
+            let mercator = Crs::from_epsg(3857).ok()?;
+            let points = self
+                .crs()?
+                .transform_points(&mercator, &edges.collect::<Vec<_>>());
+            let (x0, y0, x1, y1) = points.iter().flatten().fold(
+                (
+                    f64::INFINITY,
+                    f64::INFINITY,
+                    f64::NEG_INFINITY,
+                    f64::NEG_INFINITY,
+                ),
+                |(x0, y0, x1, y1), &(x, y)| (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+            );
+            let ifd = self.ifds.primary();
+            let resolution = ((x1 - x0) / ifd.width() as f64).max((y1 - y0) / ifd.height() as f64);
+            resolution.is_finite().then_some(resolution)
+        }
+        #[cfg(not(feature = "proj"))]
+        None
+    }
+
+    /// Return the lowest resolution overview level whose pixels are at most `resolution` wide,
+    /// or the full resolution image if there is none
+    fn overview_for_resolution(&self, resolution: f64, orientation: Orientation) -> usize {
+        (0..self.ifds.levels().len())
+            .rev()
+            .find(|&z| {
+                self.level_geotransform(z, orientation)
+                    .is_some_and(|gt| gt.a().abs() <= resolution * (1.0 + 1e-9))
+            })
+            .unwrap_or(0)
+    }
+
+    /// Return the window of pixels of overview level `z` covering `bounds` in the CRS of the
+    /// image, clipped to the image, or `None` if they don't intersect
+    fn bounds_window(
+        &self,
+        bounds: (f64, f64, f64, f64),
+        z: usize,
+        orientation: Orientation,
+    ) -> Option<Window> {
+        let ifd = self.ifd(z).ok()?;
+        let inverse = self.level_geotransform(z, orientation)?.inverse()?;
+        let (min_x, min_y, max_x, max_y) = bounds;
+        let corners = [
+            inverse.apply(min_x, min_y),
+            inverse.apply(min_x, max_y),
+            inverse.apply(max_x, min_y),
+            inverse.apply(max_x, max_y),
+        ];
+        let col_start = corners.iter().map(|c| c.0).fold(f64::INFINITY, f64::min);
+        let col_end = corners
+            .iter()
+            .map(|c| c.0)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let row_start = corners.iter().map(|c| c.1).fold(f64::INFINITY, f64::min);
+        let row_end = corners
+            .iter()
+            .map(|c| c.1)
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        let (width, height) = ifd.oriented_size(orientation);
+        let col_start = col_start.floor().clamp(0.0, width as f64) as usize;
+        let col_end = col_end.ceil().clamp(0.0, width as f64) as usize;
+        let row_start = row_start.floor().clamp(0.0, height as f64) as usize;
+        let row_end = row_end.ceil().clamp(0.0, height as f64) as usize;
+        if col_start >= col_end || row_start >= row_end {
+            return None;
+        }
+        Some(Window::new(
+            col_start,
+            row_start,
+            col_end - col_start,
+            row_end - row_start,
+        ))
+    }
+
+    /// Fetch the tiles of overview level `z` that intersect `window` into the cache without
+    /// decoding them, e.g. to warm popular areas ahead of traffic.
+    ///
+    /// Tiles are fetched with coalesced requests as in [`COGReader::read_window`]. This is only
+    /// useful when the reader was opened with [`OpenOptions::cache_dir`]; otherwise the fetched
+    /// bytes are discarded.
+    pub async fn prefetch_window(
+        &self,
+        window: Window,
+        z: usize,
+        options: &ReadOptions,
+    ) -> Result<()> {
+        let ifd = self.ifd(z)?;
+        let orientation = ifd.read_orientation(options);
+        let window = self.clip_window(ifd, window, orientation)?;
+        let (tile_width, tile_height) = ifd.oriented_tile_size(orientation);
+        let bands = self.selected_bands(options)?;
+        let mut ranges = vec![];
+        for (x, y) in intersecting_tiles(&window, tile_width, tile_height) {
+            ranges.extend(ifd.tile_ranges(x, y, orientation, bands.as_deref())?);
         }
+        self.prefetch_ranges(&ranges, options).await
+    }
+
+    /// Fetch every tile of the given overview levels into the cache without decoding them, as in
+    /// [`COGReader::prefetch_window`].
+    pub async fn prefetch_tiles(&self, levels: Range<usize>, options: &ReadOptions) -> Result<()> {
+        let mut ranges = vec![];
+        for z in levels {
+            for tile_ranges in self.ifd(z)?.tile_byte_ranges().into_values() {
+                ranges.extend(
+                    tile_ranges
+                        .into_iter()
+                        .map(|range| range.start as usize..range.end as usize),
+                );
+            }
+        }
+        self.prefetch_ranges(&ranges, options).await
+    }
+
+    async fn prefetch_ranges(&self, ranges: &[Range<usize>], options: &ReadOptions) -> Result<()> {
+        let store = self.recording_store(options, RequestPurpose::Prefetch);
+        get_ranges_coalesced(
+            store.as_ref(),
+            &self.path,
+            ranges,
+            options.coalesce_gap_bytes,
+            options.max_range_bytes,
+            options
+                .max_concurrent_requests
+                .unwrap_or(DEFAULT_CONCURRENCY),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Return the store, reporting the reads that the disk cache doesn't serve to the hooks of
+    /// the reader and the recorder of `options`
+    fn recording_store(
+        &self,
+        options: &ReadOptions,
+        purpose: RequestPurpose,
+    ) -> Arc<dyn ObjectStore> {
+        let hooks = active_hooks(self.hooks.as_ref(), options.recorder.as_ref());
+        let store = recording_store(&self.store, hooks, purpose);
+        let store = scheduled_store(store, self.scheduler.as_ref(), purpose);
+        match &self.cache {
+            Some(cache) => Arc::new(cache.with_inner(store)),
+            None => store,
+        }
+    }
+
+    /// Decode the fetched buffers of a tile, reporting it to the hooks of the reader
+    fn decode(
+        &self,
+        ifd: &ImageFileDirectory,
+        buffers: Vec<Bytes>,
+        orientation: Orientation,
+        bands: Option<&[usize]>,
+    ) -> Result<RasterArray> {
+        let pool = self.buffer_pool.as_ref();
+        let Some(hooks) = &self.hooks else {
+            return ifd.decode(buffers, orientation, bands, pool);
+        };
+        let compressed_bytes = buffers.iter().map(|buffer| buffer.len() as u64).sum();
+        let start = now();
+        let tile = ifd.decode(buffers, orientation, bands, pool)?;
+        hooks.on_decode(&DecodeEvent {
+            compression: ifd.compression,
+            compressed_bytes,
+            decoded_bytes: tile.nbytes() as u64,
+            duration: start.map(|start| start.elapsed()),
+        });
+        Ok(tile)
+    }
+
+    /// Decode the fetched buffers of a mask tile, reporting it to the hooks of the reader
+    fn decode_mask(
+        &self,
+        mask: &ImageFileDirectory,
+        buffers: Vec<Bytes>,
+        orientation: Orientation,
+    ) -> Result<Array2<bool>> {
+        let pool = self.buffer_pool.as_ref();
+        let Some(hooks) = &self.hooks else {
+            return mask.decode_mask(buffers, orientation, pool);
+        };
+        let compressed_bytes = buffers.iter().map(|buffer| buffer.len() as u64).sum();
+        let start = now();
+        let tile = mask.decode_mask(buffers, orientation, pool)?;
+        hooks.on_decode(&DecodeEvent {
+            compression: mask.compression,
+            compressed_bytes,
+            decoded_bytes: tile.len() as u64,
+            duration: start.map(|start| start.elapsed()),
+        });
+        Ok(tile)
+    }
+
+    /// Clip a window to the extent of an IFD, failing if they don't intersect
+    fn clip_window(
+        &self,
+        ifd: &ImageFileDirectory,
+        window: Window,
+        orientation: Orientation,
+    ) -> Result<Window> {
+        let (width, height) = ifd.oriented_size(orientation);
+        window
+            .intersection(&Window::new(0, 0, width, height))
+            .ok_or_else(|| {
+                AiocogeoError::General(format!("{window:?} does not intersect the image"))
+            })
+    }
+
+    /// Stream the decoded internal tiles of overview level `z` that intersect `window`, or all
+    /// tiles when `window` is `None`.
+    ///
+    /// Tiles are fetched in file offset order, with up to
+    /// [`ReadOptions::max_concurrent_requests`] requests in flight, and yielded in that order as
+    /// soon as they're decoded. The window is in pixels of the overview level and is clipped to
+    /// the image.
+    pub fn read_tiles_stream<'a>(
+        &'a self,
+        window: Option<Window>,
+        z: usize,
+        options: &'a ReadOptions,
+    ) -> Result<impl Stream<Item = Result<Tile>> + 'a> {
+        let ifd = self.ifd(z)?;
+        let orientation = ifd.read_orientation(options);
+        let (width, height) = ifd.oriented_size(orientation);
+        let image = Window::new(0, 0, width, height);
+        let window = match window {
+            Some(window) => self.clip_window(ifd, window, orientation)?,
+            None => image,
+        };
+
+        let (tile_width, tile_height) = ifd.oriented_tile_size(orientation);
+        let mut tiles = intersecting_tiles(&window, tile_width, tile_height)
+            .into_iter()
+            .map(|(x, y)| Ok((ifd.tile_offset(x, y, orientation)?, x, y)))
+            .collect::<Result<Vec<_>>>()?;
+        tiles.sort_unstable();
+
+        let transform = self.level_geotransform(z, self.read_orientation(options));
+        let concurrency = options
+            .max_concurrent_requests
+            .unwrap_or(DEFAULT_CONCURRENCY)
+            .max(1);
+        Ok(stream::iter(tiles)
+            .map(move |(_, x, y)| async move {
+                let data = self.get_tile_with_options(x, y, z, options).await?;
+                let window = Window::new(x * tile_width, y * tile_height, tile_width, tile_height)
+                    .intersection(&image)
+                    .unwrap();
+                Ok(Tile {
+                    x,
+                    y,
+                    z,
+                    window,
+                    bounds: transform.map(|gt| window.bounds(&gt)),
+                    data,
+                })
+            })
+            .buffered(concurrency))
+    }
+
+    /// Return the colormap of a palette image as RGBA colors indexed by pixel value, with the
+    /// color of the nodata value transparent. See [`ImageData::apply_colormap`] to render it.
+    pub fn colormap(&self) -> Option<Vec<[u8; 4]>> {
+        self.ifds.primary().colormap_rgba(self.nodata())
+    }
 
-        let version = cursor.read_u16().await;
+    /// Return whether the internal tile at the given x/y index of overview level `z` is stored,
+    /// without fetching anything.
+    ///
+    /// Tiles that aren't stored are sparse: GDAL skips tiles that are entirely nodata with
+    /// `SPARSE_OK`, giving them no bytes, so mosaics can skip them. Fails if the tile is outside of
+    /// the tile grid.
+    pub fn has_tile(&self, x: usize, y: usize, z: usize) -> Result<bool> {
+        let ifd = self.ifd(z)?;
+        ifd.has_tile(x, y, ifd.read_orientation(&ReadOptions::default()))
+    }
 
-        // Assert it's a standard non-big tiff
-        assert_eq!(version, 42);
+    /// Fetch the compressed bytes of the internal tile at the given x/y index of overview level
+    /// `z`, along with its byte range and compression, without decoding.
+    ///
+    /// This is useful for serving JPEG or WebP tiles directly. The index is in stored order; the
+    /// `Orientation` tag is not applied.
+    pub async fn get_raw_tile(&self, x: usize, y: usize, z: usize) -> Result<RawTile> {
+        let ifd = self.ifd(z)?;
+        let store = self.recording_store(&ReadOptions::default(), RequestPurpose::Tile);
+        ifd.get_raw_tile(store.as_ref(), &self.path, x, y).await
+    }
 
-        let first_ifd_location = cursor.read_u32().await;
+    /// Return the data type of the samples of the full resolution image
+    pub fn dtype(&self) -> Result<DataType> {
+        self.ifds.primary().dtype()
+    }
 
-        let ifds = ImageFileDirectories::open(&mut cursor, first_ifd_location as usize)
-            .await
-            .unwrap();
+    /// Return the data type of the samples of each band of the full resolution image, as
+    /// rasterio's `DatasetReader.dtypes`. They only differ in files with mixed bits per sample
+    /// or sample formats, whose pixels can't be read.
+    pub fn dtypes(&self) -> Result<Vec<DataType>> {
+        self.ifds.primary().dtypes()
+    }
 
-        let (store, path) = cursor.into_inner();
-        Ok(Self { store, path, ifds })
+    /// Return the width of the full resolution image in pixels, in visual orientation
+    pub fn width(&self) -> usize {
+        self.shape().1
     }
 
-    /// Return the EPSG code representing the crs of the image
-    pub fn epsg(&self) -> Option<u16> {
-        let ifd = &self.ifds.as_ref()[0];
-        ifd.geo_key_directory
-            .as_ref()
-            .and_then(|gkd| gkd.epsg_code())
+    /// Return the height of the full resolution image in pixels, in visual orientation
+    pub fn height(&self) -> usize {
+        self.shape().0
+    }
+
+    /// Return the number of bands of the image
+    pub fn count(&self) -> usize {
+        self.ifds.primary().bands() as usize
+    }
+
+    /// Return the `(height, width)` of the full resolution image in visual orientation, as
+    /// rasterio's `DatasetReader.shape`
+    pub fn shape(&self) -> (usize, usize) {
+        let primary = self.ifds.primary();
+        let (width, height) = primary.oriented_size(primary.orientation());
+        (height, width)
+    }
+
+    /// Return the `(height, width)` of the internal tiles of each band in visual orientation, as
+    /// rasterio's `DatasetReader.block_shapes`
+    pub fn block_shapes(&self) -> Vec<(usize, usize)> {
+        let primary = self.ifds.primary();
+        let (width, height) = primary.oriented_tile_size(primary.orientation());
+        vec![(height, width); self.count()]
+    }
+
+    /// Return the number of significant bits per sample, if it differs from the storage size
+    /// (GDAL's `NBITS`).
+    pub fn nbits(&self) -> Option<u16> {
+        self.ifds.primary().nbits()
+    }
+
+    /// Return the nodata value of the image (GDAL's `GDAL_NODATA`)
+    pub fn nodata(&self) -> Option<f64> {
+        self.ifds.primary().nodata()
+    }
+
+    /// Return the description of each band from GDAL metadata, e.g. `"nir"`, or `None` for bands
+    /// without one
+    pub fn band_descriptions(&self) -> Vec<Option<String>> {
+        self.ifds.primary().band_descriptions()
+    }
+
+    /// Return the index of the first band with the given description, as selected by
+    /// [`ReadOptions::band_names`]
+    pub fn band_index(&self, name: &str) -> Option<usize> {
+        self.band_descriptions()
+            .iter()
+            .position(|description| description.as_deref() == Some(name))
+    }
+
+    /// Return the scale of each band from GDAL metadata, defaulting to 1
+    pub fn scales(&self) -> Vec<f64> {
+        self.ifds.primary().scales()
+    }
+
+    /// Return the offset of each band from GDAL metadata, defaulting to 0
+    pub fn offsets(&self) -> Vec<f64> {
+        self.ifds.primary().offsets()
+    }
+
+    /// Return the parsed `GDAL_METADATA` tag of the full resolution image, if any
+    pub fn gdal_metadata(&self) -> Option<&GdalMetadata> {
+        self.ifds.primary().gdal_metadata.as_ref()
+    }
+
+    /// Return the color interpretation of each band of the full resolution image
+    pub fn color_interp(&self) -> Vec<ColorInterp> {
+        self.ifds.primary().color_interp()
+    }
+
+    /// Return the orientation of the full resolution image
+    pub fn orientation(&self) -> Orientation {
+        self.ifds.primary().orientation()
+    }
+
+    /// Return the geotransform of the full resolution image in visual orientation, matching
+    /// tiles read with the `Orientation` tag applied
+    pub fn geotransform(&self) -> Option<AffineTransform> {
+        self.ifds.primary().oriented_geotransform()
+    }
+
+    /// Return the orientation that the full resolution image is read in with `options`
+    fn read_orientation(&self, options: &ReadOptions) -> Orientation {
+        self.ifds.primary().read_orientation(options)
+    }
+
+    /// Return the geotransform of overview level `z` read in the given orientation
+    fn level_geotransform(&self, z: usize, orientation: Orientation) -> Option<AffineTransform> {
+        let primary = self.ifds.primary();
+        let ifd = self.ifds.levels().get(z)?;
+        let gt = primary.geotransform_in(orientation)?;
+        // Overviews cover the same extent as the full resolution image with fewer pixels
+        let (full_width, full_height) = primary.oriented_size(orientation);
+        let (width, height) = ifd.oriented_size(orientation);
+        let decimation = AffineTransform::new(
+            full_width as f64 / width as f64,
+            0.0,
+            0.0,
+            0.0,
+            full_height as f64 / height as f64,
+            0.0,
+        );
+        Some(gt.compose(&decimation))
+    }
+
+    /// Return the geotransform of `window` within overview level `z`, mapping pixels of a
+    /// [`COGReader::read_window`] result in visual orientation to model coordinates
+    pub fn window_transform(&self, window: Window, z: usize) -> Option<AffineTransform> {
+        self.window_transform_with_options(window, z, &ReadOptions::default())
+    }
+
+    /// Return the geotransform of `window` within overview level `z`, as in
+    /// [`COGReader::window_transform`], for a window read with custom options, e.g.
+    /// [`ReadOptions::north_up`]
+    pub fn window_transform_with_options(
+        &self,
+        window: Window,
+        z: usize,
+        options: &ReadOptions,
+    ) -> Option<AffineTransform> {
+        let offset = AffineTransform::new(
+            1.0,
+            0.0,
+            window.col_off as f64,
+            0.0,
+            1.0,
+            window.row_off as f64,
+        );
+        Some(
+            self.level_geotransform(z, self.read_orientation(options))?
+                .compose(&offset),
+        )
+    }
+
+    /// Return the `(min_x, min_y, max_x, max_y)` bounds of tile `(x, y)` of overview level `z` in
+    /// native crs, including any padding beyond the edge of the image.
+    ///
+    /// Tile indices are in visual orientation, as in [`COGReader::get_tile`].
+    pub fn tile_bounds(&self, x: usize, y: usize, z: usize) -> Option<(f64, f64, f64, f64)> {
+        let ifd = self.ifd(z).ok()?;
+        let orientation = self.orientation();
+        let (x_count, y_count) = ifd.tile_count();
+        let (x_count, y_count) = if orientation.transposes() {
+            (y_count, x_count)
+        } else {
+            (x_count, y_count)
+        };
+        if x >= x_count || y >= y_count {
+            return None;
+        }
+        let (tile_width, tile_height) = ifd.oriented_tile_size(orientation);
+        let gt = self.level_geotransform(z, orientation)?;
+        let (col_start, row_start) = ((x * tile_width) as f64, (y * tile_height) as f64);
+        let (col_end, row_end) = (
+            col_start + tile_width as f64,
+            row_start + tile_height as f64,
+        );
+        let corners = [
+            gt.apply(col_start, row_start),
+            gt.apply(col_end, row_start),
+            gt.apply(col_start, row_end),
+            gt.apply(col_end, row_end),
+        ];
+        Some(corners.iter().fold(
+            (
+                f64::INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::NEG_INFINITY,
+            ),
+            |(x0, y0, x1, y1), &(x, y)| (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+        ))
+    }
+
+    /// Return the window of pixels of overview level `z` in visual orientation covering `bounds`
+    /// `(min_x, min_y, max_x, max_y)` in native crs, clipped to the image, or `None` if they
+    /// don't intersect
+    pub fn window_for_bounds(&self, bounds: (f64, f64, f64, f64), z: usize) -> Option<Window> {
+        self.bounds_window(bounds, z, self.orientation())
     }
 
     /// Return the bounds of the image in native crs
     pub fn native_bounds(&self) -> Option<(f64, f64, f64, f64)> {
-        let ifd = &self.ifds.as_ref()[0];
+        let ifd = self.ifds.primary();
         ifd.native_bounds()
     }
+
+    /// Return the units of the native crs, which apply to [`Self::native_bounds`] and
+    /// [`Self::resolution`]
+    pub fn units(&self) -> Option<Units> {
+        self.geo_key_directory()?.units()
+    }
+
+    /// Return the x/y size of a full resolution pixel in native crs units
+    pub fn resolution(&self) -> Option<(f64, f64)> {
+        let gt = self.ifds.primary().geotransform()?;
+        Some((gt.a().abs(), gt.e().abs()))
+    }
+
+    /// Return the bounds of the image in meters, converting from feet or other linear units of a
+    /// projected crs.
+    ///
+    /// Returns `None` for geographic crs or when the linear units are unknown.
+    pub fn native_bounds_in_meters(&self) -> Option<(f64, f64, f64, f64)> {
+        let factor = self.meters_per_unit()?;
+        let (minx, miny, maxx, maxy) = self.native_bounds()?;
+        Some((minx * factor, miny * factor, maxx * factor, maxy * factor))
+    }
+
+    /// Return the x/y size of a full resolution pixel in meters, converting from feet or other
+    /// linear units of a projected crs.
+    ///
+    /// Returns `None` for geographic crs or when the linear units are unknown.
+    pub fn resolution_in_meters(&self) -> Option<(f64, f64)> {
+        let factor = self.meters_per_unit()?;
+        let (x_res, y_res) = self.resolution()?;
+        Some((x_res * factor, y_res * factor))
+    }
+
+    /// Return the bounds of the image in degrees, converting from radians, grads or other
+    /// angular units of a geographic crs.
+    ///
+    /// Returns `None` for projected crs or when the angular units are unknown.
+    pub fn native_bounds_in_degrees(&self) -> Option<(f64, f64, f64, f64)> {
+        let factor = self.degrees_per_unit()?;
+        let (minx, miny, maxx, maxy) = self.native_bounds()?;
+        Some((minx * factor, miny * factor, maxx * factor, maxy * factor))
+    }
+
+    /// Return the x/y size of a full resolution pixel in degrees, converting from radians, grads
+    /// or other angular units of a geographic crs.
+    ///
+    /// Returns `None` for projected crs or when the angular units are unknown.
+    pub fn resolution_in_degrees(&self) -> Option<(f64, f64)> {
+        let factor = self.degrees_per_unit()?;
+        let (x_res, y_res) = self.resolution()?;
+        Some((x_res * factor, y_res * factor))
+    }
+
+    fn meters_per_unit(&self) -> Option<f64> {
+        match self.units()? {
+            Units::Linear(unit) => unit.meters_per_unit(),
+            Units::Angular(_) => None,
+        }
+    }
+
+    fn degrees_per_unit(&self) -> Option<f64> {
+        match self.units()? {
+            Units::Linear(_) => None,
+            Units::Angular(unit) => unit.radians_per_unit().map(f64::to_degrees),
+        }
+    }
+}
+
+/// The fetched buffers of an image tile and of its mask tile, `None` for sparse tiles, and the
+/// mask tile `None` without a mask
+type TileBuffers = (
+    Option<Result<Vec<Bytes>>>,
+    Option<Option<Result<Vec<Bytes>>>>,
+);
+
+/// What decoding the tiles of a read needs, owned so that tiles can be decoded on other threads
+struct DecodeJob {
+    reader: COGReader,
+    z: usize,
+    with_mask: bool,
+    orientation: Orientation,
+    bands: Option<Vec<usize>>,
+    options: ReadOptions,
+}
+
+impl DecodeJob {
+    /// Decode an image tile and its mask tile from their fetched buffers
+    fn decode(
+        &self,
+        (image_buffers, mask_buffers): TileBuffers,
+    ) -> Result<(RasterArray, Option<Array2<bool>>)> {
+        let (reader, options) = (&self.reader, &self.options);
+        let ifd = reader.ifd(self.z)?;
+        let tile = match image_buffers {
+            Some(buffers) => {
+                let bands = self.bands.as_deref();
+                let tile = reader.decode(ifd, buffers?, self.orientation, bands)?;
+                reader.postprocess(tile, options)?
+            }
+            None => reader.sparse_tile(ifd, options)?,
+        };
+        let mask = reader.ifds.mask(self.z).filter(|_| self.with_mask);
+        let tile_mask = match (mask, mask_buffers) {
+            (Some(mask), Some(Some(buffers))) => {
+                Some(reader.decode_mask(mask, buffers?, self.orientation)?)
+            }
+            (_, Some(None)) => {
+                let (tile_width, tile_height) = ifd.oriented_tile_size(self.orientation);
+                Some(Array2::from_elem((tile_height, tile_width), false))
+            }
+            _ => None,
+        };
+        Ok((tile, tile_mask))
+    }
+}
+
+/// Run `decode` on the rayon thread pool with the `rayon` feature, and right away otherwise
+fn spawn_decode(decode: impl FnOnce() + Send + 'static) {
+    #[cfg(feature = "rayon")]
+    rayon::spawn(decode);
+    #[cfg(not(feature = "rayon"))]
+    decode();
+}
+
+/// Copy the mask of `area`, a part of `window`, into the mask of the window, from the top left of
+/// `src`, or mark the whole area valid without a source mask
+fn paste_mask(
+    output: &mut Array2<bool>,
+    window: Window,
+    area: Window,
+    src: Option<ArrayView2<bool>>,
+) {
+    let (row, col) = (area.row_off - window.row_off, area.col_off - window.col_off);
+    let mut dst = output.slice_mut(s![row..row + area.height, col..col + area.width]);
+    match src {
+        Some(src) => dst.assign(&src.slice(s![..area.height, ..area.width])),
+        None => dst.fill(true),
+    }
+}
+
+/// Return the namespace of the store of the file in the header and disk caches
+fn cache_namespace(options: &OpenOptions) -> Result<&str> {
+    options.cache_namespace.as_deref().ok_or_else(|| {
+        AiocogeoError::General(
+            "OpenOptions::cache_namespace must be set to identify the store of cached files"
+                .to_string(),
+        )
+    })
+}
+
+/// Return `store`, wrapped to re-create it with [`OpenOptions::refresh`] on auth errors, if set
+fn refreshing_store(
+    store: Arc<dyn ObjectStore>,
+    path: &Path,
+    options: &OpenOptions,
+) -> Arc<dyn ObjectStore> {
+    match &options.refresh {
+        Some(refresher) => Arc::new(RefreshingStore::new(store, path.clone(), refresher.clone())),
+        None => store,
+    }
+}
+
+/// The `(min_x, min_y, max_x, max_y)` bounding box of the exterior rings of polygons
+fn geometry_bounds(polygons: &MultiPolygon<f64>) -> Option<(f64, f64, f64, f64)> {
+    polygons
+        .iter()
+        .flat_map(|polygon| polygon.exterior().coords())
+        .fold(None, |bounds, coord| {
+            let (min_x, min_y, max_x, max_y) =
+                bounds.unwrap_or((coord.x, coord.y, coord.x, coord.y));
+            Some((
+                min_x.min(coord.x),
+                min_y.min(coord.y),
+                max_x.max(coord.x),
+                max_y.max(coord.y),
+            ))
+        })
+}
+
+/// Return the transform of a `(height, width)` grid covering `bounds`, and the location of each
+/// of its pixel centers in row-major order
+fn grid_centers(
+    bounds: (f64, f64, f64, f64),
+    shape: (usize, usize),
+) -> (AffineTransform, Vec<(f64, f64)>) {
+    let (height, width) = shape;
+    let (min_x, min_y, max_x, max_y) = bounds;
+    let transform = AffineTransform::new(
+        (max_x - min_x) / width as f64,
+        0.0,
+        min_x,
+        0.0,
+        -(max_y - min_y) / height as f64,
+        max_y,
+    );
+    let centers = (0..height)
+        .flat_map(|row| (0..width).map(move |col| (row, col)))
+        .map(|(row, col)| transform.apply(col as f64 + 0.5, row as f64 + 0.5))
+        .collect();
+    (transform, centers)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::options::StoreRefresher;
+    use crate::recorder::RecordedRequest;
+    use crate::scheduler::{RequestPriority, SchedulerMetrics};
+    use crate::store::HeaderCache;
+    use crate::testing::{CogBuilder, MockStore, TEST_PATH};
+    use futures::{FutureExt, TryStreamExt};
+    use ndarray::{s, Array3};
+    #[cfg(not(target_arch = "wasm32"))]
     use object_store::local::LocalFileSystem;
+    use std::sync::Mutex;
+    use tiff::tags::CompressionMethod;
+
+    /// Open the default test file
+    async fn open_default() -> (COGReader, Arc<MockStore>) {
+        CogBuilder::default().open().await.unwrap()
+    }
 
     #[tokio::test]
     async fn tmp() {
@@ -69,4 +2800,873 @@ mod test {
         let store = Arc::new(LocalFileSystem::new_with_prefix(folder).unwrap());
         let _reader = COGReader::try_open(store, path).await.unwrap();
     }
+
+    #[test]
+    fn shared_reader() {
+        fn assert_shareable<T: Clone + Send + Sync + 'static>() {}
+        assert_shareable::<COGReader>();
+    }
+
+    #[tokio::test]
+    async fn read_window_rows() {
+        let (reader, _) = open_default().await;
+        let options = ReadOptions::default();
+        // Extends past the bottom of the image, which is 48 pixels high with 32 pixel tiles
+        let window = Window::new(10, 5, 40, 60);
+        let expected = reader
+            .read_window(window, 0, &options)
+            .await
+            .unwrap()
+            .to_f64()
+            .unwrap();
+        let bands: Vec<_> = reader
+            .read_window_rows(window, 0, &options)
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        let rows: Vec<_> = bands
+            .iter()
+            .map(|(band, _)| (band.row_off, band.height))
+            .collect();
+        assert_eq!(rows, [(5, 27), (32, 32), (64, 1)]);
+        for (band, data) in bands {
+            let start = band.row_off - window.row_off;
+            let expected = expected.slice(s![.., start..start + band.height, ..]);
+            assert_eq!(data.to_f64().unwrap(), expected);
+        }
+        assert!(reader
+            .read_window_rows(Window::new(100, 0, 10, 10), 0, &options)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn partial_reads() {
+        #[derive(Debug, Default)]
+        struct Failures(Mutex<Vec<TileFailure>>);
+        impl RequestHooks for Failures {
+            fn on_tile_error(&self, failure: &TileFailure) {
+                self.0.lock().unwrap().push(failure.clone());
+            }
+        }
+
+        // Corrupt the first tile and drop the end of the last one
+        let builder = CogBuilder {
+            compression: CompressionMethod::Deflate,
+            ..Default::default()
+        };
+        let (reader, _) = builder.open().await.unwrap();
+        let mut bytes = builder.build().unwrap().to_vec();
+        bytes[reader.ifds()[0].tile_offsets[0] as usize + 4] ^= 0xff;
+        bytes.truncate(bytes.len() - 1);
+        let store = Arc::new(InMemory::new());
+        let path = Path::from(TEST_PATH);
+        store.put(&path, bytes.into()).await.unwrap();
+        let hooks = Arc::new(Failures::default());
+        let options = OpenOptions {
+            hooks: Some(hooks.clone()),
+            ..Default::default()
+        };
+        let reader = COGReader::try_open_with_options(store, path, &options)
+            .await
+            .unwrap();
+
+        let window = Window::new(0, 0, 64, 48);
+        let options = ReadOptions::default();
+        assert!(reader.read_window(window, 0, &options).await.is_err());
+
+        let (data, failures) = reader
+            .read_window_partial(window, 0, &options)
+            .await
+            .unwrap();
+        let tiles: Vec<_> = failures.iter().map(|f| (f.x, f.y)).collect();
+        assert_eq!(tiles, [(0, 0), (1, 1)]);
+        let RasterArray::Uint8(data) = data else {
+            panic!("unexpected data type")
+        };
+        let RasterArray::Uint8(expected) = builder.expected(0).unwrap() else {
+            panic!("unexpected data type")
+        };
+        assert_eq!(data[[0, 0, 0]], 0);
+        assert_eq!(data[[0, 0, 40]], expected[[0, 0, 40]]);
+        assert_eq!(data[[0, 40, 0]], expected[[0, 40, 0]]);
+        assert_eq!(data[[0, 40, 40]], 0);
+        assert_eq!(hooks.0.lock().unwrap().len(), 2);
+
+        let options = ReadOptions {
+            on_tile_error: TileErrorPolicy::Fill,
+            ..Default::default()
+        };
+        let filled = reader.read_window(window, 0, &options).await.unwrap();
+        assert_eq!(filled, RasterArray::Uint8(data));
+        assert_eq!(hooks.0.lock().unwrap().len(), 4);
+
+        // Nothing is returned when every tile fails
+        let window = Window::new(0, 0, 8, 8);
+        assert!(reader.read_window(window, 0, &options).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn headless_open() {
+        let store = Arc::new(MockStore::new());
+        let path = Path::from("test.tif");
+        let bytes = CogBuilder::default().build().unwrap();
+        store.put(&path, bytes.clone().into()).await.unwrap();
+
+        // The metadata used to pin reads comes from the first ranged read of the header
+        let reader =
+            COGReader::try_open_with_options(store.clone(), path.clone(), &Default::default())
+                .await
+                .unwrap();
+        assert_eq!(store.head_request_count(), 0);
+        assert_eq!(store.requests()[0], 0..8);
+        assert_eq!(reader.meta.as_ref().unwrap().size, bytes.len());
+
+        // So does the size checked against the whole file threshold
+        store.clear();
+        let options = OpenOptions {
+            header_bytes: 16,
+            whole_file_threshold: Some(bytes.len()),
+            ..Default::default()
+        };
+        let reader = COGReader::try_open_with_options(store.clone(), path, &options)
+            .await
+            .unwrap();
+        assert_eq!(store.head_request_count(), 0);
+        assert_eq!(store.requests(), vec![0..16, 0..bytes.len()]);
+        let window = Window::new(0, 0, 64, 48);
+        reader
+            .read_window(window, 0, &ReadOptions::default())
+            .await
+            .unwrap();
+        store.assert_request_count(2);
+    }
+
+    #[tokio::test]
+    async fn open_many() {
+        let (_, store) = open_default().await;
+        let paths = ["test.tif", "missing.tif", "test.tif"].map(Path::from);
+        let options = OpenOptions {
+            buffer_pool: Some(BufferPool::new(1 << 20)),
+            ..Default::default()
+        };
+        let readers = COGReader::open_many(store, paths, &options, 2).await;
+        assert_eq!(readers.len(), 3);
+        assert!(matches!(readers[1], Err(AiocogeoError::ObjectStore(_))));
+        for reader in [&readers[0], &readers[2]] {
+            assert_eq!(reader.as_ref().unwrap().ifds().len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn trailing_ifds() {
+        let builder = CogBuilder {
+            overviews: vec![2],
+            trailing_ifds: true,
+            ..Default::default()
+        };
+        let (_, store) = builder.open().await.unwrap();
+        let path = Path::from("test.tif");
+        let size = store.head(&path).await.unwrap().size;
+
+        // The IFDs and their values are parsed from a single read of the tail
+        let options = OpenOptions {
+            tail_bytes: 4096,
+            ..Default::default()
+        };
+        let reader = COGReader::try_open_with_options(store.clone(), path.clone(), &options)
+            .await
+            .unwrap();
+        assert_eq!(store.requests(), vec![0..8, size - 4096..size]);
+        assert_eq!(reader.ifds().len(), 2);
+        let window = Window::new(0, 0, 64, 48);
+        let data = reader.read_window(window, 0, &ReadOptions::default()).await;
+        assert_eq!(data.unwrap(), builder.expected(0).unwrap());
+
+        // Without the tail, the metadata is fetched from the first IFD past the header
+        store.clear();
+        let options = OpenOptions {
+            header_bytes: 64,
+            ..Default::default()
+        };
+        let reader = COGReader::try_open_with_options(store.clone(), path.clone(), &options)
+            .await
+            .unwrap();
+        let first_ifd = store.requests()[1].start;
+        assert_eq!(
+            store.requests(),
+            vec![0..64, first_ifd..first_ifd + 32 * 1024]
+        );
+        assert_eq!(reader.ifds().len(), 2);
+
+        // Or each value is read separately
+        store.clear();
+        let options = OpenOptions::default();
+        COGReader::try_open_with_options(store.clone(), path, &options)
+            .await
+            .unwrap();
+        store.assert_request_count(117);
+    }
+
+    #[tokio::test]
+    async fn fill_value() {
+        let builder = CogBuilder {
+            nodata: Some(7.0),
+            sparse_tiles: vec![1],
+            ..Default::default()
+        };
+        let (reader, store) = builder.open().await.unwrap();
+        let RasterArray::Uint8(expected) = builder.expected(0).unwrap() else {
+            panic!("unexpected data type")
+        };
+
+        // The sparse tile isn't fetched and reads as nodata
+        let options = ReadOptions::default();
+        let tile = reader.get_tile_with_options(1, 0, 0, &options).await;
+        assert_eq!(
+            tile.unwrap(),
+            RasterArray::Uint8(Array3::from_elem((1, 32, 32), 7))
+        );
+        store.assert_request_count(0);
+
+        // So do parts of windows outside the image
+        let window = Window::new(16, 16, 64, 48);
+        let RasterArray::Uint8(data) = reader.read_window(window, 0, &options).await.unwrap()
+        else {
+            panic!("unexpected data type")
+        };
+        assert_eq!(data[[0, 0, 0]], expected[[0, 16, 16]]);
+        assert_eq!(data[[0, 0, 32]], 7);
+        assert_eq!(data[[0, 20, 20]], expected[[0, 36, 36]]);
+        assert_eq!(data[[0, 40, 60]], 7);
+
+        // The fill value of the options overrides nodata, saturating at the bounds of the type
+        let options = ReadOptions {
+            fill_value: Some(PixelValue::Float64(300.0)),
+            ..Default::default()
+        };
+        let RasterArray::Uint8(data) = reader.read_window(window, 0, &options).await.unwrap()
+        else {
+            panic!("unexpected data type")
+        };
+        assert_eq!((data[[0, 0, 32]], data[[0, 40, 60]]), (255, 255));
+
+        // Fill values in the output type keep values that f64 can't hold
+        let (reader, _) = CogBuilder {
+            data_type: DataType::Uint64,
+            sparse_tiles: vec![1],
+            ..Default::default()
+        }
+        .open()
+        .await
+        .unwrap();
+        let options = ReadOptions {
+            fill_value: Some(PixelValue::Uint64(u64::MAX - 1)),
+            ..Default::default()
+        };
+        let tile = reader.get_tile_with_options(1, 0, 0, &options).await;
+        assert_eq!(
+            tile.unwrap(),
+            RasterArray::Uint64(Array3::from_elem((1, 32, 32), u64::MAX - 1))
+        );
+    }
+
+    #[tokio::test]
+    async fn band_names() {
+        let builder = CogBuilder {
+            bands: 3,
+            band_descriptions: vec!["red".to_string(), "green".to_string(), "nir".to_string()],
+            ..Default::default()
+        };
+        let (reader, _) = builder.open().await.unwrap();
+        let descriptions = reader.band_descriptions();
+        assert_eq!(descriptions[0].as_deref(), Some("red"));
+        assert_eq!(reader.band_index("nir"), Some(2));
+
+        let window = Window::new(0, 0, 64, 48);
+        let options = ReadOptions {
+            band_names: Some(vec!["nir".to_string(), "red".to_string()]),
+            ..Default::default()
+        };
+        let data = reader.read_window(window, 0, &options).await.unwrap();
+        assert_eq!(data, builder.expected(0).unwrap().select_bands(&[2, 0]));
+
+        let options = ReadOptions {
+            band_names: Some(vec!["swir".to_string()]),
+            ..Default::default()
+        };
+        let err = reader.read_window(window, 0, &options).await.unwrap_err();
+        assert!(err.to_string().contains("swir"));
+    }
+
+    #[tokio::test]
+    async fn north_up() {
+        // A negative pixel size flips both axes of the geotransform
+        let builder = CogBuilder {
+            height: 64,
+            origin: Some((100.0, 50.0, -1.0)),
+            ..Default::default()
+        };
+        let (reader, _) = builder.open().await.unwrap();
+        let RasterArray::Uint8(expected) = builder.expected(0).unwrap() else {
+            panic!("unexpected data type")
+        };
+        let window = Window::new(0, 0, 64, 64);
+        let options = ReadOptions {
+            north_up: true,
+            ..Default::default()
+        };
+        let data = reader.read_window(window, 0, &options).await.unwrap();
+        let flipped = expected.slice(s![.., ..;-1, ..;-1]).to_owned();
+        assert_eq!(data, RasterArray::Uint8(flipped));
+
+        let gt = reader.geotransform().unwrap();
+        assert_eq!((gt.a(), gt.e()), (-1.0, 1.0));
+        let gt = reader
+            .window_transform_with_options(window, 0, &options)
+            .unwrap();
+        assert_eq!((gt.a(), gt.e(), gt.c(), gt.f()), (1.0, -1.0, 36.0, 114.0));
+    }
+
+    #[tokio::test]
+    async fn interleaved_mask() {
+        let options = ReadOptions {
+            coalesce_gap_bytes: 0,
+            ..Default::default()
+        };
+        for interleave_mask in [false, true] {
+            let builder = CogBuilder {
+                compression: CompressionMethod::Deflate,
+                mask: true,
+                interleave_mask,
+                ..Default::default()
+            };
+            let (reader, store) = builder.open().await.unwrap();
+            assert_eq!(reader.is_mask_interleaved(), interleave_mask);
+
+            // An interleaved mask tile is fetched with the same request as its image tile
+            let window = Window::new(0, 0, 32, 32);
+            let (data, mask) = reader
+                .read_window_masked(window, 0, &options)
+                .await
+                .unwrap();
+            store.assert_request_count(if interleave_mask { 1 } else { 2 });
+            let expected = builder.expected(0).unwrap();
+            assert_eq!(
+                data.to_f64().unwrap(),
+                expected.to_f64().unwrap().slice(s![.., ..32, ..32])
+            );
+            assert_eq!(mask, builder.expected_mask(0).slice(s![..32, ..32]));
+
+            // Pixels outside the image are masked out
+            store.clear();
+            let window = Window::new(32, 16, 48, 48);
+            let (_, mask) = reader
+                .read_window_masked(window, 0, &options)
+                .await
+                .unwrap();
+            let expected = builder.expected_mask(0);
+            assert_eq!(mask.slice(s![..32, ..32]), expected.slice(s![16.., 32..]));
+            assert!(!mask.slice(s![32.., ..]).iter().any(|&valid| valid));
+            assert!(!mask.slice(s![.., 32..]).iter().any(|&valid| valid));
+        }
+
+        // Every pixel of a level without a mask is valid
+        let (reader, _) = open_default().await;
+        assert!(!reader.is_mask_interleaved());
+        let window = Window::new(0, 0, 64, 48);
+        let (_, mask) = reader
+            .read_window_masked(window, 0, &options)
+            .await
+            .unwrap();
+        assert!(mask.iter().all(|&valid| valid));
+    }
+
+    #[tokio::test]
+    async fn read_mask() {
+        let builder = CogBuilder {
+            compression: CompressionMethod::Deflate,
+            mask: true,
+            overviews: vec![2],
+            ..Default::default()
+        };
+        let (reader, store) = builder.open().await.unwrap();
+        let options = ReadOptions::default();
+        for z in 0..2 {
+            let expected = builder.expected_mask(z);
+            let (height, width) = expected.dim();
+            let window = Window::new(0, 0, width, height);
+            let mask = reader.read_mask(window, z, &options).await.unwrap();
+            assert_eq!(mask, expected);
+        }
+
+        // Only the mask tiles are fetched
+        let mask_ifd = reader.mask_ifd(0).unwrap();
+        let mask_tiles: Vec<_> = (mask_ifd.tile_offsets.iter())
+            .zip(&mask_ifd.tile_byte_counts)
+            .map(|(&offset, &count)| offset as usize..(offset + count) as usize)
+            .collect();
+        store.clear();
+        let window = Window::new(16, 16, 32, 32);
+        let mask = reader.read_mask(window, 0, &options).await.unwrap();
+        assert_eq!(mask, builder.expected_mask(0).slice(s![16..48, 16..48]));
+        for request in store.requests() {
+            assert!(mask_tiles
+                .iter()
+                .any(|tile| tile.start <= request.start && request.start < tile.end));
+        }
+    }
+
+    #[tokio::test]
+    async fn dataset_shape() {
+        let builder = CogBuilder {
+            data_type: DataType::Int16,
+            bands: 3,
+            tile_height: 16,
+            ..Default::default()
+        };
+        let (reader, _) = builder.open().await.unwrap();
+        assert_eq!(reader.dtype().unwrap(), DataType::Int16);
+        assert_eq!(
+            (reader.width(), reader.height(), reader.count()),
+            (64, 48, 3)
+        );
+        assert_eq!(reader.shape(), (48, 64));
+        assert_eq!(reader.block_shapes(), vec![(16, 32); 3]);
+    }
+
+    #[tokio::test]
+    async fn decode_while_fetching() {
+        #[derive(Debug, Default)]
+        struct Events(Mutex<Vec<&'static str>>);
+        impl RequestHooks for Events {
+            fn on_request_start(&self, purpose: RequestPurpose, _range: Option<Range<u64>>) {
+                if purpose == RequestPurpose::Tile {
+                    self.0.lock().unwrap().push("request");
+                }
+            }
+            fn on_decode(&self, _decode: &DecodeEvent) {
+                // With rayon, the first decode runs on the pool and waits for the second request,
+                // which is only made if responses are polled while the tile is decoded
+                #[cfg(feature = "rayon")]
+                for _ in 0..500 {
+                    let events = self.0.lock().unwrap();
+                    if events.len() != 1 {
+                        break;
+                    }
+                    drop(events);
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                }
+                self.0.lock().unwrap().push("decode");
+            }
+        }
+
+        let builder = CogBuilder::default();
+        let store = Arc::new(InMemory::new());
+        let path = Path::from(TEST_PATH);
+        store
+            .put(&path, builder.build().unwrap().into())
+            .await
+            .unwrap();
+        let hooks = Arc::new(Events::default());
+        let options = OpenOptions {
+            hooks: Some(hooks.clone()),
+            ..Default::default()
+        };
+        let reader = COGReader::try_open_with_options(store, path, &options)
+            .await
+            .unwrap();
+
+        // The tiles of the first column aren't adjacent in the file, so they take two requests.
+        // Without rayon the first tile is decoded before the second request is made, while with
+        // rayon the second request is made while the first tile is decoded
+        let options = ReadOptions {
+            coalesce_gap_bytes: 0,
+            max_concurrent_requests: Some(1),
+            ..Default::default()
+        };
+        let window = Window::new(0, 0, 32, 48);
+        let data = reader.read_window(window, 0, &options).await.unwrap();
+        #[cfg(not(feature = "rayon"))]
+        let expected = ["request", "decode", "request", "decode"];
+        #[cfg(feature = "rayon")]
+        let expected = ["request", "request", "decode", "decode"];
+        assert_eq!(*hooks.0.lock().unwrap(), expected);
+        let RasterArray::Uint8(expected) = builder.expected(0).unwrap() else {
+            panic!("unexpected data type")
+        };
+        assert_eq!(
+            data,
+            RasterArray::Uint8(expected.slice(s![.., .., ..32]).to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn split_rejected_requests() {
+        // The four 1 KB tiles are adjacent, so they're coalesced into a single request that the
+        // store rejects, then fetched in two halves
+        let builder = CogBuilder::default();
+        let store = Arc::new(MockStore::new().with_max_range_bytes(2048));
+        let path = Path::from(TEST_PATH);
+        store
+            .put(&path, builder.build().unwrap().into())
+            .await
+            .unwrap();
+        let reader = COGReader::try_open(store.clone(), path).await.unwrap();
+        store.clear();
+
+        let window = Window::new(0, 0, 64, 48);
+        let options = ReadOptions::default();
+        let data = reader.read_window(window, 0, &options).await.unwrap();
+        assert_eq!(data, builder.expected(0).unwrap());
+        let lengths: Vec<_> = store.requests().iter().map(|r| r.len()).collect();
+        assert_eq!(lengths, [4096, 2048, 2048]);
+
+        // Requests are only merged up to the configured maximum, so none is rejected
+        store.clear();
+        let options = ReadOptions {
+            max_range_bytes: Some(2048),
+            ..Default::default()
+        };
+        let data = reader.read_window(window, 0, &options).await.unwrap();
+        assert_eq!(data, builder.expected(0).unwrap());
+        let lengths: Vec<_> = store.requests().iter().map(|r| r.len()).collect();
+        assert_eq!(lengths, [2048, 2048]);
+    }
+
+    #[tokio::test]
+    async fn revalidate_cached_header() {
+        #[derive(Debug, Default)]
+        struct HeaderReads(Mutex<Vec<bool>>);
+        impl RequestHooks for HeaderReads {
+            fn on_request_complete(&self, request: &RecordedRequest, success: bool) {
+                if request.purpose == RequestPurpose::Header {
+                    self.0.lock().unwrap().push(success);
+                }
+            }
+        }
+
+        let builder = CogBuilder::default();
+        let store = Arc::new(MockStore::new());
+        let path = Path::from(TEST_PATH);
+        store
+            .put(&path, builder.build().unwrap().into())
+            .await
+            .unwrap();
+        let hooks = Arc::new(HeaderReads::default());
+        let cache = HeaderCache::new(4);
+        let options = OpenOptions {
+            header_bytes: 16 * 1024,
+            header_cache: Some(cache.clone()),
+            cache_namespace: Some("mock".to_string()),
+            hooks: Some(hooks.clone()),
+            ..Default::default()
+        };
+
+        // The second open is answered with 304 Not Modified and reuses the cached header
+        for _ in 0..2 {
+            let reader = COGReader::try_open_with_options(store.clone(), path.clone(), &options)
+                .await
+                .unwrap();
+            let window = Window::new(0, 0, 64, 48);
+            let data = reader.read_window(window, 0, &ReadOptions::default()).await;
+            assert_eq!(data.unwrap(), builder.expected(0).unwrap());
+        }
+        assert_eq!(cache.len(), 1);
+        let header_reads = hooks.0.lock().unwrap().clone();
+        assert_eq!(header_reads.iter().filter(|&&success| success).count(), 1);
+
+        // A replaced file is downloaded again
+        let builder = CogBuilder {
+            width: 32,
+            ..Default::default()
+        };
+        store
+            .put(&path, builder.build().unwrap().into())
+            .await
+            .unwrap();
+        let reader = COGReader::try_open_with_options(store.clone(), path.clone(), &options)
+            .await
+            .unwrap();
+        assert_eq!(reader.width(), 32);
+    }
+
+    #[tokio::test]
+    async fn refresh_expired_store() {
+        let builder = CogBuilder::default();
+        let path = Path::from(TEST_PATH);
+        let (store, renewed) = (Arc::new(MockStore::new()), Arc::new(MockStore::new()));
+        for store in [&store, &renewed] {
+            store
+                .put(&path, builder.build().unwrap().into())
+                .await
+                .unwrap();
+        }
+        let refresher = {
+            let (renewed, path) = (renewed.clone(), path.clone());
+            StoreRefresher::new(move || {
+                let store: Arc<dyn ObjectStore> = renewed.clone();
+                let path = path.clone();
+                async move { Ok((store, path)) }.boxed()
+            })
+        };
+        let options = OpenOptions {
+            refresh: Some(refresher),
+            ..Default::default()
+        };
+        let reader = COGReader::try_open_with_options(store.clone(), path, &options)
+            .await
+            .unwrap();
+        renewed.clear();
+
+        // Once the credentials expire, tiles are read from the refreshed store
+        store.expire();
+        let window = Window::new(0, 0, 64, 48);
+        let data = reader.read_window(window, 0, &ReadOptions::default()).await;
+        assert_eq!(data.unwrap(), builder.expected(0).unwrap());
+        assert!(renewed.request_count() > 0);
+    }
+
+    #[tokio::test]
+    async fn cache_namespaces() {
+        // In-memory stores number their ETags from 0, so both files have the same path and ETag
+        let mut stores = vec![];
+        for width in [64, 32] {
+            let builder = CogBuilder {
+                width,
+                ..Default::default()
+            };
+            let store = Arc::new(InMemory::new());
+            let path = Path::from(TEST_PATH);
+            store
+                .put(&path, builder.build().unwrap().into())
+                .await
+                .unwrap();
+            stores.push(store);
+        }
+        let cache = HeaderCache::new(4);
+        let path = Path::from(TEST_PATH);
+        for (store, namespace, width) in [(&stores[0], "a", 64), (&stores[1], "b", 32)] {
+            let options = OpenOptions {
+                header_cache: Some(cache.clone()),
+                cache_namespace: Some(namespace.to_string()),
+                ..Default::default()
+            };
+            let reader = COGReader::try_open_with_options(store.clone(), path.clone(), &options)
+                .await
+                .unwrap();
+            assert_eq!(reader.width(), width);
+        }
+        assert_eq!(cache.len(), 2);
+
+        // Caches can't tell stores apart without a namespace
+        let options = OpenOptions {
+            header_cache: Some(cache.clone()),
+            ..Default::default()
+        };
+        let store = stores[0].clone();
+        let result = COGReader::try_open_with_options(store, path, &options).await;
+        assert!(matches!(result, Err(AiocogeoError::General(_))));
+    }
+
+    #[tokio::test]
+    async fn scheduled_reads() {
+        let builder = CogBuilder::default();
+        let store = Arc::new(MockStore::new());
+        let path = Path::from(TEST_PATH);
+        store
+            .put(&path, builder.build().unwrap().into())
+            .await
+            .unwrap();
+        let scheduler = RequestScheduler::new(1, 1);
+        let options = OpenOptions {
+            scheduler: Some(scheduler.clone()),
+            ..Default::default()
+        };
+        let reader = COGReader::try_open_with_options(store, path, &options)
+            .await
+            .unwrap();
+        assert_eq!(scheduler.metrics(), SchedulerMetrics::default());
+
+        // The read waits for the slot held elsewhere, and frees it once done
+        let slot = scheduler.acquire(RequestPriority::Interactive).await;
+        let window = Window::new(0, 0, 64, 48);
+        let read_options = ReadOptions::default();
+        let mut read = Box::pin(reader.read_window(window, 0, &read_options));
+        assert!((&mut read).now_or_never().is_none());
+        assert_eq!(scheduler.metrics().interactive_waiting, 1);
+        drop(slot);
+        assert_eq!(read.await.unwrap(), builder.expected(0).unwrap());
+        assert_eq!(scheduler.metrics(), SchedulerMetrics::default());
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn disk_cache() {
+        let cache_dir = std::env::temp_dir().join(format!("aiocogeo-cache-{}", std::process::id()));
+        let builder = CogBuilder::default();
+        let options = OpenOptions {
+            cache_dir: Some(cache_dir.join("blocks")),
+            cache_namespace: Some("mock".to_string()),
+            ..Default::default()
+        };
+        let (reader, store) = builder.open_with_options(&options).await.unwrap();
+
+        let window = Window::new(0, 0, 64, 48);
+        let read_options = ReadOptions::default();
+        let data = reader.read_window(window, 0, &read_options).await.unwrap();
+        assert_eq!(data, builder.expected(0).unwrap());
+        // The directory is created by the first write to the cache
+        assert!(cache_dir.join("blocks").is_dir());
+
+        // Later reads of the same bytes are served from the cache, and make no request to record
+        store.clear();
+        let recorder = crate::recorder::RequestRecorder::new();
+        let read_options = ReadOptions {
+            recorder: Some(recorder.clone()),
+            ..Default::default()
+        };
+        let data = reader.read_window(window, 0, &read_options).await.unwrap();
+        assert_eq!(data, builder.expected(0).unwrap());
+        store.assert_request_count(0);
+        assert_eq!(recorder.requests(), []);
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn prefetch_into_cache() {
+        let cache_dir =
+            std::env::temp_dir().join(format!("aiocogeo-prefetch-{}", std::process::id()));
+        // Tiles past the first cache block, which is fetched at open
+        let builder = CogBuilder {
+            width: 512,
+            height: 512,
+            overviews: vec![2],
+            ..Default::default()
+        };
+        let options = OpenOptions {
+            cache_dir: Some(cache_dir.clone()),
+            cache_namespace: Some("mock".to_string()),
+            ..Default::default()
+        };
+        let (reader, store) = builder.open_with_options(&options).await.unwrap();
+        let read_options = ReadOptions::default();
+
+        // Prefetching fetches the tiles without decoding them, and later reads make no request
+        let window = Window::new(400, 400, 40, 20);
+        reader
+            .prefetch_window(window, 0, &read_options)
+            .await
+            .unwrap();
+        store.assert_request_count(1);
+        store.clear();
+        let data = reader.read_window(window, 0, &read_options).await.unwrap();
+        let RasterArray::Uint8(expected) = builder.expected(0).unwrap() else {
+            panic!("unexpected data type")
+        };
+        let expected = expected.slice(s![.., 400..420, 400..440]).to_owned();
+        assert_eq!(data, RasterArray::Uint8(expected));
+        store.assert_request_count(0);
+
+        reader.prefetch_tiles(1..2, &read_options).await.unwrap();
+        store.assert_request_count(1);
+        store.clear();
+        let window = Window::new(0, 0, 256, 256);
+        let data = reader.read_window(window, 1, &read_options).await.unwrap();
+        assert_eq!(data, builder.expected(1).unwrap());
+        store.assert_request_count(0);
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn open_local_file() {
+        let builder = CogBuilder::default();
+        let path = std::env::temp_dir().join(format!("aiocogeo-{}.tif", std::process::id()));
+        std::fs::write(&path, builder.build().unwrap()).unwrap();
+        let reader = COGReader::from_path(&path).await.unwrap();
+        let window = Window::new(0, 0, 64, 48);
+        let data = reader.read_window(window, 0, &ReadOptions::default()).await;
+        assert_eq!(data.unwrap(), builder.expected(0).unwrap());
+        assert_eq!(reader.path.filename(), path.file_name().unwrap().to_str());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn expression_mask() {
+        let builder = CogBuilder {
+            bands: 2,
+            mask: true,
+            nodata: Some(10.0),
+            ..Default::default()
+        };
+        let (reader, _) = builder.open().await.unwrap();
+        let expression = Expression::parse("b2 - b1").unwrap();
+        // Extends past the right and bottom edges of the 64x48 image
+        let window = Window::new(40, 30, 32, 32);
+        let (values, mask) = reader
+            .read_expression(&expression, window, 0, &ReadOptions::default())
+            .await
+            .unwrap();
+
+        let RasterArray::Uint8(expected) = builder.expected(0).unwrap() else {
+            panic!("unexpected data type")
+        };
+        let expected_mask = builder.expected_mask(0);
+        for ((row, col), &valid) in mask.indexed_iter() {
+            let (row, col) = (row + 30, col + 40);
+            if row >= 48 || col >= 64 {
+                assert!(!valid);
+                continue;
+            }
+            let (b1, b2) = (expected[[0, row, col]], expected[[1, row, col]]);
+            let expected_valid = expected_mask[[row, col]] && b1 != 10 && b2 != 10;
+            assert_eq!(valid, expected_valid, "pixel ({row}, {col})");
+            assert_eq!(values[[row - 30, col - 40]], b2 as f64 - b1 as f64);
+        }
+        // Pixels are excluded by nodata in either band, and by the internal mask
+        assert_eq!((expected[[0, 34, 62]], expected[[1, 34, 62]]), (230, 10));
+        assert!(expected_mask[[34, 62]] && !mask[[4, 22]]);
+        assert_eq!((expected[[0, 30, 45]], expected[[1, 30, 45]]), (208, 239));
+        assert!(!expected_mask[[30, 45]] && !mask[[0, 5]]);
+        assert!(mask[[0, 0]]);
+        assert_eq!(values[[0, 0]], 31.0);
+    }
+
+    #[tokio::test]
+    async fn statistics_exclude_mask() {
+        let builder = CogBuilder {
+            mask: true,
+            ..Default::default()
+        };
+        let (reader, _) = builder.open().await.unwrap();
+        let stats = reader.statistics(0, 4, &ReadOptions::default()).await;
+        let stats = stats.unwrap().remove(0);
+
+        // 439 of the 3072 pixels are masked out, which moves the mean from 123.13 to 123.15
+        assert_eq!(stats.count, 2633);
+        assert_eq!(stats.mean, Some(324258.0 / 2633.0));
+        assert_eq!((stats.min, stats.max), (Some(0.0), Some(250.0)));
+        assert_eq!(stats.histogram.iter().sum::<usize>(), 2633);
+    }
+
+    #[tokio::test]
+    async fn default_read_options() {
+        // Reads without options use those the reader was opened with
+        let builder = CogBuilder {
+            sparse_tiles: vec![1],
+            ..Default::default()
+        };
+        let options = OpenOptions {
+            read_options: ReadOptions {
+                fill_value: Some(PixelValue::Uint8(7)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (reader, _) = builder.open_with_options(&options).await.unwrap();
+        let tile = reader.get_tile(1, 0, 0).await.unwrap();
+        assert_eq!(tile, RasterArray::Uint8(Array3::from_elem((1, 32, 32), 7)));
+    }
 }
@@ -0,0 +1,147 @@
+//! Classify how a file is encoded into the profiles of rio-cogeo's `rio cogeo create
+//! --cog-profile`, to report the encodings of a catalog without fetching any tile.
+use std::fmt::{self, Display};
+
+use tiff::tags::{CompressionMethod, PhotometricInterpretation, PlanarConfiguration, Predictor};
+
+use crate::ifd::ImageFileDirectory;
+
+/// GDAL's `LercParameters` tag, holding the LERC version and the compression applied on top
+const LERC_PARAMETERS: u16 = 50674;
+
+/// The profiles of rio-cogeo, each named after the compression it writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProfileName {
+    Jpeg,
+    Webp,
+    Zstd,
+    Lzw,
+    Deflate,
+    Packbits,
+    Lzma,
+    Lerc,
+    LercDeflate,
+    LercZstd,
+    /// Uncompressed tiles
+    Raw,
+}
+
+impl ProfileName {
+    /// Return the name of the profile as passed to `rio cogeo create`, e.g. `"lerc_zstd"`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpeg",
+            Self::Webp => "webp",
+            Self::Zstd => "zstd",
+            Self::Lzw => "lzw",
+            Self::Deflate => "deflate",
+            Self::Packbits => "packbits",
+            Self::Lzma => "lzma",
+            Self::Lerc => "lerc",
+            Self::LercDeflate => "lerc_deflate",
+            Self::LercZstd => "lerc_zstd",
+            Self::Raw => "raw",
+        }
+    }
+}
+
+impl Display for ProfileName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The encoding of the full resolution image of a file, as returned by
+/// [`crate::COGReader::profile`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CogProfile {
+    /// The rio-cogeo profile with the compression of the file, or `None` for compressions that
+    /// no profile writes, like CCITT fax or old-style JPEG
+    pub name: Option<ProfileName>,
+    pub compression: CompressionMethod,
+    pub photometric: PhotometricInterpretation,
+    pub predictor: Predictor,
+    /// The `(width, height)` of the tiles, in pixels
+    pub block_size: (u32, u32),
+    pub interleave: PlanarConfiguration,
+}
+
+impl CogProfile {
+    pub(crate) fn new(ifd: &ImageFileDirectory) -> Self {
+        Self {
+            name: profile_name(ifd),
+            compression: ifd.compression,
+            photometric: ifd.photometric_interpretation,
+            predictor: ifd.predictor.unwrap_or(Predictor::None),
+            block_size: ifd.tile_size(),
+            interleave: ifd.planar_configuration,
+        }
+    }
+
+    /// Return whether the file is encoded as rio-cogeo writes its profile by default: with
+    /// 512x512 pixel-interleaved tiles, and for `jpeg`, RGB images converted to YCbCr
+    pub fn is_default(&self) -> bool {
+        self.name.is_some()
+            && self.block_size == (512, 512)
+            && self.interleave == PlanarConfiguration::Chunky
+            && (self.name != Some(ProfileName::Jpeg)
+                || self.photometric != PhotometricInterpretation::RGB)
+    }
+}
+
+fn profile_name(ifd: &ImageFileDirectory) -> Option<ProfileName> {
+    Some(match ifd.compression {
+        CompressionMethod::None => ProfileName::Raw,
+        CompressionMethod::LZW => ProfileName::Lzw,
+        CompressionMethod::ModernJPEG => ProfileName::Jpeg,
+        CompressionMethod::Deflate | CompressionMethod::OldDeflate => ProfileName::Deflate,
+        CompressionMethod::PackBits => ProfileName::Packbits,
+        CompressionMethod::Unknown(34887) => {
+            // The second value is 0 for none, 1 for deflate and 2 for zstd
+            let additional = ifd
+                .tag_by_code(LERC_PARAMETERS)
+                .and_then(|value| value.clone().into_u32_vec().ok())
+                .and_then(|values| values.get(1).copied());
+            match additional {
+                Some(1) => ProfileName::LercDeflate,
+                Some(2) => ProfileName::LercZstd,
+                _ => ProfileName::Lerc,
+            }
+        }
+        CompressionMethod::Unknown(34925) => ProfileName::Lzma,
+        CompressionMethod::Unknown(50000) => ProfileName::Zstd,
+        CompressionMethod::Unknown(50001) => ProfileName::Webp,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::CogBuilder;
+
+    #[tokio::test]
+    async fn deflate_profile() {
+        let (reader, _) = CogBuilder {
+            compression: CompressionMethod::Deflate,
+            predictor: Predictor::Horizontal,
+            bands: 3,
+            ..Default::default()
+        }
+        .open()
+        .await
+        .unwrap();
+        let profile = reader.profile();
+        assert_eq!(profile.name, Some(ProfileName::Deflate));
+        assert_eq!(profile.name.unwrap().to_string(), "deflate");
+        assert_eq!(profile.predictor, Predictor::Horizontal);
+        assert_eq!(profile.block_size, (32, 32));
+        // rio-cogeo writes 512x512 tiles
+        assert!(!profile.is_default());
+        let profile = CogProfile {
+            block_size: (512, 512),
+            ..profile
+        };
+        assert!(profile.is_default());
+    }
+}
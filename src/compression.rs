@@ -1,59 +1,310 @@
-use num_enum::{IntoPrimitive, TryFromPrimitive};
+use std::io::Read;
 
-#[derive(Clone, Copy, Debug, PartialEq, TryFromPrimitive, IntoPrimitive)]
-#[repr(u16)]
-enum Compression {
-    Uncompressed = 1,
-    Lzw = 5,
-    // TODO: can jpeg be 6 or 7?
-    Jpeg = 6,
-    // Jpeg = 7,
-    Deflate = 8,
-    Packbits = 32773,
-    Webp = 50001,
-}
+use bytes::Bytes;
+use flate2::bufread::ZlibDecoder;
+use jpeg_decoder::{PixelFormat, UnsupportedFeature};
+use tiff::tags::CompressionMethod;
+
+use crate::cursor::Endianness;
+use crate::error::{AiocogeoError, Result};
 
 trait Decompressor {
     // TODO: should this return an ndarray?
-    fn decompress(&self, tile: Vec<u8>) -> Vec<u8>;
+    /// Decompress a tile, appending to `out` where the format allows it so that its allocation
+    /// can be reused
+    fn decompress(&self, tile: Bytes, out: Vec<u8>) -> Result<Vec<u8>>;
+}
+
+/// Decompress a single tile's bytes according to the IFD's compression method, with multi-byte
+/// samples in the file's byte order, into the empty buffer `out` where possible
+pub(crate) fn decompress_tile(
+    compression: CompressionMethod,
+    tile: Bytes,
+    jpeg_tables: Option<&[u8]>,
+    endianness: Endianness,
+    out: Vec<u8>,
+) -> Result<Vec<u8>> {
+    match compression {
+        CompressionMethod::None => UncompressedDecompressor {}.decompress(tile, out),
+        CompressionMethod::LZW => LZWDecompressor {}.decompress(tile, out),
+        CompressionMethod::ModernJPEG => JPEGDecompressor {
+            jpeg_tables,
+            endianness,
+        }
+        .decompress(tile, out),
+        CompressionMethod::Deflate | CompressionMethod::OldDeflate => {
+            DeflateDecompressor {}.decompress(tile, out)
+        }
+        CompressionMethod::PackBits => PackbitsDecompressor {}.decompress(tile, out),
+        CompressionMethod::Unknown(50001) => WebPDecompressor {}.decompress(tile, out),
+        method => Err(AiocogeoError::UnsupportedCompression(method.to_u16())),
+    }
 }
 
-pub(crate) struct JPEGDecompressor {}
+pub(crate) struct UncompressedDecompressor {}
 
-impl Decompressor for JPEGDecompressor {
-    fn decompress(&self, tile: Vec<u8>) -> Vec<u8> {
-        todo!()
+impl Decompressor for UncompressedDecompressor {
+    fn decompress(&self, tile: Bytes, mut out: Vec<u8>) -> Result<Vec<u8>> {
+        out.extend_from_slice(&tile);
+        Ok(out)
+    }
+}
+
+/// The start and end of image markers of a JPEG stream
+const JPEG_SOI: [u8; 2] = [0xFF, 0xD8];
+const JPEG_EOI: [u8; 2] = [0xFF, 0xD9];
+
+pub(crate) struct JPEGDecompressor<'a> {
+    /// The contents of the `JPEGTables` tag of the tile's IFD, shared between all its tiles
+    jpeg_tables: Option<&'a [u8]>,
+    /// The byte order of the file, which samples of more than 8 bits are returned in
+    endianness: Endianness,
+}
+
+impl JPEGDecompressor<'_> {
+    /// Return the stream to decode: the tile with the IFD's tables inserted after its start of
+    /// image marker.
+    ///
+    /// The tables are stored as an abbreviated JPEG stream, so their markers are dropped before
+    /// joining them. Tiles may also be complete streams with their own tables, which replace the
+    /// shared ones as they come later, or have no shared tables at all.
+    fn stream(&self, tile: &[u8]) -> Vec<u8> {
+        let tables = self.jpeg_tables.unwrap_or_default();
+        let tables = tables.strip_prefix(&JPEG_SOI).unwrap_or(tables);
+        let tables = tables.strip_suffix(&JPEG_EOI).unwrap_or(tables);
+        if tables.is_empty() {
+            return tile.to_vec();
+        }
+        let body = tile.strip_prefix(&JPEG_SOI).unwrap_or(tile);
+        let mut data = Vec::with_capacity(JPEG_SOI.len() + tables.len() + body.len());
+        data.extend_from_slice(&JPEG_SOI);
+        data.extend_from_slice(tables);
+        data.extend_from_slice(body);
+        data
+    }
+}
+
+impl Decompressor for JPEGDecompressor<'_> {
+    /// The JPEG decoder allocates its own output, so `out` is unused
+    fn decompress(&self, tile: Bytes, _out: Vec<u8>) -> Result<Vec<u8>> {
+        let mut decoder = jpeg_decoder::Decoder::new(std::io::Cursor::new(self.stream(&tile)));
+        let mut pixels = decoder.decode().map_err(|err| match err {
+            jpeg_decoder::Error::Unsupported(UnsupportedFeature::SamplePrecision(precision)) => {
+                AiocogeoError::Decompression(format!(
+                    "{precision}-bit JPEG is only supported with lossless coding; the JPEG \
+                     decoder can't decode lossy JPEG of more than 8 bits"
+                ))
+            }
+            err => {
+                let hint = if self.jpeg_tables.is_none() {
+                    " (the tile's IFD has no JPEGTables)"
+                } else {
+                    ""
+                };
+                AiocogeoError::Decompression(format!("{err}{hint}"))
+            }
+        })?;
+        // Samples of more than 8 bits, e.g. 12-bit JPEG, are decoded to native endian 16 bit
+        // values
+        let wide = decoder
+            .info()
+            .is_some_and(|info| info.pixel_format == PixelFormat::L16);
+        let big_endian = matches!(self.endianness, Endianness::BigEndian);
+        if wide && big_endian != cfg!(target_endian = "big") {
+            pixels
+                .chunks_exact_mut(2)
+                .for_each(|sample| sample.swap(0, 1));
+        }
+        Ok(pixels)
     }
 }
 
 pub(crate) struct LZWDecompressor {}
 
 impl Decompressor for LZWDecompressor {
-    fn decompress(&self, tile: Vec<u8>) -> Vec<u8> {
-        todo!()
+    fn decompress(&self, tile: Bytes, mut out: Vec<u8>) -> Result<Vec<u8>> {
+        let mut decoder = weezl::decode::Decoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8);
+        decoder
+            .into_vec(&mut out)
+            .decode_all(&tile)
+            .status
+            .map_err(|err| AiocogeoError::Decompression(err.to_string()))?;
+        Ok(out)
     }
 }
 
 pub(crate) struct WebPDecompressor {}
 
 impl Decompressor for WebPDecompressor {
-    fn decompress(&self, tile: Vec<u8>) -> Vec<u8> {
-        todo!()
+    fn decompress(&self, _tile: Bytes, _out: Vec<u8>) -> Result<Vec<u8>> {
+        Err(AiocogeoError::Decompression(
+            "WebP decompression is not yet supported".to_string(),
+        ))
     }
 }
 
 pub(crate) struct DeflateDecompressor {}
 
 impl Decompressor for DeflateDecompressor {
-    fn decompress(&self, tile: Vec<u8>) -> Vec<u8> {
-        todo!()
+    fn decompress(&self, tile: Bytes, mut out: Vec<u8>) -> Result<Vec<u8>> {
+        let mut decoder = ZlibDecoder::new(tile.as_ref());
+        decoder.read_to_end(&mut out)?;
+        Ok(out)
     }
 }
 
 pub(crate) struct PackbitsDecompressor {}
 
 impl Decompressor for PackbitsDecompressor {
-    fn decompress(&self, tile: Vec<u8>) -> Vec<u8> {
-        todo!()
+    fn decompress(&self, tile: Bytes, mut buf: Vec<u8>) -> Result<Vec<u8>> {
+        // https://www.awaresystems.be/imaging/tiff/tifftags/compression.html
+        buf.reserve(tile.len() * 2);
+        let mut idx = 0;
+        while idx < tile.len() {
+            let header = tile[idx] as i8;
+            idx += 1;
+            if header >= 0 {
+                // Copy the next n + 1 bytes literally
+                let count = header as usize + 1;
+                let literal = tile.get(idx..idx + count).ok_or_else(|| {
+                    AiocogeoError::Decompression("truncated packbits literal run".to_string())
+                })?;
+                buf.extend_from_slice(literal);
+                idx += count;
+            } else if header != -128 {
+                // Repeat the next byte 1 - n times
+                let value = *tile.get(idx).ok_or_else(|| {
+                    AiocogeoError::Decompression("truncated packbits repeat run".to_string())
+                })?;
+                buf.extend(std::iter::repeat_n(value, (1 - header as isize) as usize));
+                idx += 1;
+            }
+        }
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Return the tables and frame of a JPEG stream of an 8x8 grayscale image of value 128
+    fn jpeg_parts() -> (Vec<u8>, Vec<u8>) {
+        let mut tables = vec![0xFF, 0xDB, 0x00, 0x43, 0x00];
+        tables.extend([1; 64]);
+        // DC and AC Huffman tables with a single 1 bit code, for a 0 difference and end of block
+        for class in [0x00, 0x10] {
+            tables.extend([0xFF, 0xC4, 0x00, 0x14, class, 1]);
+            tables.extend([0; 16]);
+        }
+        // An 8 bit frame of 1 component, and a scan of its single block coded as a 0 difference
+        // followed by end of block, padded with 1s
+        let mut frame = vec![0xFF, 0xC0, 0x00, 0x0B, 8, 0, 8, 0, 8, 1, 1, 0x11, 0];
+        frame.extend([0xFF, 0xDA, 0x00, 0x08, 1, 1, 0x00, 0, 63, 0, 0b0011_1111]);
+        (tables, frame)
+    }
+
+    fn jpeg_stream(parts: &[&[u8]]) -> Bytes {
+        let mut stream = JPEG_SOI.to_vec();
+        parts.iter().for_each(|part| stream.extend_from_slice(part));
+        stream.extend(JPEG_EOI);
+        stream.into()
+    }
+
+    #[test]
+    fn jpeg_tables() {
+        let (tables, frame) = jpeg_parts();
+        let expected = vec![128; 64];
+
+        // Abbreviated tiles with shared tables
+        let shared = jpeg_stream(&[&tables]);
+        let abbreviated = jpeg_stream(&[&frame]);
+        let decompressor = JPEGDecompressor {
+            jpeg_tables: Some(&shared),
+            endianness: Endianness::LittleEndian,
+        };
+        assert_eq!(
+            decompressor
+                .decompress(abbreviated.clone(), vec![])
+                .unwrap(),
+            expected
+        );
+
+        // Complete tiles, with or without shared tables
+        let complete = jpeg_stream(&[&tables, &frame]);
+        for jpeg_tables in [None, Some(&shared[..]), Some(&JPEG_SOI[..]), Some(&[][..])] {
+            let decompressor = JPEGDecompressor {
+                jpeg_tables,
+                endianness: Endianness::LittleEndian,
+            };
+            assert_eq!(
+                decompressor.decompress(complete.clone(), vec![]).unwrap(),
+                expected
+            );
+        }
+
+        // Quantization tables are required
+        let err = JPEGDecompressor {
+            jpeg_tables: None,
+            endianness: Endianness::LittleEndian,
+        }
+        .decompress(abbreviated, vec![])
+        .unwrap_err();
+        assert!(err.to_string().contains("no JPEGTables"));
+    }
+
+    #[test]
+    fn jpeg_12_bit() {
+        // A lossless 12-bit frame whose samples all equal the initial prediction of 2048, coded
+        // as 0 differences with a single 1 bit code
+        let mut huffman = vec![0xFF, 0xC4, 0x00, 0x14, 0x00, 1];
+        huffman.extend([0; 16]);
+        let frame = [0xFF, 0xC3, 0x00, 0x0B, 12, 0, 8, 0, 8, 1, 1, 0x11, 0];
+        let mut scan = vec![0xFF, 0xDA, 0x00, 0x08, 1, 1, 0x00, 1, 0, 0];
+        scan.extend([0; 8]);
+        let tile = jpeg_stream(&[&huffman, &frame, &scan]);
+        for (endianness, sample) in [
+            (Endianness::LittleEndian, [0x00, 0x08]),
+            (Endianness::BigEndian, [0x08, 0x00]),
+        ] {
+            let pixels = decompress_tile(
+                CompressionMethod::ModernJPEG,
+                tile.clone(),
+                None,
+                endianness,
+                vec![],
+            )
+            .unwrap();
+            assert_eq!(pixels, sample.repeat(64));
+        }
+
+        // Lossy 12-bit frames are not supported by the decoder
+        let (tables, mut frame) = jpeg_parts();
+        frame[1] = 0xC1;
+        frame[4] = 12;
+        let err = decompress_tile(
+            CompressionMethod::ModernJPEG,
+            jpeg_stream(&[&tables, &frame]),
+            None,
+            Endianness::LittleEndian,
+            vec![],
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("12-bit JPEG"));
+    }
+
+    #[test]
+    fn packbits() {
+        // Example from the TIFF 6.0 specification, section 9
+        let packed = Bytes::from_static(&[
+            0xFE, 0xAA, 0x02, 0x80, 0x00, 0x2A, 0xFD, 0xAA, 0x03, 0x80, 0x00, 0x2A, 0x22, 0xF7,
+            0xAA,
+        ]);
+        let expected = [
+            0xAA, 0xAA, 0xAA, 0x80, 0x00, 0x2A, 0xAA, 0xAA, 0xAA, 0xAA, 0x80, 0x00, 0x2A, 0x22,
+            0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA,
+        ];
+        let out = PackbitsDecompressor {}.decompress(packed, vec![]).unwrap();
+        assert_eq!(out, expected);
     }
 }
@@ -1,10 +1,11 @@
-use std::io::Cursor;
+use std::io::{self, Cursor};
+use std::ops::Range;
 use std::sync::Arc;
 
 use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
 use bytes::Bytes;
 use object_store::path::Path;
-use object_store::ObjectStore;
+use object_store::{GetOptions, GetRange, ObjectStore};
 
 #[derive(Debug, Clone, Copy, Default)]
 pub enum Endianness {
@@ -20,16 +21,19 @@ pub(crate) struct ObjectStoreCursor {
     path: Path,
     offset: usize,
     endianness: Endianness,
+    /// Chunks of the file that are already known, as `(offset, bytes)`, used to serve reads
+    /// without making requests
+    chunks: Vec<(usize, Bytes)>,
 }
 
 /// Macro to generate functions to read scalar values from the cursor
 macro_rules! impl_read_byteorder {
     ($method_name:ident, $typ:ty) => {
-        pub(crate) async fn $method_name(&mut self) -> $typ {
-            let mut buf = Cursor::new(self.read(<$typ>::BITS as usize / 8).await);
+        pub(crate) async fn $method_name(&mut self) -> io::Result<$typ> {
+            let mut buf = Cursor::new(self.read(<$typ>::BITS as usize / 8).await?);
             match self.endianness {
-                Endianness::LittleEndian => buf.$method_name::<LittleEndian>().unwrap(),
-                Endianness::BigEndian => buf.$method_name::<BigEndian>().unwrap(),
+                Endianness::LittleEndian => buf.$method_name::<LittleEndian>(),
+                Endianness::BigEndian => buf.$method_name::<BigEndian>(),
             }
         }
     };
@@ -42,33 +46,138 @@ impl ObjectStoreCursor {
             path,
             offset: 0,
             endianness: Default::default(),
+            chunks: vec![],
         }
     }
 
+    /// Serve reads that fall entirely within the first `header.len()` bytes of the file from
+    /// `header` instead of the store
+    pub(crate) fn set_header(&mut self, header: Bytes) {
+        self.add_chunk(0, header);
+    }
+
+    /// Serve reads that fall entirely within `bytes`, found at `offset` in the file, from
+    /// `bytes` instead of the store
+    pub(crate) fn add_chunk(&mut self, offset: usize, bytes: Bytes) {
+        if !bytes.is_empty() {
+            self.chunks.push((offset, bytes));
+        }
+    }
+
+    /// Whether reading `range` would be served from known chunks of the file
+    pub(crate) fn is_known(&self, range: &Range<usize>) -> bool {
+        self.chunk(range).is_some()
+    }
+
+    fn chunk(&self, range: &Range<usize>) -> Option<Bytes> {
+        self.chunks.iter().find_map(|(offset, bytes)| {
+            (range.start >= *offset && range.end <= offset + bytes.len())
+                .then(|| bytes.slice(range.start - offset..range.end - offset))
+        })
+    }
+
+    /// Fetch `range` with a single request, clamped to the end of the file, and serve later reads
+    /// within it without making requests
+    pub(crate) async fn fetch_chunk(&mut self, range: Range<usize>) -> io::Result<()> {
+        let options = GetOptions {
+            range: Some(range.into()),
+            ..Default::default()
+        };
+        let result = self
+            .store
+            .get_opts(&self.path, options)
+            .await
+            .map_err(io::Error::other)?;
+        let offset = result.range.start;
+        self.add_chunk(offset, result.bytes().await.map_err(io::Error::other)?);
+        Ok(())
+    }
+
+    /// Fetch the last `length` bytes of the file with a single request, and serve later reads
+    /// within them without making requests.
+    ///
+    /// Uses a suffix range request, falling back to a bounded range if the store doesn't support
+    /// them. The bounded range is computed from `size`, or from the size returned by a `HEAD`
+    /// request if it isn't known.
+    pub(crate) async fn fetch_tail(
+        &mut self,
+        length: usize,
+        size: Option<usize>,
+    ) -> io::Result<()> {
+        let options = GetOptions {
+            range: Some(GetRange::Suffix(length)),
+            ..Default::default()
+        };
+        let (offset, bytes) = match self.store.get_opts(&self.path, options).await {
+            Ok(result) => {
+                let offset = result.range.start;
+                (offset, result.bytes().await.map_err(io::Error::other)?)
+            }
+            Err(object_store::Error::NotSupported { .. } | object_store::Error::NotImplemented) => {
+                let size = match size {
+                    Some(size) => size,
+                    None => {
+                        self.store
+                            .head(&self.path)
+                            .await
+                            .map_err(io::Error::other)?
+                            .size
+                    }
+                };
+                let range = size.saturating_sub(length)..size;
+                let offset = range.start;
+                let bytes = self.store.get_range(&self.path, range).await;
+                (offset, bytes.map_err(io::Error::other)?)
+            }
+            Err(err) => return Err(io::Error::other(err)),
+        };
+        self.add_chunk(offset, bytes);
+        Ok(())
+    }
+
     pub(crate) fn set_endianness(&mut self, endianness: Endianness) {
         self.endianness = endianness;
     }
 
-    pub(crate) fn into_inner(self) -> (Arc<dyn ObjectStore>, Path) {
-        (self.store, self.path)
+    pub(crate) fn endianness(&self) -> Endianness {
+        self.endianness
     }
 
-    pub(crate) async fn read(&mut self, length: usize) -> Bytes {
-        let range = self.offset..self.offset + length;
-        self.offset += length;
-        self.store.get_range(&self.path, range).await.unwrap()
+    pub(crate) fn into_inner(self) -> (Arc<dyn ObjectStore>, Path) {
+        (self.store, self.path)
     }
 
-    /// Read a u8 from the cursor
-    pub(crate) async fn read_u8(&mut self) -> u8 {
-        let buf = self.read(u8::BITS as usize / 8).await;
-        Cursor::new(buf).read_u8().unwrap()
+    /// Read `length` bytes, failing if they extend past the end of the file
+    pub(crate) async fn read(&mut self, length: usize) -> io::Result<Bytes> {
+        let end = self.offset.checked_add(length).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "read past the end of the address space",
+            )
+        })?;
+        let range = self.offset..end;
+        self.offset = end;
+        if let Some(bytes) = self.chunk(&range) {
+            return Ok(bytes);
+        }
+        let bytes = self
+            .store
+            .get_range(&self.path, range.clone())
+            .await
+            .map_err(io::Error::other)?;
+        if bytes.len() != length {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("bytes {range:?} extend past the end of the file"),
+            ));
+        }
+        Ok(bytes)
     }
 
     /// Read a i8 from the cursor
-    pub(crate) async fn read_i8(&mut self) -> i8 {
-        let buf = self.read(1).await;
-        Cursor::new(buf).read_i8().unwrap()
+    pub(crate) async fn read_i8(&mut self) -> io::Result<i8> {
+        let buf = self.read(1).await?;
+        Cursor::new(buf).read_i8()
     }
 
     impl_read_byteorder!(read_u16, u16);
@@ -78,19 +187,19 @@ impl ObjectStoreCursor {
     impl_read_byteorder!(read_i32, i32);
     impl_read_byteorder!(read_i64, i64);
 
-    pub(crate) async fn read_f32(&mut self) -> f32 {
-        let mut buf = Cursor::new(self.read(4).await);
+    pub(crate) async fn read_f32(&mut self) -> io::Result<f32> {
+        let mut buf = Cursor::new(self.read(4).await?);
         match self.endianness {
-            Endianness::LittleEndian => buf.read_f32::<LittleEndian>().unwrap(),
-            Endianness::BigEndian => buf.read_f32::<BigEndian>().unwrap(),
+            Endianness::LittleEndian => buf.read_f32::<LittleEndian>(),
+            Endianness::BigEndian => buf.read_f32::<BigEndian>(),
         }
     }
 
-    pub(crate) async fn read_f64(&mut self) -> f64 {
-        let mut buf = Cursor::new(self.read(8).await);
+    pub(crate) async fn read_f64(&mut self) -> io::Result<f64> {
+        let mut buf = Cursor::new(self.read(8).await?);
         match self.endianness {
-            Endianness::LittleEndian => buf.read_f64::<LittleEndian>().unwrap(),
-            Endianness::BigEndian => buf.read_f64::<BigEndian>().unwrap(),
+            Endianness::LittleEndian => buf.read_f64::<LittleEndian>(),
+            Endianness::BigEndian => buf.read_f64::<BigEndian>(),
         }
     }
 
@@ -107,3 +216,41 @@ impl ObjectStoreCursor {
         self.offset
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use object_store::memory::InMemory;
+
+    #[tokio::test]
+    async fn read_from_header() {
+        // The store is empty, so any request would fail
+        let store = Arc::new(InMemory::new());
+        let mut cursor = ObjectStoreCursor::new(store, Path::from("test.tif"));
+        cursor.set_header(Bytes::from_static(&[1, 0, 2, 0, 0, 0]));
+        assert_eq!(cursor.read_u16().await.unwrap(), 1);
+        assert_eq!(cursor.read_u32().await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn read_from_tail() {
+        let store = Arc::new(InMemory::new());
+        let path = Path::from("test.tif");
+        let data: Vec<u8> = (0..100).collect();
+        store.put(&path, data.into()).await.unwrap();
+        let mut cursor = ObjectStoreCursor::new(store.clone(), path.clone());
+        cursor.fetch_tail(10, None).await.unwrap();
+        assert!(cursor.is_known(&(90..100)));
+        assert!(!cursor.is_known(&(89..91)));
+
+        // Reads within the tail are served without the store
+        store.delete(&path).await.unwrap();
+        cursor.seek(95);
+        assert_eq!(
+            cursor.read(5).await.unwrap().as_ref(),
+            &[95, 96, 97, 98, 99]
+        );
+        cursor.seek(88);
+        assert!(cursor.read(4).await.is_err());
+    }
+}
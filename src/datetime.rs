@@ -0,0 +1,163 @@
+//! Parse the timestamps found in TIFF tags and GDAL metadata, which are written in a handful of
+//! formats depending on the writer.
+use std::fmt::{self, Display};
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime};
+
+/// Formats of timestamps without an offset, tried in order
+const FORMATS: [&str; 4] = [
+    // TIFF `DateTime` and EXIF
+    "%Y:%m:%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y/%m/%d %H:%M:%S",
+];
+
+/// Formats of dates without a time, read as midnight
+const DATE_FORMATS: [&str; 3] = ["%Y-%m-%d", "%Y:%m:%d", "%Y%m%d"];
+
+/// A timestamp read from a file, which has an offset from UTC only if the file says so
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timestamp {
+    /// A timestamp with a known offset from UTC, e.g. `2024-01-31T12:00:00+02:00`
+    Offset(DateTime<FixedOffset>),
+    /// A timestamp in an unknown time zone, as TIFF `DateTime` tags are
+    Local(NaiveDateTime),
+}
+
+impl Timestamp {
+    /// Return the offset from UTC, if it's known
+    pub fn offset(&self) -> Option<FixedOffset> {
+        match self {
+            Self::Offset(datetime) => Some(*datetime.offset()),
+            Self::Local(_) => None,
+        }
+    }
+
+    /// Return the time in UTC if the offset is known, and as written otherwise
+    pub fn naive(&self) -> NaiveDateTime {
+        match self {
+            Self::Offset(datetime) => datetime.naive_utc(),
+            Self::Local(datetime) => *datetime,
+        }
+    }
+}
+
+/// Formats as RFC 3339, without an offset for local timestamps
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Offset(datetime) => write!(f, "{}", datetime.to_rfc3339()),
+            Self::Local(datetime) => write!(f, "{}", datetime.format("%Y-%m-%dT%H:%M:%S%.f")),
+        }
+    }
+}
+
+/// Parse a timestamp as written in TIFF `DateTime` tags (`2024:01:31 12:00:00`), as ISO 8601 /
+/// RFC 3339 (`2024-01-31T12:00:00.5Z`), or as a bare date (`2024-01-31`).
+///
+/// Timestamps with an offset, including a trailing `Z`, keep it; others are
+/// [`Timestamp::Local`], as TIFF doesn't say which time zone they're in. Returns `None` for blank
+/// or unrecognized values, including the all-zero and space-filled values some writers use for
+/// unknown times.
+pub(crate) fn parse_datetime(value: &str) -> Option<Timestamp> {
+    let value = value.trim().trim_end_matches('\0');
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Some(Timestamp::Offset(datetime));
+    }
+    // RFC 3339 requires seconds and a `T`, which writers often omit
+    if let Ok(datetime) = DateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f%:z") {
+        return Some(Timestamp::Offset(datetime));
+    }
+    // A trailing `Z` without an offset is UTC
+    let (naive, utc) = match value.strip_suffix('Z') {
+        Some(naive) => (naive, true),
+        None => (value, false),
+    };
+    let datetime = FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(naive, format).ok())
+        .or_else(|| {
+            DATE_FORMATS.iter().find_map(|format| {
+                NaiveDate::parse_from_str(naive, format)
+                    .ok()
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+            })
+        })?;
+    Some(if utc {
+        Timestamp::Offset(datetime.and_utc().fixed_offset())
+    } else {
+        Timestamp::Local(datetime)
+    })
+}
+
+/// Parse an offset from UTC as written in EXIF `OffsetTime` tags, e.g. `+02:00`
+pub(crate) fn parse_offset(value: &str) -> Option<FixedOffset> {
+    value.trim().trim_end_matches('\0').parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_formats() {
+        let expected = NaiveDate::from_ymd_opt(2024, 1, 31)
+            .unwrap()
+            .and_hms_opt(12, 30, 5)
+            .unwrap();
+        for value in [
+            "2024:01:31 12:30:05",
+            "2024:01:31 12:30:05\0",
+            "2024-01-31T12:30:05",
+            "2024-01-31T12:30:05Z",
+            "2024-01-31T14:30:05+02:00",
+            "2024-01-31 12:30:05",
+            "2024/01/31 12:30:05",
+        ] {
+            assert_eq!(
+                parse_datetime(value).map(|t| t.naive()),
+                Some(expected),
+                "{value}"
+            );
+        }
+        assert_eq!(
+            parse_datetime("2024-01-31T12:30:05.250Z").unwrap().naive(),
+            expected + chrono::Duration::milliseconds(250)
+        );
+        assert_eq!(
+            parse_datetime("2024-01-31"),
+            NaiveDate::from_ymd_opt(2024, 1, 31)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .map(Timestamp::Local)
+        );
+
+        // Offsets are kept when the value has one, and only then
+        let hours = |h| FixedOffset::east_opt(h * 3600);
+        assert_eq!(
+            parse_datetime("2024:01:31 12:30:05").unwrap().offset(),
+            None
+        );
+        assert_eq!(
+            parse_datetime("2024-01-31T12:30:05Z").unwrap().offset(),
+            hours(0)
+        );
+        let datetime = parse_datetime("2024-01-31 14:30:05+02:00").unwrap();
+        assert_eq!(datetime.offset(), hours(2));
+        assert_eq!(datetime.to_string(), "2024-01-31T14:30:05+02:00");
+        assert_eq!(
+            parse_datetime("2024:01:31 12:30:05").unwrap().to_string(),
+            "2024-01-31T12:30:05"
+        );
+        assert_eq!(parse_offset("-05:00\0"), hours(-5));
+        for value in [
+            "",
+            "    :  :     :  :  ",
+            "0000:00:00 00:00:00",
+            "yesterday",
+        ] {
+            assert_eq!(parse_datetime(value), None, "{value}");
+        }
+    }
+}
@@ -0,0 +1,596 @@
+use ndarray::{Array2, Array3, Axis};
+use num_complex::Complex;
+use tiff::tags::{PlanarConfiguration, Predictor};
+
+use crate::array::{map_array, RasterArray};
+use crate::cursor::Endianness;
+use crate::enums::{DataType, FillOrder, Orientation};
+use crate::error::{AiocogeoError, Result};
+use crate::pool::{give_buffer, take_buffer, BufferPool};
+
+/// Unpack bit-packed samples (1, 2 or 4 bits per sample) into one `u8` per sample.
+///
+/// Each row of the packed data starts on a byte boundary, so any padding bits at the end of a row
+/// are skipped.
+pub(crate) fn unpack_bits(
+    data: &[u8],
+    bits_per_sample: u16,
+    samples_per_row: usize,
+    rows: usize,
+    fill_order: FillOrder,
+) -> Result<Vec<u8>> {
+    if !matches!(bits_per_sample, 1 | 2 | 4) {
+        return Err(AiocogeoError::General(format!(
+            "cannot unpack {bits_per_sample} bits per sample"
+        )));
+    }
+
+    let bits = bits_per_sample as usize;
+    let row_bytes = (samples_per_row * bits).div_ceil(8);
+    if data.len() < row_bytes * rows {
+        return Err(AiocogeoError::General(format!(
+            "expected at least {} bytes of packed data, got {}",
+            row_bytes * rows,
+            data.len()
+        )));
+    }
+
+    let mask = (1u8 << bits) - 1;
+    let samples_per_byte = 8 / bits;
+    let mut out = Vec::with_capacity(samples_per_row * rows);
+    for row in data.chunks_exact(row_bytes).take(rows) {
+        for sample_idx in 0..samples_per_row {
+            let mut byte = row[sample_idx / samples_per_byte];
+            if fill_order == FillOrder::LsbToMsb {
+                byte = byte.reverse_bits();
+            }
+            let shift = 8 - bits * (sample_idx % samples_per_byte + 1);
+            out.push((byte >> shift) & mask);
+        }
+    }
+    Ok(out)
+}
+
+/// Unpack a decompressed 1-bit transparency mask into a `(height, width)` array that is `true`
+/// where pixels are valid.
+///
+/// As with [`unpack_bits`], each row starts on a byte boundary.
+pub(crate) fn unpack_mask(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    fill_order: FillOrder,
+) -> Result<Array2<bool>> {
+    let row_bytes = width.div_ceil(8);
+    if data.len() < row_bytes * height {
+        return Err(AiocogeoError::General(format!(
+            "expected at least {} bytes of mask data, got {}",
+            row_bytes * height,
+            data.len()
+        )));
+    }
+
+    Ok(Array2::from_shape_fn((height, width), |(row, col)| {
+        let mut byte = data[row * row_bytes + col / 8];
+        if fill_order == FillOrder::LsbToMsb {
+            byte = byte.reverse_bits();
+        }
+        byte & (0x80 >> (col % 8)) != 0
+    }))
+}
+
+/// Convert raw sample bytes in the file's byte order into native values
+pub(crate) trait FromBytes: Sized {
+    fn from_bytes(bytes: &[u8], endianness: Endianness) -> Self;
+}
+
+macro_rules! impl_from_bytes {
+    ($typ:ty) => {
+        impl FromBytes for $typ {
+            fn from_bytes(bytes: &[u8], endianness: Endianness) -> Self {
+                let bytes = bytes.try_into().unwrap();
+                match endianness {
+                    Endianness::LittleEndian => <$typ>::from_le_bytes(bytes),
+                    Endianness::BigEndian => <$typ>::from_be_bytes(bytes),
+                }
+            }
+        }
+    };
+}
+
+impl_from_bytes!(u8);
+impl_from_bytes!(u16);
+impl_from_bytes!(u32);
+impl_from_bytes!(u64);
+impl_from_bytes!(i8);
+impl_from_bytes!(i16);
+impl_from_bytes!(i32);
+impl_from_bytes!(i64);
+impl_from_bytes!(f32);
+impl_from_bytes!(f64);
+
+/// Complex samples are stored as the real part followed by the imaginary part
+impl<T: FromBytes> FromBytes for Complex<T> {
+    fn from_bytes(bytes: &[u8], endianness: Endianness) -> Self {
+        let (re, im) = bytes.split_at(bytes.len() / 2);
+        Complex::new(T::from_bytes(re, endianness), T::from_bytes(im, endianness))
+    }
+}
+
+/// The layout of a decompressed tile, as described by its IFD
+pub(crate) struct TileLayout {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) bands: usize,
+    pub(crate) bits_per_sample: u16,
+    pub(crate) data_type: DataType,
+    pub(crate) planar_configuration: PlanarConfiguration,
+    pub(crate) fill_order: FillOrder,
+    pub(crate) endianness: Endianness,
+    pub(crate) predictor: Predictor,
+}
+
+impl TileLayout {
+    /// The number of samples stored in each row of a single decompressed buffer
+    fn samples_per_row(&self) -> usize {
+        match self.planar_configuration {
+            PlanarConfiguration::Chunky => self.width * self.bands,
+            _ => self.width,
+        }
+    }
+
+    /// The number of bytes of a single decompressed buffer, with rows padded to whole bytes
+    pub(crate) fn buffer_len(&self) -> usize {
+        (self.samples_per_row() * self.bits_per_sample as usize).div_ceil(8) * self.height
+    }
+
+    /// The number of samples between a sample and the same band of the previous pixel
+    fn sample_stride(&self) -> usize {
+        match self.planar_configuration {
+            PlanarConfiguration::Chunky => self.bands,
+            _ => 1,
+        }
+    }
+
+    /// The shape of the samples once decoded, before moving bands to the first axis
+    fn storage_shape(&self) -> (usize, usize, usize) {
+        match self.planar_configuration {
+            PlanarConfiguration::Chunky => (self.height, self.width, self.bands),
+            _ => (self.bands, self.height, self.width),
+        }
+    }
+}
+
+/// Convert decompressed tile buffers into a `(bands, height, width)` array.
+///
+/// `buffers` holds a single buffer for pixel-interleaved data, or one buffer per band for
+/// band-interleaved data. The buffers and scratch space are returned to `pool`, if any.
+pub(crate) fn decode_tile(
+    buffers: Vec<Vec<u8>>,
+    layout: &TileLayout,
+    pool: Option<&BufferPool>,
+) -> Result<RasterArray> {
+    let buffer_count = buffers.len();
+    let expected_len = layout.samples_per_row() * layout.height * layout.data_type.size();
+
+    let data_len = expected_len * buffer_count;
+    let mut data = take_buffer(pool, data_len);
+    for buf in buffers {
+        let unpacked = match layout.bits_per_sample {
+            1 | 2 | 4 => Some(unpack_bits(
+                &buf,
+                layout.bits_per_sample,
+                layout.samples_per_row(),
+                layout.height,
+                layout.fill_order,
+            )?),
+            _ => None,
+        };
+        let samples = unpacked.as_deref().unwrap_or(&buf);
+        if samples.len() < expected_len {
+            return Err(AiocogeoError::General(format!(
+                "decoded tile is {} bytes, expected {expected_len}",
+                samples.len()
+            )));
+        }
+        let start = data.len();
+        data.extend_from_slice(&samples[..expected_len]);
+        undo_predictor(&mut data[start..], layout)?;
+        give_buffer(pool, buf, layout.buffer_len());
+    }
+
+    let out = match layout.data_type {
+        DataType::Uint8 => to_array::<u8>(&data, layout)?.into(),
+        DataType::Uint16 => to_array::<u16>(&data, layout)?.into(),
+        DataType::Uint32 => to_array::<u32>(&data, layout)?.into(),
+        DataType::Uint64 => to_array::<u64>(&data, layout)?.into(),
+        DataType::Int8 => to_array::<i8>(&data, layout)?.into(),
+        DataType::Int16 => to_array::<i16>(&data, layout)?.into(),
+        DataType::Int32 => to_array::<i32>(&data, layout)?.into(),
+        DataType::Int64 => to_array::<i64>(&data, layout)?.into(),
+        DataType::Float32 => to_array::<f32>(&data, layout)?.into(),
+        DataType::Float64 => to_array::<f64>(&data, layout)?.into(),
+        DataType::CInt16 => to_array::<Complex<i16>>(&data, layout)?.into(),
+        DataType::CInt32 => to_array::<Complex<i32>>(&data, layout)?.into(),
+        DataType::CFloat32 => to_array::<Complex<f32>>(&data, layout)?.into(),
+        DataType::CFloat64 => to_array::<Complex<f64>>(&data, layout)?.into(),
+    };
+    give_buffer(pool, data, data_len);
+    Ok(out)
+}
+
+/// Reverse the differencing applied to each row of a decompressed buffer before compression.
+///
+/// https://www.awaresystems.be/imaging/tiff/tifftags/predictor.html
+fn undo_predictor(buf: &mut [u8], layout: &TileLayout) -> Result<()> {
+    let size = layout.data_type.size();
+    let samples_per_row = layout.samples_per_row();
+    let stride = layout.sample_stride();
+    let supported = layout.bits_per_sample >= 8 && !layout.data_type.is_complex();
+    match layout.predictor {
+        Predictor::None => {}
+        // Each sample is stored as the difference to the same band of the previous pixel
+        Predictor::Horizontal if supported => {
+            let read = |bytes: &[u8]| {
+                let mut value = [0; 8];
+                match layout.endianness {
+                    Endianness::LittleEndian => {
+                        value[..size].copy_from_slice(bytes);
+                        u64::from_le_bytes(value)
+                    }
+                    Endianness::BigEndian => {
+                        value[8 - size..].copy_from_slice(bytes);
+                        u64::from_be_bytes(value)
+                    }
+                }
+            };
+            for row in buf.chunks_exact_mut(samples_per_row * size) {
+                if size == 1 {
+                    for i in stride..row.len() {
+                        row[i] = row[i].wrapping_add(row[i - stride]);
+                    }
+                    continue;
+                }
+                for i in stride..samples_per_row {
+                    let previous = read(&row[(i - stride) * size..][..size]);
+                    let sample = &mut row[i * size..][..size];
+                    let value = read(sample).wrapping_add(previous);
+                    match layout.endianness {
+                        Endianness::LittleEndian => {
+                            sample.copy_from_slice(&value.to_le_bytes()[..size])
+                        }
+                        Endianness::BigEndian => {
+                            sample.copy_from_slice(&value.to_be_bytes()[8 - size..])
+                        }
+                    }
+                }
+            }
+        }
+        // The bytes of each row are split into planes from the most to the least significant
+        // byte of each sample, and each byte is stored as the difference to the previous pixel's
+        Predictor::FloatingPoint if supported => {
+            let mut planes = vec![0; samples_per_row * size];
+            for row in buf.chunks_exact_mut(samples_per_row * size) {
+                for i in stride..row.len() {
+                    row[i] = row[i].wrapping_add(row[i - stride]);
+                }
+                planes.copy_from_slice(row);
+                for sample in 0..samples_per_row {
+                    for byte in 0..size {
+                        let idx = match layout.endianness {
+                            Endianness::BigEndian => byte,
+                            Endianness::LittleEndian => size - 1 - byte,
+                        };
+                        row[sample * size + idx] = planes[byte * samples_per_row + sample];
+                    }
+                }
+            }
+        }
+        predictor => {
+            return Err(AiocogeoError::General(format!(
+                "predictor {predictor:?} is not supported for {}-bit {:?} samples",
+                layout.bits_per_sample, layout.data_type
+            )))
+        }
+    }
+    Ok(())
+}
+
+/// Interpret sample bytes as values of type `T` and reshape them to `(bands, height, width)`
+fn to_array<T: FromBytes + Clone>(data: &[u8], layout: &TileLayout) -> Result<Array3<T>> {
+    let values = data
+        .chunks_exact(std::mem::size_of::<T>())
+        .map(|chunk| T::from_bytes(chunk, layout.endianness))
+        .collect();
+    let arr = Array3::from_shape_vec(layout.storage_shape(), values)
+        .map_err(|err| AiocogeoError::General(err.to_string()))?;
+    match layout.planar_configuration {
+        PlanarConfiguration::Chunky => Ok(arr
+            .permuted_axes([2, 0, 1])
+            .as_standard_layout()
+            .into_owned()),
+        _ => Ok(arr),
+    }
+}
+
+/// Clear the bits of unsigned integer samples above their `nbits` significant bits.
+///
+/// GDAL stores `NBITS` data right-justified, e.g. 12-bit values in `0..4096` in a `u16`, so the
+/// significant bits are kept where they are and any bits above them are dropped.
+pub(crate) fn normalize_nbits(arr: &mut RasterArray, nbits: u16) {
+    macro_rules! mask {
+        ($arr:expr, $typ:ty) => {{
+            if (nbits as u32) < <$typ>::BITS {
+                let mask = (1 << nbits) - 1;
+                $arr.mapv_inplace(|v: $typ| v & mask);
+            }
+        }};
+    }
+
+    match arr {
+        RasterArray::Uint8(arr) => mask!(arr, u8),
+        RasterArray::Uint16(arr) => mask!(arr, u16),
+        RasterArray::Uint32(arr) => mask!(arr, u32),
+        RasterArray::Uint64(arr) => mask!(arr, u64),
+        _ => {}
+    }
+}
+
+/// Convert a tile to floating point and apply `value * scale + offset` per band
+pub(crate) fn apply_scale_offset(
+    tile: RasterArray,
+    scales: &[f64],
+    offsets: &[f64],
+) -> Result<RasterArray> {
+    macro_rules! scaled {
+        ($arr:expr, $out:ty) => {{
+            let mut out = $arr.mapv(|v| v as $out);
+            for (band, mut values) in out.axis_iter_mut(Axis(0)).enumerate() {
+                let scale = scales.get(band).copied().unwrap_or(1.0) as $out;
+                let offset = offsets.get(band).copied().unwrap_or(0.0) as $out;
+                values.mapv_inplace(|v| v * scale + offset);
+            }
+            RasterArray::from(out)
+        }};
+    }
+
+    Ok(match tile {
+        RasterArray::Uint8(arr) => scaled!(arr, f32),
+        RasterArray::Uint16(arr) => scaled!(arr, f32),
+        RasterArray::Int8(arr) => scaled!(arr, f32),
+        RasterArray::Int16(arr) => scaled!(arr, f32),
+        RasterArray::Float32(arr) => scaled!(arr, f32),
+        RasterArray::Uint32(arr) => scaled!(arr, f64),
+        RasterArray::Uint64(arr) => scaled!(arr, f64),
+        RasterArray::Int32(arr) => scaled!(arr, f64),
+        RasterArray::Int64(arr) => scaled!(arr, f64),
+        RasterArray::Float64(arr) => scaled!(arr, f64),
+        tile => {
+            return Err(AiocogeoError::General(format!(
+                "cannot apply scale and offset to {:?} data",
+                tile.dtype()
+            )))
+        }
+    })
+}
+
+/// Flip and/or transpose a stored tile so that it is in visual orientation
+pub(crate) fn apply_orientation(tile: RasterArray, orientation: Orientation) -> RasterArray {
+    map_array!(tile, arr => {
+        let mut view = arr.view();
+        if orientation.flips_rows() {
+            view.invert_axis(Axis(1));
+        }
+        if orientation.flips_columns() {
+            view.invert_axis(Axis(2));
+        }
+        if orientation.transposes() {
+            view.swap_axes(1, 2);
+        }
+        view.as_standard_layout().into_owned()
+    })
+}
+
+/// Reorient a `(height, width)` mask as [`apply_orientation`] does tiles
+pub(crate) fn apply_mask_orientation(mask: Array2<bool>, orientation: Orientation) -> Array2<bool> {
+    let mut view = mask.view();
+    if orientation.flips_rows() {
+        view.invert_axis(Axis(0));
+    }
+    if orientation.flips_columns() {
+        view.invert_axis(Axis(1));
+    }
+    if orientation.transposes() {
+        view.swap_axes(0, 1);
+    }
+    view.as_standard_layout().into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unpack_1bit_with_row_padding() {
+        // Two rows of 3 samples each; every row is padded out to a full byte
+        let data = [0b1010_0000, 0b0110_0000];
+        let out = unpack_bits(&data, 1, 3, 2, FillOrder::MsbToLsb).unwrap();
+        assert_eq!(out, [1, 0, 1, 0, 1, 1]);
+    }
+
+    #[test]
+    fn unpack_mask_with_row_padding() {
+        let data = [0b1010_0000, 0b0110_0000];
+        let mask = unpack_mask(&data, 3, 2, FillOrder::MsbToLsb).unwrap();
+        let expected = [[true, false, true], [false, true, true]];
+        assert_eq!(mask, ndarray::arr2(&expected));
+
+        let data = [0b0000_0101, 0b0000_0110];
+        let mask = unpack_mask(&data, 3, 2, FillOrder::LsbToMsb).unwrap();
+        assert_eq!(mask, ndarray::arr2(&expected));
+        assert!(unpack_mask(&data, 9, 2, FillOrder::MsbToLsb).is_err());
+    }
+
+    #[test]
+    fn unpack_2bit() {
+        let data = [0b00_01_10_11];
+        let out = unpack_bits(&data, 2, 4, 1, FillOrder::MsbToLsb).unwrap();
+        assert_eq!(out, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn decode_int16_big_endian() {
+        let layout = TileLayout {
+            width: 2,
+            height: 1,
+            bands: 1,
+            bits_per_sample: 16,
+            data_type: DataType::Int16,
+            planar_configuration: PlanarConfiguration::Chunky,
+            fill_order: FillOrder::MsbToLsb,
+            endianness: Endianness::BigEndian,
+            predictor: Predictor::None,
+        };
+        let out = decode_tile(vec![vec![0xFF, 0xFE, 0x00, 0x02]], &layout, None).unwrap();
+        let RasterArray::Int16(arr) = out else {
+            panic!("expected int16 output")
+        };
+        assert_eq!(arr.into_raw_vec_and_offset().0, [-2, 2]);
+    }
+
+    #[test]
+    fn decode_predictors() {
+        let mut layout = TileLayout {
+            width: 3,
+            height: 1,
+            bands: 1,
+            bits_per_sample: 16,
+            data_type: DataType::Uint16,
+            planar_configuration: PlanarConfiguration::Chunky,
+            fill_order: FillOrder::MsbToLsb,
+            endianness: Endianness::BigEndian,
+            predictor: Predictor::Horizontal,
+        };
+        let out = decode_tile(vec![vec![0, 1, 0, 1, 0xFF, 0xFF]], &layout, None).unwrap();
+        let RasterArray::Uint16(arr) = out else {
+            panic!("expected uint16 output")
+        };
+        assert_eq!(arr.into_raw_vec_and_offset().0, [1, 2, 1]);
+
+        // 1.0 and 2.0 are 0x3F800000 and 0x40000000, split into byte planes and differenced
+        layout.width = 2;
+        layout.bits_per_sample = 32;
+        layout.data_type = DataType::Float32;
+        layout.endianness = Endianness::LittleEndian;
+        layout.predictor = Predictor::FloatingPoint;
+        let data = vec![0x3F, 0x01, 0x40, 0x80, 0, 0, 0, 0];
+        let out = decode_tile(vec![data], &layout, None).unwrap();
+        let RasterArray::Float32(arr) = out else {
+            panic!("expected float32 output")
+        };
+        assert_eq!(arr.into_raw_vec_and_offset().0, [1.0, 2.0]);
+    }
+
+    #[test]
+    fn decode_float64_planar() {
+        let layout = TileLayout {
+            width: 1,
+            height: 1,
+            bands: 2,
+            bits_per_sample: 64,
+            data_type: DataType::Float64,
+            planar_configuration: PlanarConfiguration::Planar,
+            fill_order: FillOrder::MsbToLsb,
+            endianness: Endianness::LittleEndian,
+            predictor: Predictor::None,
+        };
+        let buffers = vec![
+            1.5f64.to_le_bytes().to_vec(),
+            (-0.25f64).to_le_bytes().to_vec(),
+        ];
+        let out = decode_tile(buffers, &layout, None).unwrap();
+        assert_eq!(out.shape(), (2, 1, 1));
+        let RasterArray::Float64(arr) = out else {
+            panic!("expected float64 output")
+        };
+        assert_eq!(arr[[0, 0, 0]], 1.5);
+        assert_eq!(arr[[1, 0, 0]], -0.25);
+    }
+
+    #[test]
+    fn decode_cint16() {
+        let layout = TileLayout {
+            width: 1,
+            height: 1,
+            bands: 1,
+            bits_per_sample: 32,
+            data_type: DataType::CInt16,
+            planar_configuration: PlanarConfiguration::Chunky,
+            fill_order: FillOrder::MsbToLsb,
+            endianness: Endianness::LittleEndian,
+            predictor: Predictor::None,
+        };
+        let out = decode_tile(vec![vec![0x03, 0x00, 0xFC, 0xFF]], &layout, None).unwrap();
+        let RasterArray::CInt16(arr) = out else {
+            panic!("expected cint16 output")
+        };
+        assert_eq!(arr[[0, 0, 0]], Complex::new(3, -4));
+    }
+
+    #[test]
+    fn normalize_12bit_in_u16() {
+        // An uncompressed little endian tile of NBITS=12 data in UInt16, as GDAL writes it: the
+        // values are right-justified, here 0, 1, 2048 and 4095
+        let layout = TileLayout {
+            width: 4,
+            height: 1,
+            bands: 1,
+            bits_per_sample: 16,
+            data_type: DataType::Uint16,
+            planar_configuration: PlanarConfiguration::Chunky,
+            fill_order: FillOrder::MsbToLsb,
+            endianness: Endianness::LittleEndian,
+            predictor: Predictor::None,
+        };
+        let tile = vec![0x00, 0x00, 0x01, 0x00, 0x00, 0x08, 0xFF, 0x0F];
+        let mut arr = decode_tile(vec![tile], &layout, None).unwrap();
+        normalize_nbits(&mut arr, 12);
+        let expected = Array3::from_shape_vec((1, 1, 4), vec![0u16, 1, 2048, 4095]).unwrap();
+        assert_eq!(arr, RasterArray::Uint16(expected));
+
+        // Bits above NBITS are cleared rather than shifted into the significant bits
+        let mut arr = RasterArray::Uint16(Array3::from_elem((1, 1, 2), 0xFFF0));
+        normalize_nbits(&mut arr, 12);
+        assert_eq!(
+            arr,
+            RasterArray::Uint16(Array3::from_elem((1, 1, 2), 0x0FF0))
+        );
+    }
+
+    #[test]
+    fn scale_offset_per_band() {
+        let tile = RasterArray::Int16(Array3::from_elem((2, 1, 1), 100));
+        let out = apply_scale_offset(tile, &[0.5, 2.0], &[-1.0]).unwrap();
+        let RasterArray::Float32(arr) = out else {
+            panic!("expected float32 output")
+        };
+        assert_eq!(arr.into_raw_vec_and_offset().0, [49.0, 200.0]);
+    }
+
+    #[test]
+    fn orientation_right_top() {
+        // Stored rows are visual columns, read from right to left
+        let stored = Array3::from_shape_vec((1, 2, 3), vec![1u8, 2, 3, 4, 5, 6]).unwrap();
+        let out = apply_orientation(RasterArray::Uint8(stored), Orientation::RightTop);
+        let expected = Array3::from_shape_vec((1, 3, 2), vec![4u8, 1, 5, 2, 6, 3]).unwrap();
+        assert_eq!(out, RasterArray::Uint8(expected));
+    }
+
+    #[test]
+    fn unpack_4bit_lsb_fill_order() {
+        // With LSB-to-MSB fill order, the bits of each byte are reversed before unpacking
+        let data = [0b1000_0100];
+        let out = unpack_bits(&data, 4, 2, 1, FillOrder::LsbToMsb).unwrap();
+        assert_eq!(out, [2, 1]);
+    }
+}
@@ -0,0 +1,341 @@
+//! Compare the structure, metadata and tiles of two files, e.g. to validate that a re-processing
+//! pipeline only changed what it was meant to.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display};
+
+use tiff::decoder::ifd::Value;
+use tiff::tags::Tag;
+
+use crate::cog::COGReader;
+use crate::dump::{IfdKind, IfdStructure};
+use crate::error::Result;
+use crate::geo_key_directory::GeoKeyTag;
+use crate::ifd::parse_geo_keys;
+use crate::options::{DiffOptions, ParseMode};
+
+/// Tags compared separately: tile offsets and byte counts depend on where tiles were written and
+/// GeoKeys are compared key by key
+const SKIPPED_TAGS: [Tag; 5] = [
+    Tag::TileOffsets,
+    Tag::TileByteCounts,
+    Tag::GeoKeyDirectoryTag,
+    Tag::GeoAsciiParamsTag,
+    Tag::GeoDoubleParamsTag,
+];
+
+/// A difference between two files, as returned by [`COGReader::diff`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    /// What differs, e.g. `subdataset 0 overview 1: ImageWidth`
+    pub location: String,
+    /// The value in the first file, or `None` if it's missing there
+    pub left: Option<String>,
+    /// The value in the second file, or `None` if it's missing there
+    pub right: Option<String>,
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let missing = "missing".to_string();
+        write!(
+            f,
+            "{}: {} != {}",
+            self.location,
+            self.left.as_ref().unwrap_or(&missing),
+            self.right.as_ref().unwrap_or(&missing)
+        )
+    }
+}
+
+/// Collects differences between two files
+#[derive(Default)]
+struct Differences(Vec<Difference>);
+
+impl Differences {
+    fn compare<T: PartialEq + fmt::Debug>(&mut self, location: impl Display, left: T, right: T) {
+        if left != right {
+            self.push(
+                location,
+                Some(format!("{left:?}")),
+                Some(format!("{right:?}")),
+            );
+        }
+    }
+
+    fn push(&mut self, location: impl Display, left: Option<String>, right: Option<String>) {
+        self.0.push(Difference {
+            location: location.to_string(),
+            left,
+            right,
+        });
+    }
+}
+
+/// The address of an IFD that is stable across files: its role rather than its offset
+type IfdKey = (usize, u8, usize);
+
+fn ifd_key(ifd: &IfdStructure) -> IfdKey {
+    let kind = match ifd.kind {
+        IfdKind::Image | IfdKind::Overview => 0,
+        IfdKind::Mask => 1,
+    };
+    (ifd.subdataset, kind, ifd.level)
+}
+
+fn ifd_name(ifd: &IfdStructure) -> String {
+    format!("subdataset {} {} {}", ifd.subdataset, ifd.kind, ifd.level)
+}
+
+/// Return the GeoKeys of an IFD, resolved from its raw tags
+fn geo_keys(ifd: &IfdStructure) -> BTreeMap<u16, Value> {
+    let tag = |tag: Tag| {
+        ifd.tags
+            .iter()
+            .find(|(t, _)| *t == tag)
+            .map(|(_, value)| value.clone())
+    };
+    let Some(Ok(directory)) = tag(Tag::GeoKeyDirectoryTag).map(Value::into_u16_vec) else {
+        return BTreeMap::new();
+    };
+    let ascii = tag(Tag::GeoAsciiParamsTag).and_then(|value| value.into_string().ok());
+    let doubles = tag(Tag::GeoDoubleParamsTag).and_then(|value| value.into_f64_vec().ok());
+    parse_geo_keys(
+        &directory,
+        ascii.as_deref(),
+        doubles.as_deref(),
+        ParseMode::Lenient,
+    )
+    .unwrap_or_default()
+    .into_iter()
+    .map(|(key, value)| (u16::from(key), value))
+    .collect()
+}
+
+/// Return the value of a tag of an IFD, if present
+fn tag_value(ifd: &IfdStructure, tag: Tag) -> Option<&Value> {
+    ifd.tags.iter().find(|(t, _)| *t == tag).map(|(_, v)| v)
+}
+
+/// Compare two files, as documented on [`COGReader::diff`]
+pub(crate) async fn diff(
+    left: &COGReader,
+    right: &COGReader,
+    options: &DiffOptions,
+) -> Result<Vec<Difference>> {
+    let mut differences = Differences::default();
+    let (left_structure, right_structure) = (left.dump_structure(), right.dump_structure());
+    differences.compare(
+        "byte order",
+        byte_order(left_structure.big_endian),
+        byte_order(right_structure.big_endian),
+    );
+
+    let left_ifds: BTreeMap<_, _> = left_structure
+        .ifds
+        .iter()
+        .map(|i| (ifd_key(i), i))
+        .collect();
+    let right_ifds: BTreeMap<_, _> = right_structure
+        .ifds
+        .iter()
+        .map(|i| (ifd_key(i), i))
+        .collect();
+    let keys: BTreeSet<_> = left_ifds.keys().chain(right_ifds.keys()).collect();
+    for key in keys {
+        let (l, r) = match (left_ifds.get(key), right_ifds.get(key)) {
+            (Some(l), Some(r)) => (*l, *r),
+            (Some(l), None) => {
+                differences.push(ifd_name(l), Some("present".to_string()), None);
+                continue;
+            }
+            (None, Some(r)) => {
+                differences.push(ifd_name(r), None, Some("present".to_string()));
+                continue;
+            }
+            (None, None) => unreachable!(),
+        };
+        let name = ifd_name(l);
+        differences.compare(format!("{name}: size"), l.size, r.size);
+        differences.compare(format!("{name}: tile size"), l.tile_size, r.tile_size);
+        differences.compare(format!("{name}: tile count"), l.tile_count, r.tile_count);
+        differences.compare(
+            format!("{name}: sparse tiles"),
+            l.sparse_tiles,
+            r.sparse_tiles,
+        );
+
+        let tags: BTreeSet<_> = l
+            .tags
+            .iter()
+            .chain(&r.tags)
+            .map(|(tag, _)| tag.to_u16())
+            .collect();
+        for code in tags {
+            let tag = Tag::from_u16_exhaustive(code);
+            if SKIPPED_TAGS.contains(&tag) || options.ignore_tags.contains(&tag) {
+                continue;
+            }
+            let location = match tag {
+                Tag::Unknown(_) => format!("{name}: tag {code}"),
+                tag => format!("{name}: {tag:?}"),
+            };
+            let (lv, rv) = (tag_value(l, tag), tag_value(r, tag));
+            if lv != rv {
+                let show = |value: Option<&Value>| value.map(|v| format!("{v:?}"));
+                differences.push(location, show(lv), show(rv));
+            }
+        }
+
+        let (left_keys, right_keys) = (geo_keys(l), geo_keys(r));
+        let codes: BTreeSet<_> = left_keys.keys().chain(right_keys.keys()).collect();
+        for code in codes {
+            let (lv, rv) = (left_keys.get(code), right_keys.get(code));
+            if lv != rv {
+                let key = GeoKeyTag::try_from(*code).map_or(code.to_string(), |k| format!("{k:?}"));
+                let show = |value: Option<&Value>| value.map(|v| format!("{v:?}"));
+                differences.push(format!("{name}: GeoKey {key}"), show(lv), show(rv));
+            }
+        }
+
+        let same_encoding = [Tag::Compression, Tag::Predictor]
+            .into_iter()
+            .all(|tag| tag_value(l, tag) == tag_value(r, tag));
+        let same_layout = (l.tile_count, l.planes) == (r.tile_count, r.planes);
+        if options.compare_tiles && !(same_encoding && same_layout) {
+            // Every stored tile differs, so report that the tiles weren't compared rather than
+            // nothing, which would read as identical tiles
+            differences.push(
+                format!("{name}: tiles not compared"),
+                Some(tile_encoding(l)),
+                Some(tile_encoding(r)),
+            );
+        } else if options.compare_tiles {
+            let left_checksums = left.tile_checksums(l, &options.read_options).await?;
+            let right_checksums = right.tile_checksums(r, &options.read_options).await?;
+            let (x_count, y_count) = l.tile_count;
+            for (i, (lc, rc)) in left_checksums.iter().zip(&right_checksums).enumerate() {
+                if lc != rc {
+                    let (plane, index) = (i / (x_count * y_count), i % (x_count * y_count));
+                    let (x, y) = (index % x_count, index / x_count);
+                    let mut location = format!("{name}: tile ({x}, {y})");
+                    if l.planes > 1 {
+                        location.push_str(&format!(" of band {plane}"));
+                    }
+                    let show = |checksum: &Option<u32>| {
+                        Some(checksum.map_or("sparse".to_string(), |c| format!("crc32 {c:08x}")))
+                    };
+                    differences.push(location, show(lc), show(rc));
+                }
+            }
+        }
+    }
+    Ok(differences.0)
+}
+
+/// Describe what makes the stored bytes of tiles comparable
+fn tile_encoding(ifd: &IfdStructure) -> String {
+    let show = |tag: Tag| tag_value(ifd, tag).map_or("none".to_string(), |v| format!("{v:?}"));
+    format!(
+        "compression {}, predictor {}, {:?} tiles, {} planes",
+        show(Tag::Compression),
+        show(Tag::Predictor),
+        ifd.tile_count,
+        ifd.planes
+    )
+}
+
+fn byte_order(big_endian: bool) -> &'static str {
+    if big_endian {
+        "MM"
+    } else {
+        "II"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::CogBuilder;
+    use tiff::tags::CompressionMethod;
+
+    #[tokio::test]
+    async fn diff() {
+        let builder = CogBuilder {
+            origin: Some((0.0, 0.0, 1.0)),
+            epsg: Some(3857),
+            overviews: vec![2],
+            ..Default::default()
+        };
+        let (reader, _) = builder.open().await.unwrap();
+        let options = DiffOptions {
+            compare_tiles: true,
+            ..Default::default()
+        };
+        assert!(reader.diff(&reader, &options).await.unwrap().is_empty());
+
+        // Moving tiles around by adding a mask changes offsets but not tile checksums
+        let (masked, _) = CogBuilder {
+            mask: true,
+            ..builder.clone()
+        }
+        .open()
+        .await
+        .unwrap();
+        let differences = reader.diff(&masked, &options).await.unwrap();
+        let locations: Vec<_> = differences.iter().map(|d| d.location.as_str()).collect();
+        assert_eq!(locations, ["subdataset 0 mask 0", "subdataset 0 mask 1"]);
+        assert_eq!(differences[0].left, None);
+
+        let (other, _) = CogBuilder {
+            epsg: Some(32631),
+            width: 62,
+            overviews: vec![],
+            ..builder.clone()
+        }
+        .open()
+        .await
+        .unwrap();
+        let differences = reader.diff(&other, &options).await.unwrap();
+        let text: Vec<_> = differences.iter().map(|d| d.to_string()).collect();
+        assert!(text.contains(&"subdataset 0 image 0: size: (64, 48) != (62, 48)".to_string()));
+        assert!(text.contains(
+            &"subdataset 0 image 0: ImageWidth: Unsigned(64) != Unsigned(62)".to_string()
+        ));
+        assert!(text.contains(
+            &"subdataset 0 image 0: GeoKey ProjectedType: Short(3857) != Short(32631)".to_string()
+        ));
+        assert!(text.contains(&"subdataset 0 overview 1: present != missing".to_string()));
+        // Every tile differs as rows are shorter
+        assert!(text
+            .iter()
+            .any(|t| t.starts_with("subdataset 0 image 0: tile (0, 0): crc32")));
+
+        let (deflate, _) = CogBuilder {
+            compression: CompressionMethod::Deflate,
+            ..builder
+        }
+        .open()
+        .await
+        .unwrap();
+        let options = DiffOptions {
+            ignore_tags: vec![Tag::Compression],
+            ..options
+        };
+        // Tiles are reported as not compared rather than silently skipped
+        let differences = reader.diff(&deflate, &options).await.unwrap();
+        let locations: Vec<_> = differences.iter().map(|d| d.location.as_str()).collect();
+        assert_eq!(
+            locations,
+            [
+                "subdataset 0 image 0: tiles not compared",
+                "subdataset 0 overview 1: tiles not compared"
+            ]
+        );
+        assert_eq!(
+            differences[0].to_string(),
+            "subdataset 0 image 0: tiles not compared: \
+             compression Short(1), predictor none, (2, 2) tiles, 1 planes != \
+             compression Short(8), predictor none, (2, 2) tiles, 1 planes"
+        );
+    }
+}
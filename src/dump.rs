@@ -0,0 +1,379 @@
+//! A listing of the IFDs and tags of a file, like `tiffdump`, to debug malformed files.
+use std::fmt::{self, Display, Write};
+
+use tiff::decoder::ifd::Value;
+use tiff::tags::Tag;
+
+use crate::cursor::Endianness;
+use crate::ifd::ImageFileDirectory;
+
+/// The number of values of a tag shown by [`Display`] before the rest are elided
+const DISPLAY_VALUES: usize = 16;
+
+/// The role of an IFD within a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IfdKind {
+    /// A full resolution image
+    Image,
+    /// A reduced resolution copy of an image
+    Overview,
+    /// A transparency mask of an image or overview
+    Mask,
+}
+
+impl Display for IfdKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Image => "image",
+            Self::Overview => "overview",
+            Self::Mask => "mask",
+        })
+    }
+}
+
+/// The structure of a single IFD, as listed by [`FileStructure`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct IfdStructure {
+    /// The byte offset of the IFD within the file
+    pub offset: u64,
+    /// The byte offset of the next IFD in the chain, if any
+    pub next_ifd_offset: Option<u64>,
+    /// How the reader uses the IFD
+    pub kind: IfdKind,
+    /// The index of the image the IFD belongs to, as passed to [`crate::COGReader::subdataset`]
+    pub subdataset: usize,
+    /// The overview level of the IFD within its image, where level 0 is the full resolution
+    pub level: usize,
+    /// The `(width, height)` of the image in pixels
+    pub size: (u32, u32),
+    /// The `(width, height)` of the tiles in pixels
+    pub tile_size: (u32, u32),
+    /// The number of `(x, y)` tiles
+    pub tile_count: (usize, usize),
+    /// The number of tiles stored for each position: 1, or the number of bands if bands are
+    /// stored in separate tiles
+    pub planes: usize,
+    /// The number of tiles that are not stored, which read as nodata
+    pub sparse_tiles: usize,
+    /// Every tag of the IFD with its raw value, sorted by tag code
+    pub tags: Vec<(Tag, Value)>,
+}
+
+/// The structure of a file: its byte order and the layout and tags of every IFD, returned by
+/// [`crate::COGReader::dump_structure`].
+///
+/// [`Display`] formats a human-readable listing like `tiffdump`, and [`FileStructure::to_json`] a
+/// JSON document to attach to bug reports.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileStructure {
+    /// Whether the file is big endian (`MM`) rather than little endian (`II`)
+    pub big_endian: bool,
+    /// The size of the file in bytes, if known
+    pub size: Option<u64>,
+    /// Every IFD of the file, sorted by offset
+    pub ifds: Vec<IfdStructure>,
+}
+
+impl IfdStructure {
+    pub(crate) fn new(
+        ifd: &ImageFileDirectory,
+        kind: IfdKind,
+        subdataset: usize,
+        level: usize,
+    ) -> Self {
+        let (x_count, y_count) = ifd.tile_count();
+        let sparse_tiles = ifd
+            .tile_offsets
+            .iter()
+            .zip(&ifd.tile_byte_counts)
+            .filter(|(&offset, &count)| offset == 0 || count == 0)
+            .count();
+        Self {
+            offset: ifd.offset as u64,
+            next_ifd_offset: ifd.next_ifd_offset.map(|offset| offset as u64),
+            kind,
+            subdataset,
+            level,
+            size: (ifd.width(), ifd.height()),
+            tile_size: ifd.tile_size(),
+            tile_count: (x_count, y_count),
+            planes: ifd.tile_offsets.len() / (x_count * y_count).max(1),
+            sparse_tiles,
+            tags: ifd
+                .tags()
+                .into_iter()
+                .map(|(tag, value)| (tag, value.clone()))
+                .collect(),
+        }
+    }
+}
+
+impl FileStructure {
+    pub(crate) fn new(endianness: Endianness, size: Option<u64>, ifds: Vec<IfdStructure>) -> Self {
+        let mut ifds = ifds;
+        ifds.sort_by_key(|ifd| ifd.offset);
+        Self {
+            big_endian: matches!(endianness, Endianness::BigEndian),
+            size,
+            ifds,
+        }
+    }
+
+    /// Format the structure as a JSON document, with every value of every tag
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        let size = self
+            .size
+            .map_or("null".to_string(), |size| size.to_string());
+        write!(
+            out,
+            r#"{{"byte_order":"{}","size":{size},"ifds":["#,
+            if self.big_endian { "MM" } else { "II" }
+        )
+        .unwrap();
+        for (i, ifd) in self.ifds.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let next = ifd
+                .next_ifd_offset
+                .map_or("null".to_string(), |offset| offset.to_string());
+            write!(
+                out,
+                r#"{{"offset":{},"next_ifd_offset":{next},"kind":"{}","subdataset":{},"level":{},"#,
+                ifd.offset, ifd.kind, ifd.subdataset, ifd.level
+            )
+            .unwrap();
+            write!(
+                out,
+                r#""width":{},"height":{},"tile_width":{},"tile_height":{},"tiles_across":{},"tiles_down":{},"planes":{},"sparse_tiles":{},"tags":["#,
+                ifd.size.0,
+                ifd.size.1,
+                ifd.tile_size.0,
+                ifd.tile_size.1,
+                ifd.tile_count.0,
+                ifd.tile_count.1,
+                ifd.planes,
+                ifd.sparse_tiles
+            )
+            .unwrap();
+            for (j, (tag, value)) in ifd.tags.iter().enumerate() {
+                if j > 0 {
+                    out.push(',');
+                }
+                write!(
+                    out,
+                    r#"{{"code":{},"name":{},"value":"#,
+                    tag.to_u16(),
+                    json_string(&tag_name(*tag))
+                )
+                .unwrap();
+                write_json_value(&mut out, value);
+                out.push('}');
+            }
+            out.push_str("]}");
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+impl Display for FileStructure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let byte_order = if self.big_endian {
+            "Big endian (MM)"
+        } else {
+            "Little endian (II)"
+        };
+        write!(f, "{byte_order} TIFF")?;
+        if let Some(size) = self.size {
+            write!(f, ", {size} bytes")?;
+        }
+        writeln!(f, ", {} IFDs", self.ifds.len())?;
+        for ifd in &self.ifds {
+            write!(
+                f,
+                "\nIFD at byte {}: level {} {} of subdataset {}",
+                ifd.offset, ifd.level, ifd.kind, ifd.subdataset
+            )?;
+            match ifd.next_ifd_offset {
+                Some(next) => writeln!(f, ", next IFD at byte {next}")?,
+                None => writeln!(f, ", last IFD")?,
+            }
+            write!(
+                f,
+                "  {}x{} pixels in {}x{} tiles of {}x{}",
+                ifd.size.0,
+                ifd.size.1,
+                ifd.tile_count.0,
+                ifd.tile_count.1,
+                ifd.tile_size.0,
+                ifd.tile_size.1
+            )?;
+            if ifd.planes > 1 {
+                write!(f, " for each of {} bands", ifd.planes)?;
+            }
+            if ifd.sparse_tiles > 0 {
+                write!(f, ", {} sparse", ifd.sparse_tiles)?;
+            }
+            writeln!(f)?;
+            for (tag, value) in &ifd.tags {
+                writeln!(
+                    f,
+                    "  {} ({}): {}",
+                    tag_name(*tag),
+                    tag.to_u16(),
+                    display_value(value)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The name of a tag, or `Unknown` for private tags
+fn tag_name(tag: Tag) -> String {
+    match tag {
+        Tag::Unknown(_) => "Unknown".to_string(),
+        tag => format!("{tag:?}"),
+    }
+}
+
+/// Format a value on one line, eliding all but the first values of long lists
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::List(values) => {
+            let mut shown: Vec<_> = values
+                .iter()
+                .take(DISPLAY_VALUES)
+                .map(display_value)
+                .collect();
+            if values.len() > DISPLAY_VALUES {
+                shown.push(format!("... ({} values)", values.len()));
+            }
+            format!("[{}]", shown.join(", "))
+        }
+        Value::Ascii(s) => format!("{s:?}"),
+        Value::Rational(n, d) => format!("{n}/{d}"),
+        Value::RationalBig(n, d) => format!("{n}/{d}"),
+        Value::SRational(n, d) => format!("{n}/{d}"),
+        Value::SRationalBig(n, d) => format!("{n}/{d}"),
+        value => scalar(value),
+    }
+}
+
+/// Format a numeric value
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::Byte(v) => v.to_string(),
+        Value::Short(v) => v.to_string(),
+        Value::Signed(v) => v.to_string(),
+        Value::SignedBig(v) => v.to_string(),
+        Value::Unsigned(v) | Value::Ifd(v) => v.to_string(),
+        Value::UnsignedBig(v) | Value::IfdBig(v) => v.to_string(),
+        Value::Float(v) => v.to_string(),
+        Value::Double(v) => v.to_string(),
+        value => format!("{value:?}"),
+    }
+}
+
+/// Write a value as JSON: numbers, strings, `[numerator, denominator]` pairs for rationals and
+/// arrays for lists. Non-finite floats are written as `null`.
+fn write_json_value(out: &mut String, value: &Value) {
+    match value {
+        Value::List(values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json_value(out, value);
+            }
+            out.push(']');
+        }
+        Value::Ascii(s) => out.push_str(&json_string(s)),
+        Value::Float(v) if !v.is_finite() => out.push_str("null"),
+        Value::Double(v) if !v.is_finite() => out.push_str("null"),
+        Value::Rational(..)
+        | Value::RationalBig(..)
+        | Value::SRational(..)
+        | Value::SRationalBig(..) => {
+            let pair = display_value(value).replace('/', ",");
+            write!(out, "[{pair}]").unwrap();
+        }
+        value => out.push_str(&scalar(value)),
+    }
+}
+
+/// Quote and escape a JSON string
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::CogBuilder;
+
+    #[tokio::test]
+    async fn dump_structure() {
+        let builder = CogBuilder {
+            mask: true,
+            overviews: vec![2],
+            ..Default::default()
+        };
+        let (reader, _) = builder.open().await.unwrap();
+        let structure = reader.dump_structure();
+        assert!(!structure.big_endian);
+        let kinds: Vec<_> = structure.ifds.iter().map(|ifd| ifd.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                IfdKind::Image,
+                IfdKind::Mask,
+                IfdKind::Overview,
+                IfdKind::Mask
+            ]
+        );
+        assert_eq!(structure.ifds[0].offset, 8);
+        assert_eq!(
+            structure.ifds[0].next_ifd_offset,
+            Some(structure.ifds[1].offset)
+        );
+        assert_eq!(structure.ifds[2].tile_count, (1, 1));
+        assert_eq!(structure.ifds[3].level, 1);
+
+        let text = structure.to_string();
+        assert!(text.starts_with("Little endian (II) TIFF"));
+        assert!(text.contains("  ImageWidth (256): 64\n"));
+        assert!(text.contains("64x48 pixels in 2x2 tiles of 32x32"));
+
+        let json = structure.to_json();
+        assert!(json.starts_with(r#"{"byte_order":"II","size":"#));
+        assert!(json.contains(r#"{"code":256,"name":"ImageWidth","value":64}"#));
+        assert!(json.contains(r#""kind":"mask","subdataset":0,"level":1,"#));
+    }
+
+    #[test]
+    fn json_values() {
+        let mut out = String::new();
+        let value = Value::List(vec![Value::Rational(1, 2), Value::Double(f64::NAN)]);
+        write_json_value(&mut out, &value);
+        assert_eq!(out, "[[1,2],null]");
+        assert_eq!(json_string("a\"b\n\u{1}"), r#""a\"b\n\u0001""#);
+    }
+}
@@ -1 +1,261 @@
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use tiff::tags::{PhotometricInterpretation, SampleFormat};
 
+/// The logical order of bits within a byte.
+///
+/// https://www.awaresystems.be/imaging/tiff/tifftags/fillorder.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, TryFromPrimitive, IntoPrimitive)]
+#[repr(u16)]
+pub enum FillOrder {
+    /// Pixels with lower column values are stored in the higher-order bits of the byte.
+    #[default]
+    MsbToLsb = 1,
+    /// Pixels with lower column values are stored in the lower-order bits of the byte.
+    LsbToMsb = 2,
+}
+
+/// The data type of a single sample, derived from the `BitsPerSample` and `SampleFormat` tags.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DataType {
+    Uint8,
+    Uint16,
+    Uint32,
+    Uint64,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Float32,
+    Float64,
+    /// Complex values with 16-bit signed integer real and imaginary parts
+    CInt16,
+    /// Complex values with 32-bit signed integer real and imaginary parts
+    CInt32,
+    /// Complex values with 32-bit float real and imaginary parts
+    CFloat32,
+    /// Complex values with 64-bit float real and imaginary parts
+    CFloat64,
+}
+
+impl DataType {
+    /// Construct a data type from the bits per sample and sample format of an image.
+    ///
+    /// Sub-byte unsigned samples (1, 2 or 4 bits) are unpacked to [`DataType::Uint8`] on decode.
+    pub(crate) fn from_tiff(bits_per_sample: u16, sample_format: SampleFormat) -> Option<Self> {
+        match (sample_format, bits_per_sample) {
+            (SampleFormat::Uint, 1 | 2 | 4 | 8) => Some(Self::Uint8),
+            (SampleFormat::Uint, 16) => Some(Self::Uint16),
+            (SampleFormat::Uint, 32) => Some(Self::Uint32),
+            (SampleFormat::Uint, 64) => Some(Self::Uint64),
+            (SampleFormat::Int, 8) => Some(Self::Int8),
+            (SampleFormat::Int, 16) => Some(Self::Int16),
+            (SampleFormat::Int, 32) => Some(Self::Int32),
+            (SampleFormat::Int, 64) => Some(Self::Int64),
+            (SampleFormat::IEEEFP, 32) => Some(Self::Float32),
+            (SampleFormat::IEEEFP, 64) => Some(Self::Float64),
+            // The tiff crate doesn't have variants for the complex sample formats. For these,
+            // bits per sample covers both the real and imaginary parts.
+            (SampleFormat::Unknown(5), 32) => Some(Self::CInt16),
+            (SampleFormat::Unknown(5), 64) => Some(Self::CInt32),
+            (SampleFormat::Unknown(6), 64) => Some(Self::CFloat32),
+            (SampleFormat::Unknown(6), 128) => Some(Self::CFloat64),
+            _ => None,
+        }
+    }
+
+    /// The size of a single sample of this data type in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Uint8 | Self::Int8 => 1,
+            Self::Uint16 | Self::Int16 => 2,
+            Self::Uint32 | Self::Int32 | Self::Float32 | Self::CInt16 => 4,
+            Self::Uint64 | Self::Int64 | Self::Float64 | Self::CInt32 | Self::CFloat32 => 8,
+            Self::CFloat64 => 16,
+        }
+    }
+
+    /// Returns true if this data type holds complex values
+    pub fn is_complex(&self) -> bool {
+        matches!(
+            self,
+            Self::CInt16 | Self::CInt32 | Self::CFloat32 | Self::CFloat64
+        )
+    }
+}
+
+/// The orientation of the image with respect to the rows and columns.
+///
+/// Each variant is named after the visual position of the 0th row and 0th column, e.g.
+/// [`Orientation::BottomLeft`] stores the 0th row at the visual bottom and the 0th column at the
+/// visual left.
+///
+/// https://www.awaresystems.be/imaging/tiff/tifftags/orientation.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, TryFromPrimitive, IntoPrimitive)]
+#[repr(u16)]
+pub enum Orientation {
+    #[default]
+    TopLeft = 1,
+    TopRight = 2,
+    BottomRight = 3,
+    BottomLeft = 4,
+    LeftTop = 5,
+    RightTop = 6,
+    RightBottom = 7,
+    LeftBottom = 8,
+}
+
+impl Orientation {
+    /// Returns true if stored rows become displayed columns
+    pub fn transposes(&self) -> bool {
+        matches!(
+            self,
+            Self::LeftTop | Self::RightTop | Self::RightBottom | Self::LeftBottom
+        )
+    }
+
+    /// Returns true if the stored columns are in reverse visual order
+    pub fn flips_columns(&self) -> bool {
+        matches!(
+            self,
+            Self::TopRight | Self::BottomRight | Self::RightBottom | Self::LeftBottom
+        )
+    }
+
+    /// Returns true if the stored rows are in reverse visual order
+    pub fn flips_rows(&self) -> bool {
+        matches!(
+            self,
+            Self::BottomRight | Self::BottomLeft | Self::RightTop | Self::RightBottom
+        )
+    }
+
+    /// Return the orientation that transposes and flips stored pixels as given, the inverse of
+    /// [`Self::transposes`], [`Self::flips_columns`] and [`Self::flips_rows`]
+    pub(crate) fn from_flags(transposes: bool, flips_columns: bool, flips_rows: bool) -> Self {
+        match (transposes, flips_columns, flips_rows) {
+            (false, false, false) => Self::TopLeft,
+            (false, true, false) => Self::TopRight,
+            (false, true, true) => Self::BottomRight,
+            (false, false, true) => Self::BottomLeft,
+            (true, false, false) => Self::LeftTop,
+            (true, false, true) => Self::RightTop,
+            (true, true, true) => Self::RightBottom,
+            (true, true, false) => Self::LeftBottom,
+        }
+    }
+}
+
+/// The order of the values of a multi-band array in memory
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interleave {
+    /// Band-sequential `(bands, height, width)` order, also called CHW: every pixel of the first
+    /// band, then every pixel of the second band, and so on
+    #[default]
+    Band,
+    /// Pixel-interleaved `(height, width, bands)` order, also called HWC: every band of the first
+    /// pixel, then every band of the second pixel, and so on
+    Pixel,
+}
+
+/// The color interpretation of a band, matching GDAL's `GDALColorInterp`.
+///
+/// https://gdal.org/api/raster_c_api.html#_CPPv415GDALColorInterp
+#[derive(Clone, Copy, Debug, PartialEq, Eq, TryFromPrimitive, IntoPrimitive)]
+#[repr(u16)]
+pub enum ColorInterp {
+    Undefined = 0,
+    Gray = 1,
+    Palette = 2,
+    Red = 3,
+    Green = 4,
+    Blue = 5,
+    Alpha = 6,
+    Hue = 7,
+    Saturation = 8,
+    Lightness = 9,
+    Cyan = 10,
+    Magenta = 11,
+    Yellow = 12,
+    Black = 13,
+    Y = 14,
+    Cb = 15,
+    Cr = 16,
+}
+
+impl ColorInterp {
+    /// Derive the color interpretation of each band from the `PhotometricInterpretation` and
+    /// `ExtraSamples` tags.
+    ///
+    /// Bands declared as extra samples are the last bands of the image; associated and
+    /// unassociated alpha are both reported as [`ColorInterp::Alpha`]. `decoded_as_rgb` should be
+    /// set for JPEG-compressed YCbCr images, which are converted to RGB on decode.
+    pub(crate) fn from_tiff(
+        photometric_interpretation: PhotometricInterpretation,
+        bands: usize,
+        extra_samples: &[u8],
+        decoded_as_rgb: bool,
+    ) -> Vec<Self> {
+        let color_bands: &[Self] = match photometric_interpretation {
+            PhotometricInterpretation::WhiteIsZero | PhotometricInterpretation::BlackIsZero => {
+                &[Self::Gray]
+            }
+            PhotometricInterpretation::RGBPalette => &[Self::Palette],
+            PhotometricInterpretation::RGB => &[Self::Red, Self::Green, Self::Blue],
+            PhotometricInterpretation::YCbCr if decoded_as_rgb => {
+                &[Self::Red, Self::Green, Self::Blue]
+            }
+            PhotometricInterpretation::YCbCr => &[Self::Y, Self::Cb, Self::Cr],
+            PhotometricInterpretation::CMYK => {
+                &[Self::Cyan, Self::Magenta, Self::Yellow, Self::Black]
+            }
+            _ => &[],
+        };
+
+        let first_extra = bands.saturating_sub(extra_samples.len());
+        (0..bands)
+            .map(|band| {
+                if band >= first_extra {
+                    match extra_samples[band - first_extra] {
+                        1 | 2 => Self::Alpha,
+                        _ => Self::Undefined,
+                    }
+                } else {
+                    color_bands.get(band).copied().unwrap_or(Self::Undefined)
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn color_interp_rgba() {
+        let interp = ColorInterp::from_tiff(PhotometricInterpretation::RGB, 4, &[2], false);
+        assert_eq!(
+            interp,
+            vec![
+                ColorInterp::Red,
+                ColorInterp::Green,
+                ColorInterp::Blue,
+                ColorInterp::Alpha
+            ]
+        );
+    }
+
+    #[test]
+    fn color_interp_gray_extra_bands() {
+        let interp =
+            ColorInterp::from_tiff(PhotometricInterpretation::BlackIsZero, 3, &[0, 0], false);
+        assert_eq!(
+            interp,
+            vec![
+                ColorInterp::Gray,
+                ColorInterp::Undefined,
+                ColorInterp::Undefined
+            ]
+        );
+    }
+}
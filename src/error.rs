@@ -1,5 +1,6 @@
 use std::fmt::Debug;
 use thiserror::Error;
+use tiff::tags::Tag;
 
 /// Enum with all errors in this crate.
 #[derive(Error, Debug)]
@@ -8,6 +9,80 @@ pub enum AiocogeoError {
     /// General error.
     #[error("General error: {0}")]
     General(String),
+
+    /// Error while decompressing a tile
+    #[error("Decompression error: {0}")]
+    Decompression(String),
+
+    /// The file doesn't start with a TIFF header, or is a TIFF variant that isn't supported
+    #[error("Invalid header: {0}")]
+    InvalidHeader(String),
+
+    /// A tag has a type or value that can't be read, e.g. a numeric tag holding text
+    #[error("Invalid tag {tag:?} at byte {offset}: {reason}")]
+    InvalidTag {
+        /// The tag
+        tag: Tag,
+        /// The byte offset of the tag's entry in its IFD
+        offset: u64,
+        /// What is wrong with the tag
+        reason: String,
+    },
+
+    /// The chain of IFDs loops back to the IFD at this byte offset, so following it would never
+    /// end
+    #[error("Cyclic IFD chain: the IFD at byte {0} is referenced twice")]
+    CyclicIfdChain(u64),
+
+    /// The file exceeds one of the [`Limits`](crate::Limits) it was opened with
+    #[error("Limit exceeded: {0}")]
+    LimitExceeded(String),
+
+    /// A read would return more bytes than [`ReadOptions::memory_limit`](crate::ReadOptions)
+    #[error(
+        "Memory limit exceeded: the read needs {required} bytes, more than the limit of {limit}"
+    )]
+    MemoryLimitExceeded {
+        /// The size in bytes of the array the read would return
+        required: usize,
+        /// The limit
+        limit: usize,
+    },
+
+    /// A tag needed to read the image is missing
+    #[error("Missing required tag {0:?}")]
+    MissingRequiredTag(Tag),
+
+    /// Tiles are compressed with a method that isn't supported, by `Compression` tag code
+    #[error("Unsupported compression {0}")]
+    UnsupportedCompression(u16),
+
+    /// IO Error.
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+
+    /// Error from [object_store]
+    #[error(transparent)]
+    ObjectStore(object_store::Error),
+
+    /// The file was modified after it was opened, so reading more of it could mix bytes from two
+    /// versions of the file
+    #[error("Source changed: {0} was modified after it was opened")]
+    SourceChanged(String),
+
+    /// Error from the [tiff] crate
+    #[error(transparent)]
+    Tiff(#[from] tiff::TiffError),
+}
+
+impl From<object_store::Error> for AiocogeoError {
+    fn from(value: object_store::Error) -> Self {
+        match value {
+            // Conditional requests are only made to pin the version of an opened file
+            object_store::Error::Precondition { path, .. } => Self::SourceChanged(path),
+            err => Self::ObjectStore(err),
+        }
+    }
 }
 
 /// Crate-specific result type.
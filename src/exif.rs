@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use tiff::decoder::ifd::Value;
+use tiff::tags::Tag;
+
+use crate::cursor::ObjectStoreCursor;
+use crate::error::Result;
+use crate::ifd::read_ifd_tags;
+use crate::options::Limits;
+
+const DATE_TIME_ORIGINAL: u16 = 36867;
+const OFFSET_TIME_ORIGINAL: u16 = 36881;
+
+/// The EXIF sub-IFD of an image, holding camera and capture metadata
+///
+/// https://www.awaresystems.be/imaging/tiff/tifftags/privateifd/exif.html
+#[derive(Debug, Clone)]
+pub struct ExifDirectory {
+    pub(crate) tags: HashMap<Tag, Value>,
+}
+
+impl ExifDirectory {
+    pub(crate) async fn read(
+        cursor: &mut ObjectStoreCursor,
+        offset: usize,
+        limits: &Limits,
+    ) -> Result<Self> {
+        let (tags, _next_ifd_offset) = read_ifd_tags(cursor, offset, limits).await?;
+        Ok(Self { tags: tags.values })
+    }
+
+    /// Return the raw value of the EXIF tag with the given numeric code
+    pub fn tag(&self, code: u16) -> Option<&Value> {
+        self.tags.get(&Tag::from_u16_exhaustive(code))
+    }
+
+    /// Return all EXIF tags with their raw values, sorted by tag code
+    pub fn tags(&self) -> Vec<(u16, &Value)> {
+        let mut tags: Vec<_> = self
+            .tags
+            .iter()
+            .map(|(tag, value)| (tag.to_u16(), value))
+            .collect();
+        tags.sort_by_key(|(code, _)| *code);
+        tags
+    }
+
+    /// Return the date and time the image was captured, as stored in `DateTimeOriginal`
+    pub fn date_time_original(&self) -> Option<String> {
+        self.tag(DATE_TIME_ORIGINAL)?.clone().into_string().ok()
+    }
+
+    /// Return the offset from UTC of `DateTimeOriginal`, as stored in `OffsetTimeOriginal`,
+    /// e.g. `+02:00`
+    pub fn offset_time_original(&self) -> Option<String> {
+        self.tag(OFFSET_TIME_ORIGINAL)?.clone().into_string().ok()
+    }
+}
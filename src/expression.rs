@@ -0,0 +1,253 @@
+use ndarray::{Array2, ArrayView3, Axis};
+
+use crate::error::{AiocogeoError, Result};
+
+/// A node of a parsed expression
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number(f64),
+    /// A zero-based band index
+    Band(usize),
+    Neg(Box<Node>),
+    Binary(Op, Box<Node>, Box<Node>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+/// A band math expression like `(b4 - b3) / (b4 + b3)`, evaluated per pixel.
+///
+/// Bands are referenced by one-based index as `b1`, `b2`, ..., as in rio-tiler. Expressions
+/// support numbers, `+`, `-`, `*`, `/`, `**` and parentheses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    root: Node,
+    bands: Vec<usize>,
+}
+
+impl Expression {
+    /// Parse an expression
+    pub fn parse(expression: &str) -> Result<Self> {
+        let mut parser = Parser {
+            input: expression,
+            pos: 0,
+            bands: vec![],
+        };
+        let root = parser.expr()?;
+        parser.skip_whitespace();
+        if parser.pos < expression.len() {
+            return Err(parser.error("unexpected input"));
+        }
+        let mut bands = parser.bands;
+        bands.sort_unstable();
+        bands.dedup();
+        Ok(Self { root, bands })
+    }
+
+    /// The zero-based indices of the bands referenced by the expression, in ascending order
+    pub fn bands(&self) -> &[usize] {
+        &self.bands
+    }
+
+    /// Evaluate the expression for every pixel of `values`, an array of shape
+    /// `(bands, height, width)` holding the bands returned by [`Expression::bands`] in that
+    /// order.
+    pub fn evaluate(&self, values: ArrayView3<f64>) -> Result<Array2<f64>> {
+        if values.len_of(Axis(0)) != self.bands.len() {
+            return Err(AiocogeoError::General(format!(
+                "expression references {} bands but {} were given",
+                self.bands.len(),
+                values.len_of(Axis(0))
+            )));
+        }
+        let (_, height, width) = values.dim();
+        Ok(self.evaluate_node(&self.root, &values, (height, width)))
+    }
+
+    fn evaluate_node(
+        &self,
+        node: &Node,
+        values: &ArrayView3<f64>,
+        shape: (usize, usize),
+    ) -> Array2<f64> {
+        match node {
+            Node::Number(value) => Array2::from_elem(shape, *value),
+            Node::Band(band) => {
+                // Every referenced band is in `self.bands`
+                let idx = self.bands.binary_search(band).unwrap();
+                values.index_axis(Axis(0), idx).to_owned()
+            }
+            Node::Neg(node) => -self.evaluate_node(node, values, shape),
+            Node::Binary(op, lhs, rhs) => {
+                let mut lhs = self.evaluate_node(lhs, values, shape);
+                let rhs = self.evaluate_node(rhs, values, shape);
+                lhs.zip_mut_with(&rhs, |l, &r| {
+                    *l = match op {
+                        Op::Add => *l + r,
+                        Op::Sub => *l - r,
+                        Op::Mul => *l * r,
+                        Op::Div => *l / r,
+                        Op::Pow => l.powf(r),
+                    }
+                });
+                lhs
+            }
+        }
+    }
+}
+
+/// A recursive descent parser over the expression grammar:
+///
+/// ```text
+/// expr  = term (("+" | "-") term)*
+/// term  = unary (("*" | "/") unary)*
+/// unary = "-" unary | power
+/// power = atom ("**" unary)?
+/// atom  = number | "b" digits | "(" expr ")"
+/// ```
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    bands: Vec<usize>,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> AiocogeoError {
+        AiocogeoError::General(format!(
+            "invalid expression {:?}: {message} at position {}",
+            self.input, self.pos
+        ))
+    }
+
+    fn rest(&self) -> &str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consume `token` if it comes next
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<Node> {
+        let mut node = self.term()?;
+        loop {
+            let op = if self.eat("+") {
+                Op::Add
+            } else if self.eat("-") {
+                Op::Sub
+            } else {
+                return Ok(node);
+            };
+            node = Node::Binary(op, Box::new(node), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Node> {
+        let mut node = self.unary()?;
+        loop {
+            let op = if self.eat("*") {
+                Op::Mul
+            } else if self.eat("/") {
+                Op::Div
+            } else {
+                return Ok(node);
+            };
+            node = Node::Binary(op, Box::new(node), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node> {
+        if self.eat("-") {
+            return Ok(Node::Neg(Box::new(self.unary()?)));
+        }
+        let base = self.atom()?;
+        if self.eat("**") {
+            // Right associative, and binding tighter than a leading minus as in Python
+            return Ok(Node::Binary(
+                Op::Pow,
+                Box::new(base),
+                Box::new(self.unary()?),
+            ));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Node> {
+        if self.eat("(") {
+            let node = self.expr()?;
+            if !self.eat(")") {
+                return Err(self.error("expected `)`"));
+            }
+            return Ok(node);
+        }
+
+        self.skip_whitespace();
+        let rest = self.rest();
+        if let Some(digits) = rest.strip_prefix('b') {
+            let len = digits.len()
+                - digits
+                    .trim_start_matches(|c: char| c.is_ascii_digit())
+                    .len();
+            let band = digits[..len]
+                .parse::<usize>()
+                .ok()
+                .filter(|&band| band > 0)
+                .ok_or_else(|| self.error("expected a band like `b1`"))?;
+            self.pos += 1 + len;
+            self.bands.push(band - 1);
+            return Ok(Node::Band(band - 1));
+        }
+
+        let len = rest.len()
+            - rest
+                .trim_start_matches(|c: char| c.is_ascii_digit() || c == '.')
+                .len();
+        let value = rest[..len]
+            .parse::<f64>()
+            .map_err(|_| self.error("expected a number, band or `(`"))?;
+        self.pos += len;
+        Ok(Node::Number(value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ndarray::Array3;
+
+    #[test]
+    fn parse_and_evaluate() {
+        let expression = Expression::parse("(b4 - b3) / (b4 + b3)").unwrap();
+        assert_eq!(expression.bands(), [2, 3]);
+
+        let values = Array3::from_shape_vec((2, 1, 2), vec![1.0, 0.0, 3.0, 0.0]).unwrap();
+        let out = expression.evaluate(values.view()).unwrap();
+        assert_eq!(out[[0, 0]], 0.5);
+        assert!(out[[0, 1]].is_nan());
+
+        let expression = Expression::parse("-b1 ** 2 + 2 * 3").unwrap();
+        let values = Array3::from_elem((1, 1, 1), 3.0);
+        assert_eq!(expression.evaluate(values.view()).unwrap()[[0, 0]], -3.0);
+
+        assert!(Expression::parse("b0 + 1").is_err());
+        assert!(Expression::parse("(b1 + 1").is_err());
+        assert!(Expression::parse("b1 +").is_err());
+    }
+}
@@ -0,0 +1,145 @@
+/// A single `<Item>` of GDAL metadata
+#[derive(Debug, Clone, PartialEq)]
+pub struct GdalMetadataItem {
+    /// The `name` attribute of the item
+    pub name: String,
+    /// The band the item applies to, or `None` for dataset-level items
+    pub sample: Option<usize>,
+    /// The `role` attribute of the item, e.g. `scale` or `offset`
+    pub role: Option<String>,
+    /// The metadata domain of the item, e.g. `IMAGE_STRUCTURE`. `None` is the default domain.
+    pub domain: Option<String>,
+    /// The unescaped text content of the item
+    pub value: String,
+}
+
+/// The parsed contents of the `GDAL_METADATA` tag (42112)
+///
+/// GDAL stores dataset and band metadata as a small XML document:
+///
+/// ```xml
+/// <GDALMetadata>
+///   <Item name="NBITS" domain="IMAGE_STRUCTURE">12</Item>
+///   <Item name="SCALE" sample="0" role="scale">0.01</Item>
+/// </GDALMetadata>
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GdalMetadata {
+    items: Vec<GdalMetadataItem>,
+}
+
+impl GdalMetadata {
+    /// Parse the XML string stored in the `GDAL_METADATA` tag.
+    ///
+    /// Malformed items are skipped rather than failing the whole document.
+    pub(crate) fn parse(xml: &str) -> Self {
+        let mut items = vec![];
+        let mut rest = xml;
+        while let Some(start) = rest.find("<Item") {
+            rest = &rest[start + "<Item".len()..];
+            let Some(tag_end) = rest.find('>') else {
+                break;
+            };
+            let attributes = &rest[..tag_end];
+
+            // Self-closing items have no value
+            if attributes.ends_with('/') {
+                rest = &rest[tag_end + 1..];
+                continue;
+            }
+
+            let content = &rest[tag_end + 1..];
+            let Some(content_end) = content.find("</Item>") else {
+                break;
+            };
+
+            if let Some(name) = attribute(attributes, "name") {
+                items.push(GdalMetadataItem {
+                    name,
+                    sample: attribute(attributes, "sample").and_then(|s| s.parse().ok()),
+                    role: attribute(attributes, "role"),
+                    domain: attribute(attributes, "domain"),
+                    value: unescape(&content[..content_end]),
+                });
+            }
+            rest = &content[content_end + "</Item>".len()..];
+        }
+        Self { items }
+    }
+
+    /// All items in the document
+    pub fn items(&self) -> &[GdalMetadataItem] {
+        &self.items
+    }
+
+    /// Find the value of the first item with the given name, band and domain.
+    pub fn get(&self, name: &str, sample: Option<usize>, domain: Option<&str>) -> Option<&str> {
+        self.items
+            .iter()
+            .find(|item| {
+                item.name == name && item.sample == sample && item.domain.as_deref() == domain
+            })
+            .map(|item| item.value.as_str())
+    }
+}
+
+/// Extract the value of an XML attribute from the inside of a start tag
+fn attribute(attributes: &str, key: &str) -> Option<String> {
+    let mut rest = attributes;
+    while let Some(idx) = rest.find(key) {
+        let preceded_by_space = rest[..idx]
+            .chars()
+            .next_back()
+            .is_none_or(char::is_whitespace);
+        let after = rest[idx + key.len()..].trim_start();
+        if preceded_by_space {
+            if let Some(after) = after.strip_prefix('=') {
+                let after = after.trim_start();
+                let quote = after.chars().next()?;
+                if quote == '"' || quote == '\'' {
+                    let value = &after[1..];
+                    let end = value.find(quote)?;
+                    return Some(unescape(&value[..end]));
+                }
+            }
+        }
+        rest = &rest[idx + key.len()..];
+    }
+    None
+}
+
+/// Replace the predefined XML entities
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_items() {
+        let xml = r#"<GDALMetadata>
+  <Item name="NBITS" domain="IMAGE_STRUCTURE">12</Item>
+  <Item name="SCALE" sample="0" role="scale">0.01</Item>
+  <Item name="DESCRIPTION" sample="1" role="description">Near &amp; far</Item>
+</GDALMetadata>"#;
+        let metadata = GdalMetadata::parse(xml);
+        assert_eq!(metadata.items().len(), 3);
+        assert_eq!(
+            metadata.get("NBITS", None, Some("IMAGE_STRUCTURE")),
+            Some("12")
+        );
+        assert_eq!(metadata.get("SCALE", Some(0), None), Some("0.01"));
+        assert_eq!(metadata.items()[1].role.as_deref(), Some("scale"));
+        assert_eq!(
+            metadata.get("DESCRIPTION", Some(1), None),
+            Some("Near & far")
+        );
+        assert_eq!(metadata.get("SCALE", Some(1), None), None);
+    }
+}
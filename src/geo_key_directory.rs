@@ -1,9 +1,13 @@
 use std::collections::HashMap;
+use std::fmt::{self, Display};
 
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 use tiff::decoder::ifd::Value;
 use tiff::{TiffError, TiffResult};
 
+use crate::citation::Citation;
+use crate::units::{AngularUnit, LinearUnit, Units, USER_DEFINED};
+
 #[derive(Clone, Copy, Debug, PartialEq, TryFromPrimitive, IntoPrimitive, Eq, Hash)]
 #[repr(u16)]
 pub enum GeoKeyTag {
@@ -61,6 +65,24 @@ pub enum GeoKeyTag {
     VerticalUnits = 4099,
 }
 
+/// A CRS made of a horizontal and a vertical CRS, by EPSG code, as GeoTIFFs with both
+/// `ProjectedCSTypeGeoKey` or `GeographicTypeGeoKey` and `VerticalGeoKey` define.
+///
+/// [`Display`] formats the CRS as GDAL and PROJ name compound CRSs, e.g. `EPSG:26918+5703`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CompoundCrs {
+    /// The EPSG code of the horizontal CRS
+    pub horizontal: u16,
+    /// The EPSG code of the vertical CRS
+    pub vertical: u16,
+}
+
+impl Display for CompoundCrs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EPSG:{}+{}", self.horizontal, self.vertical)
+    }
+}
+
 /// http://docs.opengeospatial.org/is/19-008r4/19-008r4.html#_requirements_class_geokeydirectorytag
 #[derive(Debug, Clone)]
 pub struct GeoKeyDirectory {
@@ -278,12 +300,495 @@ impl GeoKeyDirectory {
         })
     }
 
-    /// Return the EPSG code representing the crs of the image
+    /// Return the EPSG code of the horizontal CRS of the image, or `None` if it is user-defined.
+    /// See [`GeoKeyDirectory::compound_crs`] for its vertical CRS.
     pub fn epsg_code(&self) -> Option<u16> {
-        if let Some(projected_type) = self.projected_type {
-            Some(projected_type)
+        match self.projected_type {
+            // A user-defined projection of a known geographic CRS has no code of its own
+            Some(USER_DEFINED) => None,
+            Some(projected_type) => Some(projected_type),
+            None => self.geographic_type.filter(|&code| code != USER_DEFINED),
+        }
+    }
+
+    /// Return a PROJ.4 definition of the CRS built from its GeoKeys, e.g. `+proj=tmerc
+    /// +lat_0=0 +lon_0=-75 +k=0.9996 +x_0=500000 +y_0=0 +datum=NAD83 +units=m +no_defs`, to use
+    /// CRSs that are user-defined rather than referenced by EPSG code.
+    ///
+    /// Supports the projections of `ProjCoordTransGeoKey` that PROJ.4 names, such as `tmerc`,
+    /// `lcc`, `aea`, `laea` and `stere`, and geographic CRSs. The datum comes from well-known
+    /// geographic CRS, datum or ellipsoid codes, or the ellipsoid axes, and defaults to WGS 84 as
+    /// in GDAL.
+    pub fn proj_string(&self) -> Option<String> {
+        let mut parts = if self.is_projected() {
+            self.projection_parameters()?
+        } else if self.model_type == Some(2) || self.geographic_type.is_some() {
+            vec!["+proj=longlat".to_string()]
         } else {
-            self.geographic_type
+            return None;
+        };
+        parts.push(self.datum_parameters());
+        if let Some(longitude) = self.geog_prime_meridian_long.filter(|&long| long != 0.0) {
+            parts.push(format!(
+                "+pm={}",
+                longitude * self.degrees_per_angular_unit()
+            ));
         }
+        if self.is_projected() {
+            parts.push(match self.linear_units() {
+                None | Some(LinearUnit::Meter) => "+units=m".to_string(),
+                Some(LinearUnit::Foot) => "+units=ft".to_string(),
+                Some(LinearUnit::UsSurveyFoot) => "+units=us-ft".to_string(),
+                Some(LinearUnit::Kilometer) => "+units=km".to_string(),
+                Some(unit) => format!("+to_meter={}", unit.meters_per_unit()?),
+            });
+        }
+        parts.push("+no_defs".to_string());
+        Some(parts.join(" "))
+    }
+
+    /// Whether the model is projected rather than geographic, from `ModelTypeGeoKey` or whether
+    /// a projected CRS is set
+    fn is_projected(&self) -> bool {
+        // ModelTypeGeoKey: 1 is projected, 2 is geographic
+        match self.model_type {
+            Some(1) => true,
+            Some(2) => false,
+            _ => self.projected_type.is_some(),
+        }
+    }
+
+    /// The size of the unit of angular GeoKeys in degrees
+    fn degrees_per_angular_unit(&self) -> f64 {
+        (self.angular_units())
+            .and_then(|unit| unit.radians_per_unit())
+            .map_or(1.0, f64::to_degrees)
+    }
+
+    /// The `+proj` and projection parameters of a projected CRS, converted to degrees and meters
+    /// as PROJ.4 expects, or `None` if its projection isn't supported
+    fn projection_parameters(&self) -> Option<Vec<String>> {
+        let degrees = self.degrees_per_angular_unit();
+        let meters = (self.linear_units())
+            .and_then(|unit| unit.meters_per_unit())
+            .unwrap_or(1.0);
+        let angle = |value: Option<f64>| value.map(|value| value * degrees);
+        let length = |value: Option<f64>| value.map(|value| value * meters);
+        // Writers don't agree on which keys hold the origin, so fall back to the others as GDAL
+        // does, preferring the keys of the projection's own definition
+        let nat_origin_lat = (self.proj_nat_origin_lat)
+            .or(self.proj_false_origin_lat)
+            .or(self.proj_center_lat);
+        let nat_origin_long = (self.proj_nat_origin_long)
+            .or(self.proj_false_origin_long)
+            .or(self.proj_center_long);
+        let false_origin_lat = self.proj_false_origin_lat.or(nat_origin_lat);
+        let false_origin_long = self.proj_false_origin_long.or(nat_origin_long);
+        let center_lat = self.proj_center_lat.or(nat_origin_lat);
+        let center_long = self.proj_center_long.or(nat_origin_long);
+        let false_easting = (self.proj_false_easting)
+            .or(self.proj_false_origin_easting)
+            .or(self.proj_center_easting);
+        let false_northing = (self.proj_false_northing)
+            .or(self.proj_false_origin_northing)
+            .or(self.proj_center_northing);
+        let scale = self.proj_scale_at_nat_origin.or(self.proj_scale_at_center);
+        let offsets = [
+            ("x_0", length(false_easting)),
+            ("y_0", length(false_northing)),
+        ];
+
+        // The projections of `ProjCoordTransGeoKey`
+        let (name, parameters): (&str, Vec<(&str, Option<f64>)>) = match self.proj_coord_trans? {
+            // CT_TransverseMercator
+            1 => (
+                "tmerc",
+                vec![
+                    ("lat_0", angle(nat_origin_lat)),
+                    ("lon_0", angle(nat_origin_long)),
+                    ("k", scale),
+                ],
+            ),
+            // CT_ObliqueMercator
+            3 => (
+                "omerc",
+                vec![
+                    ("lat_0", angle(center_lat)),
+                    ("lonc", angle(center_long)),
+                    ("alpha", angle(self.proj_azimuth_angle)),
+                    ("k", scale),
+                ],
+            ),
+            // CT_Mercator, with a standard parallel for the 2SP variant
+            7 => match self.proj_std_parallel1 {
+                Some(parallel) => (
+                    "merc",
+                    vec![
+                        ("lat_ts", angle(Some(parallel))),
+                        ("lon_0", angle(nat_origin_long)),
+                    ],
+                ),
+                None => (
+                    "merc",
+                    vec![("lon_0", angle(nat_origin_long)), ("k", scale)],
+                ),
+            },
+            // CT_LambertConfConic_2SP
+            8 => {
+                let offsets = [
+                    (
+                        "x_0",
+                        length(self.proj_false_origin_easting.or(false_easting)),
+                    ),
+                    (
+                        "y_0",
+                        length(self.proj_false_origin_northing.or(false_northing)),
+                    ),
+                ];
+                let parameters = vec![
+                    ("lat_1", angle(self.proj_std_parallel1)),
+                    ("lat_2", angle(self.proj_std_parallel2)),
+                    ("lat_0", angle(false_origin_lat)),
+                    ("lon_0", angle(false_origin_long)),
+                ];
+                return Some(proj_parameters("lcc", parameters, offsets));
+            }
+            // CT_LambertConfConic_1SP
+            9 => (
+                "lcc",
+                vec![
+                    ("lat_1", angle(nat_origin_lat)),
+                    ("lat_0", angle(nat_origin_lat)),
+                    ("lon_0", angle(nat_origin_long)),
+                    ("k_0", scale),
+                ],
+            ),
+            // CT_LambertAzimEqualArea
+            10 => (
+                "laea",
+                vec![("lat_0", angle(center_lat)), ("lon_0", angle(center_long))],
+            ),
+            // CT_AlbersEqualArea
+            11 => (
+                "aea",
+                vec![
+                    ("lat_1", angle(self.proj_std_parallel1)),
+                    ("lat_2", angle(self.proj_std_parallel2)),
+                    ("lat_0", angle(nat_origin_lat)),
+                    ("lon_0", angle(nat_origin_long)),
+                ],
+            ),
+            // CT_AzimuthalEquidistant
+            12 => (
+                "aeqd",
+                vec![("lat_0", angle(center_lat)), ("lon_0", angle(center_long))],
+            ),
+            // CT_Stereographic
+            14 => (
+                "stere",
+                vec![
+                    ("lat_0", angle(center_lat)),
+                    ("lon_0", angle(center_long)),
+                    ("k", scale),
+                ],
+            ),
+            // CT_PolarStereographic, centered on the pole on the side of the latitude of origin,
+            // which is either the pole with a scale factor or the latitude of true scale
+            15 => {
+                let latitude = angle(nat_origin_lat).unwrap_or(90.0);
+                let pole = if latitude < 0.0 { -90.0 } else { 90.0 };
+                let longitude = self.proj_straight_vert_pole_long.or(nat_origin_long);
+                let mut parameters = vec![("lat_0", Some(pole))];
+                if latitude.abs() != 90.0 {
+                    parameters.push(("lat_ts", Some(latitude)));
+                }
+                parameters.extend([("lon_0", angle(longitude)), ("k", scale)]);
+                ("stere", parameters)
+            }
+            // CT_ObliqueStereographic
+            16 => (
+                "sterea",
+                vec![
+                    ("lat_0", angle(nat_origin_lat)),
+                    ("lon_0", angle(nat_origin_long)),
+                    ("k", scale),
+                ],
+            ),
+            // CT_Equirectangular
+            17 => (
+                "eqc",
+                vec![
+                    ("lat_ts", angle(self.proj_std_parallel1)),
+                    ("lat_0", angle(center_lat)),
+                    ("lon_0", angle(center_long)),
+                ],
+            ),
+            // CT_CassiniSoldner
+            18 => (
+                "cass",
+                vec![
+                    ("lat_0", angle(nat_origin_lat)),
+                    ("lon_0", angle(nat_origin_long)),
+                ],
+            ),
+            // CT_MillerCylindrical
+            20 => ("mill", vec![("lon_0", angle(center_long))]),
+            // CT_Orthographic
+            21 => (
+                "ortho",
+                vec![("lat_0", angle(center_lat)), ("lon_0", angle(center_long))],
+            ),
+            // CT_Polyconic
+            22 => (
+                "poly",
+                vec![
+                    ("lat_0", angle(nat_origin_lat)),
+                    ("lon_0", angle(nat_origin_long)),
+                ],
+            ),
+            // CT_Robinson
+            23 => ("robin", vec![("lon_0", angle(center_long))]),
+            // CT_Sinusoidal
+            24 => ("sinu", vec![("lon_0", angle(center_long))]),
+            _ => return None,
+        };
+        Some(proj_parameters(name, parameters, offsets))
+    }
+
+    /// The `+datum` or `+ellps` parameters of the geographic CRS
+    fn datum_parameters(&self) -> String {
+        // Well-known geographic CRSs, then datums, then ellipsoids
+        let known = match (self.geographic_type, self.geog_geodetic_datum) {
+            (Some(4326), _) | (_, Some(6326)) => Some("+datum=WGS84"),
+            (Some(4269), _) | (_, Some(6269)) => Some("+datum=NAD83"),
+            (Some(4267), _) | (_, Some(6267)) => Some("+datum=NAD27"),
+            (Some(4258), _) | (_, Some(6258)) => Some("+ellps=GRS80 +towgs84=0,0,0"),
+            _ => match self.geog_ellipsoid {
+                Some(7030) => Some("+ellps=WGS84"),
+                Some(7019) => Some("+ellps=GRS80"),
+                Some(7008) => Some("+ellps=clrk66"),
+                Some(7022) => Some("+ellps=intl"),
+                Some(7004) => Some("+ellps=bessel"),
+                Some(7043) => Some("+ellps=WGS72"),
+                _ => None,
+            },
+        };
+        if let Some(known) = known {
+            return known.to_string();
+        }
+        // The axes are in `GeogLinearUnitsGeoKey`, meters by default
+        let meters = (self.geog_linear_units)
+            .map(|code| LinearUnit::from_code(code, self.geog_linear_unit_size))
+            .and_then(|unit| unit.meters_per_unit())
+            .unwrap_or(1.0);
+        match (
+            self.geog_semi_major_axis,
+            self.geog_inv_flattening,
+            self.geog_semi_minor_axis,
+        ) {
+            (Some(a), Some(rf), _) if rf != 0.0 => format!("+a={} +rf={rf}", a * meters),
+            (Some(a), _, Some(b)) => format!("+a={} +b={}", a * meters, b * meters),
+            (Some(a), _, None) => format!("+a={0} +b={0}", a * meters),
+            _ => "+datum=WGS84".to_string(),
+        }
+    }
+
+    /// Return the citation of the CRS (`GTCitationGeoKey`), if any
+    pub fn citation(&self) -> Option<Citation> {
+        self.citation.as_deref().map(Citation::parse)
+    }
+
+    /// Return the citation of the geographic CRS (`GeogCitationGeoKey`), if any
+    pub fn geog_citation(&self) -> Option<Citation> {
+        self.geog_citation.as_deref().map(Citation::parse)
+    }
+
+    /// Return the citation of the projected CRS (`ProjCitationGeoKey`), if any
+    pub fn proj_citation(&self) -> Option<Citation> {
+        self.proj_citation.as_deref().map(Citation::parse)
+    }
+
+    /// Return the name of the CRS from the citations, e.g. `UTM Zone 18N`, to describe CRSs
+    /// that are user-defined rather than referenced by EPSG code
+    pub fn crs_name(&self) -> Option<String> {
+        [self.proj_citation(), self.citation(), self.geog_citation()]
+            .into_iter()
+            .flatten()
+            .find_map(|citation| citation.crs_name().map(str::to_string))
+    }
+
+    /// Return the linear units of a projected CRS (`ProjLinearUnitsGeoKey`), if any
+    pub fn linear_units(&self) -> Option<LinearUnit> {
+        self.proj_linear_units
+            .map(|code| LinearUnit::from_code(code, self.proj_linear_unit_size))
+    }
+
+    /// Return the angular units of a geographic CRS (`GeogAngularUnitsGeoKey`), if any
+    pub fn angular_units(&self) -> Option<AngularUnit> {
+        self.geog_angular_units
+            .map(|code| AngularUnit::from_code(code, self.geog_angular_unit_size))
+    }
+
+    /// Return the units of the model coordinate system.
+    ///
+    /// Geographic CRSs default to degrees when `GeogAngularUnitsGeoKey` is absent; projected CRSs
+    /// without `ProjLinearUnitsGeoKey` have unknown units.
+    pub fn units(&self) -> Option<Units> {
+        if self.is_projected() {
+            self.linear_units().map(Units::Linear)
+        } else if self.model_type == Some(2) || self.geographic_type.is_some() {
+            Some(Units::Angular(
+                self.angular_units().unwrap_or(AngularUnit::Degree),
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Return the vertical CRS code (`VerticalGeoKey`), if any
+    pub fn vertical(&self) -> Option<u16> {
+        self.vertical
+    }
+
+    /// Return the EPSG code of the vertical CRS, if `VerticalGeoKey` holds one rather than
+    /// marking the vertical CRS as undefined or user-defined
+    pub fn vertical_epsg_code(&self) -> Option<u16> {
+        self.vertical
+            .filter(|&code| code != 0 && code != USER_DEFINED)
+    }
+
+    /// Return the horizontal and vertical CRSs, if both have an EPSG code
+    pub fn compound_crs(&self) -> Option<CompoundCrs> {
+        Some(CompoundCrs {
+            horizontal: self.epsg_code()?,
+            vertical: self.vertical_epsg_code()?,
+        })
+    }
+
+    /// Return the citation of the vertical CRS (`VerticalCitationGeoKey`), if any
+    pub fn vertical_citation(&self) -> Option<&str> {
+        self.vertical_citation.as_deref()
+    }
+
+    /// Return the vertical datum code (`VerticalDatumGeoKey`), if any
+    pub fn vertical_datum(&self) -> Option<u16> {
+        self.vertical_datum
+    }
+
+    /// Return the vertical unit code (`VerticalUnitsGeoKey`), if any
+    pub fn vertical_units(&self) -> Option<u16> {
+        self.vertical_units
+    }
+}
+
+/// Format the `+proj` of a projection and its parameters, leaving out the parameters whose keys
+/// are missing for PROJ to default
+fn proj_parameters(
+    name: &str,
+    parameters: Vec<(&str, Option<f64>)>,
+    offsets: [(&str, Option<f64>); 2],
+) -> Vec<String> {
+    let parameters = parameters.into_iter().chain(offsets);
+    std::iter::once(format!("+proj={name}"))
+        .chain(parameters.filter_map(|(key, value)| Some(format!("+{key}={}", value?))))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compound_crs() {
+        let keys = HashMap::from([
+            (GeoKeyTag::ModelType, Value::Short(1)),
+            (GeoKeyTag::ProjectedType, Value::Short(26918)),
+            (GeoKeyTag::Vertical, Value::Short(5703)),
+        ]);
+        let gkd = GeoKeyDirectory::from_tags(keys).unwrap();
+        assert_eq!(gkd.epsg_code(), Some(26918));
+        assert_eq!(gkd.vertical_epsg_code(), Some(5703));
+        assert_eq!(gkd.compound_crs().unwrap().to_string(), "EPSG:26918+5703");
+
+        // User-defined vertical CRSs have no code
+        let keys = HashMap::from([
+            (GeoKeyTag::GeographicType, Value::Short(4326)),
+            (GeoKeyTag::Vertical, Value::Short(USER_DEFINED)),
+        ]);
+        let gkd = GeoKeyDirectory::from_tags(keys).unwrap();
+        assert_eq!(gkd.compound_crs(), None);
+    }
+
+    #[test]
+    fn user_defined_proj_string() {
+        // UTM zone 18N on NAD83, as EPSG:26918, with the false easting in US survey feet
+        let keys = HashMap::from([
+            (GeoKeyTag::ModelType, Value::Short(1)),
+            (GeoKeyTag::GeographicType, Value::Short(4269)),
+            (GeoKeyTag::ProjectedType, Value::Short(USER_DEFINED)),
+            (GeoKeyTag::Projection, Value::Short(USER_DEFINED)),
+            (GeoKeyTag::ProjCoordTrans, Value::Short(1)),
+            (GeoKeyTag::ProjLinearUnits, Value::Short(9003)),
+            (GeoKeyTag::ProjNatOriginLat, Value::Double(0.0)),
+            (GeoKeyTag::ProjNatOriginLong, Value::Double(-75.0)),
+            (GeoKeyTag::ProjScaleAtNatOrigin, Value::Double(0.9996)),
+            (
+                GeoKeyTag::ProjFalseEasting,
+                Value::Double(1640416.6666666667),
+            ),
+            (GeoKeyTag::ProjFalseNorthing, Value::Double(0.0)),
+        ]);
+        let gkd = GeoKeyDirectory::from_tags(keys).unwrap();
+        assert_eq!(gkd.epsg_code(), None);
+        let definition = gkd.proj_string().unwrap();
+        let false_easting = 1640416.6666666667 * 1200.0 / 3937.0;
+        assert_eq!(
+            definition,
+            format!(
+                "+proj=tmerc +lat_0=0 +lon_0=-75 +k=0.9996 +x_0={false_easting} +y_0=0 \
+                 +datum=NAD83 +units=us-ft +no_defs"
+            )
+        );
+
+        // Lambert conformal conic on a user-defined ellipsoid
+        let keys = HashMap::from([
+            (GeoKeyTag::ModelType, Value::Short(1)),
+            (GeoKeyTag::ProjectedType, Value::Short(USER_DEFINED)),
+            (GeoKeyTag::ProjCoordTrans, Value::Short(8)),
+            (GeoKeyTag::ProjStdParallel1, Value::Double(33.0)),
+            (GeoKeyTag::ProjStdParallel2, Value::Double(45.0)),
+            (GeoKeyTag::ProjFalseOriginLat, Value::Double(23.0)),
+            (GeoKeyTag::ProjFalseOriginLong, Value::Double(-96.0)),
+            (GeoKeyTag::GeogSemiMajorAxis, Value::Double(6378137.0)),
+            (GeoKeyTag::GeogInvFlattening, Value::Double(298.257222101)),
+        ]);
+        let gkd = GeoKeyDirectory::from_tags(keys).unwrap();
+        assert_eq!(
+            gkd.proj_string().unwrap(),
+            "+proj=lcc +lat_1=33 +lat_2=45 +lat_0=23 +lon_0=-96 +a=6378137 +rf=298.257222101 \
+             +units=m +no_defs"
+        );
+    }
+
+    #[cfg(feature = "proj")]
+    #[test]
+    fn reproject_user_defined_crs() {
+        use crate::reproject::Crs;
+
+        let keys = HashMap::from([
+            (GeoKeyTag::ModelType, Value::Short(1)),
+            (GeoKeyTag::GeographicType, Value::Short(4326)),
+            (GeoKeyTag::ProjectedType, Value::Short(USER_DEFINED)),
+            (GeoKeyTag::ProjCoordTrans, Value::Short(1)),
+            (GeoKeyTag::ProjNatOriginLong, Value::Double(-75.0)),
+            (GeoKeyTag::ProjScaleAtNatOrigin, Value::Double(0.9996)),
+            (GeoKeyTag::ProjFalseEasting, Value::Double(500000.0)),
+        ]);
+        let gkd = GeoKeyDirectory::from_tags(keys).unwrap();
+        let user_defined = Crs::from_proj_string(&gkd.proj_string().unwrap()).unwrap();
+        let utm = Crs::from_epsg(32618).unwrap();
+        let points = [(585000.0, 4511000.0)];
+        let (x, y) = user_defined.transform_points(&utm, &points)[0].unwrap();
+        assert!((x - 585000.0).abs() < 1e-6 && (y - 4511000.0).abs() < 1e-6);
     }
 }
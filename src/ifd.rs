@@ -1,62 +1,294 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::{Cursor, Read};
+use std::ops::Range;
 
-use byteorder::{LittleEndian, ReadBytesExt};
-use bytes::Buf;
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+use bytes::{Buf, Bytes};
+use ndarray::{Array2, Axis};
 use num_enum::TryFromPrimitive;
+use object_store::path::Path;
+use object_store::ObjectStore;
 use tiff::decoder::ifd::Value;
 use tiff::tags::{
     CompressionMethod, PhotometricInterpretation, PlanarConfiguration, Predictor, ResolutionUnit,
     SampleFormat, Tag, Type,
 };
-use tiff::{TiffError, TiffResult};
+use tiff::{TiffError, TiffFormatError, TiffResult};
 
 use crate::affine::AffineTransform;
-use crate::cursor::ObjectStoreCursor;
+use crate::array::RasterArray;
+use crate::compression::decompress_tile;
+use crate::cursor::{Endianness, ObjectStoreCursor};
+use crate::datetime::{parse_datetime, parse_offset, Timestamp};
+use crate::decoder::{
+    apply_mask_orientation, apply_orientation, decode_tile, unpack_mask, TileLayout,
+};
+use crate::enums::{ColorInterp, DataType, FillOrder, Orientation};
+use crate::error::{AiocogeoError, Result};
+use crate::exif::ExifDirectory;
+use crate::gdal_metadata::GdalMetadata;
 use crate::geo_key_directory::{GeoKeyDirectory, GeoKeyTag};
+use crate::options::{Limits, OpenOptions, ParseMode, ReadOptions};
+use crate::pool::{give_buffer, take_buffer, BufferPool};
+use crate::rpc::RpcCoefficients;
 
 const DOCUMENT_NAME: u16 = 269;
-
-/// A collection of all the IFD
-// TODO: maybe separate out the primary/first image IFD out of the vec, as that one should have
-// geospatial metadata?
+const GDAL_METADATA: u16 = 42112;
+const RPC_COEFFICIENT: u16 = 50844;
+const XMP: u16 = 700;
+const ICC_PROFILE: u16 = 34675;
+const EXIF_IFD: u16 = 34665;
+const GDAL_NODATA: u16 = 42113;
+
+/// The most bytes between an image tile and the mask tile after it in GDAL's
+/// `MASK_INTERLEAVED_WITH_IMAGERY` layout: the 4 byte trailer of the image tile and the 4 byte
+/// leader of the mask tile
+pub(crate) const MASK_INTERLEAVE_GAP: usize = 8;
+
+/// The IFDs of one image of a file, grouped by their role
+#[derive(Debug, Clone)]
 pub(crate) struct ImageFileDirectories {
-    /// There's always at least one IFD in a TIFF. We store this separately
-    ifds: Vec<ImageFileDirectory>,
-    // Is it guaranteed that if masks exist that there will be one per image IFD? Or could there be
-    // different numbers of image ifds and mask ifds?
-    // mask_ifds: Option<Vec<IFD>>,
+    /// The full resolution image, which holds the geospatial metadata, followed by its overviews
+    /// from highest to lowest resolution. There's always at least one.
+    levels: Vec<ImageFileDirectory>,
+    /// Internal transparency masks, from highest to lowest resolution
+    masks: Vec<ImageFileDirectory>,
+    /// The overview level of each mask, that of the level with the same dimensions, or its index
+    /// in `masks` if no level has them
+    mask_levels: Vec<usize>,
+    /// Whether the overviews are stored from highest to lowest resolution in the IFD chain
+    overviews_sorted: bool,
 }
 
-impl AsRef<[ImageFileDirectory]> for ImageFileDirectories {
-    fn as_ref(&self) -> &[ImageFileDirectory] {
-        &self.ifds
+impl ImageFileDirectories {
+    /// Split IFDs in file order into images, each starting at a full resolution IFD that isn't a
+    /// mask and followed by its overviews and masks, as GDAL exposes subdatasets
+    fn split_images(ifds: Vec<ImageFileDirectory>) -> Result<Vec<Self>> {
+        let mut images: Vec<Vec<ImageFileDirectory>> = vec![];
+        for ifd in ifds {
+            let starts_image = match images.last().and_then(|image| image.first()) {
+                None => true,
+                // Some writers don't tag overviews with `NewSubfileType`, so an untagged IFD
+                // smaller than the current image is taken as one of its overviews
+                Some(primary) if ifd.new_subfile_type.is_none() => {
+                    ifd.image_width >= primary.image_width
+                        || ifd.image_height >= primary.image_height
+                }
+                Some(_) => ifd.is_full_resolution() && !ifd.is_mask(),
+            };
+            match images.last_mut() {
+                Some(image) if !starts_image => image.push(ifd),
+                _ => images.push(vec![ifd]),
+            }
+        }
+        images.into_iter().map(Self::from_ifds).collect()
     }
-}
 
-impl ImageFileDirectories {
+    /// Group the IFDs of one image into the full resolution image, its overviews and masks
+    fn from_ifds(ifds: Vec<ImageFileDirectory>) -> Result<Self> {
+        let (mut masks, mut levels): (Vec<_>, Vec<_>) =
+            ifds.into_iter().partition(|ifd| ifd.is_mask());
+        if levels.is_empty() {
+            return Err(AiocogeoError::General(
+                "the file has no image besides masks".to_string(),
+            ));
+        }
+        let overviews_sorted =
+            (levels[1..].windows(2)).all(|pair| pair[0].image_width > pair[1].image_width);
+        levels[1..].sort_by_key(|ifd| std::cmp::Reverse(ifd.image_width));
+        masks.sort_by_key(|ifd| std::cmp::Reverse(ifd.image_width));
+        if let Some(gt) = levels[0].geotransform() {
+            let north_up = north_up_orientation(&gt);
+            for ifd in levels.iter_mut().chain(&mut masks) {
+                ifd.north_up_orientation = north_up;
+            }
+        }
+        // Masks may be missing for some levels, e.g. when only overviews are masked, so match
+        // them by dimensions rather than by position
+        let mask_levels = masks
+            .iter()
+            .enumerate()
+            .map(|(index, mask)| {
+                levels
+                    .iter()
+                    .position(|ifd| {
+                        (ifd.image_width, ifd.image_height) == (mask.image_width, mask.image_height)
+                    })
+                    .unwrap_or(index)
+            })
+            .collect();
+        Ok(Self {
+            levels,
+            masks,
+            mask_levels,
+            overviews_sorted,
+        })
+    }
+
+    /// The full resolution image
+    pub(crate) fn primary(&self) -> &ImageFileDirectory {
+        &self.levels[0]
+    }
+
+    /// The full resolution image followed by its overviews, indexed by overview level
+    pub(crate) fn levels(&self) -> &[ImageFileDirectory] {
+        &self.levels
+    }
+
+    /// The reduced resolution overviews, from highest to lowest resolution
+    pub(crate) fn overviews(&self) -> &[ImageFileDirectory] {
+        &self.levels[1..]
+    }
+
+    /// The decimation factor of each overview, the width of the full resolution image divided
+    /// by that of the overview, rounded as GDAL does
+    pub(crate) fn overview_factors(&self) -> Vec<u32> {
+        let width = self.primary().image_width as f64;
+        (self.overviews().iter())
+            .map(|ifd| (width / ifd.image_width as f64).round() as u32)
+            .collect()
+    }
+
+    /// Whether the overviews are stored from highest to lowest resolution in the file. They are
+    /// sorted on open regardless.
+    pub(crate) fn overviews_sorted(&self) -> bool {
+        self.overviews_sorted
+    }
+
+    /// The transparency masks, from highest to lowest resolution
+    pub(crate) fn masks(&self) -> &[ImageFileDirectory] {
+        &self.masks
+    }
+
+    /// The transparency masks with the overview level each applies to
+    pub(crate) fn masks_by_level(&self) -> impl Iterator<Item = (usize, &ImageFileDirectory)> {
+        self.mask_levels.iter().copied().zip(&self.masks)
+    }
+
+    /// Whether every mask is interleaved with the tiles of the level it masks, as in COGs written
+    /// by GDAL with `MASK_INTERLEAVED_WITH_IMAGERY=YES`
+    pub(crate) fn mask_interleaved(&self) -> bool {
+        let mut masks = self.masks_by_level().peekable();
+        masks.peek().is_some()
+            && masks.all(|(level, mask)| {
+                self.levels
+                    .get(level)
+                    .is_some_and(|ifd| ifd.interleaves_mask(mask))
+            })
+    }
+
+    /// The transparency mask of overview level `z`, the one with the same dimensions
+    pub(crate) fn mask(&self, z: usize) -> Option<&ImageFileDirectory> {
+        let level = self.levels.get(z)?;
+        self.masks_by_level()
+            .find(|&(mask_level, mask)| {
+                mask_level == z
+                    && (mask.image_width, mask.image_height)
+                        == (level.image_width, level.image_height)
+            })
+            .map(|(_, mask)| mask)
+    }
+
+    /// Read the chain of IFDs starting at `ifd_offset`, returning one entry per image in the file
     pub(crate) async fn open(
         cursor: &mut ObjectStoreCursor,
         ifd_offset: usize,
-    ) -> TiffResult<Self> {
+        options: &OpenOptions,
+    ) -> Result<Vec<Self>> {
         let mut next_ifd_offset = Some(ifd_offset);
 
         let mut ifds = vec![];
+        let mut visited = HashSet::new();
         while let Some(offset) = next_ifd_offset {
-            let ifd = ImageFileDirectory::read(cursor, offset).await?;
+            if !visited.insert(offset) {
+                return Err(AiocogeoError::CyclicIfdChain(offset as u64));
+            }
+            if ifds.len() == options.limits.max_ifd_count {
+                return Err(AiocogeoError::LimitExceeded(format!(
+                    "the file has more than {} IFDs",
+                    options.limits.max_ifd_count
+                )));
+            }
+            let ifd = ImageFileDirectory::read(cursor, offset, options).await?;
             next_ifd_offset = ifd.next_ifd_offset();
             ifds.push(ifd);
         }
 
-        Ok(Self { ifds })
+        Self::split_images(ifds)
     }
 }
 
+/// A tile as stored in the file, before decompression
+#[derive(Debug, Clone)]
+pub struct RawTile {
+    /// The compressed bytes of the tile
+    pub bytes: Bytes,
+    /// The byte offset of the tile within the file
+    pub offset: u64,
+    /// The length of the tile in bytes
+    pub length: u64,
+    /// The compression of the tile bytes
+    pub compression: CompressionMethod,
+    /// The `JPEGTables` shared by all tiles of the image. JPEG tiles that reference these tables
+    /// are only valid images once the tables are merged in.
+    pub jpeg_tables: Option<Bytes>,
+}
+
+/// A single entry of the `ModelTiepointTag`, tying a raster point `(i, j, k)` to a model point
+/// `(x, y, z)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tiepoint {
+    pub i: f64,
+    pub j: f64,
+    pub k: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Tiepoint {
+    /// Split the flat `ModelTiepointTag` values into tiepoints of 6 values each
+    fn from_tag_values(values: &[f64]) -> Vec<Self> {
+        values
+            .chunks_exact(6)
+            .map(|v| Self {
+                i: v[0],
+                j: v[1],
+                k: v[2],
+                x: v[3],
+                y: v[4],
+                z: v[5],
+            })
+            .collect()
+    }
+}
+
+/// The descriptive tags of an image, as returned by [`ImageFileDirectory::file_metadata`], for
+/// reporting where a file comes from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileMetadata {
+    /// The name of the document the image was scanned from (`DocumentName`)
+    pub document_name: Option<String>,
+    /// A description of the subject of the image (`ImageDescription`)
+    pub image_description: Option<String>,
+    /// The software that wrote the image (`Software`), e.g. `GDAL 3.8.4`
+    pub software: Option<String>,
+    /// The time the image was written (`DateTime`), as stored. See
+    /// [`ImageFileDirectory::date_time`] to parse it.
+    pub date_time: Option<String>,
+    /// The person who created the image (`Artist`)
+    pub artist: Option<String>,
+    /// The computer the image was created on (`HostComputer`)
+    pub host_computer: Option<String>,
+    /// The copyright notice of the image (`Copyright`)
+    pub copyright: Option<String>,
+}
+
 /// An ImageFileDirectory representing Image content
 // The ordering of these tags matches the sorted order in TIFF spec Appendix A
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
-pub(crate) struct ImageFileDirectory {
+pub struct ImageFileDirectory {
     pub(crate) new_subfile_type: Option<u32>,
 
     /// The number of columns in the image, i.e., the number of pixels per row.
@@ -71,6 +303,9 @@ pub(crate) struct ImageFileDirectory {
 
     pub(crate) photometric_interpretation: PhotometricInterpretation,
 
+    /// The logical order of bits within a byte, only relevant for sub-byte samples.
+    pub(crate) fill_order: FillOrder,
+
     pub(crate) document_name: Option<String>,
 
     pub(crate) image_description: Option<String>,
@@ -79,6 +314,10 @@ pub(crate) struct ImageFileDirectory {
 
     pub(crate) orientation: Option<u16>,
 
+    /// The orientation that reads the image north-up, derived from the geotransform of the full
+    /// resolution image, which its overviews and masks share
+    pub(crate) north_up_orientation: Orientation,
+
     pub(crate) samples_per_pixel: u16,
 
     pub(crate) rows_per_strip: Option<u32>,
@@ -128,8 +367,8 @@ pub(crate) struct ImageFileDirectory {
     pub(crate) tile_width: u32,
     pub(crate) tile_height: u32,
 
-    pub(crate) tile_offsets: Vec<u32>,
-    pub(crate) tile_byte_counts: Vec<u32>,
+    pub(crate) tile_offsets: Vec<u64>,
+    pub(crate) tile_byte_counts: Vec<u64>,
 
     pub(crate) extra_samples: Option<Vec<u8>>,
 
@@ -146,36 +385,53 @@ pub(crate) struct ImageFileDirectory {
 
     // GDAL tags
     // no_data
-    // gdal_metadata
-    pub(crate) other_tags: HashMap<Tag, Value>,
+    pub(crate) gdal_metadata: Option<GdalMetadata>,
 
-    pub(crate) next_ifd_offset: Option<usize>,
-}
+    /// The EXIF sub-IFD, if the `ExifIFD` tag points to one
+    pub(crate) exif: Option<ExifDirectory>,
 
-impl ImageFileDirectory {
-    async fn read(cursor: &mut ObjectStoreCursor, offset: usize) -> TiffResult<Self> {
-        let ifd_start = offset;
-        cursor.seek(offset);
+    /// The raw values of every tag in the IFD, including those parsed into the fields above
+    pub(crate) tags: HashMap<Tag, Value>,
 
-        let tag_count = cursor.read_u16().await;
-        // dbg!(tag_count);
+    /// The byte offset of the IFD within the file
+    pub(crate) offset: usize,
 
-        let mut tags = HashMap::with_capacity(tag_count as usize);
-        for _ in 0..tag_count {
-            let (tag_name, tag_value) = read_tag(cursor).await?;
-            tags.insert(tag_name, tag_value);
-        }
+    pub(crate) next_ifd_offset: Option<usize>,
 
-        cursor.seek(ifd_start + (12 * tag_count as usize) + 2);
+    /// The byte order of the file this IFD was read from
+    pub(crate) endianness: Endianness,
+}
 
-        let next_ifd_offset = cursor.read_u32().await;
-        let next_ifd_offset = if next_ifd_offset == 0 {
-            None
-        } else {
-            Some(next_ifd_offset as usize)
+impl ImageFileDirectory {
+    async fn read(
+        cursor: &mut ObjectStoreCursor,
+        offset: usize,
+        options: &OpenOptions,
+    ) -> Result<Self> {
+        let (tags, next_ifd_offset) = read_ifd_tags(cursor, offset, &options.limits).await?;
+
+        // The EXIF IFD is a private sub-IFD which isn't part of the main IFD chain
+        let exif = match tags.values.get(&Tag::Unknown(EXIF_IFD)) {
+            Some(value) => {
+                let exif_offset = value
+                    .clone()
+                    .into_u32()
+                    .map_err(|err| tags.invalid(Tag::Unknown(EXIF_IFD), err))?
+                    as usize;
+                Some(ExifDirectory::read(cursor, exif_offset, &options.limits).await?)
+            }
+            None => None,
         };
 
-        Self::from_tags(tags, next_ifd_offset)
+        let mut ifd = Self::from_tags(
+            tags,
+            next_ifd_offset,
+            cursor.endianness(),
+            options.parse_mode,
+        )?;
+        ifd.exif = exif;
+        ifd.offset = offset;
+        Ok(ifd)
     }
 
     fn next_ifd_offset(&self) -> Option<usize> {
@@ -183,15 +439,18 @@ impl ImageFileDirectory {
     }
 
     fn from_tags(
-        mut tag_data: HashMap<Tag, Value>,
+        tag_data: IfdTags,
         next_ifd_offset: Option<usize>,
-    ) -> TiffResult<Self> {
+        endianness: Endianness,
+        mode: ParseMode,
+    ) -> Result<Self> {
         let mut new_subfile_type = None;
         let mut image_width = None;
         let mut image_height = None;
         let mut bits_per_sample = None;
         let mut compression = None;
         let mut photometric_interpretation = None;
+        let mut fill_order = None;
         let mut document_name = None;
         let mut image_description = None;
         let mut strip_offsets = None;
@@ -224,187 +483,272 @@ impl ImageFileDirectory {
         let mut model_tiepoint = None;
         let mut geo_ascii_params: Option<String> = None;
         let mut geo_double_params: Option<Vec<f64>> = None;
+        let mut gdal_metadata = None;
+
+        // Values are parsed from the tags by reference, so that the raw values can be kept without
+        // copying the largest of them, like the tile offsets
+        tag_data.values.iter().try_for_each(|(&tag, value)| {
+            let mut parse = || {
+                match tag {
+                    Tag::NewSubfileType => new_subfile_type = Some(value.clone().into_u32()?),
+                    Tag::ImageWidth => {
+                        image_width = Some(value.clone().into_u32()?);
+                    }
+                    Tag::ImageLength => {
+                        image_height = Some(value.clone().into_u32()?);
+                    }
+                    Tag::BitsPerSample => {
+                        bits_per_sample =
+                            Some(to_vec(value, Value::into_u16, Value::into_u16_vec)?);
+                    }
+                    Tag::Compression => {
+                        compression = Some(CompressionMethod::from_u16_exhaustive(
+                            value.clone().into_u16()?,
+                        ))
+                    }
+                    Tag::PhotometricInterpretation => {
+                        photometric_interpretation =
+                            PhotometricInterpretation::from_u16(value.clone().into_u16()?)
+                    }
+                    Tag::FillOrder => {
+                        fill_order = FillOrder::try_from_primitive(value.clone().into_u16()?).ok()
+                    }
+                    Tag::ImageDescription => image_description = Some(value.clone().into_string()?),
+                    Tag::StripOffsets => {
+                        strip_offsets = Some(to_vec(value, Value::into_u32, Value::into_u32_vec)?)
+                    }
+                    Tag::Orientation => orientation = Some(value.clone().into_u16()?),
+                    Tag::SamplesPerPixel => samples_per_pixel = Some(value.clone().into_u16()?),
+                    Tag::RowsPerStrip => rows_per_strip = Some(value.clone().into_u32()?),
+                    Tag::StripByteCounts => {
+                        strip_byte_counts =
+                            Some(to_vec(value, Value::into_u32, Value::into_u32_vec)?)
+                    }
+                    Tag::MinSampleValue => {
+                        min_sample_value =
+                            Some(to_vec(value, Value::into_u16, Value::into_u16_vec)?)
+                    }
+                    Tag::MaxSampleValue => {
+                        max_sample_value =
+                            Some(to_vec(value, Value::into_u16, Value::into_u16_vec)?)
+                    }
+                    Tag::XResolution => match *value {
+                        Value::Rational(n, d) => x_resolution = Some(n as f64 / d as f64),
+                        _ => mode.violation(|| "XResolution is not a rational".to_string())?,
+                    },
+                    Tag::YResolution => match *value {
+                        Value::Rational(n, d) => y_resolution = Some(n as f64 / d as f64),
+                        _ => mode.violation(|| "YResolution is not a rational".to_string())?,
+                    },
+                    Tag::PlanarConfiguration => {
+                        planar_configuration =
+                            PlanarConfiguration::from_u16(value.clone().into_u16()?)
+                    }
+                    Tag::ResolutionUnit => {
+                        resolution_unit = ResolutionUnit::from_u16(value.clone().into_u16()?)
+                    }
+                    Tag::Software => software = Some(value.clone().into_string()?),
+                    Tag::DateTime => date_time = Some(value.clone().into_string()?),
+                    Tag::Artist => artist = Some(value.clone().into_string()?),
+                    Tag::HostComputer => host_computer = Some(value.clone().into_string()?),
+                    Tag::Predictor => predictor = Predictor::from_u16(value.clone().into_u16()?),
+                    Tag::ColorMap => {
+                        color_map = Some(to_vec(value, Value::into_u16, Value::into_u16_vec)?)
+                    }
+                    Tag::TileWidth => tile_width = Some(value.clone().into_u32()?),
+                    Tag::TileLength => tile_height = Some(value.clone().into_u32()?),
+                    Tag::TileOffsets => {
+                        tile_offsets = Some(to_vec(value, Value::into_u64, Value::into_u64_vec)?)
+                    }
+                    Tag::TileByteCounts => {
+                        tile_byte_counts =
+                            Some(to_vec(value, Value::into_u64, Value::into_u64_vec)?)
+                    }
+                    Tag::ExtraSamples => {
+                        extra_samples = Some(to_vec(value, Value::into_u8, Value::into_u8_vec)?)
+                    }
+                    Tag::SampleFormat => {
+                        let values = to_vec(value, Value::into_u16, Value::into_u16_vec)?;
+                        sample_format = Some(
+                            values
+                                .into_iter()
+                                .map(SampleFormat::from_u16_exhaustive)
+                                .collect(),
+                        );
+                        // sample_format = SampleFormat::from_u16(to_vec(value, Value::into_u16, Value::into_u16_vec).unwrap())
+                    }
+                    Tag::JPEGTables => {
+                        jpeg_tables = Some(to_vec(value, Value::into_u8, Value::into_u8_vec)?)
+                    }
+                    Tag::Copyright => copyright = Some(value.clone().into_string()?),
 
-        let mut other_tags = HashMap::new();
-
-        tag_data.drain().try_for_each(|(tag, value)| {
-            match tag {
-                Tag::NewSubfileType => new_subfile_type = Some(value.into_u32()?),
-                Tag::ImageWidth => {
-                    image_width = Some(value.into_u32()?);
-                }
-                Tag::ImageLength => {
-                    image_height = Some(value.into_u32()?);
-                }
-                Tag::BitsPerSample => {
-                    bits_per_sample = Some(value.into_u16_vec()?);
-                }
-                Tag::Compression => {
-                    compression = Some(CompressionMethod::from_u16_exhaustive(
-                        value.into_u16().unwrap(),
-                    ))
-                }
-                Tag::PhotometricInterpretation => {
-                    photometric_interpretation =
-                        PhotometricInterpretation::from_u16(value.into_u16().unwrap())
-                }
-                Tag::ImageDescription => image_description = Some(value.into_string()?),
-                Tag::StripOffsets => strip_offsets = Some(value.into_u32_vec()?),
-                Tag::Orientation => orientation = Some(value.into_u16().unwrap()),
-                Tag::SamplesPerPixel => samples_per_pixel = Some(value.into_u16().unwrap()),
-                Tag::RowsPerStrip => rows_per_strip = Some(value.into_u32()?),
-                Tag::StripByteCounts => strip_byte_counts = Some(value.into_u32_vec()?),
-                Tag::MinSampleValue => min_sample_value = Some(value.into_u16_vec()?),
-                Tag::MaxSampleValue => max_sample_value = Some(value.into_u16_vec()?),
-                Tag::XResolution => match value {
-                    Value::Rational(n, d) => x_resolution = Some(n as f64 / d as f64),
-                    _ => unreachable!(),
-                },
-                Tag::YResolution => match value {
-                    Value::Rational(n, d) => y_resolution = Some(n as f64 / d as f64),
-                    _ => unreachable!(),
-                },
-                Tag::PlanarConfiguration => {
-                    planar_configuration = PlanarConfiguration::from_u16(value.into_u16().unwrap())
-                }
-                Tag::ResolutionUnit => {
-                    resolution_unit = ResolutionUnit::from_u16(value.into_u16().unwrap())
-                }
-                Tag::Software => software = Some(value.into_string()?),
-                Tag::DateTime => date_time = Some(value.into_string()?),
-                Tag::Artist => artist = Some(value.into_string()?),
-                Tag::HostComputer => host_computer = Some(value.into_string()?),
-                Tag::Predictor => predictor = Predictor::from_u16(value.into_u16().unwrap()),
-                Tag::ColorMap => color_map = Some(value.into_u16_vec()?),
-                Tag::TileWidth => tile_width = Some(value.into_u32()?),
-                Tag::TileLength => tile_height = Some(value.into_u32()?),
-                Tag::TileOffsets => tile_offsets = Some(value.into_u32_vec()?),
-                Tag::TileByteCounts => tile_byte_counts = Some(value.into_u32_vec()?),
-                Tag::ExtraSamples => extra_samples = Some(value.into_u8_vec()?),
-                Tag::SampleFormat => {
-                    let values = value.into_u16_vec()?;
-                    sample_format = Some(
-                        values
-                            .into_iter()
-                            .map(SampleFormat::from_u16_exhaustive)
-                            .collect(),
-                    );
-                    // sample_format = SampleFormat::from_u16(value.into_u16_vec().unwrap())
-                }
-                Tag::JPEGTables => jpeg_tables = Some(value.into_u8_vec()?),
-                Tag::Copyright => copyright = Some(value.into_string()?),
-
-                // Geospatial tags
-                Tag::GeoKeyDirectoryTag => {
-                    // http://geotiff.maptools.org/spec/geotiff2.4.html
-                    geo_key_directory_data = Some(value.into_u16_vec()?);
-                }
-                Tag::ModelPixelScaleTag => model_pixel_scale = Some(value.into_f64_vec()?),
-                Tag::ModelTiepointTag => model_tiepoint = Some(value.into_f64_vec()?),
-                Tag::GeoAsciiParamsTag => {
-                    geo_ascii_params = Some(value.into_string()?);
-                    // let s = value.into_string()?;
-                    // geo_ascii_params = Some(s.split('|').map(|s| s.to_string()).collect())
-                }
-                Tag::GeoDoubleParamsTag => {
-                    geo_double_params = Some(value.into_f64_vec()?);
-                }
-                // Tag::GdalNodata
-                // Tags for which the tiff crate doesn't have a hard-coded enum variant
-                Tag::Unknown(DOCUMENT_NAME) => document_name = Some(value.into_string()?),
-                _ => {
-                    other_tags.insert(tag, value);
-                }
+                    // Geospatial tags
+                    Tag::GeoKeyDirectoryTag => {
+                        // http://geotiff.maptools.org/spec/geotiff2.4.html
+                        geo_key_directory_data =
+                            Some(to_vec(value, Value::into_u16, Value::into_u16_vec)?);
+                    }
+                    Tag::ModelPixelScaleTag => {
+                        model_pixel_scale =
+                            Some(to_vec(value, Value::into_f64, Value::into_f64_vec)?)
+                    }
+                    Tag::ModelTiepointTag => {
+                        model_tiepoint = Some(to_vec(value, Value::into_f64, Value::into_f64_vec)?)
+                    }
+                    Tag::GeoAsciiParamsTag => {
+                        geo_ascii_params = Some(value.clone().into_string()?);
+                        // let s = value.clone().into_string()?;
+                        // geo_ascii_params = Some(s.split('|').map(|s| s.to_string()).collect())
+                    }
+                    Tag::GeoDoubleParamsTag => {
+                        geo_double_params =
+                            Some(to_vec(value, Value::into_f64, Value::into_f64_vec)?);
+                    }
+                    // Tags for which the tiff crate doesn't have a hard-coded enum variant
+                    Tag::Unknown(DOCUMENT_NAME) => {
+                        document_name = Some(value.clone().into_string()?)
+                    }
+                    Tag::Unknown(GDAL_METADATA) => {
+                        gdal_metadata = Some(GdalMetadata::parse(&value.clone().into_string()?))
+                    }
+                    _ => {}
+                };
+                Ok::<_, AiocogeoError>(())
             };
-            Ok::<_, TiffError>(())
+            parse().map_err(|err| match err {
+                AiocogeoError::General(reason) => tag_data.invalid(tag, reason),
+                err => tag_data.invalid(tag, err),
+            })
         })?;
 
-        let mut geo_key_directory = None;
-
         // We need to actually parse the GeoKeyDirectory after parsing all other tags because the
         // GeoKeyDirectory relies on `GeoAsciiParamsTag` having been parsed.
-        if let Some(data) = geo_key_directory_data {
-            let mut chunks = data.chunks(4);
-
-            let header = chunks.next().unwrap();
-            let key_directory_version = header[0];
-            assert_eq!(key_directory_version, 1);
-
-            let key_revision = header[1];
-            assert_eq!(key_revision, 1);
-
-            // let key_minor_revision = header[2];
-            let number_of_keys = header[3];
-
-            let mut tags = HashMap::with_capacity(number_of_keys as usize);
-            for _ in 0..number_of_keys {
-                let chunk = chunks.next().unwrap();
-
-                let key_id = chunk[0];
-                let tag_name = GeoKeyTag::try_from_primitive(key_id).unwrap();
-
-                let tag_location = chunk[1];
-                let count = chunk[2];
-                let value_offset = chunk[3];
-
-                if tag_location == 0 {
-                    tags.insert(tag_name, Value::Short(value_offset));
-                } else if Tag::from_u16_exhaustive(tag_location) == Tag::GeoAsciiParamsTag {
-                    // If the tag_location points to the value of Tag::GeoAsciiParamsTag, then we
-                    // need to extract a subslice from GeoAsciiParamsTag
-
-                    let geo_ascii_params = geo_ascii_params.as_ref().unwrap();
-                    let value_offset = value_offset as usize;
-                    let mut s = &geo_ascii_params[value_offset..value_offset + count as usize];
-
-                    // It seems that this string subslice might always include the final |
-                    // character?
-                    if s.ends_with('|') {
-                        s = &s[0..s.len() - 1];
+        let geo_key_directory = match geo_key_directory_data {
+            Some(data) => {
+                let keys = parse_geo_keys(
+                    &data,
+                    geo_ascii_params.as_deref(),
+                    geo_double_params.as_deref(),
+                    mode,
+                )
+                .and_then(|keys| Ok(GeoKeyDirectory::from_tags(keys)?))
+                .map_err(|err| match err {
+                    AiocogeoError::General(reason) => {
+                        tag_data.invalid(Tag::GeoKeyDirectoryTag, reason)
                     }
+                    err => tag_data.invalid(Tag::GeoKeyDirectoryTag, err),
+                })?;
+                Some(keys)
+            }
+            None => None,
+        };
 
-                    tags.insert(tag_name, Value::Ascii(s.to_string()));
-                } else if Tag::from_u16_exhaustive(tag_location) == Tag::GeoDoubleParamsTag {
-                    // If the tag_location points to the value of Tag::GeoDoubleParamsTag, then we
-                    // need to extract a subslice from GeoDoubleParamsTag
-
-                    let geo_double_params = geo_double_params.as_ref().unwrap();
-                    let value_offset = value_offset as usize;
-                    let value = if count == 1 {
-                        Value::Double(geo_double_params[value_offset])
-                    } else {
-                        let x = geo_double_params[value_offset..value_offset + count as usize]
-                            .iter()
-                            .map(|val| Value::Double(*val))
-                            .collect();
-                        Value::List(x)
-                    };
-                    tags.insert(tag_name, value);
+        // Tags with a default value in the TIFF spec
+        let samples_per_pixel = samples_per_pixel.unwrap_or(1);
+        let compression = compression.unwrap_or(CompressionMethod::None);
+        let planar_configuration = planar_configuration.unwrap_or(PlanarConfiguration::Chunky);
+        let sample_format = sample_format.unwrap_or_else(|| vec![SampleFormat::Uint]);
+
+        let bits_per_sample = match bits_per_sample {
+            Some(bits_per_sample) => bits_per_sample,
+            None => {
+                if mode == ParseMode::Strict {
+                    return Err(AiocogeoError::MissingRequiredTag(Tag::BitsPerSample));
                 }
+                vec![8]
+            }
+        };
+        let photometric_interpretation = match photometric_interpretation {
+            Some(photometric_interpretation) => photometric_interpretation,
+            None => {
+                if mode == ParseMode::Strict {
+                    return Err(AiocogeoError::MissingRequiredTag(
+                        Tag::PhotometricInterpretation,
+                    ));
+                }
+                if samples_per_pixel >= 3 {
+                    PhotometricInterpretation::RGB
+                } else {
+                    PhotometricInterpretation::BlackIsZero
+                }
+            }
+        };
+        let bits_per_sample =
+            per_sample(bits_per_sample, samples_per_pixel, "BitsPerSample", mode)?;
+
+        // Check the tile grid, which reads index into without further checks
+        let image_width = required(image_width, Tag::ImageWidth)?;
+        let image_height = required(image_height, Tag::ImageLength)?;
+        let tile_width = required(tile_width, Tag::TileWidth)?;
+        let tile_height = required(tile_height, Tag::TileLength)?;
+        let tile_offsets = required(tile_offsets, Tag::TileOffsets)?;
+        let tile_byte_counts = required(tile_byte_counts, Tag::TileByteCounts)?;
+        for (tag, value) in [
+            (Tag::ImageWidth, image_width),
+            (Tag::ImageLength, image_height),
+            (Tag::TileWidth, tile_width),
+            (Tag::TileLength, tile_height),
+        ] {
+            if value == 0 {
+                return Err(tag_data.invalid(tag, "must not be zero"));
+            }
+        }
+        let planes = match planar_configuration {
+            PlanarConfiguration::Planar => samples_per_pixel as usize,
+            _ => 1,
+        };
+        let tiles = [
+            image_width.div_ceil(tile_width) as u64,
+            image_height.div_ceil(tile_height) as u64,
+            planes as u64,
+        ]
+        .into_iter()
+        .try_fold(1u64, u64::checked_mul)
+        .ok_or_else(|| tag_data.invalid(Tag::ImageWidth, "too many tiles"))?;
+        for (tag, values) in [
+            (Tag::TileOffsets, &tile_offsets),
+            (Tag::TileByteCounts, &tile_byte_counts),
+        ] {
+            if (values.len() as u64) < tiles {
+                return Err(tag_data.invalid(
+                    tag,
+                    format!("has {} values for {tiles} tiles", values.len()),
+                ));
             }
-            geo_key_directory = Some(GeoKeyDirectory::from_tags(tags)?);
-            dbg!(&geo_key_directory);
         }
+        if let Some(idx) = (0..tiles as usize).find(|&idx| {
+            tile_offsets[idx]
+                .checked_add(tile_byte_counts[idx])
+                .is_none()
+        }) {
+            return Err(tag_data.invalid(
+                Tag::TileByteCounts,
+                format!("tile {idx} ends past the largest possible offset"),
+            ));
+        }
+        let sample_format = per_sample(sample_format, samples_per_pixel, "SampleFormat", mode)?;
 
         Ok(Self {
             new_subfile_type,
-            image_width: image_width.unwrap(),
-            image_height: image_height.unwrap(),
-            bits_per_sample: bits_per_sample.unwrap(),
-            compression: compression.unwrap(),
-            photometric_interpretation: photometric_interpretation.unwrap(),
+            image_width,
+            image_height,
+            bits_per_sample,
+            compression,
+            photometric_interpretation,
+            fill_order: fill_order.unwrap_or_default(),
             document_name,
             image_description,
             strip_offsets,
             orientation,
-            samples_per_pixel: samples_per_pixel.unwrap(),
+            north_up_orientation: Orientation::TopLeft,
+            samples_per_pixel,
             rows_per_strip,
             strip_byte_counts,
             min_sample_value,
             max_sample_value,
             x_resolution,
             y_resolution,
-            planar_configuration: planar_configuration.unwrap(),
+            planar_configuration,
             resolution_unit,
             software,
             date_time,
@@ -412,66 +756,122 @@ impl ImageFileDirectory {
             host_computer,
             predictor,
             color_map,
-            tile_width: tile_width.unwrap(),
-            tile_height: tile_height.unwrap(),
-            tile_offsets: tile_offsets.unwrap(),
-            tile_byte_counts: tile_byte_counts.unwrap(),
+            tile_width,
+            tile_height,
+            tile_offsets,
+            tile_byte_counts,
             extra_samples,
-            sample_format: sample_format.unwrap(),
+            sample_format,
             copyright,
             jpeg_tables,
             geo_key_directory,
             model_pixel_scale,
             model_tiepoint,
-            other_tags,
+            gdal_metadata,
+            exif: None,
+            tags: tag_data.values,
+            offset: 0,
             next_ifd_offset,
+            endianness,
         })
     }
 
+    /// Return the raw value of any tag in this IFD, including private and unknown tags
+    pub fn tag(&self, tag: Tag) -> Option<&Value> {
+        self.tags.get(&tag)
+    }
+
+    /// Return the raw value of the tag with the given numeric code
+    pub fn tag_by_code(&self, code: u16) -> Option<&Value> {
+        self.tag(Tag::from_u16_exhaustive(code))
+    }
+
+    /// Return all tags of this IFD with their raw values, sorted by tag code
+    pub fn tags(&self) -> Vec<(Tag, &Value)> {
+        let mut tags: Vec<_> = self.tags.iter().map(|(tag, value)| (*tag, value)).collect();
+        tags.sort_by_key(|(tag, _)| tag.to_u16());
+        tags
+    }
+
+    /// Parse GDAL's `RPCCoefficientTag`, if present
+    pub fn rpc_coefficients(&self) -> Option<RpcCoefficients> {
+        let values = self
+            .tag_by_code(RPC_COEFFICIENT)?
+            .clone()
+            .into_f64_vec()
+            .ok()?;
+        RpcCoefficients::from_tag_values(&values)
+    }
+
+    /// Return the embedded ICC color profile, if any
+    pub fn icc_profile(&self) -> Option<Vec<u8>> {
+        self.tag_by_code(ICC_PROFILE)?.clone().into_u8_vec().ok()
+    }
+
+    /// Return the embedded XMP metadata packet, if any
+    pub fn xmp(&self) -> Option<String> {
+        let bytes = self.tag_by_code(XMP)?.clone().into_u8_vec().ok()?;
+        let xmp = String::from_utf8(bytes).ok()?;
+        Some(xmp.trim_end_matches(char::from(0)).to_string())
+    }
+
+    /// Return the EXIF sub-IFD, if any
+    pub fn exif(&self) -> Option<&ExifDirectory> {
+        self.exif.as_ref()
+    }
+
     /// Check if an IFD is masked based on a dictionary of tiff tags
     /// https://www.awaresystems.be/imaging/tiff/tifftags/newsubfiletype.html
     /// https://gdal.org/drivers/raster/gtiff.html#internal-nodata-masks
     pub fn is_masked(&self) -> bool {
-        if let Some(subfile_type) = self.new_subfile_type {
-            (subfile_type == 1 || subfile_type == 2)
-                && self.photometric_interpretation == PhotometricInterpretation::TransparencyMask
-                && self.compression == CompressionMethod::Deflate
-        } else {
-            false
-        }
+        self.is_mask()
+    }
+
+    /// Returns true if this IFD is a transparency mask of another image, as written by GDAL for
+    /// internal nodata masks
+    pub(crate) fn is_mask(&self) -> bool {
+        self.new_subfile_type
+            .is_some_and(|subfile_type| subfile_type & 4 != 0)
+            && self.photometric_interpretation == PhotometricInterpretation::TransparencyMask
     }
 
     /// Construct colormap from colormap tag
     pub fn colormap(&self) -> Option<HashMap<usize, [u8; 3]>> {
+        let colors = self.colormap_rgba(None)?;
+        Some(
+            colors
+                .into_iter()
+                .enumerate()
+                .map(|(idx, [r, g, b, _])| (idx, [r, g, b]))
+                .collect(),
+        )
+    }
+
+    /// Return the colormap as RGBA colors indexed by pixel value, ready to look up the colors of
+    /// a palette image. The color of the `nodata` index, if any, is transparent.
+    ///
+    /// Returns `None` if there is no colormap or it has fewer than `3 * 2^BitsPerSample` values.
+    pub fn colormap_rgba(&self, nodata: Option<f64>) -> Option<Vec<[u8; 4]>> {
         fn cmap_transform(val: u16) -> u8 {
-            let val = ((val as f64 / 65535.0) * 255.0).floor();
-            if val >= 255.0 {
-                255
-            } else if val < 0.0 {
-                0
-            } else {
-                val as u8
-            }
+            ((val as f64 / 65535.0) * 255.0).floor().clamp(0.0, 255.0) as u8
         }
 
-        if let Some(cmap_data) = &self.color_map {
-            let bits_per_sample = self.bits_per_sample[0];
-            let count = 2_usize.pow(bits_per_sample as u32);
-            let mut result = HashMap::new();
-
-            // TODO: support nodata
-            for idx in 0..count {
-                let color: [u8; 3] =
-                    std::array::from_fn(|i| cmap_transform(cmap_data[idx + i * count]));
-                // TODO: Handle nodata value
-
-                result.insert(idx, color);
-            }
-
-            Some(result)
-        } else {
-            None
+        let cmap_data = self.color_map.as_ref()?;
+        let count = 1usize.checked_shl(*self.bits_per_sample.first()? as u32)?;
+        if cmap_data.len() < 3 * count {
+            return None;
         }
+        let nodata = nodata.filter(|value| value.fract() == 0.0 && *value >= 0.0);
+        Some(
+            (0..count)
+                .map(|idx| {
+                    let [r, g, b] =
+                        std::array::from_fn(|i| cmap_transform(cmap_data[idx + i * count]));
+                    let alpha = if nodata == Some(idx as f64) { 0 } else { 255 };
+                    [r, g, b, alpha]
+                })
+                .collect(),
+        )
     }
 
     pub fn compression(&self) -> CompressionMethod {
@@ -482,14 +882,209 @@ impl ImageFileDirectory {
         self.samples_per_pixel
     }
 
-    // pub fn dtype(&self)
+    /// Return the width of the image in pixels, in stored order
+    pub fn width(&self) -> u32 {
+        self.image_width
+    }
+
+    /// Return the height of the image in pixels, in stored order
+    pub fn height(&self) -> u32 {
+        self.image_height
+    }
+
+    /// Return the width and height of the internal tiles in pixels, in stored order
+    pub fn tile_size(&self) -> (u32, u32) {
+        (self.tile_width, self.tile_height)
+    }
+
+    /// Return the data type of the samples in this IFD.
+    ///
+    /// Fails if the bands have different bits per sample or sample formats, which TIFF allows
+    /// but reading doesn't support; see [`ImageFileDirectory::dtypes`].
+    pub fn dtype(&self) -> Result<DataType> {
+        let dtypes = self.dtypes()?;
+        match dtypes.split_first() {
+            Some((&first, rest)) if rest.iter().all(|&dtype| dtype == first) => Ok(first),
+            _ => Err(AiocogeoError::General(format!(
+                "unsupported data types: bands with different bits per sample {:?} or sample \
+                 formats {:?}",
+                self.bits_per_sample, self.sample_format
+            ))),
+        }
+    }
+
+    /// Return the data type of the samples of each band in this IFD, which only differ in files
+    /// with mixed bits per sample or sample formats
+    pub fn dtypes(&self) -> Result<Vec<DataType>> {
+        self.bits_per_sample
+            .iter()
+            .zip(&self.sample_format)
+            .map(|(&bits, &format)| {
+                DataType::from_tiff(self.decoded_bits_per_sample(bits), format).ok_or_else(|| {
+                    AiocogeoError::General(format!(
+                        "unsupported data type: {bits} bits per sample with sample format \
+                         {format:?}"
+                    ))
+                })
+            })
+            .collect()
+    }
+
+    /// Return whether every band has the same bits per sample and sample format
+    pub fn has_uniform_samples(&self) -> bool {
+        self.bits_per_sample
+            .windows(2)
+            .all(|pair| pair[0] == pair[1])
+            && self.sample_format.windows(2).all(|pair| pair[0] == pair[1])
+    }
+
+    /// Return the number of bits of each decompressed sample stored with `bits`: JPEG decodes
+    /// samples of more than 8 bits, e.g. 12-bit JPEG, to 16 bit values rather than packing them
+    fn decoded_bits_per_sample(&self, bits: u16) -> u16 {
+        match (self.compression, bits) {
+            (CompressionMethod::ModernJPEG, 9..=16) => 16,
+            (_, bits) => bits,
+        }
+    }
+
+    /// Return the descriptive tags of the image
+    pub fn file_metadata(&self) -> FileMetadata {
+        FileMetadata {
+            document_name: self.document_name.clone(),
+            image_description: self.image_description.clone(),
+            software: self.software.clone(),
+            date_time: self.date_time.clone(),
+            artist: self.artist.clone(),
+            host_computer: self.host_computer.clone(),
+            copyright: self.copyright.clone(),
+        }
+    }
+
+    /// Return the time the file was written, from the `DateTime` tag or GDAL's
+    /// `TIFFTAG_DATETIME` metadata item, with its offset from UTC if the value has one
+    pub fn date_time(&self) -> Option<Timestamp> {
+        self.date_time
+            .as_deref()
+            .and_then(parse_datetime)
+            .or_else(|| self.metadata_date_time("TIFFTAG_DATETIME"))
+    }
+
+    /// Return the time the image was acquired, from GDAL's `ACQUISITIONDATETIME` metadata item,
+    /// in any domain, or the EXIF `DateTimeOriginal` tag with the offset of
+    /// `OffsetTimeOriginal`, with its offset from UTC if either has one
+    pub fn acquisition_date_time(&self) -> Option<Timestamp> {
+        self.metadata_date_time("ACQUISITIONDATETIME").or_else(|| {
+            let exif = self.exif.as_ref()?;
+            let datetime = parse_datetime(&exif.date_time_original()?)?;
+            let offset = exif
+                .offset_time_original()
+                .as_deref()
+                .and_then(parse_offset);
+            Some(match (datetime, offset) {
+                (Timestamp::Local(datetime), Some(offset)) => datetime
+                    .and_local_timezone(offset)
+                    .single()
+                    .map_or(Timestamp::Local(datetime), Timestamp::Offset),
+                (datetime, _) => datetime,
+            })
+        })
+    }
+
+    /// Parse the first dataset-level GDAL metadata item with the given name, in any domain, as a
+    /// timestamp
+    fn metadata_date_time(&self, name: &str) -> Option<Timestamp> {
+        self.gdal_metadata
+            .as_ref()?
+            .items()
+            .iter()
+            .filter(|item| item.name == name && item.sample.is_none())
+            .find_map(|item| parse_datetime(&item.value))
+    }
+
+    /// Return the nodata value of the image, from GDAL's `GDAL_NODATA` tag (42113)
+    pub fn nodata(&self) -> Option<f64> {
+        match self.tag_by_code(GDAL_NODATA)? {
+            Value::Ascii(value) => value.trim().trim_end_matches('\0').parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Return the number of significant bits per sample, if it differs from the storage size.
+    ///
+    /// This is read from GDAL's `NBITS` metadata item, falling back to `BitsPerSample` when
+    /// samples are stored with a non-standard bit depth.
+    pub fn nbits(&self) -> Option<u16> {
+        let from_metadata = self.gdal_metadata.as_ref().and_then(|metadata| {
+            metadata
+                .get("NBITS", None, Some("IMAGE_STRUCTURE"))
+                .and_then(|nbits| nbits.trim().parse().ok())
+        });
+        from_metadata.or_else(|| match self.bits_per_sample[0] {
+            8 | 16 | 32 | 64 | 128 => None,
+            bits => Some(bits),
+        })
+    }
+
+    /// Return the per-band scale from GDAL metadata, defaulting to 1 for bands without one
+    pub fn scales(&self) -> Vec<f64> {
+        self.band_metadata_values("SCALE", 1.0)
+    }
+
+    /// Return the per-band offset from GDAL metadata, defaulting to 0 for bands without one
+    pub fn offsets(&self) -> Vec<f64> {
+        self.band_metadata_values("OFFSET", 0.0)
+    }
+
+    /// Return the per-band description from GDAL metadata, or `None` for bands without one.
+    ///
+    /// GDAL stores band descriptions as `<Item name="DESCRIPTION" sample="0"
+    /// role="description">`.
+    pub fn band_descriptions(&self) -> Vec<Option<String>> {
+        (0..self.bands() as usize)
+            .map(|band| {
+                self.gdal_metadata
+                    .as_ref()
+                    .and_then(|metadata| metadata.get("DESCRIPTION", Some(band), None))
+                    .map(str::to_string)
+            })
+            .collect()
+    }
 
-    // pub fn nodata(&self)
+    /// Parse a numeric per-band GDAL metadata item for every band
+    fn band_metadata_values(&self, name: &str, default: f64) -> Vec<f64> {
+        (0..self.bands() as usize)
+            .map(|band| {
+                self.gdal_metadata
+                    .as_ref()
+                    .and_then(|metadata| metadata.get(name, Some(band), None))
+                    .and_then(|value| value.trim().parse().ok())
+                    .unwrap_or(default)
+            })
+            .collect()
+    }
+
+    /// Return the color interpretation of each band
+    pub fn color_interp(&self) -> Vec<ColorInterp> {
+        ColorInterp::from_tiff(
+            self.photometric_interpretation,
+            self.bands() as usize,
+            self.extra_samples.as_deref().unwrap_or_default(),
+            self.compression == CompressionMethod::ModernJPEG,
+        )
+    }
 
     pub fn has_extra_samples(&self) -> bool {
         self.extra_samples.is_some()
     }
 
+    /// Return the orientation of the image, defaulting to top-left when the tag is absent or
+    /// invalid
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+            .and_then(|val| Orientation::try_from_primitive(val).ok())
+            .unwrap_or_default()
+    }
+
     /// Return the interleave of the IFD
     pub fn interleave(&self) -> PlanarConfiguration {
         self.planar_configuration
@@ -497,25 +1092,342 @@ impl ImageFileDirectory {
 
     /// Returns true if this IFD contains a full resolution image (not an overview)
     pub fn is_full_resolution(&self) -> bool {
-        if let Some(val) = self.new_subfile_type {
-            val != 0
+        // Bit 0 of `NewSubfileType` marks reduced resolution images
+        self.new_subfile_type
+            .is_none_or(|subfile_type| subfile_type & 1 == 0)
+    }
+
+    /// Return the orientation that tiles are read in with the given options
+    pub(crate) fn read_orientation(&self, options: &ReadOptions) -> Orientation {
+        if options.north_up {
+            self.north_up_orientation
+        } else if options.ignore_orientation {
+            Orientation::TopLeft
+        } else {
+            self.orientation()
+        }
+    }
+
+    /// Return the byte ranges of the tile at the given x/y index in the given orientation.
+    ///
+    /// Band-interleaved images store one tile per band, so this returns one range per band, or
+    /// per selected band when `bands` is given; otherwise it returns a single range.
+    pub(crate) fn tile_ranges(
+        &self,
+        x: usize,
+        y: usize,
+        orientation: Orientation,
+        bands: Option<&[usize]>,
+    ) -> Result<Vec<Range<usize>>> {
+        let (x, y) = self.stored_tile_index(x, y, orientation)?;
+        self.check_bands(bands)?;
+
+        let (x_count, y_count) = self.tile_count();
+        let idx = (y * x_count) + x;
+        // All tiles of the first band are stored before those of the second band, and so on
+        match self.planar_configuration {
+            PlanarConfiguration::Chunky => Ok(vec![self.tile_range(idx)?]),
+            _ => match bands {
+                Some(bands) => bands
+                    .iter()
+                    .map(|band| self.tile_range(band * x_count * y_count + idx))
+                    .collect(),
+                None => (0..self.bands() as usize)
+                    .map(|band| self.tile_range(band * x_count * y_count + idx))
+                    .collect(),
+            },
+        }
+    }
+
+    /// Whether the tiles of `mask` are stored right after the image tiles they mask, as in COGs
+    /// written by GDAL with `MASK_INTERLEAVED_WITH_IMAGERY=YES`: each stored mask tile starts
+    /// within [`MASK_INTERLEAVE_GAP`] bytes after the image tile of the last band at its position.
+    pub(crate) fn interleaves_mask(&self, mask: &ImageFileDirectory) -> bool {
+        let count = mask.tile_offsets.len();
+        let Some(last_band) = self.tile_offsets.len().checked_sub(count) else {
+            return false;
+        };
+        let mut stored =
+            (0..count).filter(|&idx| mask.tile_range(idx).is_ok_and(|r| !r.is_empty()));
+        let mut any = false;
+        let interleaved = stored.all(|idx| {
+            any = true;
+            match (self.tile_range(last_band + idx), mask.tile_range(idx)) {
+                (Ok(image), Ok(mask)) => {
+                    !image.is_empty()
+                        && mask.start >= image.end
+                        && mask.start - image.end <= MASK_INTERLEAVE_GAP
+                }
+                _ => false,
+            }
+        });
+        any && interleaved
+    }
+
+    /// Return whether the tile at the given x/y index in the given orientation is stored, without
+    /// fetching it. Band-interleaved tiles are stored if any of their bands is.
+    pub(crate) fn has_tile(&self, x: usize, y: usize, orientation: Orientation) -> Result<bool> {
+        Ok(self
+            .tile_ranges(x, y, orientation, None)?
+            .iter()
+            .any(|range| !range.is_empty()))
+    }
+
+    /// Check that every selected band exists in the image
+    fn check_bands(&self, bands: Option<&[usize]>) -> Result<()> {
+        let count = self.bands() as usize;
+        match bands.and_then(|bands| bands.iter().find(|&&band| band >= count)) {
+            Some(band) => Err(AiocogeoError::General(format!(
+                "band {band} is out of range for an image with {count} bands"
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Decompress and decode the compressed bytes of a tile, as fetched from
+    /// [`Self::tile_ranges`] with the same `bands`, applying the given orientation and taking
+    /// scratch space from `pool`, if any
+    pub(crate) fn decode(
+        &self,
+        tiles: Vec<Bytes>,
+        orientation: Orientation,
+        bands: Option<&[usize]>,
+        pool: Option<&BufferPool>,
+    ) -> Result<RasterArray> {
+        let mut layout = self.tile_layout()?;
+        let buffers = tiles
+            .into_iter()
+            .map(|tile| {
+                decompress_tile(
+                    self.compression,
+                    tile,
+                    self.jpeg_tables.as_deref(),
+                    self.endianness,
+                    take_buffer(pool, layout.buffer_len()),
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut tile = match (bands, self.planar_configuration) {
+            (None, _) => decode_tile(buffers, &layout, pool)?,
+            (Some(bands), PlanarConfiguration::Chunky) => {
+                decode_tile(buffers, &layout, pool)?.select_bands(bands)
+            }
+            // Only the tiles of the selected bands were fetched
+            (Some(bands), _) => {
+                layout.bands = bands.len();
+                decode_tile(buffers, &layout, pool)?
+            }
+        };
+
+        if orientation != Orientation::TopLeft {
+            tile = apply_orientation(tile, orientation);
+        }
+
+        Ok(tile)
+    }
+
+    /// Decode the fetched buffer of a tile of a transparency mask into a `(height, width)` array
+    /// in the given orientation that is `true` where pixels are valid.
+    ///
+    /// 1-bit masks, as GDAL writes them, are unpacked straight to booleans; masks with more bits
+    /// per sample are valid where they're non-zero.
+    pub(crate) fn decode_mask(
+        &self,
+        tiles: Vec<Bytes>,
+        orientation: Orientation,
+        pool: Option<&BufferPool>,
+    ) -> Result<Array2<bool>> {
+        if self.bits_per_sample[..] != [1] {
+            let tile = self.decode(tiles, orientation, None, pool)?.to_f64()?;
+            return Ok(tile.index_axis(Axis(0), 0).mapv(|value| value != 0.0));
+        }
+        let [tile] = <[Bytes; 1]>::try_from(tiles).map_err(|tiles| {
+            AiocogeoError::General(format!("expected 1 mask buffer, got {}", tiles.len()))
+        })?;
+        let layout = self.tile_layout()?;
+        let buffer = decompress_tile(
+            self.compression,
+            tile,
+            self.jpeg_tables.as_deref(),
+            self.endianness,
+            take_buffer(pool, layout.buffer_len()),
+        )?;
+        let mask = unpack_mask(&buffer, layout.width, layout.height, self.fill_order)?;
+        give_buffer(pool, buffer, layout.buffer_len());
+        Ok(apply_mask_orientation(mask, orientation))
+    }
+
+    /// Fetch the compressed bytes of the internal tile at the given x/y index, without decoding.
+    ///
+    /// The index is in stored order; the `Orientation` tag is not applied.
+    pub(crate) async fn get_raw_tile(
+        &self,
+        store: &dyn ObjectStore,
+        path: &Path,
+        x: usize,
+        y: usize,
+    ) -> Result<RawTile> {
+        if self.planar_configuration != PlanarConfiguration::Chunky {
+            return Err(AiocogeoError::General(
+                "raw tiles are not supported for band-interleaved images".to_string(),
+            ));
+        }
+        let (x, y) = self.stored_tile_index(x, y, Orientation::TopLeft)?;
+        let (x_count, _) = self.tile_count();
+        let range = self.tile_range(y * x_count + x)?;
+        let bytes = store.get_range(path, range.clone()).await?;
+        Ok(RawTile {
+            bytes,
+            offset: range.start as u64,
+            length: range.len() as u64,
+            compression: self.compression,
+            jpeg_tables: self.jpeg_tables.clone().map(Bytes::from),
+        })
+    }
+
+    /// Return the width and height of the image in the given orientation
+    pub(crate) fn oriented_size(&self, orientation: Orientation) -> (usize, usize) {
+        let (width, height) = (self.image_width as usize, self.image_height as usize);
+        if orientation.transposes() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Return the width and height of a tile in the given orientation
+    pub(crate) fn oriented_tile_size(&self, orientation: Orientation) -> (usize, usize) {
+        let (width, height) = (self.tile_width as usize, self.tile_height as usize);
+        if orientation.transposes() {
+            (height, width)
         } else {
-            true
+            (width, height)
+        }
+    }
+
+    /// Return the byte offset of the tile at the given x/y index in the given orientation. For
+    /// band-interleaved images, this is the offset of the first band's tile.
+    pub(crate) fn tile_offset(&self, x: usize, y: usize, orientation: Orientation) -> Result<u64> {
+        let (x, y) = self.stored_tile_index(x, y, orientation)?;
+        let (x_count, _) = self.tile_count();
+        Ok(self.tile_offsets[y * x_count + x])
+    }
+
+    /// Return the byte range of every internal tile, keyed by its x/y index in stored order.
+    ///
+    /// Band-interleaved images store one tile per band, so each index maps to one range per band;
+    /// otherwise each index maps to a single range. Sparse tiles, which GDAL writes with an
+    /// offset and length of 0, have an empty range.
+    pub fn tile_byte_ranges(&self) -> HashMap<(usize, usize), Vec<Range<u64>>> {
+        let (x_count, y_count) = self.tile_count();
+        let planes = match self.planar_configuration {
+            PlanarConfiguration::Chunky => 1,
+            _ => self.bands() as usize,
+        };
+        let mut result = HashMap::with_capacity(x_count * y_count);
+        for y in 0..y_count {
+            for x in 0..x_count {
+                let idx = y * x_count + x;
+                let ranges = (0..planes)
+                    .map(|plane| self.tile_byte_range(plane * x_count * y_count + idx))
+                    .collect();
+                result.insert((x, y), ranges);
+            }
         }
+        result
     }
 
-    pub async fn get_tile(&self, x: usize, y: usize) {
-        let idx = (y * self.tile_count().0) + x;
+    /// The byte range of the tile at the given position in the `TileOffsets` array
+    fn tile_byte_range(&self, idx: usize) -> Range<u64> {
         let offset = self.tile_offsets[idx];
         // TODO: aiocogeo has a -1 here, but I think that was in error
         let byte_count = self.tile_byte_counts[idx];
-        todo!()
+        // Checked not to overflow when parsing the IFD
+        offset..offset + byte_count
+    }
+
+    /// The byte range of the tile at the given position in the `TileOffsets` array, as a range of
+    /// `usize` for [`ObjectStore`] requests, which fails on 32-bit targets for files past 4 GiB
+    fn tile_range(&self, idx: usize) -> Result<Range<usize>> {
+        let range = self.tile_byte_range(idx);
+        match (usize::try_from(range.start), usize::try_from(range.end)) {
+            (Ok(start), Ok(end)) => Ok(start..end),
+            _ => Err(AiocogeoError::General(format!(
+                "byte range {range:?} is not addressable on this platform"
+            ))),
+        }
+    }
+
+    /// Map the index of a tile in visual orientation to the index of the stored tile
+    fn stored_tile_index(
+        &self,
+        x: usize,
+        y: usize,
+        orientation: Orientation,
+    ) -> Result<(usize, usize)> {
+        let (x_count, y_count) = self.tile_count();
+        let (u, v) = if orientation.transposes() {
+            (y, x)
+        } else {
+            (x, y)
+        };
+        if u >= x_count || v >= y_count {
+            let (width, height) = if orientation.transposes() {
+                (y_count, x_count)
+            } else {
+                (x_count, y_count)
+            };
+            return Err(AiocogeoError::General(format!(
+                "tile ({x}, {y}) is outside of the {width}x{height} tile grid"
+            )));
+        }
+
+        // When an axis is reversed and the image doesn't fill its last tile, the visual tile grid
+        // doesn't line up with the stored tiles.
+        if (orientation.flips_columns() && !self.image_width.is_multiple_of(self.tile_width))
+            || (orientation.flips_rows() && !self.image_height.is_multiple_of(self.tile_height))
+        {
+            return Err(AiocogeoError::General(format!(
+                "cannot apply orientation {orientation:?} to an image whose size is not a multiple \
+                 of its tile size; use `ReadOptions::ignore_orientation` for raw tiles"
+            )));
+        }
+
+        let x = if orientation.flips_columns() {
+            x_count - 1 - u
+        } else {
+            u
+        };
+        let y = if orientation.flips_rows() {
+            y_count - 1 - v
+        } else {
+            v
+        };
+        Ok((x, y))
+    }
+
+    /// Describe the layout of decompressed tiles in this IFD
+    fn tile_layout(&self) -> Result<TileLayout> {
+        // Fails unless every band has the same bits per sample
+        let data_type = self.dtype()?;
+        Ok(TileLayout {
+            width: self.tile_width as usize,
+            height: self.tile_height as usize,
+            bands: self.bands() as usize,
+            bits_per_sample: self.decoded_bits_per_sample(self.bits_per_sample[0]),
+            data_type,
+            planar_configuration: self.planar_configuration,
+            fill_order: self.fill_order,
+            endianness: self.endianness,
+            predictor: self.predictor.unwrap_or(Predictor::None),
+        })
     }
 
     /// Return the number of x/y tiles in the IFD
     pub fn tile_count(&self) -> (usize, usize) {
-        let x_count = (self.image_width as f64 / self.tile_width as f64).ceil();
-        let y_count = (self.image_height as f64 / self.tile_height as f64).ceil();
+        let x_count = self.image_width.div_ceil(self.tile_width);
+        let y_count = self.image_height.div_ceil(self.tile_height);
         (x_count as usize, y_count as usize)
     }
 
@@ -523,20 +1435,71 @@ impl ImageFileDirectory {
     ///
     /// This does not yet implement decimation
     pub fn geotransform(&self) -> Option<AffineTransform> {
-        if let (Some(model_pixel_scale), Some(model_tiepoint)) =
-            (&self.model_pixel_scale, &self.model_tiepoint)
-        {
-            Some(AffineTransform::new(
-                model_pixel_scale[0],
-                0.0,
-                model_tiepoint[3],
-                0.0,
-                -model_pixel_scale[1],
-                model_tiepoint[4],
-            ))
+        let model_pixel_scale = self.model_pixel_scale.as_ref()?;
+        let tiepoint = self.tiepoints().into_iter().next()?;
+
+        // The tiepoint may reference any raster location, not just the top-left corner
+        Some(AffineTransform::new(
+            model_pixel_scale[0],
+            0.0,
+            tiepoint.x - tiepoint.i * model_pixel_scale[0],
+            0.0,
+            -model_pixel_scale[1],
+            tiepoint.y + tiepoint.j * model_pixel_scale[1],
+        ))
+    }
+
+    /// Return all tiepoints of the `ModelTiepointTag`, including their Z components
+    pub fn tiepoints(&self) -> Vec<Tiepoint> {
+        self.model_tiepoint
+            .as_deref()
+            .map(Tiepoint::from_tag_values)
+            .unwrap_or_default()
+    }
+
+    /// Return the Z component of the `ModelPixelScaleTag`, i.e. the model units per raster value
+    /// along the vertical axis.
+    ///
+    /// Note that many writers set this to 0 when the raster values are not heights.
+    pub fn vertical_scale(&self) -> Option<f64> {
+        self.model_pixel_scale.as_ref()?.get(2).copied()
+    }
+
+    /// Return the model Z coordinate corresponding to a raster value of 0, derived from the first
+    /// tiepoint and the vertical scale.
+    pub fn vertical_origin(&self) -> Option<f64> {
+        let tiepoint = self.tiepoints().into_iter().next()?;
+        let scale = self.vertical_scale().unwrap_or(0.0);
+        Some(tiepoint.z - tiepoint.k * scale)
+    }
+
+    /// Return the geotransform of the image in visual orientation
+    ///
+    /// This maps pixels of tiles read with the `Orientation` tag applied, whereas
+    /// [`Self::geotransform`] maps pixels in stored order.
+    pub fn oriented_geotransform(&self) -> Option<AffineTransform> {
+        self.geotransform_in(self.orientation())
+    }
+
+    /// Return the geotransform of the image read in the given orientation
+    pub(crate) fn geotransform_in(&self, orientation: Orientation) -> Option<AffineTransform> {
+        let gt = self.geotransform()?;
+        let (col_scale, col_offset) = if orientation.flips_columns() {
+            (-1.0, self.image_width as f64)
         } else {
-            None
-        }
+            (1.0, 0.0)
+        };
+        let (row_scale, row_offset) = if orientation.flips_rows() {
+            (-1.0, self.image_height as f64)
+        } else {
+            (1.0, 0.0)
+        };
+        let visual_to_stored = if orientation.transposes() {
+            AffineTransform::new(0.0, col_scale, col_offset, row_scale, 0.0, row_offset)
+        } else {
+            AffineTransform::new(col_scale, 0.0, col_offset, 0.0, row_scale, row_offset)
+        };
+        Some(gt.compose(&visual_to_stored))
     }
 
     /// Return the bounds of the image in native crs
@@ -554,18 +1517,233 @@ impl ImageFileDirectory {
     }
 }
 
+/// Return the orientation that reads an image with the geotransform `gt` north-up, with rows
+/// from north to south and columns from west to east, to the nearest quarter turn
+fn north_up_orientation(gt: &AffineTransform) -> Orientation {
+    if gt.a().abs() + gt.e().abs() >= gt.b().abs() + gt.d().abs() {
+        Orientation::from_flags(false, gt.a() < 0.0, gt.e() > 0.0)
+    } else {
+        // Stored rows run north-south, so they become visual columns
+        Orientation::from_flags(true, gt.d() > 0.0, gt.b() < 0.0)
+    }
+}
+
+/// Return the value of a tag that files can't be read without
+fn required<T>(value: Option<T>, tag: Tag) -> Result<T> {
+    value.ok_or(AiocogeoError::MissingRequiredTag(tag))
+}
+
+/// Check that a tag has one value per sample. In lenient mode, a single value applies to all
+/// samples and missing values repeat the first one.
+fn per_sample<T: Copy>(
+    mut values: Vec<T>,
+    samples_per_pixel: u16,
+    tag: &str,
+    mode: ParseMode,
+) -> Result<Vec<T>> {
+    let samples = samples_per_pixel as usize;
+    if values.len() != samples {
+        mode.violation(|| {
+            format!(
+                "{tag} has {} values for {samples} samples per pixel",
+                values.len()
+            )
+        })?;
+        match values.first() {
+            Some(&first) => values.resize(samples, first),
+            None => return Err(AiocogeoError::General(format!("{tag} has no values"))),
+        }
+    }
+    Ok(values)
+}
+
+/// Parse the keys of a `GeoKeyDirectoryTag`, resolving values stored in `GeoAsciiParamsTag` and
+/// `GeoDoubleParamsTag`.
+///
+/// Keys with unknown IDs are skipped. In lenient mode, keys whose values can't be resolved are
+/// skipped too, and the version of the directory isn't checked.
+pub(crate) fn parse_geo_keys(
+    data: &[u16],
+    geo_ascii_params: Option<&str>,
+    geo_double_params: Option<&[f64]>,
+    mode: ParseMode,
+) -> Result<HashMap<GeoKeyTag, Value>> {
+    // http://geotiff.maptools.org/spec/geotiff2.4.html
+    let mut chunks = data.chunks_exact(4);
+    let Some(header) = chunks.next() else {
+        mode.violation(|| "GeoKeyDirectoryTag has no header".to_string())?;
+        return Ok(HashMap::new());
+    };
+    let (key_directory_version, key_revision) = (header[0], header[1]);
+    if (key_directory_version, key_revision) != (1, 1) {
+        mode.violation(|| {
+            format!("unsupported GeoKeyDirectory version {key_directory_version}.{key_revision}")
+        })?;
+    }
+    // let key_minor_revision = header[2];
+    let number_of_keys = header[3] as usize;
+    if chunks.len() < number_of_keys {
+        mode.violation(|| {
+            format!(
+                "GeoKeyDirectoryTag declares {number_of_keys} keys but holds {}",
+                chunks.len()
+            )
+        })?;
+    }
+
+    let mut tags = HashMap::with_capacity(number_of_keys);
+    for chunk in chunks.take(number_of_keys) {
+        let key_id = chunk[0];
+        let Ok(tag_name) = GeoKeyTag::try_from_primitive(key_id) else {
+            continue;
+        };
+
+        let tag_location = chunk[1];
+        let count = chunk[2] as usize;
+        let value_offset = chunk[3] as usize;
+
+        let value = if tag_location == 0 {
+            Some(Value::Short(chunk[3]))
+        } else if Tag::from_u16_exhaustive(tag_location) == Tag::GeoAsciiParamsTag {
+            // If the tag_location points to the value of Tag::GeoAsciiParamsTag, then we need to
+            // extract a subslice from GeoAsciiParamsTag
+            geo_ascii_params
+                .and_then(|params| params.get(value_offset..value_offset + count))
+                .map(|s| {
+                    // It seems that this string subslice might always include the final |
+                    // character?
+                    Value::Ascii(s.strip_suffix('|').unwrap_or(s).to_string())
+                })
+        } else if Tag::from_u16_exhaustive(tag_location) == Tag::GeoDoubleParamsTag {
+            // If the tag_location points to the value of Tag::GeoDoubleParamsTag, then we need to
+            // extract a subslice from GeoDoubleParamsTag
+            geo_double_params
+                .and_then(|params| params.get(value_offset..value_offset + count))
+                .map(|values| match values {
+                    [value] => Value::Double(*value),
+                    values => Value::List(values.iter().map(|v| Value::Double(*v)).collect()),
+                })
+        } else {
+            None
+        };
+
+        match value {
+            Some(value) => {
+                tags.insert(tag_name, value);
+            }
+            None => mode.violation(|| {
+                format!("the value of GeoKey {tag_name:?} is outside of tag {tag_location}")
+            })?,
+        }
+    }
+    Ok(tags)
+}
+
+/// Convert a tag value to a vector as `into_vec` does, but without cloning the whole value: the
+/// items of lists are cloned and converted one at a time with `into_item`
+fn to_vec<T>(
+    value: &Value,
+    into_item: fn(Value) -> TiffResult<T>,
+    into_vec: fn(Value) -> TiffResult<Vec<T>>,
+) -> TiffResult<Vec<T>> {
+    match value {
+        Value::List(values) => values.iter().map(|v| into_item(v.clone())).collect(),
+        value => into_vec(value.clone()),
+    }
+}
+
+/// Read all tags of the IFD at `offset`, returning them with the offset of the next IFD
+pub(crate) async fn read_ifd_tags(
+    cursor: &mut ObjectStoreCursor,
+    offset: usize,
+    limits: &Limits,
+) -> Result<(IfdTags, Option<usize>)> {
+    let ifd_start = offset;
+    cursor.seek(offset);
+
+    let tag_count = cursor.read_u16().await?;
+    // dbg!(tag_count);
+    if tag_count as usize > limits.max_tag_count {
+        return Err(AiocogeoError::LimitExceeded(format!(
+            "the IFD at byte {offset} has {tag_count} tags, more than the limit of {}",
+            limits.max_tag_count
+        )));
+    }
+
+    let mut tags = IfdTags {
+        values: HashMap::with_capacity(tag_count as usize),
+        offsets: HashMap::with_capacity(tag_count as usize),
+    };
+    for _ in 0..tag_count {
+        let entry_offset = cursor.position() as u64;
+        let (tag_name, tag_value) = read_tag(cursor, limits).await?;
+        tags.values.insert(tag_name, tag_value);
+        tags.offsets.insert(tag_name, entry_offset);
+    }
+
+    cursor.seek(ifd_start + (12 * tag_count as usize) + 2);
+
+    let next_ifd_offset = cursor.read_u32().await?;
+    let next_ifd_offset = if next_ifd_offset == 0 {
+        None
+    } else {
+        Some(next_ifd_offset as usize)
+    };
+
+    Ok((tags, next_ifd_offset))
+}
+
+/// The tags of an IFD, with the byte offset of each tag's entry for error reporting
+#[derive(Debug)]
+pub(crate) struct IfdTags {
+    pub(crate) values: HashMap<Tag, Value>,
+    pub(crate) offsets: HashMap<Tag, u64>,
+}
+
+impl IfdTags {
+    /// Return an [`AiocogeoError::InvalidTag`] for a tag of this IFD
+    pub(crate) fn invalid(&self, tag: Tag, reason: impl ToString) -> AiocogeoError {
+        AiocogeoError::InvalidTag {
+            tag,
+            offset: self.offsets.get(&tag).copied().unwrap_or_default(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
 /// Read a single tag from the cursor
-async fn read_tag(cursor: &mut ObjectStoreCursor) -> TiffResult<(Tag, Value)> {
-    let code = cursor.read_u16().await;
+async fn read_tag(cursor: &mut ObjectStoreCursor, limits: &Limits) -> Result<(Tag, Value)> {
+    let entry_offset = cursor.position() as u64;
+    let code = cursor.read_u16().await?;
     let tag_name = Tag::from_u16_exhaustive(code);
     // dbg!(&tag_name);
 
     let current_cursor_position = cursor.position();
 
-    let tag_type = Type::from_u16(cursor.read_u16().await).unwrap();
-    let count = cursor.read_u32().await as usize;
+    let invalid = |reason: String| AiocogeoError::InvalidTag {
+        tag: tag_name,
+        offset: entry_offset,
+        reason,
+    };
+    let type_code = cursor.read_u16().await?;
+    let tag_type =
+        Type::from_u16(type_code).ok_or_else(|| invalid(format!("unknown type {type_code}")))?;
+    let count = cursor.read_u32().await? as usize;
+    type_size(tag_type)
+        .ok_or_else(|| invalid(format!("unexpected type {tag_type:?}")))?
+        .checked_mul(count)
+        .filter(|&bytes| bytes <= limits.max_tag_value_bytes)
+        .ok_or_else(|| {
+            AiocogeoError::LimitExceeded(format!(
+                "tag {tag_name:?} at byte {entry_offset} has {count} values, more than the limit \
+                 of {} bytes",
+                limits.max_tag_value_bytes
+            ))
+        })?;
 
-    let tag_value = read_tag_value(cursor, tag_type, count).await?;
+    let tag_value = read_tag_value(cursor, tag_type, count)
+        .await
+        .map_err(|err| invalid(err.to_string()))?;
 
     // TODO: better handle management of cursor state
     cursor.seek(current_cursor_position + 10);
@@ -589,20 +1767,20 @@ async fn read_tag_value(
         return Ok(Value::List(vec![]));
     }
 
-    let tag_size = match tag_type {
-        Type::BYTE | Type::SBYTE | Type::ASCII | Type::UNDEFINED => 1,
-        Type::SHORT | Type::SSHORT => 2,
-        Type::LONG | Type::SLONG | Type::FLOAT | Type::IFD => 4,
-        Type::LONG8
-        | Type::SLONG8
-        | Type::DOUBLE
-        | Type::RATIONAL
-        | Type::SRATIONAL
-        | Type::IFD8 => 8,
-        t => panic!("unexpected type {t:?}"),
-    };
-
-    let value_byte_length = count.checked_mul(tag_size).unwrap();
+    let value_byte_length = type_size(tag_type)
+        .and_then(|size| size.checked_mul(count))
+        .ok_or_else(|| unexpected_type(tag_type))?;
+
+    // Values within the entry are in the byte order of the file
+    let endianness = cursor.endianness();
+    macro_rules! read {
+        ($reader:expr, $method:ident) => {
+            match endianness {
+                Endianness::LittleEndian => $reader.$method::<LittleEndian>(),
+                Endianness::BigEndian => $reader.$method::<BigEndian>(),
+            }
+        };
+    }
 
     // Case 2: there is one value.
     if count == 1 {
@@ -614,67 +1792,68 @@ async fn read_tag_value(
         // NOTE: we should only be reading value_byte_length when it's 4 bytes or fewer. Right now
         // we're reading even if it's 8 bytes, but then only using the first 4 bytes of this
         // buffer.
-        let data = cursor.read(value_byte_length).await;
+        let data = cursor.read(value_byte_length).await?;
 
         // 2b: the value is at most 4 bytes or doesn't fit in the offset field.
         return Ok(match tag_type {
             Type::BYTE | Type::UNDEFINED => Value::Byte(data.reader().read_u8().unwrap()),
             Type::SBYTE => Value::Signed(data.reader().read_i8().unwrap() as i32),
-            Type::SHORT => Value::Short(data.reader().read_u16::<LittleEndian>().unwrap()),
-            Type::SSHORT => Value::Signed(data.reader().read_i16::<LittleEndian>().unwrap() as i32),
-            Type::LONG => Value::Unsigned(data.reader().read_u32::<LittleEndian>().unwrap()),
-            Type::SLONG => Value::Signed(data.reader().read_i32::<LittleEndian>().unwrap()),
-            Type::FLOAT => Value::Float(data.reader().read_f32::<LittleEndian>().unwrap()),
+            Type::SHORT => Value::Short(read!(data.reader(), read_u16).unwrap()),
+            Type::SSHORT => Value::Signed(read!(data.reader(), read_i16).unwrap() as i32),
+            Type::LONG => Value::Unsigned(read!(data.reader(), read_u32).unwrap()),
+            Type::SLONG => Value::Signed(read!(data.reader(), read_i32).unwrap()),
+            Type::FLOAT => Value::Float(read!(data.reader(), read_f32).unwrap()),
             Type::ASCII => {
                 if data[0] == 0 {
                     Value::Ascii("".to_string())
                 } else {
-                    panic!("Invalid tag");
-                    // return Err(TiffError::FormatError(TiffFormatError::InvalidTag));
+                    return Err(TiffError::FormatError(TiffFormatError::Format(
+                        "ASCII value is not null-terminated".to_string(),
+                    )));
                 }
             }
             Type::LONG8 => {
-                let offset = data.reader().read_u32::<LittleEndian>().unwrap();
+                let offset = read!(data.reader(), read_u32).unwrap();
                 cursor.seek(offset as usize);
-                Value::UnsignedBig(cursor.read_u64().await)
+                Value::UnsignedBig(cursor.read_u64().await?)
             }
             Type::SLONG8 => {
-                let offset = data.reader().read_u32::<LittleEndian>().unwrap();
+                let offset = read!(data.reader(), read_u32).unwrap();
                 cursor.seek(offset as usize);
-                Value::SignedBig(cursor.read_i64().await)
+                Value::SignedBig(cursor.read_i64().await?)
             }
             Type::DOUBLE => {
-                let offset = data.reader().read_u32::<LittleEndian>().unwrap();
+                let offset = read!(data.reader(), read_u32).unwrap();
                 cursor.seek(offset as usize);
-                Value::Double(cursor.read_f64().await)
+                Value::Double(cursor.read_f64().await?)
             }
             Type::RATIONAL => {
-                let offset = data.reader().read_u32::<LittleEndian>().unwrap();
+                let offset = read!(data.reader(), read_u32).unwrap();
                 cursor.seek(offset as usize);
-                let numerator = cursor.read_u32().await;
-                let denominator = cursor.read_u32().await;
+                let numerator = cursor.read_u32().await?;
+                let denominator = cursor.read_u32().await?;
                 Value::Rational(numerator, denominator)
             }
             Type::SRATIONAL => {
-                let offset = data.reader().read_u32::<LittleEndian>().unwrap();
+                let offset = read!(data.reader(), read_u32).unwrap();
                 cursor.seek(offset as usize);
-                let numerator = cursor.read_i32().await;
-                let denominator = cursor.read_i32().await;
+                let numerator = cursor.read_i32().await?;
+                let denominator = cursor.read_i32().await?;
                 Value::SRational(numerator, denominator)
             }
-            Type::IFD => Value::Ifd(data.reader().read_u32::<LittleEndian>().unwrap()),
+            Type::IFD => Value::Ifd(read!(data.reader(), read_u32).unwrap()),
             Type::IFD8 => {
-                let offset = data.reader().read_u32::<LittleEndian>().unwrap();
+                let offset = read!(data.reader(), read_u32).unwrap();
                 cursor.seek(offset as usize);
-                Value::IfdBig(cursor.read_u64().await)
+                Value::IfdBig(cursor.read_u64().await?)
             }
-            t => panic!("unexpected tag type {t:?}"),
+            t => return Err(unexpected_type(t)),
         });
     }
 
     // Case 3: There is more than one value, but it fits in the offset field.
     if value_byte_length <= 4 {
-        let data = cursor.read(value_byte_length).await;
+        let data = cursor.read(value_byte_length).await?;
         cursor.advance(4 - value_byte_length);
 
         match tag_type {
@@ -706,15 +1885,16 @@ async fn read_tag_value(
                     let v = v.trim_matches(char::from(0));
                     return Ok(Value::Ascii(v.into()));
                 } else {
-                    panic!("Invalid tag");
-                    // return Err(TiffError::FormatError(TiffFormatError::InvalidTag));
+                    return Err(TiffError::FormatError(TiffFormatError::Format(
+                        "ASCII value is not null-terminated".to_string(),
+                    )));
                 }
             }
             Type::SHORT => {
                 let mut reader = data.reader();
                 let mut v = Vec::new();
                 for _ in 0..count {
-                    v.push(Value::Short(reader.read_u16::<LittleEndian>()?));
+                    v.push(Value::Short(read!(reader, read_u16)?));
                 }
                 return Ok(Value::List(v));
             }
@@ -722,7 +1902,7 @@ async fn read_tag_value(
                 let mut reader = data.reader();
                 let mut v = Vec::new();
                 for _ in 0..count {
-                    v.push(Value::Signed(i32::from(reader.read_i16::<LittleEndian>()?)));
+                    v.push(Value::Signed(i32::from(read!(reader, read_i16)?)));
                 }
                 return Ok(Value::List(v));
             }
@@ -730,7 +1910,7 @@ async fn read_tag_value(
                 let mut reader = data.reader();
                 let mut v = Vec::new();
                 for _ in 0..count {
-                    v.push(Value::Unsigned(reader.read_u32::<LittleEndian>()?));
+                    v.push(Value::Unsigned(read!(reader, read_u32)?));
                 }
                 return Ok(Value::List(v));
             }
@@ -738,7 +1918,7 @@ async fn read_tag_value(
                 let mut reader = data.reader();
                 let mut v = Vec::new();
                 for _ in 0..count {
-                    v.push(Value::Signed(reader.read_i32::<LittleEndian>()?));
+                    v.push(Value::Signed(read!(reader, read_i32)?));
                 }
                 return Ok(Value::List(v));
             }
@@ -746,7 +1926,7 @@ async fn read_tag_value(
                 let mut reader = data.reader();
                 let mut v = Vec::new();
                 for _ in 0..count {
-                    v.push(Value::Float(reader.read_f32::<LittleEndian>()?));
+                    v.push(Value::Float(read!(reader, read_f32)?));
                 }
                 return Ok(Value::List(v));
             }
@@ -754,7 +1934,7 @@ async fn read_tag_value(
                 let mut reader = data.reader();
                 let mut v = Vec::new();
                 for _ in 0..count {
-                    v.push(Value::Ifd(reader.read_u32::<LittleEndian>()?));
+                    v.push(Value::Ifd(read!(reader, read_u32)?));
                 }
                 return Ok(Value::List(v));
             }
@@ -766,12 +1946,12 @@ async fn read_tag_value(
             | Type::IFD8 => {
                 unreachable!()
             }
-            t => panic!("unexpected tag type {t:?}"),
+            t => return Err(unexpected_type(t)),
         }
     }
 
     // Seek cursor
-    let offset = cursor.read_u32().await;
+    let offset = cursor.read_u32().await?;
     cursor.seek(offset as usize);
 
     // Case 4: there is more than one value, and it doesn't fit in the offset field.
@@ -779,58 +1959,56 @@ async fn read_tag_value(
         // TODO check if this could give wrong results
         // at a different endianess of file/computer.
         Type::BYTE | Type::UNDEFINED => {
-            let mut v = Vec::with_capacity(count);
-            for _ in 0..count {
-                v.push(Value::Byte(cursor.read_u8().await))
-            }
-            Ok(Value::List(v))
+            // Byte tags like ICC profiles and JPEG tables can be large, so read them in one go
+            let buf = cursor.read(count).await?;
+            Ok(Value::List(buf.iter().map(|b| Value::Byte(*b)).collect()))
         }
         Type::SBYTE => {
             let mut v = Vec::with_capacity(count);
             for _ in 0..count {
-                v.push(Value::Signed(cursor.read_i8().await as i32))
+                v.push(Value::Signed(cursor.read_i8().await? as i32))
             }
             Ok(Value::List(v))
         }
         Type::SHORT => {
             let mut v = Vec::with_capacity(count);
             for _ in 0..count {
-                v.push(Value::Short(cursor.read_u16().await))
+                v.push(Value::Short(cursor.read_u16().await?))
             }
             Ok(Value::List(v))
         }
         Type::SSHORT => {
             let mut v = Vec::with_capacity(count);
             for _ in 0..count {
-                v.push(Value::Signed(cursor.read_i16().await as i32))
+                v.push(Value::Signed(cursor.read_i16().await? as i32))
             }
             Ok(Value::List(v))
         }
         Type::LONG => {
             let mut v = Vec::with_capacity(count);
             for _ in 0..count {
-                v.push(Value::Unsigned(cursor.read_u32().await))
+                v.push(Value::Unsigned(cursor.read_u32().await?))
             }
             Ok(Value::List(v))
         }
         Type::SLONG => {
             let mut v = Vec::with_capacity(count);
             for _ in 0..count {
-                v.push(Value::Signed(cursor.read_i32().await))
+                v.push(Value::Signed(cursor.read_i32().await?))
             }
             Ok(Value::List(v))
         }
         Type::FLOAT => {
             let mut v = Vec::with_capacity(count);
             for _ in 0..count {
-                v.push(Value::Float(cursor.read_f32().await))
+                v.push(Value::Float(cursor.read_f32().await?))
             }
             Ok(Value::List(v))
         }
         Type::DOUBLE => {
             let mut v = Vec::with_capacity(count);
             for _ in 0..count {
-                v.push(Value::Double(cursor.read_f64().await))
+                v.push(Value::Double(cursor.read_f64().await?))
             }
             Ok(Value::List(v))
         }
@@ -838,8 +2016,8 @@ async fn read_tag_value(
             let mut v = Vec::with_capacity(count);
             for _ in 0..count {
                 v.push(Value::Rational(
-                    cursor.read_u32().await,
-                    cursor.read_u32().await,
+                    cursor.read_u32().await?,
+                    cursor.read_u32().await?,
                 ))
             }
             Ok(Value::List(v))
@@ -848,8 +2026,8 @@ async fn read_tag_value(
             let mut v = Vec::with_capacity(count);
             for _ in 0..count {
                 v.push(Value::SRational(
-                    cursor.read_i32().await,
-                    cursor.read_i32().await,
+                    cursor.read_i32().await?,
+                    cursor.read_i32().await?,
                 ))
             }
             Ok(Value::List(v))
@@ -857,35 +2035,35 @@ async fn read_tag_value(
         Type::LONG8 => {
             let mut v = Vec::with_capacity(count);
             for _ in 0..count {
-                v.push(Value::UnsignedBig(cursor.read_u64().await))
+                v.push(Value::UnsignedBig(cursor.read_u64().await?))
             }
             Ok(Value::List(v))
         }
         Type::SLONG8 => {
             let mut v = Vec::with_capacity(count);
             for _ in 0..count {
-                v.push(Value::SignedBig(cursor.read_i64().await))
+                v.push(Value::SignedBig(cursor.read_i64().await?))
             }
             Ok(Value::List(v))
         }
         Type::IFD => {
             let mut v = Vec::with_capacity(count);
             for _ in 0..count {
-                v.push(Value::Ifd(cursor.read_u32().await))
+                v.push(Value::Ifd(cursor.read_u32().await?))
             }
             Ok(Value::List(v))
         }
         Type::IFD8 => {
             let mut v = Vec::with_capacity(count);
             for _ in 0..count {
-                v.push(Value::IfdBig(cursor.read_u64().await))
+                v.push(Value::IfdBig(cursor.read_u64().await?))
             }
             Ok(Value::List(v))
         }
         Type::ASCII => {
             let n = count;
             let mut out = vec![0; n];
-            let buf = cursor.read(n).await;
+            let buf = cursor.read(n).await?;
             buf.reader().read_exact(&mut out).unwrap();
 
             // Strings may be null-terminated, so we trim anything downstream of the null byte
@@ -894,6 +2072,501 @@ async fn read_tag_value(
             }
             Ok(Value::Ascii(String::from_utf8(out)?))
         }
-        t => panic!("unexpected tag type {t:?}"),
+        t => Err(unexpected_type(t)),
+    }
+}
+
+/// Return the size in bytes of a single value of a tag type
+fn type_size(tag_type: Type) -> Option<usize> {
+    match tag_type {
+        Type::BYTE | Type::SBYTE | Type::ASCII | Type::UNDEFINED => Some(1),
+        Type::SHORT | Type::SSHORT => Some(2),
+        Type::LONG | Type::SLONG | Type::FLOAT | Type::IFD => Some(4),
+        Type::LONG8
+        | Type::SLONG8
+        | Type::DOUBLE
+        | Type::RATIONAL
+        | Type::SRATIONAL
+        | Type::IFD8 => Some(8),
+        _ => None,
+    }
+}
+
+fn unexpected_type(tag_type: Type) -> TiffError {
+    TiffError::FormatError(TiffFormatError::Format(format!(
+        "unexpected type {tag_type:?}"
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::CogBuilder;
+
+    /// Return a copy of the first IFD of the default test file, to modify
+    async fn default_ifd() -> ImageFileDirectory {
+        let (reader, _) = CogBuilder::default().open().await.unwrap();
+        reader.ifds()[0].clone()
+    }
+
+    #[test]
+    fn tiepoints_with_z() {
+        let values = [
+            0.0, 0.0, 0.0, 500000.0, 4000000.0, 0.0, 10.0, 20.0, 5.0, 1.0, 2.0, 3.0,
+        ];
+        let tiepoints = Tiepoint::from_tag_values(&values);
+        assert_eq!(tiepoints.len(), 2);
+        assert_eq!(tiepoints[1].k, 5.0);
+        assert_eq!(tiepoints[1].z, 3.0);
+    }
+
+    #[test]
+    fn parse_modes() {
+        // Version 2 directory whose citation points past the end of GeoAsciiParamsTag
+        let data = [2, 1, 0, 2, 1024, 0, 1, 1, 1026, 34737, 20, 0];
+        let tags = parse_geo_keys(&data, Some("WGS 84|"), None, ParseMode::Lenient).unwrap();
+        assert_eq!(tags.len(), 1);
+        assert!(parse_geo_keys(&data, Some("WGS 84|"), None, ParseMode::Strict).is_err());
+
+        let formats = per_sample(
+            vec![SampleFormat::Uint],
+            3,
+            "SampleFormat",
+            ParseMode::Lenient,
+        );
+        assert_eq!(formats.unwrap(), vec![SampleFormat::Uint; 3]);
+        assert!(per_sample(vec![8u16], 3, "BitsPerSample", ParseMode::Strict).is_err());
+    }
+
+    #[tokio::test]
+    async fn colormap() {
+        let mut ifd = default_ifd().await;
+        assert!(ifd.colormap_rgba(None).is_none());
+        ifd.color_map = Some((0..768).map(|i| (i % 256) as u16 * 257).collect());
+        let colors = ifd.colormap_rgba(Some(3.0)).unwrap();
+        assert_eq!(colors.len(), 256);
+        assert_eq!(colors[2], [2, 2, 2, 255]);
+        assert_eq!(colors[3], [3, 3, 3, 0]);
+        assert_eq!(ifd.colormap().unwrap()[&3], [3, 3, 3]);
+        ifd.color_map = Some(vec![0; 10]);
+        assert!(ifd.colormap().is_none());
+    }
+
+    #[tokio::test]
+    async fn date_times() {
+        let mut ifd = default_ifd().await;
+        assert_eq!(ifd.date_time(), None);
+        ifd.gdal_metadata = Some(GdalMetadata::parse(
+            r#"<GDALMetadata>
+  <Item name="TIFFTAG_DATETIME">2023:06:01 08:00:00</Item>
+  <Item name="ACQUISITIONDATETIME" domain="IMAGERY">2023-05-30T10:15:00Z</Item>
+</GDALMetadata>"#,
+        ));
+        let date = |y, m, d, h, min| {
+            chrono::NaiveDate::from_ymd_opt(y, m, d).and_then(|date| date.and_hms_opt(h, min, 0))
+        };
+        assert_eq!(
+            ifd.date_time(),
+            date(2023, 6, 1, 8, 0).map(Timestamp::Local)
+        );
+        // A trailing `Z` is UTC
+        let acquired = ifd.acquisition_date_time().unwrap();
+        assert_eq!(Some(acquired.naive()), date(2023, 5, 30, 10, 15));
+        assert_eq!(acquired.offset(), chrono::FixedOffset::east_opt(0));
+        // The `DateTime` tag takes precedence over GDAL metadata
+        ifd.date_time = Some("2024:01:02 03:04:00".to_string());
+        assert_eq!(
+            ifd.date_time(),
+            date(2024, 1, 2, 3, 4).map(Timestamp::Local)
+        );
+
+        // EXIF times take their offset from `OffsetTimeOriginal`
+        ifd.gdal_metadata = None;
+        let exif_tag = |code, value: &str| (Tag::Unknown(code), Value::Ascii(value.to_string()));
+        ifd.exif = Some(ExifDirectory {
+            tags: [exif_tag(36867, "2023:05:30 12:15:00")].into(),
+        });
+        let acquired = ifd.acquisition_date_time().unwrap();
+        assert_eq!(
+            (acquired.naive(), acquired.offset()),
+            (date(2023, 5, 30, 12, 15).unwrap(), None)
+        );
+        ifd.exif = Some(ExifDirectory {
+            tags: [
+                exif_tag(36867, "2023:05:30 12:15:00"),
+                exif_tag(36881, "+02:00"),
+            ]
+            .into(),
+        });
+        let acquired = ifd.acquisition_date_time().unwrap();
+        assert_eq!(acquired.naive(), date(2023, 5, 30, 10, 15).unwrap());
+        assert_eq!(acquired.to_string(), "2023-05-30T12:15:00+02:00");
+    }
+
+    #[tokio::test]
+    async fn file_metadata() {
+        let mut ifd = default_ifd().await;
+        assert_eq!(ifd.file_metadata(), FileMetadata::default());
+        ifd.software = Some("GDAL 3.8.4".to_string());
+        ifd.copyright = Some("CC-BY-4.0".to_string());
+        let metadata = ifd.file_metadata();
+        assert_eq!(metadata.software.as_deref(), Some("GDAL 3.8.4"));
+        assert_eq!(metadata.copyright.as_deref(), Some("CC-BY-4.0"));
+        assert_eq!(metadata.artist, None);
+    }
+
+    #[test]
+    fn north_up_orientation() {
+        let orientation =
+            |a, b, d, e| super::north_up_orientation(&AffineTransform::new(a, b, 0.0, d, e, 0.0));
+        assert_eq!(orientation(1.0, 0.0, 0.0, -1.0), Orientation::TopLeft);
+        assert_eq!(orientation(1.0, 0.0, 0.0, 1.0), Orientation::BottomLeft);
+        assert_eq!(orientation(-1.0, 0.0, 0.0, 1.0), Orientation::BottomRight);
+        // Stored rows run from south to north and columns from west to east
+        assert_eq!(orientation(0.0, 1.0, 1.0, 0.0), Orientation::LeftBottom);
+        // Small rotations keep the stored order
+        assert_eq!(orientation(1.0, 0.1, 0.1, -1.0), Orientation::TopLeft);
+    }
+
+    #[tokio::test]
+    async fn sparse_tiles() {
+        let mut ifd = default_ifd().await;
+        ifd.tile_offsets[1] = 0;
+        ifd.tile_byte_counts[1] = 0;
+        assert!(ifd.has_tile(0, 0, Orientation::TopLeft).unwrap());
+        assert!(!ifd.has_tile(1, 0, Orientation::TopLeft).unwrap());
+        // Transposed orientations swap the tile indices
+        assert!(!ifd.has_tile(0, 1, Orientation::LeftTop).unwrap());
+        assert!(ifd.has_tile(2, 0, Orientation::TopLeft).is_err());
+    }
+
+    #[tokio::test]
+    async fn limits() {
+        use object_store::memory::InMemory;
+        use std::sync::Arc;
+
+        let cursor = |bytes: Vec<u8>| {
+            let store = Arc::new(InMemory::new());
+            let mut cursor = ObjectStoreCursor::new(store, Path::from("test.tif"));
+            cursor.set_header(Bytes::from(bytes));
+            cursor
+        };
+
+        // An IFD of two tags, the first of which is a LONG tag claiming a billion values
+        let mut ifd = vec![2, 0];
+        ifd.extend([0, 1, 4, 0]);
+        ifd.extend(1_000_000_000u32.to_le_bytes());
+        ifd.extend([0; 4]);
+        let limits = Limits {
+            max_tag_count: 1,
+            ..Default::default()
+        };
+        let err = read_ifd_tags(&mut cursor(ifd.clone()), 0, &limits)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AiocogeoError::LimitExceeded(_)));
+        let err = read_ifd_tags(&mut cursor(ifd), 0, &Limits::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AiocogeoError::LimitExceeded(_)));
+
+        // A truncated IFD fails instead of panicking
+        let err = read_ifd_tags(&mut cursor(vec![2, 0, 0, 1]), 0, &Limits::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AiocogeoError::IOError(_)));
+    }
+
+    #[tokio::test]
+    async fn cyclic_ifd_chain() {
+        use object_store::memory::InMemory;
+        use std::sync::Arc;
+
+        // A single tile IFD at byte 2 whose next IFD is itself
+        let mut file = vec![0, 0, 6, 0];
+        for tag in [256u16, 257, 322, 323, 324, 325] {
+            file.extend(tag.to_le_bytes());
+            file.extend(4u16.to_le_bytes());
+            file.extend(1u32.to_le_bytes());
+            file.extend(16u32.to_le_bytes());
+        }
+        file.extend(2u32.to_le_bytes());
+
+        let store = Arc::new(InMemory::new());
+        let mut cursor = ObjectStoreCursor::new(store, Path::from("test.tif"));
+        cursor.set_header(Bytes::from(file));
+        let err = ImageFileDirectories::open(&mut cursor, 2, &OpenOptions::default())
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, AiocogeoError::CyclicIfdChain(2)));
+    }
+
+    #[tokio::test]
+    async fn big_endian_tags() {
+        use object_store::memory::InMemory;
+        use std::sync::Arc;
+
+        // A SHORT, a LONG and two SHORTs held within their entries, in Motorola byte order
+        let mut ifd = vec![0, 3];
+        for (tag, tag_type, count, value) in [
+            (256u16, 3u16, 1u32, [1, 0, 0, 0]),
+            (257, 4, 1, [0, 0, 2, 0]),
+            (258, 3, 2, [0, 8, 0, 16]),
+        ] {
+            ifd.extend(tag.to_be_bytes());
+            ifd.extend(tag_type.to_be_bytes());
+            ifd.extend(count.to_be_bytes());
+            ifd.extend(value);
+        }
+        ifd.extend([0; 4]);
+
+        let store = Arc::new(InMemory::new());
+        let mut cursor = ObjectStoreCursor::new(store, Path::from("test.tif"));
+        cursor.set_header(Bytes::from(ifd));
+        cursor.set_endianness(Endianness::BigEndian);
+        let (tags, next) = read_ifd_tags(&mut cursor, 0, &Limits::default())
+            .await
+            .unwrap();
+        assert_eq!(next, None);
+        assert!(matches!(tags.values[&Tag::ImageWidth], Value::Short(256)));
+        assert!(matches!(
+            tags.values[&Tag::ImageLength],
+            Value::Unsigned(512)
+        ));
+        let Value::List(bits) = &tags.values[&Tag::BitsPerSample] else {
+            panic!("expected a list");
+        };
+        assert!(matches!(bits[..], [Value::Short(8), Value::Short(16)]));
+    }
+
+    #[test]
+    fn large_tile_offsets() {
+        let tags = |offset: u64, byte_count: u64| {
+            let values = HashMap::from([
+                (Tag::ImageWidth, Value::Unsigned(16)),
+                (Tag::ImageLength, Value::Unsigned(16)),
+                (Tag::TileWidth, Value::Unsigned(16)),
+                (Tag::TileLength, Value::Unsigned(16)),
+                (Tag::TileOffsets, Value::UnsignedBig(offset)),
+                (Tag::TileByteCounts, Value::UnsignedBig(byte_count)),
+            ]);
+            IfdTags {
+                values,
+                offsets: HashMap::new(),
+            }
+        };
+        let parse = |tags| {
+            ImageFileDirectory::from_tags(tags, None, Endianness::LittleEndian, ParseMode::Lenient)
+        };
+
+        let ifd = parse(tags(5 << 32, 100)).unwrap();
+        assert_eq!(
+            ifd.tile_byte_ranges()[&(0, 0)],
+            vec![5 << 32..(5 << 32) + 100]
+        );
+        assert!(matches!(
+            parse(tags(u64::MAX, 100)),
+            Err(AiocogeoError::InvalidTag {
+                tag: Tag::TileByteCounts,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn raw_tags() {
+        let offsets: Vec<_> = (0..4).map(|i| Value::Unsigned(100 + i)).collect();
+        let values = HashMap::from([
+            (Tag::ImageWidth, Value::Unsigned(32)),
+            (Tag::ImageLength, Value::Unsigned(32)),
+            (Tag::TileWidth, Value::Unsigned(16)),
+            (Tag::TileLength, Value::Unsigned(16)),
+            (Tag::TileOffsets, Value::List(offsets.clone())),
+            (
+                Tag::TileByteCounts,
+                Value::List(vec![Value::Unsigned(1); 4]),
+            ),
+            (Tag::Unknown(65000), Value::Ascii("vendor".to_string())),
+        ]);
+        let tags = IfdTags {
+            values,
+            offsets: HashMap::new(),
+        };
+        let ifd =
+            ImageFileDirectory::from_tags(tags, None, Endianness::LittleEndian, ParseMode::Lenient)
+                .unwrap();
+
+        // Parsed tags keep their raw values
+        assert_eq!(ifd.tile_offsets, [100, 101, 102, 103]);
+        assert_eq!(ifd.tag(Tag::TileOffsets), Some(&Value::List(offsets)));
+        assert_eq!(ifd.tag(Tag::TileWidth), Some(&Value::Unsigned(16)));
+        assert_eq!(ifd.tag(Tag::Software), None);
+
+        // Unknown tags are looked up by code
+        let vendor = Value::Ascii("vendor".to_string());
+        assert_eq!(ifd.tag(Tag::Unknown(65000)), Some(&vendor));
+        assert_eq!(ifd.tag_by_code(65000), Some(&vendor));
+        assert_eq!(ifd.tag_by_code(65001), None);
+        // Known tags are found by code too
+        assert_eq!(ifd.tag_by_code(322), Some(&Value::Unsigned(16)));
+
+        let codes: Vec<_> = ifd.tags().iter().map(|(tag, _)| tag.to_u16()).collect();
+        assert_eq!(codes, [256, 257, 322, 323, 324, 325, 65000]);
+    }
+
+    #[test]
+    fn mixed_bits_per_sample() {
+        let tags = |bits: Vec<u16>| {
+            let values = HashMap::from([
+                (Tag::ImageWidth, Value::Unsigned(16)),
+                (Tag::ImageLength, Value::Unsigned(16)),
+                (Tag::TileWidth, Value::Unsigned(16)),
+                (Tag::TileLength, Value::Unsigned(16)),
+                (Tag::SamplesPerPixel, Value::Short(2)),
+                (Tag::PhotometricInterpretation, Value::Short(1)),
+                (
+                    Tag::BitsPerSample,
+                    Value::List(bits.into_iter().map(Value::Short).collect()),
+                ),
+                (Tag::TileOffsets, Value::Unsigned(8)),
+                (Tag::TileByteCounts, Value::Unsigned(768)),
+            ]);
+            let tags = IfdTags {
+                values,
+                offsets: HashMap::new(),
+            };
+            ImageFileDirectory::from_tags(tags, None, Endianness::LittleEndian, ParseMode::Lenient)
+                .unwrap()
+        };
+
+        let ifd = tags(vec![16, 16]);
+        assert!(ifd.has_uniform_samples());
+        assert_eq!(ifd.dtype().unwrap(), DataType::Uint16);
+
+        // Bands with different data types are reported, but can't be read
+        let ifd = tags(vec![8, 16]);
+        assert!(!ifd.has_uniform_samples());
+        assert_eq!(
+            ifd.dtypes().unwrap(),
+            vec![DataType::Uint8, DataType::Uint16]
+        );
+        assert!(ifd.dtype().is_err());
+        let tile = Bytes::from(vec![0; 768]);
+        assert!(ifd
+            .decode(vec![tile], Orientation::TopLeft, None, None)
+            .is_err());
+    }
+
+    #[test]
+    fn structured_errors() {
+        let values = HashMap::from([
+            (Tag::ImageWidth, Value::Unsigned(16)),
+            (Tag::ImageLength, Value::Unsigned(16)),
+            (Tag::Compression, Value::Ascii("LZW".to_string())),
+        ]);
+        let offsets = HashMap::from([(Tag::Compression, 34)]);
+        let tags = IfdTags { values, offsets };
+        let err =
+            ImageFileDirectory::from_tags(tags, None, Endianness::LittleEndian, ParseMode::Lenient)
+                .unwrap_err();
+        assert!(matches!(
+            err,
+            AiocogeoError::InvalidTag {
+                tag: Tag::Compression,
+                offset: 34,
+                ..
+            }
+        ));
+
+        let values = HashMap::from([
+            (Tag::ImageWidth, Value::Unsigned(16)),
+            (Tag::ImageLength, Value::Unsigned(16)),
+        ]);
+        let tags = IfdTags {
+            values,
+            offsets: HashMap::new(),
+        };
+        let err =
+            ImageFileDirectory::from_tags(tags, None, Endianness::LittleEndian, ParseMode::Lenient)
+                .unwrap_err();
+        assert!(matches!(
+            err,
+            AiocogeoError::MissingRequiredTag(Tag::TileWidth)
+        ));
+    }
+
+    #[test]
+    fn groups() {
+        let ifd = |width: u32, subfile_type: u32, photometric: u16| {
+            let values = HashMap::from([
+                (Tag::NewSubfileType, Value::Unsigned(subfile_type)),
+                (Tag::ImageWidth, Value::Unsigned(width)),
+                (Tag::ImageLength, Value::Unsigned(width)),
+                (Tag::TileWidth, Value::Unsigned(width)),
+                (Tag::TileLength, Value::Unsigned(width)),
+                (Tag::PhotometricInterpretation, Value::Short(photometric)),
+                (Tag::TileOffsets, Value::Unsigned(0)),
+                (Tag::TileByteCounts, Value::Unsigned(0)),
+            ]);
+            let tags = IfdTags {
+                values,
+                offsets: HashMap::new(),
+            };
+            ImageFileDirectory::from_tags(tags, None, Endianness::LittleEndian, ParseMode::Lenient)
+                .unwrap()
+        };
+
+        // GDAL's layout: image, its mask, then each overview followed by its mask
+        let ifds = ImageFileDirectories::from_ifds(vec![
+            ifd(16, 0, 1),
+            ifd(16, 4, 4),
+            ifd(4, 1, 1),
+            ifd(8, 1, 1),
+            ifd(8, 5, 4),
+        ])
+        .unwrap();
+        assert_eq!(ifds.primary().image_width, 16);
+        assert!(ifds.primary().is_full_resolution());
+        let widths = |ifds: &[ImageFileDirectory]| {
+            ifds.iter().map(|ifd| ifd.image_width).collect::<Vec<_>>()
+        };
+        assert_eq!(widths(ifds.levels()), vec![16, 8, 4]);
+        assert_eq!(widths(ifds.overviews()), vec![8, 4]);
+        assert_eq!(widths(ifds.masks()), vec![16, 8]);
+        assert!(!ifds.overviews()[0].is_full_resolution());
+        assert_eq!(ifds.mask(1).unwrap().image_width, 8);
+        assert!(ifds.mask(2).is_none());
+
+        // Masks of only some levels are matched to them by dimensions
+        let ifds = ImageFileDirectories::from_ifds(vec![
+            ifd(16, 0, 1),
+            ifd(8, 1, 1),
+            ifd(8, 5, 4),
+            ifd(4, 1, 1),
+            ifd(4, 5, 4),
+        ])
+        .unwrap();
+        assert!(ifds.mask(0).is_none());
+        assert_eq!(ifds.mask(1).unwrap().image_width, 8);
+        assert_eq!(ifds.mask(2).unwrap().image_width, 4);
+        let levels: Vec<_> = ifds.masks_by_level().map(|(level, _)| level).collect();
+        assert_eq!(levels, vec![1, 2]);
+
+        assert!(ImageFileDirectories::from_ifds(vec![ifd(16, 4, 4)]).is_err());
+
+        // Two pages, each with an overview
+        let images = ImageFileDirectories::split_images(vec![
+            ifd(16, 0, 1),
+            ifd(8, 1, 1),
+            ifd(32, 0, 1),
+            ifd(32, 4, 4),
+            ifd(16, 1, 1),
+        ])
+        .unwrap();
+        assert_eq!(images.len(), 2);
+        assert_eq!(widths(images[0].levels()), vec![16, 8]);
+        assert_eq!(widths(images[1].levels()), vec![32, 16]);
+        assert_eq!(widths(images[1].masks()), vec![32]);
     }
 }
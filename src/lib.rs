@@ -1,12 +1,91 @@
 mod affine;
+mod alignment;
+mod array;
+#[cfg(feature = "arrow")]
+mod arrow;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod citation;
 mod cog;
+mod cog_profile;
 mod compression;
 mod cursor;
-mod enums;
+mod datetime;
+mod decoder;
+mod diff;
+mod dump;
+pub mod enums;
 pub mod error;
+mod exif;
+mod expression;
+mod gdal_metadata;
 mod geo_key_directory;
 mod ifd;
+mod mosaic;
+mod options;
 mod partial_reads;
+mod png;
+mod pool;
+mod profile;
+mod rasterize;
+mod recorder;
+mod replay;
+#[cfg(feature = "proj")]
+mod reproject;
+mod rpc;
+mod scheduler;
+#[cfg(feature = "server")]
+pub mod server;
+mod statistics;
+mod storage;
+mod store;
 mod tag;
+pub mod terrain;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod tms;
+mod units;
+mod validate;
 
+pub use affine::AffineTransform;
+pub use alignment::{AlignmentReport, LevelAlignment};
+pub use array::{PixelValue, RasterArray};
+pub use citation::Citation;
 pub use cog::COGReader;
+pub use cog_profile::{CogProfile, ProfileName};
+pub use datetime::Timestamp;
+pub use diff::Difference;
+pub use dump::{FileStructure, IfdKind, IfdStructure};
+pub use enums::{ColorInterp, DataType, Interleave, Orientation};
+pub use exif::ExifDirectory;
+pub use expression::Expression;
+pub use gdal_metadata::{GdalMetadata, GdalMetadataItem};
+pub use geo_key_directory::{CompoundCrs, GeoKeyDirectory};
+pub use ifd::{FileMetadata, ImageFileDirectory, RawTile, Tiepoint};
+pub use mosaic::{
+    AssetDefinition, Candidate, MosaicAsset, MosaicDefinition, MosaicReader, PixelSelection,
+    SelectionMethod,
+};
+pub use options::{
+    DiffOptions, Limits, OpenOptions, ParseMode, ReadOptions, RefreshedStore, Resampling, Spawner,
+    StoreRefresher, TileErrorPolicy, DEFAULT_CONCURRENCY,
+};
+pub use partial_reads::{ImageData, Tile, Window};
+pub use pool::{BufferPool, PoolMetrics};
+pub use profile::ProfileSample;
+pub use recorder::{DecodeEvent, RecordedRequest, RequestHooks, RequestPurpose, RequestRecorder};
+pub use replay::{CaptureStore, ReplayStore};
+#[cfg(feature = "proj")]
+pub use reproject::Crs;
+pub use rpc::RpcCoefficients;
+pub use scheduler::{RequestPriority, RequestScheduler, SchedulerMetrics};
+pub use statistics::BandStatistics;
+pub use storage::{LevelStorage, StorageReport, TileSizes};
+pub use store::{HeaderCache, CACHE_BLOCK_SIZE};
+pub use tms::{TileMatrix, TileMatrixSet};
+pub use units::{AngularUnit, LinearUnit, Units};
+pub use validate::{OverviewIssue, TileFailure, TileValidation};
+
+pub use chrono::{DateTime, FixedOffset, NaiveDateTime};
+pub use tiff::decoder::ifd::Value;
+pub use tiff::tags::Tag;
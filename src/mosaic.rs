@@ -0,0 +1,861 @@
+//! Read many COGs sharing a CRS as one virtual image, e.g. the scenes of a satellite mosaic.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use futures::stream::{self, StreamExt};
+use geo_types::{LineString, Polygon};
+use ndarray::Array2;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use url::Url;
+
+use crate::array::RasterArray;
+use crate::cog::COGReader;
+use crate::error::{AiocogeoError, Result};
+use crate::options::{OpenOptions, ReadOptions, Resampling};
+use crate::partial_reads::ImageData;
+use crate::tms::TileMatrixSet;
+
+/// A declarative description of a mosaic: the files it is made of, in the order they are
+/// composited, and optionally the area each covers.
+///
+/// With the `json` feature, descriptions can be loaded from and saved as JSON to share mosaics
+/// between services:
+///
+/// ```json
+/// {"assets": [{"path": "scenes/a.tif", "footprint": [[[0, 0], [10, 0], [10, 10], [0, 0]]]}]}
+/// ```
+///
+/// Asset paths may also be URLs, e.g. `s3://bucket/scenes/a.tif`, to mix files of several
+/// stores.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct MosaicDefinition {
+    /// The files of the mosaic, the first of which is drawn on top
+    pub assets: Vec<AssetDefinition>,
+}
+
+/// A file of a [`MosaicDefinition`]
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct AssetDefinition {
+    /// The path of the file in the store the mosaic is opened from, or its URL, e.g.
+    /// `s3://bucket/scene.tif`. URLs are opened with [`object_store::parse_url`], so schemes
+    /// other than `file` and `memory` need the matching features of `object_store`.
+    pub path: String,
+    /// The area with valid pixels, as the rings of a polygon in the CRS of the file, with the
+    /// exterior first as in the `coordinates` of a GeoJSON polygon. Defaults to the bounds of the
+    /// file.
+    #[cfg_attr(
+        feature = "json",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub footprint: Option<Vec<Vec<[f64; 2]>>>,
+}
+
+impl AssetDefinition {
+    /// Return the store and path of the file: its URL's, with the scheme and host of the URL as
+    /// cache namespace, or `store` and the path
+    fn location(
+        &self,
+        store: &Arc<dyn ObjectStore>,
+        stores: &mut HashMap<String, Arc<dyn ObjectStore>>,
+    ) -> Result<(Arc<dyn ObjectStore>, Path, Option<String>)> {
+        match Url::parse(&self.path) {
+            // Windows drive letters parse as a one letter scheme
+            Ok(url) if url.scheme().len() > 1 => {
+                let (url_store, path) = object_store::parse_url(&url)?;
+                // Files of the same bucket or host share a store
+                let key = format!("{}://{}", url.scheme(), url.authority());
+                let url_store = stores
+                    .entry(key.clone())
+                    .or_insert_with(|| Arc::from(url_store));
+                Ok((url_store.clone(), path, Some(key)))
+            }
+            _ => Ok((store.clone(), Path::from(self.path.as_str()), None)),
+        }
+    }
+
+    fn footprint_polygon(&self) -> Option<Polygon<f64>> {
+        let mut rings =
+            self.footprint.as_ref()?.iter().map(|ring| {
+                LineString::from(ring.iter().map(|&[x, y]| (x, y)).collect::<Vec<_>>())
+            });
+        let exterior = rings.next()?;
+        Some(Polygon::new(exterior, rings.collect()))
+    }
+}
+
+#[cfg(feature = "json")]
+impl MosaicDefinition {
+    /// Parse a description from JSON
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json)
+            .map_err(|err| AiocogeoError::General(format!("invalid mosaic description: {err}")))
+    }
+
+    /// Serialize the description as JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("mosaic descriptions are always serializable")
+    }
+}
+
+/// An opened file of a [`MosaicReader`]
+#[derive(Clone)]
+pub struct MosaicAsset {
+    /// The reader of the file
+    pub reader: COGReader,
+    /// The area with valid pixels in the CRS of the file, if known
+    pub footprint: Option<Polygon<f64>>,
+}
+
+impl MosaicAsset {
+    /// Whether the asset may have pixels within `bounds` `(min_x, min_y, max_x, max_y)` in the
+    /// CRS of EPSG code `epsg`, judging from its bounds and footprint. Assets whose extent can't
+    /// be compared with the bounds, e.g. in another CRS without the `proj` feature, are assumed
+    /// to.
+    pub fn intersects(&self, bounds: (f64, f64, f64, f64), epsg: u16) -> bool {
+        let Some(bounds) = native_bounds_of(&self.reader, bounds, epsg) else {
+            return true;
+        };
+        let in_bounds = self
+            .reader
+            .native_bounds()
+            .is_none_or(|native| boxes_intersect(native, bounds));
+        in_bounds
+            && self
+                .footprint
+                .as_ref()
+                .is_none_or(|footprint| polygon_intersects_box(footprint, bounds))
+    }
+}
+
+/// Return the bounding box of `bounds` in the CRS of EPSG code `epsg` in the CRS of `reader`, if
+/// both CRSs are known
+fn native_bounds_of(
+    reader: &COGReader,
+    bounds: (f64, f64, f64, f64),
+    epsg: u16,
+) -> Option<(f64, f64, f64, f64)> {
+    if reader.epsg() == Some(epsg) {
+        return Some(bounds);
+    }
+    transform_bounds(reader, bounds, epsg)
+}
+
+#[cfg(feature = "proj")]
+fn transform_bounds(
+    reader: &COGReader,
+    bounds: (f64, f64, f64, f64),
+    epsg: u16,
+) -> Option<(f64, f64, f64, f64)> {
+    // Edges are curved in the other CRS, so transform points along them rather than the corners
+    const STEPS: usize = 16;
+    let (min_x, min_y, max_x, max_y) = bounds;
+    let points: Vec<_> = (0..=STEPS)
+        .flat_map(|i| {
+            let t = i as f64 / STEPS as f64;
+            let (x, y) = (min_x + t * (max_x - min_x), min_y + t * (max_y - min_y));
+            [(x, min_y), (x, max_y), (min_x, y), (max_x, y)]
+        })
+        .collect();
+    let src = crate::reproject::Crs::from_epsg(epsg).ok()?;
+    let transformed = src.transform_points(&reader.crs()?, &points);
+    transformed.into_iter().try_fold(None, |bbox, point| {
+        let (x, y) = point?;
+        let (x0, y0, x1, y1) = bbox.unwrap_or((x, y, x, y));
+        Some(Some((x0.min(x), y0.min(y), x1.max(x), y1.max(y))))
+    })?
+}
+
+#[cfg(not(feature = "proj"))]
+fn transform_bounds(
+    _reader: &COGReader,
+    _bounds: (f64, f64, f64, f64),
+    _epsg: u16,
+) -> Option<(f64, f64, f64, f64)> {
+    None
+}
+
+fn boxes_intersect(a: (f64, f64, f64, f64), b: (f64, f64, f64, f64)) -> bool {
+    a.0 < b.2 && b.0 < a.2 && a.1 < b.3 && b.1 < a.3
+}
+
+/// Whether the exterior of `polygon` overlaps the box `(min_x, min_y, max_x, max_y)`. Holes are
+/// ignored, so boxes within holes count as overlapping.
+fn polygon_intersects_box(polygon: &Polygon<f64>, bounds: (f64, f64, f64, f64)) -> bool {
+    let (min_x, min_y, max_x, max_y) = bounds;
+    let ring = &polygon.exterior().0;
+    let inside_box =
+        |c: &geo_types::Coord<f64>| c.x >= min_x && c.x <= max_x && c.y >= min_y && c.y <= max_y;
+    if ring.iter().any(inside_box) {
+        return true;
+    }
+    // The box is within the polygon if its center is, as no vertex is inside the box
+    let (cx, cy) = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+    let mut inside = false;
+    for edge in ring.windows(2) {
+        let (a, b) = (edge[0], edge[1]);
+        if (a.y > cy) != (b.y > cy) && cx < a.x + (cy - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+    }
+    if inside {
+        return true;
+    }
+    // Otherwise they overlap only if an edge of the polygon crosses the box
+    let corners = [
+        (min_x, min_y),
+        (max_x, min_y),
+        (max_x, max_y),
+        (min_x, max_y),
+    ];
+    let box_edges = (0..4).map(|i| (corners[i], corners[(i + 1) % 4]));
+    let cross = |(p, q): ((f64, f64), (f64, f64)), (r, s): ((f64, f64), (f64, f64))| {
+        let orient = |a: (f64, f64), b: (f64, f64), c: (f64, f64)| {
+            (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+        };
+        orient(p, q, r) * orient(p, q, s) < 0.0 && orient(r, s, p) * orient(r, s, q) < 0.0
+    };
+    ring.windows(2).any(|edge| {
+        let edge = ((edge[0].x, edge[0].y), (edge[1].x, edge[1].y));
+        box_edges.clone().any(|box_edge| cross(edge, box_edge))
+    })
+}
+
+/// A valid pixel of an asset, one of those a [`PixelSelection`] chooses from
+#[derive(Clone, Copy)]
+pub struct Candidate<'a> {
+    /// The index of the asset in [`MosaicReader::assets`]
+    pub index: usize,
+    /// The asset
+    pub asset: &'a MosaicAsset,
+    /// The acquisition time of the asset, or the time its file was written if unknown, in UTC if
+    /// the file gives its offset (see [`crate::Timestamp::naive`])
+    pub date_time: Option<NaiveDateTime>,
+    /// The value of each band of the pixel
+    pub values: &'a [f64],
+}
+
+/// A rule choosing the value of each pixel of a mosaic from the assets with a valid pixel there,
+/// e.g. the one with the lowest cloud score
+pub trait PixelSelection: Send + Sync {
+    /// Write the value of each band of a pixel to `out`, given the assets with a valid pixel
+    /// there in compositing order. `candidates` is never empty.
+    fn select(&self, candidates: &[Candidate<'_>], out: &mut [f64]);
+
+    /// Whether pixels only depend on the first asset with a valid pixel there, so that assets
+    /// past those covering a whole tile aren't read
+    fn first_only(&self) -> bool {
+        false
+    }
+}
+
+/// The built-in rules of [`PixelSelection`]. Values computed for integer images, like the mean,
+/// are truncated to the data type of the image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SelectionMethod {
+    /// Take the pixel of the first asset with a valid pixel
+    #[default]
+    First,
+    /// Take the highest value of each band
+    Highest,
+    /// Take the pixel of the asset acquired last, by [`Candidate::date_time`]. Assets without a
+    /// time are older than any other.
+    Latest,
+    /// Take the mean of each band
+    Mean,
+    /// Take the median of each band, the mean of the middle two values for an even count
+    Median,
+}
+
+impl PixelSelection for SelectionMethod {
+    fn select(&self, candidates: &[Candidate<'_>], out: &mut [f64]) {
+        let band = |i: usize| candidates.iter().map(move |candidate| candidate.values[i]);
+        match self {
+            Self::First => out.copy_from_slice(candidates[0].values),
+            Self::Latest => {
+                // The first of the latest assets wins ties, as `max_by_key` returns the last
+                let latest = candidates
+                    .iter()
+                    .rev()
+                    .max_by_key(|candidate| candidate.date_time)
+                    .unwrap();
+                out.copy_from_slice(latest.values);
+            }
+            Self::Highest => {
+                for (i, value) in out.iter_mut().enumerate() {
+                    *value = band(i).fold(f64::NEG_INFINITY, f64::max);
+                }
+            }
+            Self::Mean => {
+                for (i, value) in out.iter_mut().enumerate() {
+                    *value = band(i).sum::<f64>() / candidates.len() as f64;
+                }
+            }
+            Self::Median => {
+                let mut values = Vec::with_capacity(candidates.len());
+                for (i, value) in out.iter_mut().enumerate() {
+                    values.clear();
+                    values.extend(band(i));
+                    values.sort_by(f64::total_cmp);
+                    let mid = values.len() / 2;
+                    *value = if values.len() % 2 == 0 {
+                        (values[mid - 1] + values[mid]) / 2.0
+                    } else {
+                        values[mid]
+                    };
+                }
+            }
+        }
+    }
+
+    fn first_only(&self) -> bool {
+        *self == Self::First
+    }
+}
+
+/// A reader of many COGs sharing a CRS as one virtual image.
+///
+/// Tiles are composited from the valid pixels of every asset, which are those not masked out by
+/// its reads whose bands aren't all its nodata value, with the rule of
+/// [`MosaicReader::with_selection`]. By default each pixel is taken from the first asset with a
+/// valid pixel there. Assets whose bounds or footprint don't intersect a tile are skipped without
+/// reading them.
+#[derive(Clone)]
+pub struct MosaicReader {
+    assets: Vec<MosaicAsset>,
+    selection: Arc<dyn PixelSelection>,
+}
+
+impl MosaicReader {
+    /// Create a mosaic of already opened files, the first of which is drawn on top. Fails if
+    /// the files don't all have the same number of bands and data type, as pixels are
+    /// composited band by band.
+    pub fn new(assets: Vec<MosaicAsset>) -> Result<Self> {
+        if let Some((first, rest)) = assets.split_first() {
+            let layout = |asset: &MosaicAsset| -> Result<_> {
+                Ok((asset.reader.count(), asset.reader.dtype()?))
+            };
+            let expected = layout(first)?;
+            for (index, asset) in rest.iter().enumerate() {
+                let (count, dtype) = layout(asset)?;
+                if (count, dtype) != expected {
+                    return Err(AiocogeoError::General(format!(
+                        "asset {} has {count} {dtype:?} bands, but asset 0 has {} {:?} bands",
+                        index + 1,
+                        expected.0,
+                        expected.1
+                    )));
+                }
+            }
+        }
+        Ok(Self {
+            assets,
+            selection: Arc::new(SelectionMethod::First),
+        })
+    }
+
+    /// Composite tiles with another rule
+    pub fn with_selection(self, selection: impl PixelSelection + 'static) -> Self {
+        Self {
+            selection: Arc::new(selection),
+            ..self
+        }
+    }
+
+    /// Open the files of `definition` from `store`, or from the store of their URL, with
+    /// `options`, at most `concurrency` at a time. Fails if any file can't be opened.
+    ///
+    /// Files given as URLs are cached under the scheme and host of their URL rather than
+    /// [`OpenOptions::cache_namespace`], which identifies `store`.
+    pub async fn open(
+        store: Arc<dyn ObjectStore>,
+        definition: &MosaicDefinition,
+        options: &OpenOptions,
+        concurrency: usize,
+    ) -> Result<Self> {
+        let mut stores = HashMap::new();
+        let locations = definition
+            .assets
+            .iter()
+            .map(|asset| asset.location(&store, &mut stores))
+            .collect::<Result<Vec<_>>>()?;
+        let readers: Vec<_> = stream::iter(locations)
+            .map(|(store, path, namespace)| async move {
+                let Some(namespace) = namespace else {
+                    return COGReader::try_open_with_options(store, path, options).await;
+                };
+                let options = OpenOptions {
+                    cache_namespace: Some(namespace),
+                    ..options.clone()
+                };
+                COGReader::try_open_with_options(store, path, &options).await
+            })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await;
+        let assets = definition
+            .assets
+            .iter()
+            .zip(readers)
+            .map(|(asset, reader)| {
+                Ok(MosaicAsset {
+                    reader: reader?,
+                    footprint: asset.footprint_polygon(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Self::new(assets)
+    }
+
+    /// Return the files of the mosaic, in compositing order
+    pub fn assets(&self) -> &[MosaicAsset] {
+        &self.assets
+    }
+
+    /// Read tile `(x, y)` of zoom level `z` of a tiling grid from every asset, as in
+    /// [`COGReader::read_tms_tile`], and composite them. Pixels no asset has are masked out and
+    /// set to the fill value of the first asset read. Complex images aren't supported.
+    pub async fn read_tms_tile(
+        &self,
+        tms: &TileMatrixSet,
+        x: usize,
+        y: usize,
+        z: usize,
+        resampling: Resampling,
+        options: &ReadOptions,
+    ) -> Result<ImageData> {
+        let bounds = tms.tile_bounds(x, y, z)?;
+        // The values and valid pixels of each asset
+        let mut layers = vec![];
+        let mut covered: Option<Array2<bool>> = None;
+        for (index, asset) in self.assets.iter().enumerate() {
+            if !asset.intersects(bounds, tms.epsg) {
+                continue;
+            }
+            let image = asset
+                .reader
+                .read_tms_tile(tms, x, y, z, resampling, options)
+                .await?;
+            let values = image.data.to_f64()?;
+            let mut valid = image.mask.clone();
+            if let Some(nodata) = asset.reader.nodata() {
+                let is_nodata = |value: f64| value == nodata || (nodata.is_nan() && value.is_nan());
+                // Pixels are nodata only if every band is, as in GDAL's nodata masks
+                for ((row, col), valid) in valid.indexed_iter_mut() {
+                    let pixel = values.slice(ndarray::s![.., row, col]);
+                    *valid &= !pixel.iter().all(|&value| is_nodata(value));
+                }
+            }
+            // Store the bands of each pixel contiguously
+            let values = values
+                .permuted_axes([1, 2, 0])
+                .as_standard_layout()
+                .into_owned();
+            let covered = covered.get_or_insert_with(|| Array2::from_elem(valid.dim(), false));
+            covered.zip_mut_with(&valid, |covered, &valid| *covered |= valid);
+            let date_time = asset
+                .reader
+                .acquisition_date_time()
+                .or_else(|| asset.reader.date_time())
+                .map(|date_time| date_time.naive());
+            layers.push((index, asset, date_time, image, values, valid));
+            if self.selection.first_only() && covered.iter().all(|&covered| covered) {
+                break;
+            }
+        }
+
+        let Some((_, _, _, first, _, _)) = layers.first() else {
+            let first = self
+                .assets
+                .first()
+                .ok_or_else(|| AiocogeoError::General("the mosaic has no assets".to_string()))?;
+            return first
+                .reader
+                .empty_tms_tile(tms, x, y, z, resampling, options)
+                .await;
+        };
+        let dtype = first.data.dtype();
+        let transform = first.transform;
+        // Pixels no asset has keep the fill value of the first
+        let mut composite = first.data.to_f64()?;
+        let (bands, height, width) = composite.dim();
+        let mut out = vec![0.0; bands];
+        let mut candidates = Vec::with_capacity(layers.len());
+        for row in 0..height {
+            for col in 0..width {
+                candidates.clear();
+                for (index, asset, date_time, _, values, valid) in &layers {
+                    if valid[[row, col]] {
+                        candidates.push(Candidate {
+                            index: *index,
+                            asset,
+                            date_time: *date_time,
+                            values: values.slice(ndarray::s![row, col, ..]).to_slice().unwrap(),
+                        });
+                    }
+                }
+                if candidates.is_empty() {
+                    continue;
+                }
+                self.selection.select(&candidates, &mut out);
+                for (band, &value) in out.iter().enumerate() {
+                    composite[[band, row, col]] = value;
+                }
+            }
+        }
+        Ok(ImageData {
+            data: RasterArray::from_f64(dtype, composite),
+            mask: covered.unwrap(),
+            transform,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::enums::DataType;
+    use crate::testing::{CogBuilder, MockStore};
+
+    /// Put two adjacent 64x48 images into a store, as `west.tif` and `east.tif`
+    async fn mosaic_store() -> Arc<MockStore> {
+        let store = Arc::new(MockStore::new());
+        for (name, x) in [("west.tif", 0.0), ("east.tif", 64.0)] {
+            let builder = CogBuilder {
+                origin: Some((x, 48.0, 1.0)),
+                epsg: Some(3857),
+                ..Default::default()
+            };
+            let bytes = builder.build().unwrap();
+            store.put(&Path::from(name), bytes.into()).await.unwrap();
+        }
+        store
+    }
+
+    #[tokio::test]
+    async fn composite_tile() {
+        let store = mosaic_store().await;
+        let definition = MosaicDefinition {
+            assets: ["west.tif", "east.tif"]
+                .map(|path| AssetDefinition {
+                    path: path.to_string(),
+                    footprint: None,
+                })
+                .to_vec(),
+        };
+        let mosaic = MosaicReader::open(store, &definition, &OpenOptions::default(), 2)
+            .await
+            .unwrap();
+        assert_eq!(mosaic.assets().len(), 2);
+
+        // The zoom level 17 tile north east of the origin covers both images
+        let tms = TileMatrixSet::web_mercator_quad();
+        let (x, y, z) = (1 << 16, (1 << 16) - 1, 17);
+        let options = ReadOptions::default();
+        let tile = mosaic
+            .read_tms_tile(&tms, x, y, z, Resampling::Nearest, &options)
+            .await
+            .unwrap();
+        let mut images = vec![];
+        for asset in mosaic.assets() {
+            let image = asset
+                .reader
+                .read_tms_tile(&tms, x, y, z, Resampling::Nearest, &options);
+            images.push(image.await.unwrap());
+        }
+        let [west, east] = &images[..] else {
+            unreachable!()
+        };
+        let (
+            RasterArray::Uint8(data),
+            RasterArray::Uint8(west_data),
+            RasterArray::Uint8(east_data),
+        ) = (&tile.data, &west.data, &east.data)
+        else {
+            panic!("unexpected data type")
+        };
+
+        // The tile straddles both images, so the mosaic covers both of their pixels
+        let count = |image: &ImageData| image.mask.iter().filter(|&&valid| valid).count();
+        assert_eq!((count(west), count(east), count(&tile)), (2160, 2120, 4280));
+        for ((row, col), &valid) in tile.mask.indexed_iter() {
+            let expected = if west.mask[[row, col]] {
+                Some(west_data[[0, row, col]])
+            } else if east.mask[[row, col]] {
+                Some(east_data[[0, row, col]])
+            } else {
+                None
+            };
+            assert_eq!(valid.then(|| data[[0, row, col]]), expected, "{row}, {col}");
+        }
+        // Pixels of 1.19m, so the bottom row of the tile is the bottom row of the images, and
+        // column 54 is the second column of the east image
+        let RasterArray::Uint8(pixels) = CogBuilder::default().expected(0).unwrap() else {
+            panic!("unexpected data type")
+        };
+        assert_eq!(data[[0, 255, 0]], pixels[[0, 47, 0]]);
+        assert_eq!(data[[0, 255, 54]], pixels[[0, 47, 1]]);
+        assert!(!tile.mask[[0, 0]]);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn url_assets() {
+        let store = mosaic_store().await;
+        let file = std::env::temp_dir().join(format!("aiocogeo-mosaic-{}.tif", std::process::id()));
+        let bytes = store.get(&Path::from("east.tif")).await.unwrap();
+        std::fs::write(&file, bytes.bytes().await.unwrap()).unwrap();
+        let url = Url::from_file_path(&file).unwrap();
+        let definition = MosaicDefinition {
+            assets: ["west.tif", url.as_str()]
+                .map(|path| AssetDefinition {
+                    path: path.to_string(),
+                    footprint: None,
+                })
+                .to_vec(),
+        };
+        let mosaic = MosaicReader::open(store.clone(), &definition, &OpenOptions::default(), 2)
+            .await
+            .unwrap();
+        // Only the relative path is read from the store
+        let [west, east] = mosaic.assets() else {
+            panic!("expected two assets")
+        };
+        assert_eq!(west.reader.native_bounds(), Some((0.0, 0.0, 64.0, 48.0)));
+        assert_eq!(east.reader.native_bounds(), Some((64.0, 0.0, 128.0, 48.0)));
+        store.clear();
+        let window = crate::Window::new(0, 0, 64, 48);
+        let options = ReadOptions::default();
+        let data = east.reader.read_window(window, 0, &options).await.unwrap();
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(data, CogBuilder::default().expected(0).unwrap());
+        store.assert_request_count(0);
+    }
+
+    #[tokio::test]
+    async fn skip_disjoint_assets() {
+        let store = mosaic_store().await;
+        let definition = MosaicDefinition {
+            assets: vec![
+                AssetDefinition {
+                    path: "west.tif".to_string(),
+                    footprint: None,
+                },
+                // The footprint of the east image only covers its southern half
+                AssetDefinition {
+                    path: "east.tif".to_string(),
+                    footprint: Some(vec![vec![
+                        [64.0, 0.0],
+                        [128.0, 0.0],
+                        [128.0, 24.0],
+                        [64.0, 0.0],
+                    ]]),
+                },
+            ],
+        };
+        let mosaic = MosaicReader::open(store.clone(), &definition, &OpenOptions::default(), 2)
+            .await
+            .unwrap();
+        let [west, east] = mosaic.assets() else {
+            panic!("expected two assets")
+        };
+        assert!(west.intersects((0.0, 0.0, 10.0, 10.0), 3857));
+        assert!(!east.intersects((0.0, 0.0, 10.0, 10.0), 3857));
+        assert!(!east.intersects((70.0, 40.0, 80.0, 48.0), 3857));
+        assert!(east.intersects((100.0, 5.0, 110.0, 10.0), 3857));
+        // The polygon's edge crosses the box without any vertex inside it
+        assert!(east.intersects((60.0, -10.0, 140.0, 1.0), 3857));
+
+        // Tiles far from every asset are read without any request
+        let tms = TileMatrixSet::web_mercator_quad();
+        store.clear();
+        let options = ReadOptions::default();
+        let tile = mosaic
+            .read_tms_tile(&tms, 0, 0, 17, Resampling::Nearest, &options)
+            .await
+            .unwrap();
+        assert!(tile.mask.iter().all(|&valid| !valid));
+        assert_eq!(tile.data.shape(), (1, 256, 256));
+        store.assert_request_count(0);
+    }
+
+    #[tokio::test]
+    async fn selection_methods() {
+        let store = Arc::new(MockStore::new());
+        let builder = CogBuilder {
+            origin: Some((0.0, 48.0, 1.0)),
+            epsg: Some(3857),
+            ..Default::default()
+        };
+        let RasterArray::Uint8(pixels) = builder.expected(0).unwrap() else {
+            panic!("unexpected data type")
+        };
+        // The second image is shifted by a pixel, and its first pixel value is nodata
+        let nodata = pixels[[0, 0, 0]];
+        let shifted = CogBuilder {
+            origin: Some((1.0, 48.0, 1.0)),
+            nodata: Some(nodata as f64),
+            ..builder.clone()
+        };
+        for (name, builder) in [("a.tif", &builder), ("b.tif", &shifted)] {
+            let bytes = builder.build().unwrap();
+            store.put(&Path::from(name), bytes.into()).await.unwrap();
+        }
+        let definition = MosaicDefinition {
+            assets: ["a.tif", "b.tif"]
+                .map(|path| AssetDefinition {
+                    path: path.to_string(),
+                    footprint: None,
+                })
+                .to_vec(),
+        };
+        let mosaic = MosaicReader::open(store, &definition, &OpenOptions::default(), 2)
+            .await
+            .unwrap();
+
+        let tms = TileMatrixSet::web_mercator_quad();
+        let (x, y, z) = (1 << 16, (1 << 16) - 1, 17);
+        let options = ReadOptions::default();
+        let mut layers = vec![];
+        for asset in mosaic.assets() {
+            let image = asset
+                .reader
+                .read_tms_tile(&tms, x, y, z, Resampling::Nearest, &options);
+            let image = image.await.unwrap();
+            let RasterArray::Uint8(data) = image.data else {
+                panic!("unexpected data type")
+            };
+            layers.push((data, image.mask));
+        }
+        let [(a, a_mask), (b, b_mask)] = &layers[..] else {
+            unreachable!()
+        };
+
+        type Combine = fn(u8, u8) -> u8;
+        let methods: [(SelectionMethod, Combine); 3] = [
+            (SelectionMethod::First, |a, _| a),
+            (SelectionMethod::Highest, |a, b| a.max(b)),
+            (SelectionMethod::Mean, |a, b| {
+                ((a as f64 + b as f64) / 2.0) as u8
+            }),
+        ];
+        for (method, combine) in methods {
+            let mosaic = mosaic.clone().with_selection(method);
+            let tile = mosaic.read_tms_tile(&tms, x, y, z, Resampling::Nearest, &options);
+            let tile = tile.await.unwrap();
+            let RasterArray::Uint8(data) = tile.data else {
+                panic!("unexpected data type")
+            };
+            let mut overlaps = 0;
+            for ((row, col), &valid) in tile.mask.indexed_iter() {
+                let (a_value, b_value) = (a[[0, row, col]], b[[0, row, col]]);
+                let a_valid = a_mask[[row, col]];
+                let b_valid = b_mask[[row, col]] && b_value != nodata;
+                assert_eq!(valid, a_valid || b_valid);
+                let expected = match (a_valid, b_valid) {
+                    (true, true) => combine(a_value, b_value),
+                    (true, false) => a_value,
+                    (false, true) => b_value,
+                    (false, false) => continue,
+                };
+                overlaps += usize::from(a_valid && b_valid);
+                assert_eq!(data[[0, row, col]], expected, "{method:?} at {row}, {col}");
+            }
+            assert_eq!(overlaps, 2108);
+        }
+    }
+
+    #[tokio::test]
+    async fn mismatched_assets() {
+        let store = mosaic_store().await;
+        for other in [
+            CogBuilder {
+                bands: 3,
+                ..Default::default()
+            },
+            CogBuilder {
+                data_type: DataType::Uint16,
+                ..Default::default()
+            },
+        ] {
+            let bytes = other.build().unwrap();
+            store
+                .put(&Path::from("other.tif"), bytes.into())
+                .await
+                .unwrap();
+            let definition = MosaicDefinition {
+                assets: ["west.tif", "other.tif"]
+                    .map(|path| AssetDefinition {
+                        path: path.to_string(),
+                        footprint: None,
+                    })
+                    .to_vec(),
+            };
+            let mosaic =
+                MosaicReader::open(store.clone(), &definition, &OpenOptions::default(), 2).await;
+            let Err(err) = mosaic else {
+                panic!("opened a mosaic of mismatched assets")
+            };
+            assert!(err.to_string().contains("asset 1 has"), "{err}");
+        }
+    }
+
+    #[tokio::test]
+    async fn select_pixels() {
+        let (reader, _) = CogBuilder::default().open().await.unwrap();
+        let asset = MosaicAsset {
+            reader,
+            footprint: None,
+        };
+        let date = |day| chrono::NaiveDate::from_ymd_opt(2024, 1, day)?.and_hms_opt(0, 0, 0);
+        let values = [[1.0, 8.0], [4.0, 2.0], [3.0, 6.0], [9.0, 4.0]];
+        let dates = [date(2), date(5), None, date(5)];
+        let candidates: Vec<_> = values
+            .iter()
+            .zip(dates)
+            .enumerate()
+            .map(|(index, (values, date_time))| Candidate {
+                index,
+                asset: &asset,
+                date_time,
+                values,
+            })
+            .collect();
+        let select = |method: SelectionMethod, candidates: &[Candidate]| {
+            let mut out = [0.0; 2];
+            method.select(candidates, &mut out);
+            out
+        };
+        assert_eq!(select(SelectionMethod::First, &candidates), [1.0, 8.0]);
+        assert_eq!(select(SelectionMethod::Highest, &candidates), [9.0, 8.0]);
+        // The first of the latest assets wins
+        assert_eq!(select(SelectionMethod::Latest, &candidates), [4.0, 2.0]);
+        assert_eq!(select(SelectionMethod::Mean, &candidates), [4.25, 5.0]);
+        assert_eq!(select(SelectionMethod::Median, &candidates), [3.5, 5.0]);
+        assert_eq!(
+            select(SelectionMethod::Median, &candidates[..3]),
+            [3.0, 6.0]
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn definition_json() {
+        let json = r#"{"assets": [
+            {"path": "west.tif", "footprint": [[[0, 0], [64, 0], [64, 48], [0, 0]]]},
+            {"path": "east.tif"}
+        ]}"#;
+        let definition = MosaicDefinition::from_json(json).unwrap();
+        assert_eq!(definition.assets[1].footprint, None);
+        let footprint = definition.assets[0].footprint_polygon().unwrap();
+        assert_eq!(footprint.exterior().0.len(), 4);
+        assert_eq!(
+            MosaicDefinition::from_json(&definition.to_json()).unwrap(),
+            definition
+        );
+        assert!(MosaicDefinition::from_json("{}").is_err());
+    }
+}
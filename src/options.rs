@@ -0,0 +1,545 @@
+use std::fmt::{Debug, Formatter};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use tiff::tags::Tag;
+
+use crate::array::PixelValue;
+use crate::error::{AiocogeoError, Result};
+use crate::pool::BufferPool;
+use crate::recorder::{RequestHooks, RequestRecorder};
+use crate::scheduler::RequestScheduler;
+use crate::store::HeaderCache;
+
+/// Options controlling how pixel data is decoded on read
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    /// Clear the bits of unsigned integer samples above their `NBITS` significant bits.
+    ///
+    /// GDAL stores `NBITS` data right-justified, e.g. 12-bit sensor data in `0..4096` in a
+    /// `u16`, so values are not rescaled; this only drops bits that a writer left set above the
+    /// significant ones. Has no effect when the image doesn't declare an `NBITS` smaller than its
+    /// sample size.
+    pub normalize_nbits: bool,
+
+    /// Return tiles in stored order, ignoring the `Orientation` tag.
+    ///
+    /// By default, tile indices refer to the image in visual orientation and decoded tiles are
+    /// flipped and/or transposed to match.
+    pub ignore_orientation: bool,
+
+    /// Return rows from north to south and columns from west to east, whatever the
+    /// geotransform and the `Orientation` tag, e.g. for south-up files whose geotransform has a
+    /// positive `e` term.
+    ///
+    /// Tiles are flipped and/or transposed as with the `Orientation` tag, and tile indices,
+    /// windows and geotransforms refer to the image in that orientation. Images rotated by other
+    /// than a quarter turn are read at the nearest quarter turn. Takes precedence over
+    /// [`ReadOptions::ignore_orientation`], and has no effect on images that aren't
+    /// georeferenced.
+    pub north_up: bool,
+
+    /// Apply each band's GDAL scale and offset, returning `value * scale + offset`.
+    ///
+    /// Output is `f64` for 32 and 64-bit input samples and `f32` otherwise. Complex samples are
+    /// not supported.
+    pub apply_scale_offset: bool,
+
+    /// The maximum number of tiles fetched at once by streaming reads, defaulting to
+    /// [`DEFAULT_CONCURRENCY`].
+    pub max_concurrent_requests: Option<usize>,
+
+    /// Fetch tiles separated by at most this many bytes in the file with a single request,
+    /// discarding the bytes in between.
+    ///
+    /// Larger values trade extra bytes transferred for fewer requests, which usually pays off on
+    /// high latency stores like S3. Defaults to 0, which only merges tiles that are contiguous.
+    /// Merged requests that fail with status 413 or 416, or 400 with a message about the range,
+    /// as when a store or CDN caps the size of ranges, are split in halves until they succeed,
+    /// hold a single tile or have been split four times.
+    pub coalesce_gap_bytes: usize,
+
+    /// The largest range fetched by a single request, for stores and CDNs that cap the size of
+    /// ranges.
+    ///
+    /// Tiles are only merged into requests up to this size, though a larger tile is still
+    /// fetched whole. Defaults to no limit.
+    pub max_range_bytes: Option<usize>,
+
+    /// When a single tile is requested, also prefetch the adjacent tiles of the same overview
+    /// level in the background, as panning clients usually request them next.
+    ///
+    /// This requires a cache to prefetch into ([`OpenOptions::cache_dir`]) and a
+    /// [`OpenOptions::spawner`] to run the prefetch on; otherwise it has no effect.
+    pub prefetch_neighbors: bool,
+
+    /// Only read these bands, by zero-based index and in this order.
+    ///
+    /// Band-interleaved images store each band in separate tiles, so only the tiles of the
+    /// selected bands are fetched. Pixel-interleaved tiles hold every band, so they're fetched
+    /// whole and the other bands are discarded after decoding.
+    pub bands: Option<Vec<usize>>,
+
+    /// Only read the bands with these descriptions, in this order, as with
+    /// [`ReadOptions::bands`].
+    ///
+    /// Descriptions are read from GDAL metadata, see [`COGReader::band_descriptions`]. Reads fail
+    /// if a name matches no band, or if [`ReadOptions::bands`] is also set.
+    ///
+    /// [`COGReader::band_descriptions`]: crate::COGReader::band_descriptions
+    pub band_names: Option<Vec<String>>,
+
+    /// Crop tiles along the right and bottom edges of the image to the image's extent, dropping
+    /// the padding that fills them up to the tile size.
+    ///
+    /// By default, tiles are returned whole with the padding, whose values are unspecified.
+    /// Windows assembled from tiles never include padding.
+    pub clip_edge_tiles: bool,
+
+    /// The maximum size in bytes of the array returned by a window read.
+    ///
+    /// Reads that would exceed it fail with [`AiocogeoError::MemoryLimitExceeded`] before
+    /// fetching anything, e.g. an accidental full resolution read of a 100k×100k raster.
+    /// [`COGReader::read_window_within_limit`] instead falls back to a coarser overview.
+    ///
+    /// [`AiocogeoError::MemoryLimitExceeded`]: crate::error::AiocogeoError::MemoryLimitExceeded
+    /// [`COGReader::read_window_within_limit`]: crate::COGReader::read_window_within_limit
+    pub memory_limit: Option<usize>,
+
+    /// Log the byte ranges requested by the read, including background prefetches
+    pub recorder: Option<RequestRecorder>,
+
+    /// What window reads do when a tile can't be fetched or decoded, e.g. because its bytes are
+    /// corrupt or the store rejects its range. Defaults to failing the whole read.
+    pub on_tile_error: TileErrorPolicy,
+
+    /// The value of pixels with no data: sparse tiles, parts of windows outside the image, tiles
+    /// filled under [`TileErrorPolicy::Fill`] and pixels masked out of feature and tiling grid
+    /// reads.
+    ///
+    /// It is converted to the data type of the output as with `as`, saturating at the bounds of
+    /// integer types; give it in the output data type, e.g. `PixelValue::Uint64(u64::MAX)`, to
+    /// set values that `f64` can't hold. Defaults to the nodata value of the image if it has one,
+    /// and zero otherwise.
+    pub fill_value: Option<PixelValue>,
+}
+
+impl ReadOptions {
+    /// Return the default options, overridden by environment variables as in Python aiocogeo:
+    ///
+    /// - `AIOCOGEO_HTTP_MERGE_RANGE_GAP`: [`ReadOptions::coalesce_gap_bytes`]
+    /// - `AIOCOGEO_MAX_CONCURRENT_REQUESTS`: [`ReadOptions::max_concurrent_requests`]
+    /// - `AIOCOGEO_MEMORY_LIMIT`: [`ReadOptions::memory_limit`], in bytes
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(&process_env)
+    }
+
+    /// Return the default options, overridden by the variables of `vars`
+    fn from_vars(vars: EnvVars) -> Result<Self> {
+        let defaults = Self::default();
+        Ok(Self {
+            coalesce_gap_bytes: env_var(vars, "AIOCOGEO_HTTP_MERGE_RANGE_GAP")?
+                .unwrap_or(defaults.coalesce_gap_bytes),
+            max_concurrent_requests: env_var(vars, "AIOCOGEO_MAX_CONCURRENT_REQUESTS")?
+                .or(defaults.max_concurrent_requests),
+            memory_limit: env_var(vars, "AIOCOGEO_MEMORY_LIMIT")?.or(defaults.memory_limit),
+            ..defaults
+        })
+    }
+}
+
+/// Look up an environment variable, in the environment of the process or, in tests, in a map
+type EnvVars<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+/// Look up an environment variable of the process
+fn process_env(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+/// Parse the environment variable `name`, if it is set
+fn env_var<T: FromStr>(vars: EnvVars, name: &str) -> Result<Option<T>> {
+    let Some(value) = vars(name) else {
+        return Ok(None);
+    };
+    value
+        .trim()
+        .parse()
+        .map(Some)
+        .map_err(|_| AiocogeoError::General(format!("invalid value {value:?} of {name}")))
+}
+
+/// Parse the boolean environment variable `name`, if it is set, accepting `TRUE`/`FALSE`,
+/// `YES`/`NO`, `ON`/`OFF` and `1`/`0` in any case, as GDAL does
+fn env_flag(vars: EnvVars, name: &str) -> Result<Option<bool>> {
+    let Some(value) = vars(name) else {
+        return Ok(None);
+    };
+    match value.trim().to_ascii_uppercase().as_str() {
+        "TRUE" | "YES" | "ON" | "1" => Ok(Some(true)),
+        "FALSE" | "NO" | "OFF" | "0" => Ok(Some(false)),
+        _ => Err(AiocogeoError::General(format!(
+            "invalid value {value:?} of {name}, expected TRUE or FALSE"
+        ))),
+    }
+}
+
+/// How pixel values are interpolated when resampling
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Resampling {
+    /// The value of the source pixel under each output pixel, keeping the data type
+    #[default]
+    Nearest,
+    /// Bilinear interpolation between source pixel centers, returning `f64` values
+    Bilinear,
+}
+
+/// How window reads handle tiles that can't be fetched or decoded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TileErrorPolicy {
+    /// Fail the read with the error of the tile
+    #[default]
+    Fail,
+    /// Fill the pixels of failed tiles with [`ReadOptions::fill_value`] and report each failure to
+    /// [`RequestHooks::on_tile_error`], only failing if every tile failed
+    Fill,
+}
+
+/// How to handle files that don't follow the TIFF and GeoTIFF specs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Reject spec violations, such as a missing `PhotometricInterpretation` tag or an
+    /// unsupported GeoKey directory version, with an error describing the violation
+    Strict,
+    /// Fill in reasonable defaults for missing or invalid tags and skip GeoKeys that can't be
+    /// resolved, as GDAL does
+    #[default]
+    Lenient,
+}
+
+impl ParseMode {
+    /// Return an error describing a spec violation in strict mode, or do nothing in lenient mode
+    pub(crate) fn violation(self, message: impl FnOnce() -> String) -> Result<()> {
+        match self {
+            Self::Strict => Err(AiocogeoError::General(message())),
+            Self::Lenient => Ok(()),
+        }
+    }
+}
+
+/// Limits on the metadata of opened files, so that corrupt or malicious files fail with an error
+/// instead of exhausting memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The maximum number of tags in an IFD, defaulting to 1024
+    pub max_tag_count: usize,
+    /// The maximum size in bytes of the value of a single tag, defaulting to 16 MiB. Tile offsets
+    /// and byte counts take 4 bytes per tile.
+    pub max_tag_value_bytes: usize,
+    /// The maximum number of IFDs in the file, including overviews and masks, defaulting to 256
+    pub max_ifd_count: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_tag_count: 1024,
+            max_tag_value_bytes: 16 * 1024 * 1024,
+            max_ifd_count: 256,
+        }
+    }
+}
+
+/// The default number of concurrent tile requests of streaming reads
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Options controlling how a COG is opened
+#[derive(Debug, Clone, Default)]
+pub struct OpenOptions {
+    /// Fetch this many bytes from the start of the file with a single request at open, so that
+    /// the IFDs and tag values within them are parsed without further requests.
+    ///
+    /// If the first IFD is past these bytes, as in TIFFs that aren't cloud optimized, as many
+    /// bytes, and at least 32 KB, are fetched from it with a second request. Metadata past them
+    /// is fetched as it's parsed. Defaults to 0, which fetches each value separately.
+    pub header_bytes: usize,
+
+    /// Fetch this many bytes from the end of the file with a single request at open if the first
+    /// IFD isn't within the header, as in TIFFs written by streaming writers that place their
+    /// IFDs after the image data.
+    ///
+    /// The tail is fetched with a suffix range request, or with a bounded range from the file
+    /// size for stores that don't support them. Defaults to 0, which reads trailing IFDs value by
+    /// value.
+    pub tail_bytes: usize,
+
+    /// Download the whole file with a single request if it is at most this many bytes, serving
+    /// all subsequent reads from memory.
+    ///
+    /// For small files, one request is faster than the many small range requests needed to read
+    /// the header and tiles. The file size is taken from the response to the first read at open,
+    /// so checking it costs no extra request. Disabled by default.
+    pub whole_file_threshold: Option<usize>,
+
+    /// Don't pin reads to the version of the file seen at open.
+    ///
+    /// By default the file's metadata is taken from the response to the ranged `GET` of the
+    /// header at open, without a `HEAD` request that some endpoints don't support, and all later
+    /// reads are conditional on its ETag and modification time, so that a file replaced
+    /// mid-session fails with [`AiocogeoError::SourceChanged`] instead of silently mixing bytes
+    /// from two versions of the file. The header read fetches at least the 8 byte TIFF header,
+    /// or [`OpenOptions::header_bytes`] if larger.
+    ///
+    /// [`AiocogeoError::SourceChanged`]: crate::error::AiocogeoError::SourceChanged
+    pub disable_version_pinning: bool,
+
+    /// Persist fetched bytes as files in this local directory, and serve later reads of the same
+    /// bytes from it.
+    ///
+    /// The file is cached in blocks of [`CACHE_BLOCK_SIZE`](crate::CACHE_BLOCK_SIZE) bytes,
+    /// keyed by [`OpenOptions::cache_namespace`], which must be set, path, ETag and block, so
+    /// batch jobs can resume or re-run without downloading the same bytes again. The directory
+    /// is created when the first bytes are cached and is never cleaned up. Files whose store
+    /// doesn't report an ETag should not be cached if they may be replaced. Not supported on
+    /// `wasm32`, which has no filesystem.
+    pub cache_dir: Option<PathBuf>,
+
+    /// Reuse the headers of files opened before with this cache, revalidating them with
+    /// `If-None-Match` requests instead of downloading them again.
+    ///
+    /// Implies reading the header with a single request at open, even with
+    /// [`OpenOptions::disable_version_pinning`]. Headers are keyed by
+    /// [`OpenOptions::cache_namespace`] and path, so the namespace must be set.
+    pub header_cache: Option<HeaderCache>,
+
+    /// Identify the store of the file in [`OpenOptions::header_cache`] and
+    /// [`OpenOptions::cache_dir`], e.g. with the URL of its bucket, so that files at the same
+    /// path of different stores don't share cache entries.
+    ///
+    /// An [ObjectStore](object_store::ObjectStore) doesn't tell which bucket, endpoint or
+    /// account it reads from, so opening a file with either cache fails without a namespace.
+    /// [`crate::MosaicReader::open`] uses the scheme and host of assets given as URLs instead.
+    pub cache_namespace: Option<String>,
+
+    /// Re-create the store and path of the file when a read fails because the credentials or
+    /// presigned URL it was opened with expired, and retry the read once, so that long-running
+    /// readers outlive short-lived credentials
+    pub refresh: Option<StoreRefresher>,
+
+    /// Run background work, such as [`ReadOptions::prefetch_neighbors`], on an async runtime
+    pub spawner: Option<Spawner>,
+
+    /// Whether to reject files that violate the TIFF or GeoTIFF specs, or to read them with
+    /// reasonable defaults. Tags that no image can be read without, like `ImageWidth`, are
+    /// required in both modes.
+    pub parse_mode: ParseMode,
+
+    /// Limits on the metadata of the file, checked while parsing its IFDs
+    pub limits: Limits,
+
+    /// Log the byte ranges requested while opening the file
+    pub recorder: Option<RequestRecorder>,
+
+    /// Callbacks invoked around every request and tile decode of the reader, including those
+    /// made while opening the file
+    pub hooks: Option<Arc<dyn RequestHooks>>,
+
+    /// Reuse the scratch buffers of tile decompression and decoding from this pool, which may be
+    /// shared by many readers serving tiles of similar files
+    pub buffer_pool: Option<BufferPool>,
+
+    /// Limit the requests in flight of the reader with this scheduler, which may be shared by
+    /// many readers, so that [`ReadOptions::prefetch_neighbors`] requests only use the slots
+    /// that tile reads aren't waiting for
+    pub scheduler: Option<RequestScheduler>,
+
+    /// The options of reads made without options, like [`crate::COGReader::get_tile`]
+    pub read_options: ReadOptions,
+}
+
+impl OpenOptions {
+    /// Return the default options, overridden by environment variables as in Python aiocogeo:
+    ///
+    /// - `AIOCOGEO_INGESTED_BYTES_AT_OPEN`: [`OpenOptions::header_bytes`]
+    /// - `AIOCOGEO_WHOLE_FILE_THRESHOLD`: [`OpenOptions::whole_file_threshold`], in bytes
+    /// - `AIOCOGEO_DISABLE_VERSION_PINNING`: [`OpenOptions::disable_version_pinning`]
+    /// - `AIOCOGEO_ENABLE_BLOCK_CACHE`: cache fetched bytes on disk in `AIOCOGEO_CACHE_DIR`,
+    ///   which must then be set. Setting `AIOCOGEO_CACHE_DIR` alone also enables the cache.
+    ///   Unlike the in-memory cache of Python aiocogeo, it has no size limit and is never
+    ///   cleaned up. See [`OpenOptions::cache_dir`].
+    /// - `AIOCOGEO_CACHE_NAMESPACE`: [`OpenOptions::cache_namespace`], required with the cache
+    ///
+    /// [`OpenOptions::read_options`] are also taken from the environment, with
+    /// [`ReadOptions::from_env`].
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(&process_env)
+    }
+
+    /// Return the default options, overridden by the variables of `vars`
+    fn from_vars(vars: EnvVars) -> Result<Self> {
+        let defaults = Self::default();
+        let cache_dir: Option<PathBuf> = env_var(vars, "AIOCOGEO_CACHE_DIR")?;
+        let cache_dir = match env_flag(vars, "AIOCOGEO_ENABLE_BLOCK_CACHE")? {
+            Some(false) => None,
+            Some(true) => Some(cache_dir.ok_or_else(|| {
+                AiocogeoError::General(
+                    "AIOCOGEO_ENABLE_BLOCK_CACHE requires AIOCOGEO_CACHE_DIR, the directory to \
+                     cache fetched bytes in"
+                        .to_string(),
+                )
+            })?),
+            None => cache_dir.or(defaults.cache_dir),
+        };
+        let cache_namespace =
+            env_var(vars, "AIOCOGEO_CACHE_NAMESPACE")?.or(defaults.cache_namespace);
+        if cache_dir.is_some() && cache_namespace.is_none() {
+            return Err(AiocogeoError::General(
+                "the block cache requires AIOCOGEO_CACHE_NAMESPACE, identifying the store of \
+                 cached files"
+                    .to_string(),
+            ));
+        }
+        Ok(Self {
+            header_bytes: env_var(vars, "AIOCOGEO_INGESTED_BYTES_AT_OPEN")?
+                .unwrap_or(defaults.header_bytes),
+            whole_file_threshold: env_var(vars, "AIOCOGEO_WHOLE_FILE_THRESHOLD")?
+                .or(defaults.whole_file_threshold),
+            disable_version_pinning: env_flag(vars, "AIOCOGEO_DISABLE_VERSION_PINNING")?
+                .unwrap_or(defaults.disable_version_pinning),
+            cache_dir,
+            cache_namespace,
+            read_options: ReadOptions::from_vars(vars)?,
+            ..defaults
+        })
+    }
+}
+
+/// Options controlling how two files are compared by [`crate::COGReader::diff`]
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Also compare a CRC-32 checksum of the stored bytes of every tile, which reads both files
+    /// in full. Tiles are only compared between IFDs with the same tile layout, compression and
+    /// predictor, since any of them changes every tile; other IFDs get a `tiles not compared`
+    /// difference.
+    pub compare_tiles: bool,
+
+    /// Tags left out of the comparison, e.g. `Tag::Software` or `Tag::DateTime`, which differ
+    /// between otherwise identical outputs of a pipeline
+    pub ignore_tags: Vec<Tag>,
+
+    /// Options for the tile reads of [`DiffOptions::compare_tiles`], such as the number of
+    /// concurrent requests
+    pub read_options: ReadOptions,
+}
+
+/// A function that runs a future in the background, e.g. with `tokio::spawn`
+#[derive(Clone)]
+pub struct Spawner(Arc<dyn Fn(BoxFuture<'static, ()>) + Send + Sync>);
+
+impl Spawner {
+    pub fn new(spawn: impl Fn(BoxFuture<'static, ()>) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(spawn))
+    }
+
+    pub(crate) fn spawn(&self, future: BoxFuture<'static, ()>) {
+        (self.0)(future)
+    }
+}
+
+impl Debug for Spawner {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Spawner")
+    }
+}
+
+/// The result of a [`StoreRefresher`]: a store and the path of the file within it
+pub type RefreshedStore = object_store::Result<(Arc<dyn ObjectStore>, Path)>;
+
+/// An async function that re-creates the store and path of a file, e.g. with fresh STS
+/// credentials or a newly presigned URL, called when reads fail with authentication or
+/// permission errors
+#[derive(Clone)]
+pub struct StoreRefresher(Arc<dyn Fn() -> BoxFuture<'static, RefreshedStore> + Send + Sync>);
+
+impl StoreRefresher {
+    pub fn new(
+        refresh: impl Fn() -> BoxFuture<'static, RefreshedStore> + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(refresh))
+    }
+
+    pub(crate) async fn refresh(&self) -> RefreshedStore {
+        (self.0)().await
+    }
+}
+
+impl Debug for StoreRefresher {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("StoreRefresher")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn env_vars() {
+        std::env::set_var("AIOCOGEO_TEST_SIZE", " 4096 ");
+        std::env::set_var("AIOCOGEO_TEST_FLAG", "no");
+        std::env::set_var("AIOCOGEO_TEST_INVALID", "lots");
+        let vars = &process_env;
+        assert_eq!(
+            env_var::<usize>(vars, "AIOCOGEO_TEST_SIZE").unwrap(),
+            Some(4096)
+        );
+        assert_eq!(env_var::<usize>(vars, "AIOCOGEO_TEST_UNSET").unwrap(), None);
+        assert!(env_var::<usize>(vars, "AIOCOGEO_TEST_INVALID").is_err());
+        assert_eq!(env_flag(vars, "AIOCOGEO_TEST_FLAG").unwrap(), Some(false));
+        assert!(env_flag(vars, "AIOCOGEO_TEST_INVALID").is_err());
+    }
+
+    #[test]
+    fn open_options_from_env() {
+        let from_vars = |vars: &[(&str, &str)]| {
+            let vars: HashMap<_, _> = vars.iter().copied().collect();
+            OpenOptions::from_vars(&|name| vars.get(name).map(|value| value.to_string()))
+        };
+
+        let options = from_vars(&[
+            ("AIOCOGEO_INGESTED_BYTES_AT_OPEN", "16384"),
+            ("AIOCOGEO_DISABLE_VERSION_PINNING", "yes"),
+            ("AIOCOGEO_HTTP_MERGE_RANGE_GAP", "1024"),
+        ])
+        .unwrap();
+        assert_eq!(options.header_bytes, 16384);
+        assert!(options.disable_version_pinning);
+        assert_eq!(options.read_options.coalesce_gap_bytes, 1024);
+        assert_eq!(options.cache_dir, None);
+
+        // The block cache needs a directory and a namespace
+        let options = from_vars(&[
+            ("AIOCOGEO_ENABLE_BLOCK_CACHE", "1"),
+            ("AIOCOGEO_CACHE_DIR", "/tmp/cache"),
+            ("AIOCOGEO_CACHE_NAMESPACE", "s3://bucket"),
+        ])
+        .unwrap();
+        assert_eq!(options.cache_dir, Some(PathBuf::from("/tmp/cache")));
+        assert_eq!(options.cache_namespace.as_deref(), Some("s3://bucket"));
+        let err = from_vars(&[("AIOCOGEO_ENABLE_BLOCK_CACHE", "1")]).unwrap_err();
+        assert!(err.to_string().contains("AIOCOGEO_CACHE_DIR"));
+        let err = from_vars(&[("AIOCOGEO_CACHE_DIR", "/tmp/cache")]).unwrap_err();
+        assert!(err.to_string().contains("AIOCOGEO_CACHE_NAMESPACE"));
+        let options = from_vars(&[
+            ("AIOCOGEO_ENABLE_BLOCK_CACHE", "off"),
+            ("AIOCOGEO_CACHE_DIR", "/tmp/cache"),
+        ])
+        .unwrap();
+        assert_eq!(options.cache_dir, None);
+    }
+}
@@ -1,3 +1,17 @@
+use std::io;
+use std::ops::Range;
+
+use bytes::{Bytes, BytesMut};
+use futures::future::{self, BoxFuture, FutureExt};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use ndarray::{Array2, Array3};
+use object_store::path::Path;
+use object_store::ObjectStore;
+
+use crate::affine::AffineTransform;
+use crate::array::RasterArray;
+use crate::error::{AiocogeoError, Result};
+
 struct TileMetadata {
     /// top left corner of the partial read
     tlx: f64,
@@ -20,3 +34,524 @@ struct TileMetadata {
     /// overview level (where 0 is source)
     ovr_level: usize,
 }
+
+/// A rectangular region of pixels within an image, in visual orientation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Window {
+    /// The column of the left edge of the window
+    pub col_off: usize,
+    /// The row of the top edge of the window
+    pub row_off: usize,
+    /// The number of columns in the window
+    pub width: usize,
+    /// The number of rows in the window
+    pub height: usize,
+}
+
+impl Window {
+    pub fn new(col_off: usize, row_off: usize, width: usize, height: usize) -> Self {
+        Self {
+            col_off,
+            row_off,
+            width,
+            height,
+        }
+    }
+
+    /// Return the overlapping region of two windows, or `None` if they don't overlap
+    pub fn intersection(&self, other: &Window) -> Option<Window> {
+        let col_off = self.col_off.max(other.col_off);
+        let row_off = self.row_off.max(other.row_off);
+        let col_end = (self.col_off + self.width).min(other.col_off + other.width);
+        let row_end = (self.row_off + self.height).min(other.row_off + other.height);
+        if col_end <= col_off || row_end <= row_off {
+            return None;
+        }
+        Some(Window::new(
+            col_off,
+            row_off,
+            col_end - col_off,
+            row_end - row_off,
+        ))
+    }
+
+    /// Return the `(minx, miny, maxx, maxy)` bounds of the window in model coordinates
+    pub fn bounds(&self, transform: &AffineTransform) -> (f64, f64, f64, f64) {
+        let (left, top) = (self.col_off as f64, self.row_off as f64);
+        let (right, bottom) = (left + self.width as f64, top + self.height as f64);
+        let corners = [
+            transform.apply(left, top),
+            transform.apply(right, top),
+            transform.apply(right, bottom),
+            transform.apply(left, bottom),
+        ];
+        corners.iter().fold(
+            (
+                f64::INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::NEG_INFINITY,
+            ),
+            |(minx, miny, maxx, maxy), &(x, y)| {
+                (minx.min(x), miny.min(y), maxx.max(x), maxy.max(y))
+            },
+        )
+    }
+}
+
+/// A decoded internal tile, as yielded by [`COGReader::read_tiles_stream`]
+///
+/// [`COGReader::read_tiles_stream`]: crate::COGReader::read_tiles_stream
+#[derive(Debug, Clone)]
+pub struct Tile {
+    /// The x index of the tile
+    pub x: usize,
+    /// The y index of the tile
+    pub y: usize,
+    /// The overview level of the tile, where 0 is the full resolution image
+    pub z: usize,
+    /// The pixels of the overview level covered by the tile, excluding any padding past the edge
+    /// of the image
+    pub window: Window,
+    /// The `(minx, miny, maxx, maxy)` bounds of `window` in native crs, if the image is
+    /// georeferenced
+    pub bounds: Option<(f64, f64, f64, f64)>,
+    /// The decoded pixels of the full tile, including any padding unless
+    /// [`ReadOptions::clip_edge_tiles`] is set
+    ///
+    /// [`ReadOptions::clip_edge_tiles`]: crate::ReadOptions::clip_edge_tiles
+    pub data: RasterArray,
+}
+
+/// Pixels read onto a georeferenced grid, with a mask of the valid pixels
+#[derive(Debug, Clone)]
+pub struct ImageData {
+    /// The pixels, with shape `(bands, height, width)`
+    pub data: RasterArray,
+    /// A `(height, width)` mask that is `true` for valid pixels
+    pub mask: Array2<bool>,
+    /// The geotransform of the grid, mapping pixels to coordinates in native crs
+    pub transform: AffineTransform,
+}
+
+impl ImageData {
+    /// Map the values of a single band palette image to the RGB colors of `colormap`, e.g. from
+    /// [`crate::COGReader::colormap`], so it can be encoded with [`ImageData::to_png`].
+    ///
+    /// Pixels whose color is transparent or whose value is past the end of the colormap are
+    /// masked out. Only `uint8` and `uint16` images can be mapped.
+    pub fn apply_colormap(&self, colormap: &[[u8; 4]]) -> Result<ImageData> {
+        let (bands, height, width) = self.data.shape();
+        if bands != 1 {
+            return Err(AiocogeoError::General(format!(
+                "colormaps apply to images of 1 band, not {bands}"
+            )));
+        }
+        let index: Box<dyn Fn(usize, usize) -> usize + '_> = match &self.data {
+            RasterArray::Uint8(arr) => Box::new(|row, col| arr[[0, row, col]] as usize),
+            RasterArray::Uint16(arr) => Box::new(|row, col| arr[[0, row, col]] as usize),
+            data => {
+                return Err(AiocogeoError::General(format!(
+                    "colormaps apply to uint8 or uint16 images, not {:?}",
+                    data.dtype()
+                )))
+            }
+        };
+        let color = |row: usize, col: usize| {
+            colormap
+                .get(index(row, col))
+                .copied()
+                .filter(|color| color[3] != 0)
+        };
+
+        let mut data = Array3::zeros((3, height, width));
+        let mut mask = self.mask.clone();
+        for ((row, col), valid) in mask.indexed_iter_mut() {
+            match color(row, col) {
+                Some(color) => {
+                    for band in 0..3 {
+                        data[[band, row, col]] = color[band];
+                    }
+                }
+                None => *valid = false,
+            }
+        }
+        Ok(ImageData {
+            data: RasterArray::Uint8(data),
+            mask,
+            transform: self.transform,
+        })
+    }
+}
+
+/// Return the x/y indices of the tiles of the given size that intersect a non-empty window, in
+/// row-major order
+pub(crate) fn intersecting_tiles(
+    window: &Window,
+    tile_width: usize,
+    tile_height: usize,
+) -> Vec<(usize, usize)> {
+    let xs = window.col_off / tile_width..=(window.col_off + window.width - 1) / tile_width;
+    let ys = window.row_off / tile_height..=(window.row_off + window.height - 1) / tile_height;
+    ys.flat_map(|y| xs.clone().map(move |x| (x, y))).collect()
+}
+
+/// Sort byte ranges and merge those that overlap or are separated by at most `max_gap` bytes,
+/// dropping empty ranges. Ranges that don't overlap are only merged up to `max_len` bytes.
+pub(crate) fn coalesce_ranges(
+    ranges: &[Range<usize>],
+    max_gap: usize,
+    max_len: Option<usize>,
+) -> Vec<Range<usize>> {
+    let mut sorted: Vec<_> = ranges.iter().filter(|r| !r.is_empty()).cloned().collect();
+    sorted.sort_unstable_by_key(|r| r.start);
+
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(sorted.len());
+    for range in sorted {
+        match merged.last_mut() {
+            Some(last)
+                if range.start < last.end
+                    || range.start <= last.end.saturating_add(max_gap)
+                        && max_len.is_none_or(|max| range.end - last.start <= max) =>
+            {
+                last.end = last.end.max(range.end)
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Fetch a byte range of a file, failing if the store returns fewer bytes, e.g. because the file
+/// was truncated
+pub(crate) async fn get_range_exact(
+    store: &dyn ObjectStore,
+    path: &Path,
+    range: Range<usize>,
+) -> Result<Bytes> {
+    if range.is_empty() {
+        return Ok(Bytes::new());
+    }
+    let bytes = store.get_range(path, range.clone()).await?;
+    if bytes.len() != range.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "read {} of the {} bytes at {range:?}",
+                bytes.len(),
+                range.len()
+            ),
+        )
+        .into());
+    }
+    Ok(bytes)
+}
+
+/// The number of times a rejected request is split in halves, so that a store rejecting every
+/// request fails after at most 31 requests instead of one per part
+const MAX_RANGE_SPLITS: usize = 4;
+
+/// Fetch a coalesced byte range holding `parts`, splitting it into two requests holding half of
+/// the parts each, and so on up to [`MAX_RANGE_SPLITS`] times, if the store rejects it as some
+/// stores and CDNs do for ranges over a size limit. Other errors are returned unchanged.
+///
+/// The bytes between parts are zeroed if the request was split.
+pub(crate) fn get_coalesced_range<'a>(
+    store: &'a dyn ObjectStore,
+    path: &'a Path,
+    range: Range<usize>,
+    parts: &'a [Range<usize>],
+) -> BoxFuture<'a, Result<Bytes>> {
+    get_split_range(store, path, range, parts, MAX_RANGE_SPLITS)
+}
+
+/// Fetch a coalesced byte range as in [`get_coalesced_range`], splitting it at most `splits`
+/// more times
+fn get_split_range<'a>(
+    store: &'a dyn ObjectStore,
+    path: &'a Path,
+    range: Range<usize>,
+    parts: &'a [Range<usize>],
+    splits: usize,
+) -> BoxFuture<'a, Result<Bytes>> {
+    async move {
+        let err = match get_range_exact(store, path, range.clone()).await {
+            Ok(bytes) => return Ok(bytes),
+            Err(err) => err,
+        };
+        let parts: Vec<_> = parts
+            .iter()
+            .filter(|part| !part.is_empty())
+            .cloned()
+            .collect();
+        if splits == 0 || parts.len() < 2 || !may_be_rejected_range(&err) {
+            return Err(err);
+        }
+
+        let (first, second) = parts.split_at(parts.len() / 2);
+        let span = |half: &[Range<usize>]| {
+            let start = half.iter().map(|part| part.start).min().unwrap();
+            start..half.iter().map(|part| part.end).max().unwrap()
+        };
+        let (first_span, second_span) = (span(first), span(second));
+        let (first_bytes, second_bytes) = future::try_join(
+            get_split_range(store, path, first_span.clone(), first, splits - 1),
+            get_split_range(store, path, second_span.clone(), second, splits - 1),
+        )
+        .await?;
+        let mut out = BytesMut::zeroed(range.len());
+        for (span, bytes) in [(first_span, first_bytes), (second_span, second_bytes)] {
+            out[span.start - range.start..span.end - range.start].copy_from_slice(&bytes);
+        }
+        Ok(out.freeze())
+    }
+    .boxed()
+}
+
+/// Whether a request failed with a status that stores and CDNs return for ranges over their
+/// size limit (413 Payload Too Large, 416 Range Not Satisfiable, or 400 Bad Request with a
+/// message about the range), so that smaller requests may succeed. Other 400 errors, e.g. of
+/// request signing, aren't retried.
+fn may_be_rejected_range(err: &AiocogeoError) -> bool {
+    let AiocogeoError::ObjectStore(object_store::Error::Generic { source, .. }) = err else {
+        return false;
+    };
+    match http_status(source.as_ref()) {
+        Some((413 | 416, _)) => true,
+        Some((400, message)) => message.to_ascii_lowercase().contains("range"),
+        _ => false,
+    }
+}
+
+/// Return the HTTP status of a failed request and the message holding it from the message of an
+/// error or of its sources, e.g. `Client error with status 416 Range Not Satisfiable`, as
+/// object_store doesn't expose the error that holds it
+fn http_status(err: &(dyn std::error::Error + 'static)) -> Option<(u16, String)> {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        let message = err.to_string();
+        let status = message
+            .split_once("status ")
+            .and_then(|(_, rest)| rest.get(..3))
+            .and_then(|status| status.parse().ok());
+        if let Some(status) = status {
+            return Some((status, message));
+        }
+        source = err.source();
+    }
+    None
+}
+
+/// The number of tiles fetched at once by passes over every tile of an IFD, to bound memory use
+/// on large files
+pub(crate) const TILE_BATCH_SIZE: usize = 256;
+
+/// Fetch byte ranges of a file, merging ranges separated by at most `max_gap` bytes into single
+/// sequential requests of up to `max_len` bytes, with up to `concurrency` requests in flight.
+///
+/// Returns the bytes of each range in the order of `ranges`; the bytes of any gaps are discarded.
+/// Merged requests that the store rejects are split as in [`get_coalesced_range`].
+pub(crate) async fn get_ranges_coalesced(
+    store: &dyn ObjectStore,
+    path: &Path,
+    ranges: &[Range<usize>],
+    max_gap: usize,
+    max_len: Option<usize>,
+    concurrency: usize,
+) -> Result<Vec<Bytes>> {
+    let merged = coalesce_ranges(ranges, max_gap, max_len);
+    let mut parts = vec![vec![]; merged.len()];
+    for range in ranges.iter().filter(|range| !range.is_empty()) {
+        parts[merged.partition_point(|m| m.start <= range.start) - 1].push(range.clone());
+    }
+    let fetched: Vec<Bytes> = stream::iter(0..merged.len())
+        .map(|i| get_coalesced_range(store, path, merged[i].clone(), &parts[i]))
+        .buffered(concurrency.max(1))
+        .try_collect()
+        .await?;
+
+    Ok(ranges
+        .iter()
+        .map(|range| {
+            if range.is_empty() {
+                return Bytes::new();
+            }
+            // The merged ranges are sorted and disjoint, so the last one starting at or before
+            // this range contains it
+            let idx = merged.partition_point(|m| m.start <= range.start) - 1;
+            let start = merged[idx].start;
+            fetched[idx].slice(range.start - start..range.end - start)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn window_intersection() {
+        let window = Window::new(100, 50, 200, 100);
+        assert_eq!(
+            window.intersection(&Window::new(256, 0, 256, 256)),
+            Some(Window::new(256, 50, 44, 100))
+        );
+        assert_eq!(window.intersection(&Window::new(300, 0, 10, 10)), None);
+    }
+
+    #[test]
+    fn apply_colormap() {
+        let image = ImageData {
+            data: RasterArray::Uint8(Array3::from_shape_vec((1, 1, 4), vec![0, 1, 2, 9]).unwrap()),
+            mask: Array2::from_elem((1, 4), true),
+            transform: AffineTransform::new(1.0, 0.0, 0.0, 0.0, -1.0, 0.0),
+        };
+        let colormap = [[10, 20, 30, 255], [0, 0, 0, 0], [40, 50, 60, 255]];
+        let rgb = image.apply_colormap(&colormap).unwrap();
+        assert_eq!(rgb.data.shape(), (3, 1, 4));
+        assert_eq!(rgb.mask.as_slice().unwrap(), [true, false, true, false]);
+        let RasterArray::Uint8(data) = &rgb.data else {
+            panic!()
+        };
+        assert_eq!(data.slice(ndarray::s![.., 0, 0]).to_vec(), [10, 20, 30]);
+        assert_eq!(data.slice(ndarray::s![.., 0, 2]).to_vec(), [40, 50, 60]);
+        assert!(rgb.apply_colormap(&colormap).is_err());
+    }
+
+    #[test]
+    fn tiles_intersecting_window() {
+        let tiles = intersecting_tiles(&Window::new(250, 0, 300, 10), 256, 256);
+        assert_eq!(tiles, vec![(0, 0), (1, 0), (2, 0)]);
+    }
+
+    #[test]
+    fn coalesce_adjacent_ranges() {
+        let ranges = [300..400, 0..100, 100..200, 250..250, 350..500];
+        assert_eq!(coalesce_ranges(&ranges, 0, None), vec![0..200, 300..500]);
+        assert_eq!(coalesce_ranges(&ranges, 99, None), vec![0..200, 300..500]);
+        assert_eq!(coalesce_ranges(&ranges, 100, None), vec![0..500]);
+        // A gap of `usize::MAX` always merges
+        assert_eq!(coalesce_ranges(&ranges, usize::MAX, None), vec![0..500]);
+
+        // Ranges aren't merged past the maximum length, though longer and overlapping ranges
+        // are kept whole
+        assert_eq!(
+            coalesce_ranges(&ranges, 100, Some(200)),
+            vec![0..200, 300..500]
+        );
+        assert_eq!(
+            coalesce_ranges(&ranges, 0, Some(50)),
+            vec![0..100, 100..200, 300..500]
+        );
+    }
+
+    #[tokio::test]
+    async fn coalesced_reads_return_each_range() {
+        use object_store::memory::InMemory;
+        use object_store::PutPayload;
+
+        let store = InMemory::new();
+        let path = Path::from("test.tif");
+        let data: Vec<u8> = (0..=255).collect();
+        store
+            .put(&path, PutPayload::from(data.clone()))
+            .await
+            .unwrap();
+
+        let ranges = [100..110, 0..10, 10..20, 5..5];
+        for max_gap in [0, 1024] {
+            let fetched = get_ranges_coalesced(&store, &path, &ranges, max_gap, None, 2)
+                .await
+                .unwrap();
+            assert_eq!(fetched[0].as_ref(), &data[100..110]);
+            assert_eq!(fetched[1].as_ref(), &data[0..10]);
+            assert_eq!(fetched[2].as_ref(), &data[10..20]);
+            assert!(fetched[3].is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn bounded_splits() {
+        use crate::testing::MockStore;
+
+        // 64 parts of one byte would take six levels of splits to fetch from a store rejecting
+        // longer requests, so the read fails after four
+        let store = MockStore::new().with_max_range_bytes(1);
+        let path = Path::from("test.tif");
+        store.put(&path, vec![0; 64].into()).await.unwrap();
+        let parts: Vec<_> = (0..64).map(|i| i..i + 1).collect();
+        let err = get_coalesced_range(&store, &path, 0..64, &parts)
+            .await
+            .unwrap_err();
+        assert!(may_be_rejected_range(&err));
+        let requests = store.requests();
+        assert!(requests.iter().all(|range| range.len() >= 4));
+        assert!(requests.len() <= 1 + 2 + 4 + 8 + 16);
+    }
+
+    #[test]
+    fn rejected_ranges() {
+        let generic = |message: &str| {
+            AiocogeoError::ObjectStore(object_store::Error::Generic {
+                store: "S3",
+                source: message.to_string().into(),
+            })
+        };
+        assert!(may_be_rejected_range(&generic(
+            "Client error with status 416 Range Not Satisfiable: No Body"
+        )));
+        assert!(may_be_rejected_range(&generic(
+            "Client error with status 413 Payload Too Large: No Body"
+        )));
+
+        // Other failures aren't retried as smaller requests
+        assert!(!may_be_rejected_range(&generic(
+            "Server error, body contains Error, with status 503 Service Unavailable: No Body"
+        )));
+        assert!(!may_be_rejected_range(&generic("connection reset by peer")));
+
+        // 400 is only taken as a rejected range if the store says so
+        assert!(may_be_rejected_range(&generic(
+            "Client error with status 400 Bad Request: <Code>InvalidRange</Code>"
+        )));
+        assert!(!may_be_rejected_range(&generic(
+            "Client error with status 400 Bad Request: <Code>AuthorizationHeaderMalformed</Code>"
+        )));
+        assert!(!may_be_rejected_range(&AiocogeoError::ObjectStore(
+            object_store::Error::NotFound {
+                path: "test.tif".to_string(),
+                source: "status 400".into(),
+            }
+        )));
+    }
+
+    #[test]
+    fn coalesced_reads_without_tokio() {
+        use object_store::memory::InMemory;
+
+        // Reads must not depend on a tokio runtime being present
+        futures::executor::block_on(async {
+            let store = InMemory::new();
+            let path = Path::from("test.tif");
+            store.put(&path, vec![1u8, 2, 3, 4].into()).await.unwrap();
+            let fetched = get_ranges_coalesced(&store, &path, &[0..2, 2..4], 0, None, 4)
+                .await
+                .unwrap();
+            assert_eq!(fetched[1].as_ref(), &[3, 4]);
+
+            // Ranges past the end of a truncated file fail rather than returning fewer bytes
+            assert!(
+                get_ranges_coalesced(&store, &path, &[0..2, 2..6], 0, None, 4)
+                    .await
+                    .is_err()
+            );
+        });
+    }
+
+    #[test]
+    fn window_bounds() {
+        let gt = AffineTransform::new(10.0, 0.0, 1000.0, 0.0, -10.0, 5000.0);
+        let bounds = Window::new(1, 2, 3, 4).bounds(&gt);
+        assert_eq!(bounds, (1010.0, 4940.0, 1040.0, 4980.0));
+    }
+}
@@ -0,0 +1,133 @@
+//! Encode pixels as PNG images, e.g. to serve them as map tiles.
+use std::io::Write;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::array::RasterArray;
+use crate::error::{AiocogeoError, Result};
+use crate::partial_reads::ImageData;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+/// Color types of `IHDR`
+const GRAY_ALPHA: u8 = 4;
+const RGB_ALPHA: u8 = 6;
+
+/// Append a chunk with its length and CRC
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&crc.finalize().to_be_bytes());
+}
+
+impl ImageData {
+    /// Encode the pixels as a PNG, with the mask as its alpha channel.
+    ///
+    /// Images of 1 band are encoded as grayscale and images of 3 bands as RGB. Only `uint8` and
+    /// `uint16` data can be encoded; rescale other data types first.
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let (bands, height, width) = self.data.shape();
+        let color_type = match bands {
+            1 => GRAY_ALPHA,
+            3 => RGB_ALPHA,
+            _ => {
+                return Err(AiocogeoError::General(format!(
+                    "PNG images need 1 or 3 bands, not {bands}"
+                )))
+            }
+        };
+        let bit_depth = match &self.data {
+            RasterArray::Uint8(_) => 8,
+            RasterArray::Uint16(_) => 16,
+            data => {
+                return Err(AiocogeoError::General(format!(
+                    "PNG images need uint8 or uint16 data, not {:?}",
+                    data.dtype()
+                )))
+            }
+        };
+
+        // Each row starts with its filter type, 0 for none, followed by interleaved samples
+        let sample_bytes = bit_depth / 8;
+        let mut raw = Vec::with_capacity(height * (1 + width * (bands + 1) * sample_bytes));
+        for row in 0..height {
+            raw.push(0);
+            for col in 0..width {
+                let valid = self.mask[[row, col]];
+                match &self.data {
+                    RasterArray::Uint8(arr) => {
+                        raw.extend((0..bands).map(|band| arr[[band, row, col]]));
+                        raw.push(if valid { u8::MAX } else { 0 });
+                    }
+                    RasterArray::Uint16(arr) => {
+                        for band in 0..bands {
+                            raw.extend_from_slice(&arr[[band, row, col]].to_be_bytes());
+                        }
+                        let alpha = if valid { u16::MAX } else { 0 };
+                        raw.extend_from_slice(&alpha.to_be_bytes());
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(&raw)?;
+        let compressed = encoder.finish()?;
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(width as u32).to_be_bytes());
+        header.extend_from_slice(&(height as u32).to_be_bytes());
+        // Bit depth, color type, then default compression, filter and interlace methods
+        header.extend_from_slice(&[bit_depth as u8, color_type, 0, 0, 0]);
+
+        let mut out = SIGNATURE.to_vec();
+        write_chunk(&mut out, b"IHDR", &header);
+        write_chunk(&mut out, b"IDAT", &compressed);
+        write_chunk(&mut out, b"IEND", &[]);
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::affine::AffineTransform;
+    use flate2::read::ZlibDecoder;
+    use ndarray::{Array2, Array3};
+    use std::io::Read;
+
+    #[test]
+    fn to_png() {
+        let image = ImageData {
+            data: RasterArray::Uint8(Array3::from_shape_vec((1, 1, 2), vec![7, 9]).unwrap()),
+            mask: Array2::from_shape_vec((1, 2), vec![true, false]).unwrap(),
+            transform: AffineTransform::new(1.0, 0.0, 0.0, 0.0, -1.0, 0.0),
+        };
+        let png = image.to_png().unwrap();
+        assert_eq!(png[..8], SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(
+            png[16..29],
+            [0, 0, 0, 2, 0, 0, 0, 1, 8, GRAY_ALPHA, 0, 0, 0]
+        );
+
+        let idat_len = u32::from_be_bytes(png[33..37].try_into().unwrap()) as usize;
+        assert_eq!(&png[37..41], b"IDAT");
+        let mut raw = vec![];
+        ZlibDecoder::new(&png[41..41 + idat_len])
+            .read_to_end(&mut raw)
+            .unwrap();
+        assert_eq!(raw, vec![0, 7, 255, 9, 0]);
+        assert!(png.ends_with(&[b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]));
+
+        let image = ImageData {
+            data: RasterArray::Float32(Array3::zeros((1, 1, 2))),
+            ..image
+        };
+        assert!(image.to_png().is_err());
+    }
+}
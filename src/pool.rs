@@ -0,0 +1,166 @@
+//! A pool of byte buffers reused as the scratch space of tile decoding, to cut allocations when
+//! serving many tiles of the same size.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Counters of a [`BufferPool`], returned by [`BufferPool::metrics`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolMetrics {
+    /// The number of buffers reused from the pool
+    pub hits: u64,
+    /// The number of buffers allocated because the pool had none of the requested size
+    pub misses: u64,
+    /// The number of returned buffers freed because the pool was full
+    pub discarded: u64,
+    /// The number of buffers held by the pool
+    pub pooled_buffers: usize,
+    /// The total capacity of the buffers held by the pool, in bytes
+    pub pooled_bytes: usize,
+}
+
+/// A pool of reusable byte buffers for decompressing and decoding tiles, shared by the readers
+/// opened with it in [`crate::OpenOptions::buffer_pool`]. Clones share the same buffers.
+///
+/// Buffers are kept by the size they were requested for, which depends on the tile size, band
+/// count and data type of an IFD, so serving tiles of the same few IFDs reuses the same
+/// buffers. The pool holds at most `max_bytes` of buffers and frees any returned past that.
+#[derive(Debug, Clone)]
+pub struct BufferPool {
+    state: Arc<Mutex<PoolState>>,
+}
+
+#[derive(Debug)]
+struct PoolState {
+    max_bytes: usize,
+    buffers: HashMap<usize, Vec<Vec<u8>>>,
+    metrics: PoolMetrics,
+}
+
+impl BufferPool {
+    /// Create an empty pool holding at most `max_bytes` bytes of buffers
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(PoolState {
+                max_bytes,
+                buffers: HashMap::new(),
+                metrics: PoolMetrics::default(),
+            })),
+        }
+    }
+
+    /// Return the counters of the pool
+    pub fn metrics(&self) -> PoolMetrics {
+        self.state.lock().unwrap().metrics
+    }
+
+    /// Free every buffer held by the pool
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.buffers.clear();
+        state.metrics.pooled_buffers = 0;
+        state.metrics.pooled_bytes = 0;
+    }
+
+    /// Take an empty buffer with capacity for at least `len` bytes, reusing one returned for the
+    /// same `len` if any
+    pub(crate) fn take(&self, len: usize) -> Vec<u8> {
+        let mut state = self.state.lock().unwrap();
+        match state.buffers.get_mut(&len).and_then(Vec::pop) {
+            Some(buffer) => {
+                state.metrics.hits += 1;
+                state.metrics.pooled_buffers -= 1;
+                state.metrics.pooled_bytes -= buffer.capacity();
+                buffer
+            }
+            None => {
+                state.metrics.misses += 1;
+                Vec::with_capacity(len)
+            }
+        }
+    }
+
+    /// Return a buffer taken for `len` bytes, to be reused by later requests for the same size
+    pub(crate) fn give(&self, mut buffer: Vec<u8>, len: usize) {
+        if buffer.capacity() < len {
+            return;
+        }
+        buffer.clear();
+        let mut state = self.state.lock().unwrap();
+        if state.metrics.pooled_bytes + buffer.capacity() > state.max_bytes {
+            state.metrics.discarded += 1;
+            return;
+        }
+        state.metrics.pooled_buffers += 1;
+        state.metrics.pooled_bytes += buffer.capacity();
+        state.buffers.entry(len).or_default().push(buffer);
+    }
+}
+
+/// Take a buffer for `len` bytes from the pool, if any, or allocate one
+pub(crate) fn take_buffer(pool: Option<&BufferPool>, len: usize) -> Vec<u8> {
+    pool.map_or_else(|| Vec::with_capacity(len), |pool| pool.take(len))
+}
+
+/// Return a buffer taken with [`take_buffer`] to the pool, if any
+pub(crate) fn give_buffer(pool: Option<&BufferPool>, buffer: Vec<u8>, len: usize) {
+    if let Some(pool) = pool {
+        pool.give(buffer, len);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::options::{OpenOptions, ReadOptions};
+    use crate::partial_reads::Window;
+    use crate::testing::CogBuilder;
+    use tiff::tags::CompressionMethod;
+
+    #[test]
+    fn reuse_buffers() {
+        let pool = BufferPool::new(100);
+        let buffer = pool.take(40);
+        assert!(buffer.capacity() >= 40);
+        pool.give(buffer, 40);
+        assert_eq!(pool.metrics().pooled_buffers, 1);
+        let buffer = pool.take(40);
+        assert!(buffer.is_empty());
+        // A buffer of another size is allocated
+        let other = pool.take(80);
+        pool.give(buffer, 40);
+        pool.give(other, 80);
+        let metrics = pool.metrics();
+        assert_eq!((metrics.hits, metrics.misses, metrics.discarded), (1, 2, 1));
+        assert_eq!(metrics.pooled_bytes, 40);
+    }
+
+    #[tokio::test]
+    async fn decode_with_pool() {
+        let pool = BufferPool::new(1 << 20);
+        let builder = CogBuilder {
+            compression: CompressionMethod::Deflate,
+            ..Default::default()
+        };
+        let options = OpenOptions {
+            buffer_pool: Some(pool.clone()),
+            ..Default::default()
+        };
+        let (reader, _) = builder.open_with_options(&options).await.unwrap();
+        let window = Window::new(0, 0, 64, 48);
+        let options = ReadOptions::default();
+
+        let data = reader.read_window(window, 0, &options).await.unwrap();
+        assert_eq!(data, builder.expected(0).unwrap());
+        let misses = pool.metrics().misses;
+        assert!(misses > 0);
+        // Later reads of tiles of the same size reuse the buffers of the first
+        for _ in 0..2 {
+            let data = reader.read_window(window, 0, &options).await.unwrap();
+            assert_eq!(data, builder.expected(0).unwrap());
+        }
+        let metrics = pool.metrics();
+        assert_eq!(metrics.misses, misses);
+        assert!(metrics.hits >= 2 * misses);
+        assert!(metrics.pooled_buffers > 0);
+    }
+}
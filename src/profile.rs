@@ -0,0 +1,102 @@
+use geo_types::LineString;
+
+/// A sample of the pixel values along a line
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileSample {
+    /// The distance of the sample from the start of the line, in the units of the image's CRS
+    pub distance: f64,
+    /// The location of the sample in the image's CRS
+    pub x: f64,
+    pub y: f64,
+    /// The bilinearly interpolated value of each band, or `NaN` outside the image
+    pub values: Vec<f64>,
+}
+
+/// Return `(distance, x, y)` points every `interval` along a line, starting at its first point
+/// and ending at its last
+pub(crate) fn sample_points(line: &LineString<f64>, interval: f64) -> Vec<(f64, f64, f64)> {
+    let mut points = vec![];
+    let Some(first) = line.0.first() else {
+        return points;
+    };
+    points.push((0.0, first.x, first.y));
+
+    let mut start = 0.0;
+    let mut next = interval;
+    for segment in line.lines() {
+        let (dx, dy) = (segment.dx(), segment.dy());
+        let length = dx.hypot(dy);
+        while next <= start + length {
+            let t = (next - start) / length;
+            points.push((next, segment.start.x + t * dx, segment.start.y + t * dy));
+            next += interval;
+        }
+        start += length;
+    }
+
+    let last = line.0.last().unwrap();
+    // Avoid a duplicate when the length is a multiple of the interval
+    if points
+        .last()
+        .is_some_and(|&(distance, ..)| start - distance > interval * 1e-9)
+    {
+        points.push((start, last.x, last.y));
+    }
+    points
+}
+
+/// The pixels and weights to bilinearly interpolate a value at a `(col, row)` location of a
+/// `width` by `height` image, treating values as located at pixel centers and extending edge
+/// pixels outwards.
+pub(crate) fn bilinear_weights(
+    col: f64,
+    row: f64,
+    width: usize,
+    height: usize,
+) -> [((usize, usize), f64); 4] {
+    let axis = |pixel: f64, size: usize| {
+        let center = pixel - 0.5;
+        let lower = center.floor().clamp(0.0, (size - 1) as f64);
+        let upper = (lower as usize + 1).min(size - 1);
+        let fraction = (center - lower).clamp(0.0, 1.0);
+        (lower as usize, upper, fraction)
+    };
+    let (c0, c1, fc) = axis(col, width);
+    let (r0, r1, fr) = axis(row, height);
+    [
+        ((r0, c0), (1.0 - fr) * (1.0 - fc)),
+        ((r0, c1), (1.0 - fr) * fc),
+        ((r1, c0), fr * (1.0 - fc)),
+        ((r1, c1), fr * fc),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn points_along_line() {
+        let line = LineString::from(vec![(0.0, 0.0), (3.0, 0.0), (3.0, 2.5)]);
+        let points = sample_points(&line, 2.0);
+        assert_eq!(
+            points,
+            [
+                (0.0, 0.0, 0.0),
+                (2.0, 2.0, 0.0),
+                (4.0, 3.0, 1.0),
+                (5.5, 3.0, 2.5)
+            ]
+        );
+    }
+
+    #[test]
+    fn bilinear() {
+        let weights = bilinear_weights(1.0, 0.5, 3, 3);
+        assert_eq!(weights[0], ((0, 0), 0.5));
+        assert_eq!(weights[1], ((0, 1), 0.5));
+        assert_eq!(weights[2].1 + weights[3].1, 0.0);
+        // Edges are extended
+        assert_eq!(bilinear_weights(0.1, 0.1, 3, 3)[0], ((0, 0), 1.0));
+    }
+}
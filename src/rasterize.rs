@@ -0,0 +1,76 @@
+use geo_types::MultiPolygon;
+use ndarray::Array2;
+
+use crate::affine::AffineTransform;
+
+/// Burn polygons into a `(height, width)` mask that is `true` for pixels whose center is inside
+/// them.
+///
+/// `transform` maps pixels of the mask to the coordinates of the polygons. Rings are filled with
+/// the even-odd rule, so holes are left out.
+pub(crate) fn rasterize(
+    polygons: &MultiPolygon<f64>,
+    transform: &AffineTransform,
+    shape: (usize, usize),
+) -> Array2<bool> {
+    let (height, width) = shape;
+    let mut mask = Array2::from_elem(shape, false);
+    let Some(inverse) = transform.inverse() else {
+        return mask;
+    };
+
+    // Every edge of every ring, in pixel coordinates
+    let edges = polygons
+        .iter()
+        .flat_map(|polygon| std::iter::once(polygon.exterior()).chain(polygon.interiors()))
+        .flat_map(|ring| ring.lines())
+        .map(|line| {
+            (
+                inverse.apply(line.start.x, line.start.y),
+                inverse.apply(line.end.x, line.end.y),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut crossings = vec![];
+    for row in 0..height {
+        let y = row as f64 + 0.5;
+        crossings.clear();
+        for &((x0, y0), (x1, y1)) in &edges {
+            // Half-open so that vertices on the scanline are only counted once
+            if (y0 <= y) != (y1 <= y) {
+                crossings.push(x0 + (y - y0) / (y1 - y0) * (x1 - x0));
+            }
+        }
+        crossings.sort_unstable_by(f64::total_cmp);
+        for span in crossings.chunks_exact(2) {
+            // Pixels whose center `col + 0.5` is within the span
+            let start = (span[0] - 0.5).ceil().max(0.0) as usize;
+            let end = ((span[1] - 0.5).ceil().max(0.0) as usize).min(width);
+            for col in start..end {
+                mask[[row, col]] = true;
+            }
+        }
+    }
+    mask
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use geo_types::{polygon, Polygon};
+
+    #[test]
+    fn rasterize_polygon_with_hole() {
+        let polygon: Polygon<f64> = polygon!(
+            exterior: [(x: 0.0, y: 0.0), (x: 4.0, y: 0.0), (x: 4.0, y: -4.0), (x: 0.0, y: -4.0)],
+            interiors: [[(x: 1.0, y: -1.0), (x: 2.0, y: -1.0), (x: 2.0, y: -2.0), (x: 1.0, y: -2.0)]],
+        );
+        let transform = AffineTransform::new(1.0, 0.0, 0.0, 0.0, -1.0, 0.0);
+        let mask = rasterize(&polygon.into(), &transform, (5, 5));
+        assert_eq!(mask.iter().filter(|&&v| v).count(), 15);
+        assert!(!mask[[1, 1]]);
+        assert!(mask[[0, 0]] && mask[[3, 3]]);
+        assert!(!mask[[4, 4]] && !mask[[0, 4]]);
+    }
+}
@@ -0,0 +1,134 @@
+//! Observe the byte ranges requested from the store and the tiles decoded, to debug and cost out
+//! reads or feed metrics systems.
+use std::fmt::Debug;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tiff::tags::CompressionMethod;
+
+use crate::validate::TileFailure;
+
+/// Callbacks invoked around each request to the store and each tile decode, e.g. to export
+/// metrics to Prometheus or StatsD. Every method does nothing by default.
+///
+/// Hooks set on [`OpenOptions::hooks`] apply to every request of the reader. They are called
+/// from the task making the request, so they should return quickly.
+///
+/// [`OpenOptions::hooks`]: crate::OpenOptions::hooks
+pub trait RequestHooks: Debug + Send + Sync {
+    /// Called before a byte range is requested, with `None` for requests of the whole file
+    fn on_request_start(&self, _purpose: RequestPurpose, _range: Option<Range<u64>>) {}
+
+    /// Called when a request completes, with whether it succeeded
+    fn on_request_complete(&self, _request: &RecordedRequest, _success: bool) {}
+
+    /// Called after a tile is decompressed and decoded
+    fn on_decode(&self, _decode: &DecodeEvent) {}
+
+    /// Called when a window read fills a tile that failed instead of failing, under
+    /// [`TileErrorPolicy::Fill`]
+    ///
+    /// [`TileErrorPolicy::Fill`]: crate::TileErrorPolicy::Fill
+    fn on_tile_error(&self, _failure: &TileFailure) {}
+}
+
+/// A decoded tile, as passed to [`RequestHooks::on_decode`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeEvent {
+    /// The compression of the tile
+    pub compression: CompressionMethod,
+    /// The size of the compressed tile in bytes, summed over bands for band-interleaved images
+    pub compressed_bytes: u64,
+    /// The size of the decoded tile in bytes
+    pub decoded_bytes: u64,
+    /// How long decoding took. `None` on `wasm32`, which has no monotonic clock.
+    pub duration: Option<Duration>,
+}
+
+/// Why a byte range was requested
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RequestPurpose {
+    /// The TIFF header, IFDs and tag values read at open
+    Header,
+    /// The whole file, downloaded at open under [`OpenOptions::whole_file_threshold`]
+    ///
+    /// [`OpenOptions::whole_file_threshold`]: crate::OpenOptions::whole_file_threshold
+    WholeFile,
+    /// Tiles read to answer a request
+    Tile,
+    /// Tiles fetched ahead of time into the cache
+    Prefetch,
+}
+
+/// A single request made to the store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedRequest {
+    /// The byte offset of the requested range within the file
+    pub offset: u64,
+    /// The length of the requested range in bytes
+    pub length: u64,
+    /// How long the request took, including failed requests. `None` on `wasm32`, which has no
+    /// monotonic clock.
+    pub duration: Option<Duration>,
+    /// Why the range was requested
+    pub purpose: RequestPurpose,
+}
+
+/// Collects every byte range requested from the store while it is set on
+/// [`OpenOptions::recorder`] or [`ReadOptions::recorder`], e.g. to find out why a tile read made
+/// 40 requests.
+///
+/// Reads served by the disk cache of [`OpenOptions::cache_dir`] make no request and aren't
+/// recorded. Clones share the same log, so a recorder can be passed to an operation and
+/// inspected after it.
+///
+/// [`OpenOptions::cache_dir`]: crate::OpenOptions::cache_dir
+/// [`OpenOptions::recorder`]: crate::OpenOptions::recorder
+/// [`ReadOptions::recorder`]: crate::ReadOptions::recorder
+#[derive(Debug, Clone, Default)]
+pub struct RequestRecorder(Arc<Mutex<Vec<RecordedRequest>>>);
+
+impl RequestRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the recorded requests, in the order they completed
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Return the recorded requests and clear the log
+    pub fn take(&self) -> Vec<RecordedRequest> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+
+    /// Return the total number of bytes requested
+    pub fn total_bytes(&self) -> u64 {
+        self.0.lock().unwrap().iter().map(|r| r.length).sum()
+    }
+}
+
+impl RequestHooks for RequestRecorder {
+    fn on_request_complete(&self, request: &RecordedRequest, _success: bool) {
+        self.0.lock().unwrap().push(request.clone());
+    }
+}
+
+/// Return the hooks of a reader and the recorder of an operation, if any
+pub(crate) fn active_hooks(
+    hooks: Option<&Arc<dyn RequestHooks>>,
+    recorder: Option<&RequestRecorder>,
+) -> Vec<Arc<dyn RequestHooks>> {
+    let recorder = recorder.map(|recorder| Arc::new(recorder.clone()) as Arc<dyn RequestHooks>);
+    hooks.cloned().into_iter().chain(recorder).collect()
+}
+
+/// The current time, or `None` on wasm32, where `Instant::now` panics
+pub(crate) fn now() -> Option<Instant> {
+    #[cfg(not(target_arch = "wasm32"))]
+    return Some(Instant::now());
+    #[cfg(target_arch = "wasm32")]
+    None
+}
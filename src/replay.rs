@@ -0,0 +1,516 @@
+//! Capture the bytes read from a store to a file, and serve them back offline, so that a read of
+//! a remote file that misbehaves can be reproduced without access to the file or its
+//! credentials.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::io::{Cursor, Read};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use bytes::{Bytes, BytesMut};
+use chrono::DateTime;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use object_store::path::Path;
+use object_store::{
+    GetOptions, GetRange, GetResult, GetResultPayload, ListResult, MultipartUpload, ObjectMeta,
+    ObjectStore, PutMultipartOpts, PutOptions, PutPayload, PutResult,
+};
+
+use crate::error::{AiocogeoError, Result};
+use crate::store::impl_object_store;
+
+/// The first bytes of a capture file
+const MAGIC: &[u8; 16] = b"AIOCOGEO REPLAY\n";
+
+/// The version of the capture file format
+const FORMAT_VERSION: u32 = 1;
+
+/// The metadata and bytes read of one object
+#[derive(Debug, Clone)]
+struct CapturedObject {
+    meta: ObjectMeta,
+    /// The bytes read, by offset. Ranges may overlap.
+    ranges: BTreeMap<usize, Bytes>,
+}
+
+impl CapturedObject {
+    /// Return the bytes of `range`, or `None` unless they were all captured
+    fn read(&self, range: Range<usize>) -> Option<Bytes> {
+        // Most reads are replayed as they were captured, which needs no copy
+        let (start, bytes) = self.ranges.range(..=range.start).next_back()?;
+        if start + bytes.len() >= range.end {
+            return Some(bytes.slice(range.start - start..range.end - start));
+        }
+        let mut out = BytesMut::with_capacity(range.len());
+        let mut position = range.start;
+        while position < range.end {
+            let (start, bytes) = (self.ranges.range(..=position).rev())
+                .find(|(start, bytes)| *start + bytes.len() > position)?;
+            let end = (start + bytes.len()).min(range.end);
+            out.extend_from_slice(&bytes[position - start..end - start]);
+            position = end;
+        }
+        Some(out.freeze())
+    }
+}
+
+/// An [ObjectStore] wrapper that captures the metadata and bytes of every read made through it,
+/// to be saved and served again by a [ReplayStore].
+///
+/// Wrap the store a problematic file is read from, reproduce the issue, then attach the output
+/// of [`CaptureStore::save`] to the bug report. The capture holds the paths, metadata and bytes
+/// read, but none of the configuration or credentials of the store.
+#[derive(Debug)]
+pub struct CaptureStore {
+    inner: Arc<dyn ObjectStore>,
+    objects: Mutex<HashMap<Path, CapturedObject>>,
+}
+
+impl CaptureStore {
+    /// Wrap `inner`, capturing its reads from now on
+    pub fn new(inner: Arc<dyn ObjectStore>) -> Self {
+        Self {
+            inner,
+            objects: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Return the number of byte ranges captured, over all objects
+    pub fn range_count(&self) -> usize {
+        let objects = self.objects.lock().unwrap();
+        objects.values().map(|object| object.ranges.len()).sum()
+    }
+
+    /// Serialize the capture, to be loaded with [`ReplayStore::from_bytes`]
+    pub fn to_bytes(&self) -> Bytes {
+        let objects = self.objects.lock().unwrap();
+        let mut objects = objects.values().collect::<Vec<_>>();
+        objects.sort_by(|a, b| a.meta.location.cmp(&b.meta.location));
+        let mut out = MAGIC.to_vec();
+        out.write_u32::<LittleEndian>(FORMAT_VERSION).unwrap();
+        out.write_u32::<LittleEndian>(objects.len() as u32).unwrap();
+        for object in objects {
+            let meta = &object.meta;
+            write_str(&mut out, meta.location.as_ref());
+            out.write_u64::<LittleEndian>(meta.size as u64).unwrap();
+            let (seconds, nanos) = (
+                meta.last_modified.timestamp(),
+                meta.last_modified.timestamp_subsec_nanos(),
+            );
+            out.write_i64::<LittleEndian>(seconds).unwrap();
+            out.write_u32::<LittleEndian>(nanos).unwrap();
+            write_optional_str(&mut out, meta.e_tag.as_deref());
+            write_optional_str(&mut out, meta.version.as_deref());
+            let ranges = object.ranges.len() as u32;
+            out.write_u32::<LittleEndian>(ranges).unwrap();
+            for (start, bytes) in &object.ranges {
+                out.write_u64::<LittleEndian>(*start as u64).unwrap();
+                out.write_u64::<LittleEndian>(bytes.len() as u64).unwrap();
+                out.extend_from_slice(bytes);
+            }
+        }
+        out.into()
+    }
+
+    /// Write the capture to a file, to be loaded with [`ReplayStore::load`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        Ok(std::fs::write(path, self.to_bytes())?)
+    }
+
+    fn capture(&self, location: &Path, meta: &ObjectMeta, range: Range<usize>, bytes: &Bytes) {
+        let mut objects = self.objects.lock().unwrap();
+        let object = objects
+            .entry(location.clone())
+            .or_insert_with(|| CapturedObject {
+                meta: ObjectMeta {
+                    location: location.clone(),
+                    ..meta.clone()
+                },
+                ranges: BTreeMap::new(),
+            });
+        if range.is_empty() {
+            return;
+        }
+        let captured = object.ranges.entry(range.start).or_default();
+        if captured.len() < bytes.len() {
+            *captured = bytes.clone();
+        }
+    }
+}
+
+impl Display for CaptureStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CaptureStore({})", self.inner)
+    }
+}
+
+impl_object_store!(CaptureStore, {
+    async fn get_opts(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        let head = options.head;
+        let result = self.inner.get_opts(location, options).await?;
+        if head {
+            self.capture(location, &result.meta, 0..0, &Bytes::new());
+            return Ok(result);
+        }
+        let (meta, range, attributes) = (
+            result.meta.clone(),
+            result.range.clone(),
+            result.attributes.clone(),
+        );
+        let bytes = result.bytes().await?;
+        self.capture(location, &meta, range.clone(), &bytes);
+        Ok(GetResult {
+            payload: bytes_payload(bytes),
+            meta,
+            range,
+            attributes,
+        })
+    }
+});
+
+/// A read-only [ObjectStore] serving the reads captured by a [CaptureStore], without network
+/// access.
+///
+/// Reads of bytes that weren't captured fail with [`object_store::Error::NotFound`], so a
+/// replay only reproduces reads made with the same options as the capture. Conditional reads
+/// are checked against the captured ETag, so files opened from the replay are pinned as usual.
+#[derive(Debug)]
+pub struct ReplayStore {
+    objects: HashMap<Path, CapturedObject>,
+}
+
+impl ReplayStore {
+    /// Load a capture serialized by [`CaptureStore::to_bytes`]
+    pub fn from_bytes(bytes: Bytes) -> Result<Self> {
+        let mut cursor = Cursor::new(bytes.as_ref());
+        let mut magic = [0; MAGIC.len()];
+        cursor.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a capture file"));
+        }
+        let version = cursor.read_u32::<LittleEndian>()?;
+        if version != FORMAT_VERSION {
+            return Err(invalid(&format!("unsupported version {version}")));
+        }
+        let mut objects = HashMap::new();
+        for _ in 0..cursor.read_u32::<LittleEndian>()? {
+            let location = Path::from(read_str(&mut cursor)?);
+            let size = cursor.read_u64::<LittleEndian>()? as usize;
+            let seconds = cursor.read_i64::<LittleEndian>()?;
+            let nanos = cursor.read_u32::<LittleEndian>()?;
+            let last_modified = DateTime::from_timestamp(seconds, nanos)
+                .ok_or_else(|| invalid("last modified time out of range"))?;
+            let e_tag = read_optional_str(&mut cursor)?;
+            let version = read_optional_str(&mut cursor)?;
+            let mut ranges = BTreeMap::new();
+            for _ in 0..cursor.read_u32::<LittleEndian>()? {
+                let start = usize::try_from(cursor.read_u64::<LittleEndian>()?);
+                let len = usize::try_from(cursor.read_u64::<LittleEndian>()?);
+                let (Ok(start), Ok(len)) = (start, len) else {
+                    return Err(invalid("range out of bounds"));
+                };
+                // Reads add the offsets of ranges to their lengths
+                if start.checked_add(len).is_none() {
+                    return Err(invalid("range out of bounds"));
+                }
+                let offset = cursor.position() as usize;
+                if len > bytes.len() - offset {
+                    return Err(invalid("truncated range"));
+                }
+                ranges.insert(start, bytes.slice(offset..offset + len));
+                cursor.set_position((offset + len) as u64);
+            }
+            let meta = ObjectMeta {
+                location: location.clone(),
+                last_modified,
+                size,
+                e_tag,
+                version,
+            };
+            objects.insert(location, CapturedObject { meta, ranges });
+        }
+        Ok(Self { objects })
+    }
+
+    /// Load a capture written by [`CaptureStore::save`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        Self::from_bytes(std::fs::read(path)?.into())
+    }
+
+    fn object(&self, location: &Path) -> object_store::Result<&CapturedObject> {
+        self.objects
+            .get(location)
+            .ok_or_else(|| not_captured(location, "object"))
+    }
+}
+
+impl Display for ReplayStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ReplayStore")
+    }
+}
+
+#[async_trait]
+impl ObjectStore for ReplayStore {
+    async fn get_opts(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        let object = self.object(location)?;
+        let meta = object.meta.clone();
+        let e_tag = meta.e_tag.as_deref().unwrap_or("*");
+        if let Some(expected) = &options.if_match {
+            if expected != "*" && expected != e_tag {
+                return Err(object_store::Error::Precondition {
+                    path: location.to_string(),
+                    source: format!("{e_tag} does not match {expected}").into(),
+                });
+            }
+        }
+        if let (Some(expected), Some(_)) = (&options.if_none_match, &meta.e_tag) {
+            if expected == "*" || expected == e_tag {
+                return Err(object_store::Error::NotModified {
+                    path: location.to_string(),
+                    source: format!("{e_tag} matches {expected}").into(),
+                });
+            }
+        }
+        // As in the real stores, ranges must start within the object
+        let from_start = matches!(
+            options.range,
+            Some(GetRange::Bounded(_) | GetRange::Offset(_))
+        );
+        let range = match options.range {
+            Some(GetRange::Bounded(range)) => range.start..range.end.min(meta.size),
+            Some(GetRange::Offset(offset)) => offset..meta.size,
+            Some(GetRange::Suffix(suffix)) => meta.size.saturating_sub(suffix)..meta.size,
+            None => 0..meta.size,
+        };
+        if range.start > range.end || (from_start && range.start >= meta.size) {
+            return Err(object_store::Error::Generic {
+                store: "ReplayStore",
+                source: format!(
+                    "invalid range {range:?} of {location}, which is {} bytes long",
+                    meta.size
+                )
+                .into(),
+            });
+        }
+        if options.head {
+            return Ok(GetResult {
+                payload: bytes_payload(Bytes::new()),
+                meta,
+                range,
+                attributes: Default::default(),
+            });
+        }
+        let bytes = object
+            .read(range.clone())
+            .ok_or_else(|| not_captured(location, &format!("range {range:?}")))?;
+        Ok(GetResult {
+            payload: bytes_payload(bytes),
+            meta,
+            range,
+            attributes: Default::default(),
+        })
+    }
+
+    async fn put_opts(
+        &self,
+        _location: &Path,
+        _payload: PutPayload,
+        _opts: PutOptions,
+    ) -> object_store::Result<PutResult> {
+        Err(object_store::Error::NotImplemented)
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        _location: &Path,
+        _opts: PutMultipartOpts,
+    ) -> object_store::Result<Box<dyn MultipartUpload>> {
+        Err(object_store::Error::NotImplemented)
+    }
+
+    async fn delete(&self, _location: &Path) -> object_store::Result<()> {
+        Err(object_store::Error::NotImplemented)
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, object_store::Result<ObjectMeta>> {
+        let prefix = prefix.cloned().unwrap_or_default();
+        let metas = (self.objects.values())
+            .filter(|object| object.meta.location.prefix_matches(&prefix))
+            .map(|object| Ok(object.meta.clone()))
+            .collect::<Vec<_>>();
+        futures::stream::iter(metas).boxed()
+    }
+
+    async fn list_with_delimiter(
+        &self,
+        _prefix: Option<&Path>,
+    ) -> object_store::Result<ListResult> {
+        Err(object_store::Error::NotImplemented)
+    }
+
+    async fn copy(&self, _from: &Path, _to: &Path) -> object_store::Result<()> {
+        Err(object_store::Error::NotImplemented)
+    }
+
+    async fn copy_if_not_exists(&self, _from: &Path, _to: &Path) -> object_store::Result<()> {
+        Err(object_store::Error::NotImplemented)
+    }
+}
+
+fn bytes_payload(bytes: Bytes) -> GetResultPayload {
+    GetResultPayload::Stream(futures::stream::once(async move { Ok(bytes) }).boxed())
+}
+
+fn not_captured(location: &Path, what: &str) -> object_store::Error {
+    object_store::Error::NotFound {
+        path: location.to_string(),
+        source: format!("{what} of {location} was not captured").into(),
+    }
+}
+
+fn write_str(out: &mut Vec<u8>, value: &str) {
+    out.write_u32::<LittleEndian>(value.len() as u32).unwrap();
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn write_optional_str(out: &mut Vec<u8>, value: Option<&str>) {
+    out.push(value.is_some() as u8);
+    if let Some(value) = value {
+        write_str(out, value);
+    }
+}
+
+/// The error of a capture that can't be loaded
+fn invalid(reason: &str) -> AiocogeoError {
+    AiocogeoError::General(format!("Invalid capture: {reason}"))
+}
+
+fn read_str(cursor: &mut Cursor<&[u8]>) -> Result<String> {
+    let len = cursor.read_u32::<LittleEndian>()? as usize;
+    // Check the length before allocating, as it may be corrupt
+    if len > cursor.get_ref().len() - cursor.position() as usize {
+        return Err(invalid("truncated string"));
+    }
+    let mut value = vec![0; len];
+    cursor.read_exact(&mut value)?;
+    String::from_utf8(value).map_err(|_| invalid("string is not UTF-8"))
+}
+
+fn read_optional_str(cursor: &mut Cursor<&[u8]>) -> Result<Option<String>> {
+    match cursor.read_u8()? {
+        0 => Ok(None),
+        _ => read_str(cursor).map(Some),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{CogBuilder, MockStore, TEST_PATH};
+    use crate::{COGReader, OpenOptions, ReadOptions, Window};
+
+    #[tokio::test]
+    async fn replay_captured_reads() {
+        let builder = CogBuilder {
+            overviews: vec![2],
+            ..Default::default()
+        };
+        let store = Arc::new(MockStore::new());
+        let path = Path::from(TEST_PATH);
+        store
+            .put(&path, builder.build().unwrap().into())
+            .await
+            .unwrap();
+        let capture = Arc::new(CaptureStore::new(store));
+        let options = OpenOptions::default();
+        let window = Window::new(0, 0, 32, 24);
+        let reader = COGReader::try_open_with_options(capture.clone(), path.clone(), &options)
+            .await
+            .unwrap();
+        let data = reader.read_window(window, 1, &ReadOptions::default()).await;
+        assert_eq!(data.unwrap(), builder.expected(1).unwrap());
+        assert!(capture.range_count() > 0);
+
+        // The same reads are served offline, but not reads of other bytes
+        let replay = Arc::new(ReplayStore::from_bytes(capture.to_bytes()).unwrap());
+        let reader = COGReader::try_open_with_options(replay, path, &options)
+            .await
+            .unwrap();
+        let data = reader.read_window(window, 1, &ReadOptions::default()).await;
+        assert_eq!(data.unwrap(), builder.expected(1).unwrap());
+        let window = Window::new(0, 0, 64, 48);
+        let data = reader.read_window(window, 0, &ReadOptions::default()).await;
+        assert!(data.is_err());
+
+        let invalid = ReplayStore::from_bytes(Bytes::from_static(b"II*\0"));
+        assert!(invalid.is_err());
+    }
+
+    #[tokio::test]
+    async fn corrupt_captures() {
+        let capture = |object: &[u8]| {
+            let mut out = MAGIC.to_vec();
+            out.write_u32::<LittleEndian>(FORMAT_VERSION).unwrap();
+            out.write_u32::<LittleEndian>(1).unwrap();
+            out.extend_from_slice(object);
+            ReplayStore::from_bytes(out.into())
+        };
+        let mut object = vec![];
+        write_str(&mut object, TEST_PATH);
+        object.write_u64::<LittleEndian>(4).unwrap();
+        object.write_i64::<LittleEndian>(0).unwrap();
+        object.write_u32::<LittleEndian>(0).unwrap();
+        write_optional_str(&mut object, None);
+        write_optional_str(&mut object, None);
+        object.write_u32::<LittleEndian>(1).unwrap();
+        let with_range = |start: u64, len: u64| {
+            let mut object = object.clone();
+            object.write_u64::<LittleEndian>(start).unwrap();
+            object.write_u64::<LittleEndian>(len).unwrap();
+            object.extend_from_slice(b"II*\0");
+            object
+        };
+        let store = capture(&with_range(0, 4)).unwrap();
+        let path = Path::from(TEST_PATH);
+        assert_eq!(
+            store.get_range(&path, 0..4).await.unwrap().as_ref(),
+            b"II*\0"
+        );
+
+        // Ranges starting past the end of the object fail instead of panicking
+        for range in [GetRange::Bounded(4..8), GetRange::Offset(5)] {
+            let options = GetOptions {
+                range: Some(range),
+                ..Default::default()
+            };
+            let err = store.get_opts(&path, options).await.unwrap_err();
+            assert!(matches!(err, object_store::Error::Generic { .. }));
+        }
+
+        // Lengths past the end of the capture fail without being allocated
+        let err = capture(&u32::MAX.to_le_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), invalid("truncated string").to_string());
+        for len in [5, u32::MAX as u64] {
+            let err = capture(&with_range(0, len)).unwrap_err();
+            assert_eq!(err.to_string(), invalid("truncated range").to_string());
+        }
+
+        // Ranges that end past the largest offset would overflow when read
+        let err = capture(&with_range(u64::MAX - 1, 4)).unwrap_err();
+        assert_eq!(err.to_string(), invalid("range out of bounds").to_string());
+    }
+}
@@ -0,0 +1,122 @@
+//! Coordinate reference systems for reprojected reads, backed by `proj4rs`, a Rust port of
+//! PROJ.4.
+use std::fmt::{Debug, Formatter};
+
+use proj4rs::Proj;
+
+use crate::error::{AiocogeoError, Result};
+use crate::units::AngularUnit;
+
+/// A coordinate reference system. Geographic coordinates are in degrees, unless set otherwise
+/// with [`Crs::with_angular_unit`].
+#[derive(Clone)]
+pub struct Crs {
+    proj: Proj,
+    definition: String,
+    /// The size of the unit of geographic coordinates in degrees
+    degrees_per_unit: f64,
+}
+
+impl Crs {
+    /// Look up a CRS by its EPSG code
+    pub fn from_epsg(code: u16) -> Result<Self> {
+        let definition = crs_definitions::from_code(code)
+            .ok_or_else(|| AiocogeoError::General(format!("unknown EPSG code {code}")))?
+            .proj4;
+        Self::from_proj_string(definition)
+    }
+
+    /// Parse a PROJ.4 definition like `+proj=utm +zone=30 +datum=WGS84`
+    pub fn from_proj_string(definition: &str) -> Result<Self> {
+        let proj = Proj::from_proj_string(definition)
+            .map_err(|err| AiocogeoError::General(format!("invalid CRS {definition:?}: {err}")))?;
+        Ok(Self {
+            proj,
+            definition: definition.to_string(),
+            degrees_per_unit: 1.0,
+        })
+    }
+
+    /// Take and return the coordinates of a geographic CRS in `unit`, e.g. for images whose
+    /// `GeogAngularUnitsGeoKey` is radians or grads. Units of unknown size are taken as degrees.
+    pub fn with_angular_unit(self, unit: AngularUnit) -> Self {
+        let degrees_per_unit = unit.radians_per_unit().map_or(1.0, f64::to_degrees);
+        Self {
+            degrees_per_unit,
+            ..self
+        }
+    }
+
+    /// The PROJ.4 definition of the CRS
+    pub fn definition(&self) -> &str {
+        &self.definition
+    }
+
+    /// Transform `(x, y)` points from this CRS to `dst`, returning `None` for points that can't
+    /// be transformed
+    pub fn transform_points(&self, dst: &Crs, points: &[(f64, f64)]) -> Vec<Option<(f64, f64)>> {
+        points
+            .iter()
+            .map(|&(x, y)| {
+                // proj4rs works in radians for geographic coordinates
+                let mut point = if self.proj.is_latlong() {
+                    let degrees = self.degrees_per_unit;
+                    ((x * degrees).to_radians(), (y * degrees).to_radians())
+                } else {
+                    (x, y)
+                };
+                proj4rs::transform::transform(&self.proj, &dst.proj, &mut point).ok()?;
+                if dst.proj.is_latlong() {
+                    let degrees = dst.degrees_per_unit;
+                    point = (
+                        point.0.to_degrees() / degrees,
+                        point.1.to_degrees() / degrees,
+                    );
+                }
+                (point.0.is_finite() && point.1.is_finite()).then_some(point)
+            })
+            .collect()
+    }
+}
+
+impl Debug for Crs {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Crs").field(&self.definition).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wgs84_to_web_mercator() {
+        let wgs84 = Crs::from_epsg(4326).unwrap();
+        let mercator = Crs::from_epsg(3857).unwrap();
+        let points = wgs84.transform_points(&mercator, &[(180.0, 0.0), (0.0, 45.0)]);
+        let (x, _) = points[0].unwrap();
+        assert!((x - 20037508.342789244).abs() < 1e-3);
+        let (_, y) = points[1].unwrap();
+        assert!((y - 5621521.486192066).abs() < 1e-3);
+
+        let back = mercator.transform_points(&wgs84, &[points[1].unwrap()]);
+        let (lon, lat) = back[0].unwrap();
+        assert!(lon.abs() < 1e-9 && (lat - 45.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn angular_units() {
+        let degrees = Crs::from_epsg(4326).unwrap();
+        let grads = degrees.clone().with_angular_unit(AngularUnit::Grad);
+        let mercator = Crs::from_epsg(3857).unwrap();
+        // 50 grads are 45 degrees
+        let expected = degrees.transform_points(&mercator, &[(45.0, 45.0)]);
+        let points = grads.transform_points(&mercator, &[(50.0, 50.0)]);
+        let ((x0, y0), (x1, y1)) = (expected[0].unwrap(), points[0].unwrap());
+        assert!((x0 - x1).abs() < 1e-6 && (y0 - y1).abs() < 1e-6);
+
+        let back = mercator.transform_points(&grads, &[(x1, y1)]);
+        let (lon, lat) = back[0].unwrap();
+        assert!((lon - 50.0).abs() < 1e-9 && (lat - 50.0).abs() < 1e-9);
+    }
+}
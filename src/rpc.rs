@@ -0,0 +1,178 @@
+/// The number of values stored in GDAL's `RPCCoefficientTag`
+const RPC_VALUE_COUNT: usize = 92;
+
+/// Rational polynomial coefficients (RPC00B) describing the sensor model of a raw satellite image.
+///
+/// These are read from GDAL's `RPCCoefficientTag` (50844).
+/// http://geotiff.maptools.org/rpc_prop.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcCoefficients {
+    pub err_bias: f64,
+    pub err_rand: f64,
+    pub line_off: f64,
+    pub samp_off: f64,
+    pub lat_off: f64,
+    pub long_off: f64,
+    pub height_off: f64,
+    pub line_scale: f64,
+    pub samp_scale: f64,
+    pub lat_scale: f64,
+    pub long_scale: f64,
+    pub height_scale: f64,
+    pub line_num_coeff: [f64; 20],
+    pub line_den_coeff: [f64; 20],
+    pub samp_num_coeff: [f64; 20],
+    pub samp_den_coeff: [f64; 20],
+}
+
+impl RpcCoefficients {
+    /// Parse the 92 values of the `RPCCoefficientTag`
+    pub(crate) fn from_tag_values(values: &[f64]) -> Option<Self> {
+        if values.len() != RPC_VALUE_COUNT {
+            return None;
+        }
+        let coeffs = |start: usize| -> [f64; 20] { values[start..start + 20].try_into().unwrap() };
+        Some(Self {
+            err_bias: values[0],
+            err_rand: values[1],
+            line_off: values[2],
+            samp_off: values[3],
+            lat_off: values[4],
+            long_off: values[5],
+            height_off: values[6],
+            line_scale: values[7],
+            samp_scale: values[8],
+            lat_scale: values[9],
+            long_scale: values[10],
+            height_scale: values[11],
+            line_num_coeff: coeffs(12),
+            line_den_coeff: coeffs(32),
+            samp_num_coeff: coeffs(52),
+            samp_den_coeff: coeffs(72),
+        })
+    }
+
+    /// Project a ground location (longitude and latitude in degrees, height in meters above the
+    /// ellipsoid) to an image `(column, row)` location in pixels.
+    pub fn ground_to_image(&self, lon: f64, lat: f64, height: f64) -> (f64, f64) {
+        let p = (lat - self.lat_off) / self.lat_scale;
+        let l = (lon - self.long_off) / self.long_scale;
+        let h = (height - self.height_off) / self.height_scale;
+        let terms = polynomial_terms(p, l, h);
+
+        let line = evaluate(&self.line_num_coeff, &terms) / evaluate(&self.line_den_coeff, &terms);
+        let samp = evaluate(&self.samp_num_coeff, &terms) / evaluate(&self.samp_den_coeff, &terms);
+        (
+            samp * self.samp_scale + self.samp_off,
+            line * self.line_scale + self.line_off,
+        )
+    }
+
+    /// Find the ground location (longitude, latitude in degrees) imaged at `(column, row)` for a
+    /// given height above the ellipsoid.
+    ///
+    /// The RPC model has no closed-form inverse, so this iterates with Newton's method from the
+    /// center of the model. Returns `None` if the iteration does not converge.
+    pub fn image_to_ground(&self, col: f64, row: f64, height: f64) -> Option<(f64, f64)> {
+        const MAX_ITERATIONS: usize = 20;
+        // Converge to a hundredth of a pixel
+        const TOLERANCE: f64 = 1e-2;
+
+        let (mut lon, mut lat) = (self.long_off, self.lat_off);
+        // Finite difference steps, relative to the extent of the model
+        let d_lon = self.long_scale.abs() * 1e-6;
+        let d_lat = self.lat_scale.abs() * 1e-6;
+
+        for _ in 0..MAX_ITERATIONS {
+            let (c, r) = self.ground_to_image(lon, lat, height);
+            let (err_c, err_r) = (col - c, row - r);
+            if err_c.abs() < TOLERANCE && err_r.abs() < TOLERANCE {
+                return Some((lon, lat));
+            }
+
+            let (c_lon, r_lon) = self.ground_to_image(lon + d_lon, lat, height);
+            let (c_lat, r_lat) = self.ground_to_image(lon, lat + d_lat, height);
+            let (dc_dlon, dr_dlon) = ((c_lon - c) / d_lon, (r_lon - r) / d_lon);
+            let (dc_dlat, dr_dlat) = ((c_lat - c) / d_lat, (r_lat - r) / d_lat);
+
+            let det = dc_dlon * dr_dlat - dc_dlat * dr_dlon;
+            if det == 0.0 || !det.is_finite() {
+                return None;
+            }
+            lon += (err_c * dr_dlat - err_r * dc_dlat) / det;
+            lat += (dc_dlon * err_r - dr_dlon * err_c) / det;
+        }
+        None
+    }
+}
+
+/// The 20 cubic polynomial terms of the RPC00B model, in coefficient order
+fn polynomial_terms(p: f64, l: f64, h: f64) -> [f64; 20] {
+    [
+        1.0,
+        l,
+        p,
+        h,
+        l * p,
+        l * h,
+        p * h,
+        l * l,
+        p * p,
+        h * h,
+        p * l * h,
+        l * l * l,
+        l * p * p,
+        l * h * h,
+        l * l * p,
+        p * p * p,
+        p * h * h,
+        l * l * h,
+        p * p * h,
+        h * h * h,
+    ]
+}
+
+fn evaluate(coeffs: &[f64; 20], terms: &[f64; 20]) -> f64 {
+    coeffs.iter().zip(terms).map(|(c, t)| c * t).sum()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A model where rows follow latitude and columns follow longitude, with a small cross term
+    fn simple_rpc() -> RpcCoefficients {
+        let mut values = vec![0.0; RPC_VALUE_COUNT];
+        values[2..12].copy_from_slice(&[
+            500.0, 500.0, 40.0, -105.0, 0.0, 500.0, 500.0, 0.1, 0.1, 100.0,
+        ]);
+        // line = -P + 0.01 * L
+        values[12 + 2] = -1.0;
+        values[12 + 1] = 0.01;
+        values[32] = 1.0;
+        // samp = L + 0.05 * H
+        values[52 + 1] = 1.0;
+        values[52 + 3] = 0.05;
+        values[72] = 1.0;
+        RpcCoefficients::from_tag_values(&values).unwrap()
+    }
+
+    #[test]
+    fn ground_to_image() {
+        let rpc = simple_rpc();
+        let (col, row) = rpc.ground_to_image(-105.0, 40.0, 0.0);
+        assert_eq!((col, row), (500.0, 500.0));
+        let (col, row) = rpc.ground_to_image(-105.05, 40.1, 0.0);
+        assert!((col - 250.0).abs() < 1e-9);
+        assert!((row - (500.0 - 500.0 - 2.5)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn image_to_ground_round_trip() {
+        let rpc = simple_rpc();
+        let (col, row) = rpc.ground_to_image(-105.03, 39.95, 50.0);
+        let (lon, lat) = rpc.image_to_ground(col, row, 50.0).unwrap();
+        assert!((lon - -105.03).abs() < 1e-5);
+        assert!((lat - 39.95).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,211 @@
+//! A limit on the requests in flight shared by readers, serving interactive requests before
+//! speculative ones so that cache warming never starves map tiles.
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use futures::channel::oneshot;
+
+use crate::recorder::RequestPurpose;
+
+/// The priority of a request under a [`RequestScheduler`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RequestPriority {
+    /// Requests a caller is waiting on, like tile reads and the header reads at open
+    Interactive,
+    /// Speculative requests, like [`ReadOptions::prefetch_neighbors`], that only use slots that
+    /// no interactive request is waiting for
+    ///
+    /// [`ReadOptions::prefetch_neighbors`]: crate::ReadOptions::prefetch_neighbors
+    Background,
+}
+
+impl From<RequestPurpose> for RequestPriority {
+    fn from(purpose: RequestPurpose) -> Self {
+        match purpose {
+            RequestPurpose::Prefetch => Self::Background,
+            _ => Self::Interactive,
+        }
+    }
+}
+
+/// Counters of a [`RequestScheduler`], returned by [`RequestScheduler::metrics`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SchedulerMetrics {
+    /// The number of interactive requests in flight
+    pub interactive_in_flight: usize,
+    /// The number of background requests in flight
+    pub background_in_flight: usize,
+    /// The number of interactive requests waiting for a slot
+    pub interactive_waiting: usize,
+    /// The number of background requests waiting for a slot
+    pub background_waiting: usize,
+}
+
+/// A two-tier limit on the requests in flight of the readers opened with it in
+/// [`crate::OpenOptions::scheduler`]. Clones share the same slots.
+///
+/// At most `max_requests` requests are in flight at once, of which at most
+/// `max_background_requests` are [background](RequestPriority::Background) requests. A freed
+/// slot goes to the longest waiting interactive request, and to a background request only if no
+/// interactive request is waiting, so prefetches that fill the queue never delay the tiles a
+/// caller is waiting for by more than the background requests already in flight.
+///
+/// A slot is held until the response body has been read or dropped.
+#[derive(Debug, Clone)]
+pub struct RequestScheduler {
+    state: Arc<Mutex<SchedulerState>>,
+}
+
+#[derive(Debug)]
+struct SchedulerState {
+    max_requests: usize,
+    max_background_requests: usize,
+    in_flight: [usize; 2],
+    waiting: [VecDeque<oneshot::Sender<RequestSlot>>; 2],
+}
+
+impl SchedulerState {
+    /// Whether a request of `priority` can take a free slot now
+    fn can_start(&self, priority: RequestPriority) -> bool {
+        let total = self.in_flight[0] + self.in_flight[1];
+        match priority {
+            RequestPriority::Interactive => total < self.max_requests,
+            RequestPriority::Background => {
+                total < self.max_requests
+                    && self.in_flight[1] < self.max_background_requests
+                    && self.waiting[0].is_empty()
+            }
+        }
+    }
+}
+
+/// The index of the counters and queue of a priority in [`SchedulerState`]
+fn tier(priority: RequestPriority) -> usize {
+    match priority {
+        RequestPriority::Interactive => 0,
+        RequestPriority::Background => 1,
+    }
+}
+
+impl RequestScheduler {
+    /// Create a scheduler allowing `max_requests` requests in flight, of which at most
+    /// `max_background_requests` are background requests
+    pub fn new(max_requests: usize, max_background_requests: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(SchedulerState {
+                max_requests: max_requests.max(1),
+                max_background_requests,
+                in_flight: [0, 0],
+                waiting: [VecDeque::new(), VecDeque::new()],
+            })),
+        }
+    }
+
+    /// Return the counters of the scheduler
+    pub fn metrics(&self) -> SchedulerMetrics {
+        let state = self.state.lock().unwrap();
+        SchedulerMetrics {
+            interactive_in_flight: state.in_flight[0],
+            background_in_flight: state.in_flight[1],
+            interactive_waiting: state.waiting[0].len(),
+            background_waiting: state.waiting[1].len(),
+        }
+    }
+
+    /// Wait for a slot for a request of `priority`, held until the returned slot is dropped
+    pub(crate) async fn acquire(&self, priority: RequestPriority) -> RequestSlot {
+        let receiver = {
+            let mut state = self.state.lock().unwrap();
+            if state.waiting[tier(priority)].is_empty() && state.can_start(priority) {
+                state.in_flight[tier(priority)] += 1;
+                return self.slot(priority);
+            }
+            let (sender, receiver) = oneshot::channel();
+            state.waiting[tier(priority)].push_back(sender);
+            receiver
+        };
+        // The sender is only dropped along with the scheduler, which the slot would keep alive
+        receiver.await.expect("the scheduler outlives its waiters")
+    }
+
+    fn slot(&self, priority: RequestPriority) -> RequestSlot {
+        RequestSlot {
+            scheduler: Some(self.clone()),
+            priority,
+        }
+    }
+
+    /// Free a slot of `priority` and hand slots to the waiting requests that can now start
+    fn release(&self, priority: RequestPriority) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight[tier(priority)] -= 1;
+        for priority in [RequestPriority::Interactive, RequestPriority::Background] {
+            while state.can_start(priority) {
+                let Some(sender) = state.waiting[tier(priority)].pop_front() else {
+                    break;
+                };
+                state.in_flight[tier(priority)] += 1;
+                // A waiter that gave up returns its slot, which is taken back here rather than
+                // by its drop, as the state is locked
+                if let Err(mut slot) = sender.send(self.slot(priority)) {
+                    slot.scheduler = None;
+                    state.in_flight[tier(priority)] -= 1;
+                }
+            }
+        }
+    }
+}
+
+/// A slot of a [`RequestScheduler`], freed when dropped
+#[derive(Debug)]
+pub(crate) struct RequestSlot {
+    /// The scheduler to return the slot to, `None` once it has been taken back
+    scheduler: Option<RequestScheduler>,
+    priority: RequestPriority,
+}
+
+impl Drop for RequestSlot {
+    fn drop(&mut self) {
+        if let Some(scheduler) = self.scheduler.take() {
+            scheduler.release(self.priority);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::FutureExt;
+
+    #[test]
+    fn interactive_requests_first() {
+        let scheduler = RequestScheduler::new(2, 1);
+        let background = scheduler
+            .acquire(RequestPriority::Background)
+            .now_or_never()
+            .unwrap();
+        // Only one background request may be in flight
+        let mut queued_background = Box::pin(scheduler.acquire(RequestPriority::Background));
+        assert!((&mut queued_background).now_or_never().is_none());
+        let interactive = scheduler
+            .acquire(RequestPriority::Interactive)
+            .now_or_never()
+            .unwrap();
+
+        // Both slots are taken, and the interactive request queued after the background one gets
+        // the next free slot
+        let mut queued_interactive = Box::pin(scheduler.acquire(RequestPriority::Interactive));
+        assert!((&mut queued_interactive).now_or_never().is_none());
+        drop(background);
+        let second = (&mut queued_interactive).now_or_never().unwrap();
+        assert!((&mut queued_background).now_or_never().is_none());
+        drop(interactive);
+        let background = (&mut queued_background).now_or_never().unwrap();
+
+        // Waiters that give up don't take slots
+        let queued = scheduler.acquire(RequestPriority::Interactive);
+        assert!(Box::pin(queued).now_or_never().is_none());
+        drop((second, background));
+        assert_eq!(scheduler.metrics(), SchedulerMetrics::default());
+    }
+}
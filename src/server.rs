@@ -0,0 +1,231 @@
+//! A [`tower_service::Service`] serving PNG map tiles of a COG, to mount into axum or hyper.
+//!
+//! ```ignore
+//! let tiles = TileService::new(reader);
+//! let app = axum::Router::new().nest_service("/tiles", tiles);
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use http::{header, Request, Response, StatusCode};
+use http_body_util::Full;
+use tower_service::Service;
+
+use crate::cog::COGReader;
+use crate::error::{AiocogeoError, Result};
+use crate::options::{ReadOptions, Resampling};
+use crate::tms::TileMatrixSet;
+
+/// The number of encoded tiles cached by default
+const DEFAULT_CACHE_SIZE: usize = 256;
+
+/// The `(z, x, y)` address of a tile
+type TileKey = (usize, usize, usize);
+
+/// Encoded tiles, evicted oldest first
+#[derive(Debug, Default)]
+struct TileCache {
+    tiles: HashMap<TileKey, Bytes>,
+    order: VecDeque<TileKey>,
+    capacity: usize,
+}
+
+impl TileCache {
+    fn get(&self, key: &TileKey) -> Option<Bytes> {
+        self.tiles.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: TileKey, png: Bytes) {
+        if self.capacity == 0 || self.tiles.insert(key, png).is_some() {
+            return;
+        }
+        self.order.push_back(key);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.tiles.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// Serves `{z}/{x}/{y}.png` requests with PNG tiles read by [`COGReader::read_tms_tile`] and
+/// encoded by [`crate::ImageData::to_png`]. Palette images are rendered in the colors of their
+/// colormap, see [`COGReader::colormap`].
+///
+/// Responses are `404 Not Found` for paths that aren't tiles of the tile matrix set or tiles
+/// without valid pixels, and `500 Internal Server Error` with the error message for failed
+/// reads. Clones share the reader and the cache of recently served tiles.
+#[derive(Clone)]
+pub struct TileService {
+    reader: COGReader,
+    tms: Arc<TileMatrixSet>,
+    resampling: Resampling,
+    options: Arc<ReadOptions>,
+    colormap: Option<Arc<Vec<[u8; 4]>>>,
+    cache: Arc<Mutex<TileCache>>,
+}
+
+impl TileService {
+    /// Serve `WebMercatorQuad` tiles with nearest neighbor resampling, caching the last 256 tiles
+    pub fn new(reader: COGReader) -> Self {
+        Self {
+            colormap: reader.colormap().map(Arc::new),
+            reader,
+            tms: Arc::new(TileMatrixSet::web_mercator_quad()),
+            resampling: Resampling::default(),
+            options: Default::default(),
+            cache: Arc::new(Mutex::new(TileCache {
+                capacity: DEFAULT_CACHE_SIZE,
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Serve tiles of another tile matrix set
+    pub fn with_tile_matrix_set(self, tms: TileMatrixSet) -> Self {
+        Self {
+            tms: Arc::new(tms),
+            ..self
+        }
+    }
+
+    /// Resample tiles with another method
+    pub fn with_resampling(self, resampling: Resampling) -> Self {
+        Self { resampling, ..self }
+    }
+
+    /// Read tiles with custom options, e.g. to select the bands to serve
+    pub fn with_read_options(self, options: ReadOptions) -> Self {
+        Self {
+            options: Arc::new(options),
+            ..self
+        }
+    }
+
+    /// Cache up to `tiles` encoded tiles, or none if 0
+    pub fn with_cache_size(self, tiles: usize) -> Self {
+        Self {
+            cache: Arc::new(Mutex::new(TileCache {
+                capacity: tiles,
+                ..Default::default()
+            })),
+            ..self
+        }
+    }
+
+    /// Return tile `(x, y)` of zoom level `z` encoded as a PNG, or `None` if it has no valid
+    /// pixels
+    pub async fn tile_png(&self, x: usize, y: usize, z: usize) -> Result<Option<Bytes>> {
+        let key = (z, x, y);
+        if let Some(png) = self.cache.lock().unwrap().get(&key) {
+            return Ok(Some(png));
+        }
+        let mut image = self
+            .reader
+            .read_tms_tile(&self.tms, x, y, z, self.resampling, &self.options)
+            .await?;
+        if let Some(colormap) = self.colormap.as_ref().filter(|_| image.data.shape().0 == 1) {
+            image = image.apply_colormap(colormap)?;
+        }
+        if !image.mask.iter().any(|&valid| valid) {
+            return Ok(None);
+        }
+        let png = Bytes::from(image.to_png()?);
+        self.cache.lock().unwrap().insert(key, png.clone());
+        Ok(Some(png))
+    }
+
+    /// Serve a request for the tile at `path`
+    async fn respond(&self, path: &str) -> Response<Full<Bytes>> {
+        let Some((z, x, y)) = parse_tile_path(path).filter(|&(z, x, y)| {
+            self.tms
+                .matrix(z)
+                .is_ok_and(|matrix| x < matrix.matrix_width && y < matrix.matrix_height)
+        }) else {
+            return text_response(StatusCode::NOT_FOUND, "not a tile".to_string());
+        };
+        match self.tile_png(x, y, z).await {
+            Ok(Some(png)) => Response::builder()
+                .header(header::CONTENT_TYPE, "image/png")
+                .body(Full::new(png))
+                .unwrap(),
+            Ok(None) => text_response(StatusCode::NOT_FOUND, "tile outside the image".to_string()),
+            Err(err) => text_response(status_code(&err), err.to_string()),
+        }
+    }
+}
+
+/// The status of a failed tile read
+fn status_code(err: &AiocogeoError) -> StatusCode {
+    match err {
+        // The file was replaced, so the reader is stale rather than broken
+        AiocogeoError::SourceChanged(_) => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn text_response(status: StatusCode, body: String) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+/// Parse the `(z, x, y)` address of a path ending in `{z}/{x}/{y}.png`
+fn parse_tile_path(path: &str) -> Option<TileKey> {
+    let mut segments = path.rsplit('/');
+    let y = segments.next()?.strip_suffix(".png")?.parse().ok()?;
+    let x = segments.next()?.parse().ok()?;
+    let z = segments.next()?.parse().ok()?;
+    Some((z, x, y))
+}
+
+impl<B> Service<Request<B>> for TileService {
+    type Response = Response<Full<Bytes>>;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, std::result::Result<Self::Response, Infallible>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<std::result::Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let service = self.clone();
+        let path = request.uri().path().to_string();
+        Box::pin(async move { Ok(service.respond(&path).await) })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tile_paths() {
+        assert_eq!(parse_tile_path("/tiles/3/1/2.png"), Some((3, 1, 2)));
+        assert_eq!(parse_tile_path("3/1/2.png"), Some((3, 1, 2)));
+        assert_eq!(parse_tile_path("/tiles/3/1/2.jpg"), None);
+        assert_eq!(parse_tile_path("/tiles/1/2.png"), None);
+        assert_eq!(parse_tile_path("/tiles/a/1/2.png"), None);
+    }
+
+    #[test]
+    fn cache_eviction() {
+        let mut cache = TileCache {
+            capacity: 2,
+            ..Default::default()
+        };
+        for y in 0..3 {
+            cache.insert((0, 0, y), Bytes::from(vec![y as u8]));
+        }
+        assert!(cache.get(&(0, 0, 0)).is_none());
+        assert_eq!(cache.get(&(0, 0, 2)), Some(Bytes::from(vec![2])));
+        assert_eq!(cache.tiles.len(), 2);
+    }
+}
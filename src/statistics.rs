@@ -0,0 +1,112 @@
+use ndarray::ArrayView2;
+
+/// Summary statistics of the valid pixels of a band
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandStatistics {
+    /// The number of valid pixels
+    pub count: usize,
+    /// The statistics of the valid pixels, or `None` when there are none
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+    /// The population standard deviation
+    pub std: Option<f64>,
+    /// The number of valid pixels in each of the equal-width bins between `min` and `max`
+    pub histogram: Vec<usize>,
+    /// The `histogram.len() + 1` edges of the histogram bins
+    pub bin_edges: Vec<f64>,
+}
+
+impl BandStatistics {
+    /// The statistics of a band without valid pixels
+    pub(crate) fn empty() -> BandStatistics {
+        BandStatistics {
+            count: 0,
+            min: None,
+            max: None,
+            mean: None,
+            std: None,
+            histogram: vec![],
+            bin_edges: vec![],
+        }
+    }
+
+    /// Compute the statistics of the pixels of `values` that are `true` in `mask` and not `NaN`,
+    /// with a histogram of `bins` bins
+    pub(crate) fn compute(
+        values: ArrayView2<f64>,
+        mask: ArrayView2<bool>,
+        bins: usize,
+    ) -> BandStatistics {
+        let valid = || {
+            values
+                .iter()
+                .zip(mask.iter())
+                .filter(|(value, &valid)| valid && !value.is_nan())
+                .map(|(&value, _)| value)
+        };
+
+        let (mut count, mut sum) = (0, 0.0);
+        let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+        for value in valid() {
+            count += 1;
+            sum += value;
+            min = min.min(value);
+            max = max.max(value);
+        }
+        if count == 0 {
+            return BandStatistics::empty();
+        }
+
+        let mean = sum / count as f64;
+        let variance = valid().map(|v| (v - mean).powi(2)).sum::<f64>() / count as f64;
+
+        let bins = bins.max(1);
+        let width = (max - min) / bins as f64;
+        let mut histogram = vec![0; bins];
+        for value in valid() {
+            // The maximum belongs to the last bin, and a constant band to the first
+            let bin = if width > 0.0 {
+                (((value - min) / width) as usize).min(bins - 1)
+            } else {
+                0
+            };
+            histogram[bin] += 1;
+        }
+        let bin_edges = (0..=bins).map(|i| min + width * i as f64).collect();
+
+        BandStatistics {
+            count,
+            min: Some(min),
+            max: Some(max),
+            mean: Some(mean),
+            std: Some(variance.sqrt()),
+            histogram,
+            bin_edges,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn masked_statistics() {
+        let values = array![[1.0, 2.0, 3.0], [4.0, f64::NAN, 100.0]];
+        let mask = array![[true, true, true], [true, true, false]];
+        let stats = BandStatistics::compute(values.view(), mask.view(), 3);
+        assert_eq!(stats.count, 4);
+        assert_eq!(
+            (stats.min, stats.max, stats.mean),
+            (Some(1.0), Some(4.0), Some(2.5))
+        );
+        assert!((stats.std.unwrap() - 1.25f64.sqrt()).abs() < 1e-12);
+        assert_eq!(stats.histogram, [1, 1, 2]);
+        assert_eq!(stats.bin_edges, [1.0, 2.0, 3.0, 4.0]);
+
+        let empty = BandStatistics::compute(values.view(), mask.mapv(|_| false).view(), 3);
+        assert_eq!((empty.count, empty.mean), (0, None));
+    }
+}
@@ -0,0 +1,231 @@
+//! Report how many bytes the tiles of a file take and how well they compress, computed from the
+//! tile byte counts without fetching any tile, to audit whether datasets should be re-encoded.
+use std::fmt::{self, Display};
+
+use tiff::tags::{CompressionMethod, PlanarConfiguration};
+
+use crate::dump::IfdKind;
+use crate::ifd::ImageFileDirectory;
+
+/// The distribution of the stored sizes of the tiles of an IFD, in bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TileSizes {
+    /// The size of the smallest tile
+    pub min: u64,
+    /// The size of the middle tile when sorted by size
+    pub median: u64,
+    /// The size that 90% of the tiles don't exceed
+    pub p90: u64,
+    /// The size of the largest tile
+    pub max: u64,
+    /// The mean size of the tiles
+    pub mean: f64,
+}
+
+/// The storage of the tiles of one IFD, as returned by [`crate::COGReader::storage_report`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelStorage {
+    /// Whether the IFD is the image, an overview or a mask
+    pub kind: IfdKind,
+    /// The overview level of the IFD, where level 0 is the full resolution image
+    pub level: usize,
+    /// The compression of the tiles
+    pub compression: CompressionMethod,
+    /// The number of stored tiles, counting each band of images that store bands in separate
+    /// tiles
+    pub tiles: usize,
+    /// The number of tiles that are not stored, which read as nodata
+    pub sparse_tiles: usize,
+    /// The total size of the stored tiles
+    pub stored_bytes: u64,
+    /// The total size of the stored tiles once decompressed
+    pub uncompressed_bytes: u64,
+    /// The distribution of the sizes of the stored tiles, or `None` if every tile is sparse
+    pub tile_sizes: Option<TileSizes>,
+}
+
+impl LevelStorage {
+    fn new(ifd: &ImageFileDirectory, kind: IfdKind, level: usize) -> Self {
+        let mut sizes: Vec<u64> = ifd
+            .tile_offsets
+            .iter()
+            .zip(&ifd.tile_byte_counts)
+            .filter(|(&offset, &count)| offset != 0 && count != 0)
+            .map(|(_, &count)| count)
+            .collect();
+        sizes.sort_unstable();
+        let stored_bytes = sizes.iter().sum();
+        let tile_sizes = (!sizes.is_empty()).then(|| {
+            let percentile = |p: usize| sizes[(sizes.len() - 1) * p / 100];
+            TileSizes {
+                min: sizes[0],
+                median: percentile(50),
+                p90: percentile(90),
+                max: sizes[sizes.len() - 1],
+                mean: stored_bytes as f64 / sizes.len() as f64,
+            }
+        });
+        Self {
+            kind,
+            level,
+            compression: ifd.compression(),
+            tiles: sizes.len(),
+            sparse_tiles: ifd.tile_offsets.len() - sizes.len(),
+            stored_bytes,
+            uncompressed_bytes: sizes.len() as u64 * uncompressed_tile_bytes(ifd),
+            tile_sizes,
+        }
+    }
+
+    /// Return the ratio of the decompressed to the stored size of the tiles, or `None` if every
+    /// tile is sparse
+    pub fn compression_ratio(&self) -> Option<f64> {
+        (self.stored_bytes > 0).then(|| self.uncompressed_bytes as f64 / self.stored_bytes as f64)
+    }
+}
+
+/// The storage of the tiles of an image, its overviews and masks, returned by
+/// [`crate::COGReader::storage_report`]. [`Display`] formats it as a table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StorageReport {
+    /// The image and its overviews, indexed by overview level, followed by the masks
+    pub levels: Vec<LevelStorage>,
+}
+
+impl StorageReport {
+    pub(crate) fn new<'a>(
+        levels: &[ImageFileDirectory],
+        masks: impl Iterator<Item = (usize, &'a ImageFileDirectory)>,
+    ) -> Self {
+        let levels = levels.iter().enumerate().map(|(level, ifd)| {
+            let kind = if level == 0 {
+                IfdKind::Image
+            } else {
+                IfdKind::Overview
+            };
+            LevelStorage::new(ifd, kind, level)
+        });
+        let masks = masks.map(|(level, ifd)| LevelStorage::new(ifd, IfdKind::Mask, level));
+        Self {
+            levels: levels.chain(masks).collect(),
+        }
+    }
+
+    /// Return the total size of the stored tiles of every IFD
+    pub fn stored_bytes(&self) -> u64 {
+        self.levels.iter().map(|level| level.stored_bytes).sum()
+    }
+
+    /// Return the total size of the tiles of every IFD once decompressed
+    pub fn uncompressed_bytes(&self) -> u64 {
+        self.levels
+            .iter()
+            .map(|level| level.uncompressed_bytes)
+            .sum()
+    }
+
+    /// Return the ratio of the decompressed to the stored size of every tile, or `None` if
+    /// every tile is sparse
+    pub fn compression_ratio(&self) -> Option<f64> {
+        let stored = self.stored_bytes();
+        (stored > 0).then(|| self.uncompressed_bytes() as f64 / stored as f64)
+    }
+}
+
+impl Display for StorageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<12} {:<12} {:>7} {:>7} {:>12} {:>6} {:>10} {:>10} {:>10}",
+            "ifd", "compression", "tiles", "sparse", "bytes", "ratio", "min", "median", "max"
+        )?;
+        for level in &self.levels {
+            let sizes = level.tile_sizes.unwrap_or_default();
+            writeln!(
+                f,
+                "{:<12} {:<12} {:>7} {:>7} {:>12} {:>6.2} {:>10} {:>10} {:>10}",
+                format!("{} {}", level.kind, level.level),
+                format!("{:?}", level.compression),
+                level.tiles,
+                level.sparse_tiles,
+                level.stored_bytes,
+                level.compression_ratio().unwrap_or(0.0),
+                sizes.min,
+                sizes.median,
+                sizes.max
+            )?;
+        }
+        write!(
+            f,
+            "total: {} bytes, compression ratio {:.2}",
+            self.stored_bytes(),
+            self.compression_ratio().unwrap_or(0.0)
+        )
+    }
+}
+
+/// Return the size of a decompressed tile of an IFD, with rows padded to whole bytes
+fn uncompressed_tile_bytes(ifd: &ImageFileDirectory) -> u64 {
+    let bits = &ifd.bits_per_sample;
+    let first = bits.first().copied().unwrap_or(0) as u64;
+    let bits_per_pixel = match ifd.planar_configuration {
+        PlanarConfiguration::Chunky if bits.len() == ifd.samples_per_pixel as usize => {
+            bits.iter().map(|&bits| bits as u64).sum()
+        }
+        PlanarConfiguration::Chunky => first * ifd.samples_per_pixel as u64,
+        _ => first,
+    };
+    let row_bytes = (ifd.tile_width as u64 * bits_per_pixel).div_ceil(8);
+    row_bytes * ifd.tile_height as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::CogBuilder;
+
+    #[tokio::test]
+    async fn storage_report() {
+        let (reader, _) = CogBuilder {
+            bands: 3,
+            overviews: vec![2],
+            mask: true,
+            ..Default::default()
+        }
+        .open()
+        .await
+        .unwrap();
+        let report = reader.storage_report();
+        let levels: Vec<_> = report
+            .levels
+            .iter()
+            .map(|l| (l.kind, l.level, l.tiles))
+            .collect();
+        assert_eq!(
+            levels,
+            [
+                (IfdKind::Image, 0, 4),
+                (IfdKind::Overview, 1, 1),
+                (IfdKind::Mask, 0, 4),
+                (IfdKind::Mask, 1, 1)
+            ]
+        );
+        // Uncompressed tiles are stored as is
+        let image = &report.levels[0];
+        let tile_bytes = 32 * 32 * 3;
+        assert_eq!(image.stored_bytes, 4 * tile_bytes);
+        assert_eq!(image.compression_ratio(), Some(1.0));
+        assert_eq!(image.tile_sizes.unwrap().median, tile_bytes);
+        assert_eq!(report.levels[2].uncompressed_bytes, 4 * 32 * 32 / 8);
+
+        let (deflate, _) = CogBuilder {
+            compression: CompressionMethod::Deflate,
+            ..Default::default()
+        }
+        .open()
+        .await
+        .unwrap();
+        assert!(deflate.storage_report().compression_ratio().unwrap() > 1.0);
+        assert!(report.to_string().starts_with("ifd"));
+    }
+}
@@ -0,0 +1,1099 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use object_store::path::{Path, PathPart};
+use object_store::{
+    GetOptions, GetRange, GetResult, GetResultPayload, ListResult, MultipartUpload, ObjectMeta,
+    ObjectStore, PutMultipartOpts, PutOptions, PutPayload, PutResult,
+};
+
+use crate::options::StoreRefresher;
+use crate::recorder::{now, RecordedRequest, RequestHooks, RequestPurpose};
+use crate::scheduler::{RequestPriority, RequestScheduler};
+
+/// Implement [ObjectStore] for a wrapper type with the given methods, delegating all other
+/// required methods to `self.inner`
+macro_rules! impl_object_store {
+    ($typ:ty, { $($methods:item)* }) => {
+        #[async_trait]
+        impl ObjectStore for $typ {
+            $($methods)*
+
+            async fn put_opts(
+                &self,
+                location: &Path,
+                payload: PutPayload,
+                opts: PutOptions,
+            ) -> object_store::Result<PutResult> {
+                self.inner.put_opts(location, payload, opts).await
+            }
+
+            async fn put_multipart_opts(
+                &self,
+                location: &Path,
+                opts: PutMultipartOpts,
+            ) -> object_store::Result<Box<dyn MultipartUpload>> {
+                self.inner.put_multipart_opts(location, opts).await
+            }
+
+            async fn delete(&self, location: &Path) -> object_store::Result<()> {
+                self.inner.delete(location).await
+            }
+
+            fn list(
+                &self,
+                prefix: Option<&Path>,
+            ) -> BoxStream<'_, object_store::Result<ObjectMeta>> {
+                self.inner.list(prefix)
+            }
+
+            async fn list_with_delimiter(
+                &self,
+                prefix: Option<&Path>,
+            ) -> object_store::Result<ListResult> {
+                self.inner.list_with_delimiter(prefix).await
+            }
+
+            async fn copy(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+                self.inner.copy(from, to).await
+            }
+
+            async fn copy_if_not_exists(
+                &self,
+                from: &Path,
+                to: &Path,
+            ) -> object_store::Result<()> {
+                self.inner.copy_if_not_exists(from, to).await
+            }
+        }
+    };
+}
+
+pub(crate) use impl_object_store;
+
+/// An [ObjectStore] wrapper that pins reads of one object to the version seen at open.
+///
+/// Every read of the pinned path is sent as a conditional request against the object's ETag,
+/// version and last modified time, so that a file replaced mid-session fails with a precondition
+/// error instead of returning bytes from the new file.
+#[derive(Debug)]
+pub(crate) struct PinnedStore {
+    inner: Arc<dyn ObjectStore>,
+    meta: ObjectMeta,
+}
+
+impl PinnedStore {
+    pub(crate) fn new(inner: Arc<dyn ObjectStore>, meta: ObjectMeta) -> Self {
+        Self { inner, meta }
+    }
+}
+
+impl Display for PinnedStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PinnedStore({}, {})", self.inner, self.meta.location)
+    }
+}
+
+impl_object_store!(PinnedStore, {
+    async fn get_opts(
+        &self,
+        location: &Path,
+        mut options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        if location == &self.meta.location {
+            // Not all stores return an ETag, so also check the modification time
+            options.if_match = options.if_match.or_else(|| self.meta.e_tag.clone());
+            options.if_unmodified_since = options
+                .if_unmodified_since
+                .or(Some(self.meta.last_modified));
+            options.version = options.version.or_else(|| self.meta.version.clone());
+        }
+        self.inner.get_opts(location, options).await
+    }
+});
+
+/// An [ObjectStore] wrapper that serves reads of one object from a store and path re-created by a
+/// [StoreRefresher] when they fail with authentication or permission errors.
+///
+/// Every call is made with the latest store, and with the latest path for the object, and a read
+/// of the object that fails with an auth error refreshes them and is retried once. Concurrent
+/// failures share a single refresh.
+#[derive(Debug)]
+pub(crate) struct RefreshingStore {
+    location: Path,
+    current: RwLock<(Arc<dyn ObjectStore>, Path, u64)>,
+    refresher: StoreRefresher,
+    refreshing: futures::lock::Mutex<()>,
+}
+
+impl RefreshingStore {
+    pub(crate) fn new(
+        inner: Arc<dyn ObjectStore>,
+        location: Path,
+        refresher: StoreRefresher,
+    ) -> Self {
+        Self {
+            current: RwLock::new((inner, location.clone(), 0)),
+            location,
+            refresher,
+            refreshing: futures::lock::Mutex::new(()),
+        }
+    }
+
+    fn current(&self) -> (Arc<dyn ObjectStore>, Path, u64) {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Return the latest store, and the latest path of `location` if it is the object's
+    fn resolve(&self, location: &Path) -> (Arc<dyn ObjectStore>, Path) {
+        let (store, path, _) = self.current();
+        let location = self.latest_path(location, &path);
+        (store, location)
+    }
+
+    /// Return `path`, the latest path of the object, if `location` is the object's
+    fn latest_path(&self, location: &Path, path: &Path) -> Path {
+        if location == &self.location {
+            path.clone()
+        } else {
+            location.clone()
+        }
+    }
+
+    /// Re-create the store and path, unless another read did since `generation`
+    async fn refresh(&self, generation: u64) -> object_store::Result<()> {
+        let _refreshing = self.refreshing.lock().await;
+        if self.current().2 != generation {
+            return Ok(());
+        }
+        let (store, path) = self.refresher.refresh().await?;
+        *self.current.write().unwrap() = (store, path, generation + 1);
+        Ok(())
+    }
+}
+
+impl Display for RefreshingStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RefreshingStore({})", self.current().0)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for RefreshingStore {
+    async fn get_opts(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        if location != &self.location {
+            return self.current().0.get_opts(location, options).await;
+        }
+        let (store, path, generation) = self.current();
+        match store.get_opts(&path, options.clone()).await {
+            Err(
+                object_store::Error::PermissionDenied { .. }
+                | object_store::Error::Unauthenticated { .. },
+            ) => {
+                self.refresh(generation).await?;
+                let (store, path, _) = self.current();
+                let mut result = store.get_opts(&path, options).await?;
+                // Callers see the object at the path they read
+                result.meta.location = location.clone();
+                Ok(result)
+            }
+            result => result.map(|mut result| {
+                result.meta.location = location.clone();
+                result
+            }),
+        }
+    }
+
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> object_store::Result<PutResult> {
+        let (store, path) = self.resolve(location);
+        store.put_opts(&path, payload, opts).await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> object_store::Result<Box<dyn MultipartUpload>> {
+        let (store, path) = self.resolve(location);
+        store.put_multipart_opts(&path, opts).await
+    }
+
+    async fn delete(&self, location: &Path) -> object_store::Result<()> {
+        let (store, path) = self.resolve(location);
+        store.delete(&path).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, object_store::Result<ObjectMeta>> {
+        // The listing borrows the store, which a refresh may replace, so it is collected first
+        let store = self.current().0;
+        let prefix = prefix.cloned();
+        futures::stream::once(async move {
+            let metas: Vec<_> = store.list(prefix.as_ref()).collect().await;
+            futures::stream::iter(metas)
+        })
+        .flatten()
+        .boxed()
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> object_store::Result<ListResult> {
+        self.current().0.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        let (store, path, _) = self.current();
+        let (from, to) = (self.latest_path(from, &path), self.latest_path(to, &path));
+        store.copy(&from, &to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        let (store, path, _) = self.current();
+        let (from, to) = (self.latest_path(from, &path), self.latest_path(to, &path));
+        store.copy_if_not_exists(&from, &to).await
+    }
+}
+
+/// An [ObjectStore] wrapper that reports every read made through it to [RequestHooks]
+#[derive(Debug)]
+pub(crate) struct RecordingStore {
+    inner: Arc<dyn ObjectStore>,
+    hooks: Vec<Arc<dyn RequestHooks>>,
+    purpose: RequestPurpose,
+}
+
+/// Return `store`, wrapped to report its reads as `purpose` if there are hooks
+pub(crate) fn recording_store(
+    store: &Arc<dyn ObjectStore>,
+    hooks: Vec<Arc<dyn RequestHooks>>,
+    purpose: RequestPurpose,
+) -> Arc<dyn ObjectStore> {
+    if hooks.is_empty() {
+        return store.clone();
+    }
+    Arc::new(RecordingStore {
+        inner: store.clone(),
+        hooks,
+        purpose,
+    })
+}
+
+impl RecordingStore {
+    fn start(&self, range: Option<Range<usize>>) -> Option<Instant> {
+        for hooks in &self.hooks {
+            let range = range
+                .clone()
+                .map(|range| range.start as u64..range.end as u64);
+            hooks.on_request_start(self.purpose, range);
+        }
+        now()
+    }
+
+    fn complete(&self, range: Range<usize>, start: Option<Instant>, success: bool) {
+        let request = RecordedRequest {
+            offset: range.start as u64,
+            length: range.len() as u64,
+            duration: start.map(|start| start.elapsed()),
+            purpose: self.purpose,
+        };
+        for hooks in &self.hooks {
+            hooks.on_request_complete(&request, success);
+        }
+    }
+}
+
+impl Display for RecordingStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RecordingStore({})", self.inner)
+    }
+}
+
+impl_object_store!(RecordingStore, {
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> object_store::Result<Bytes> {
+        let start = self.start(Some(range.clone()));
+        let result = self.inner.get_range(location, range.clone()).await;
+        self.complete(range, start, result.is_ok());
+        result
+    }
+
+    async fn get_opts(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        let requested = options.range.clone();
+        let start = self.start(match &requested {
+            Some(GetRange::Bounded(range)) => Some(range.clone()),
+            _ => None,
+        });
+        let result = self.inner.get_opts(location, options).await;
+        // Requests without a bounded range only know their range once they succeed
+        let range = match (&result, requested) {
+            (Ok(result), _) => result.range.clone(),
+            (Err(_), Some(GetRange::Bounded(range))) => range,
+            (Err(_), _) => 0..0,
+        };
+        self.complete(range, start, result.is_ok());
+        result
+    }
+});
+
+/// An [ObjectStore] wrapper that waits for a slot of a [RequestScheduler] before every read,
+/// holding it until the body of the response has been read or dropped
+#[derive(Debug)]
+pub(crate) struct ScheduledStore {
+    inner: Arc<dyn ObjectStore>,
+    scheduler: RequestScheduler,
+    priority: RequestPriority,
+}
+
+/// Return `store`, wrapped to schedule its reads as `purpose` if there is a scheduler
+pub(crate) fn scheduled_store(
+    store: Arc<dyn ObjectStore>,
+    scheduler: Option<&RequestScheduler>,
+    purpose: RequestPurpose,
+) -> Arc<dyn ObjectStore> {
+    let Some(scheduler) = scheduler else {
+        return store;
+    };
+    Arc::new(ScheduledStore {
+        inner: store,
+        scheduler: scheduler.clone(),
+        priority: purpose.into(),
+    })
+}
+
+impl Display for ScheduledStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ScheduledStore({})", self.inner)
+    }
+}
+
+impl_object_store!(ScheduledStore, {
+    async fn get_opts(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        let slot = self.scheduler.acquire(self.priority).await;
+        let result = self.inner.get_opts(location, options).await?;
+        let payload = match result.payload {
+            GetResultPayload::Stream(stream) => GetResultPayload::Stream(
+                stream
+                    .map(move |chunk| {
+                        let _slot = &slot;
+                        chunk
+                    })
+                    .boxed(),
+            ),
+            payload @ GetResultPayload::File(..) => payload,
+        };
+        Ok(GetResult { payload, ..result })
+    }
+});
+
+/// The bytes read from the start of files at open, shared by the readers opened with it in
+/// [`OpenOptions::header_cache`](crate::OpenOptions::header_cache). Clones share the same
+/// entries.
+///
+/// When a file with a cached header is opened again, its header is requested with
+/// `If-None-Match` against the cached ETag, so that unchanged files are answered with
+/// `304 Not Modified` and no body, and CDNs in front of the bucket can serve the validation from
+/// their cache. Only files whose store reports an ETag are cached. The cache holds the headers
+/// of at most `max_entries` files, dropping the least recently used past that.
+///
+/// Request headers such as `Cache-Control` aren't set per request by [ObjectStore]; configure
+/// them on the HTTP store, e.g. with `ClientOptions::with_default_headers`.
+#[derive(Debug, Clone)]
+pub struct HeaderCache {
+    state: Arc<Mutex<HeaderCacheState>>,
+}
+
+#[derive(Debug)]
+struct HeaderCacheState {
+    max_entries: usize,
+    entries: HashMap<(String, Path), (ObjectMeta, Bytes)>,
+    /// The keys of `entries`, from the least to the most recently used
+    order: VecDeque<(String, Path)>,
+}
+
+impl HeaderCache {
+    /// Create an empty cache holding the headers of at most `max_entries` files
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(HeaderCacheState {
+                max_entries,
+                entries: HashMap::new(),
+                order: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Return the number of files whose header is cached
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    /// Return whether no header is cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop every cached header
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.order.clear();
+    }
+
+    /// Return the metadata and header of a file of the store identified by `namespace`
+    pub(crate) fn get(&self, namespace: &str, path: &Path) -> Option<(ObjectMeta, Bytes)> {
+        let mut state = self.state.lock().unwrap();
+        let key = (namespace.to_string(), path.clone());
+        let entry = state.entries.get(&key).cloned()?;
+        if let Some(i) = state.order.iter().position(|used| used == &key) {
+            state.order.remove(i);
+        }
+        state.order.push_back(key);
+        Some(entry)
+    }
+
+    /// Cache the header of a file, if its metadata has an ETag to validate it against later
+    pub(crate) fn insert(&self, namespace: &str, meta: ObjectMeta, header: Bytes) {
+        if meta.e_tag.is_none() {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let key = (namespace.to_string(), meta.location.clone());
+        if state.entries.insert(key.clone(), (meta, header)).is_some() {
+            state.order.retain(|used| used != &key);
+        }
+        state.order.push_back(key);
+        while state.entries.len() > state.max_entries {
+            let Some(oldest) = state.order.pop_front() else {
+                break;
+            };
+            state.entries.remove(&oldest);
+        }
+    }
+}
+
+/// The size of the blocks that files are split into by the local disk cache
+pub const CACHE_BLOCK_SIZE: usize = 64 * 1024;
+
+/// An [ObjectStore] wrapper that persists fetched bytes of one object in a second store, usually
+/// a [LocalFileSystem](object_store::local::LocalFileSystem) directory.
+///
+/// Like GDAL's `VSICACHE`, the file is cached in fixed-size blocks, so any read covered by
+/// previously fetched blocks is served from the cache regardless of how the earlier requests
+/// were split or merged. Consecutive missing blocks are fetched with a single request, and a
+/// block missed by concurrent reads is only fetched by the first of them.
+///
+/// Blocks are keyed by `namespace` (identifying the source store), path, ETag and block index.
+/// Without an ETag, a replaced file is not detected. Failures to write to the cache are ignored.
+#[derive(Debug)]
+pub(crate) struct CachingStore {
+    inner: Arc<dyn ObjectStore>,
+    cache: Arc<dyn ObjectStore>,
+    namespace: String,
+    meta: ObjectMeta,
+    block_size: usize,
+    /// Locks held on the blocks being fetched, shared with the stores of [CachingStore::with_inner]
+    fetching: Arc<Mutex<HashMap<usize, Arc<futures::lock::Mutex<()>>>>>,
+}
+
+impl CachingStore {
+    pub(crate) fn new(
+        inner: Arc<dyn ObjectStore>,
+        cache: Arc<dyn ObjectStore>,
+        namespace: String,
+        meta: ObjectMeta,
+        block_size: usize,
+    ) -> Self {
+        Self {
+            inner,
+            cache,
+            namespace,
+            meta,
+            block_size,
+            fetching: Default::default(),
+        }
+    }
+
+    /// Return a store caching the reads of `inner` in the same blocks, e.g. to cache the reads
+    /// of a store that reports or schedules them, so that cache hits aren't reported or
+    /// scheduled as requests
+    pub(crate) fn with_inner(&self, inner: Arc<dyn ObjectStore>) -> Self {
+        Self {
+            inner,
+            cache: self.cache.clone(),
+            namespace: self.namespace.clone(),
+            meta: self.meta.clone(),
+            block_size: self.block_size,
+            fetching: self.fetching.clone(),
+        }
+    }
+
+    /// The location of a cached block in the cache store
+    fn block_path(&self, block: usize) -> Path {
+        let store = PathPart::from(self.namespace.as_str());
+        let e_tag = PathPart::from(self.meta.e_tag.as_deref().unwrap_or("unversioned"));
+        let block = PathPart::from(format!("{}-{}", self.block_size, block));
+        Path::from_iter(
+            std::iter::once(store)
+                .chain(self.meta.location.parts())
+                .chain([e_tag, block]),
+        )
+    }
+
+    /// The byte range of a block within the file
+    fn block_range(&self, block: usize) -> Range<usize> {
+        block * self.block_size..((block + 1) * self.block_size).min(self.meta.size)
+    }
+
+    /// Read a block from the cache
+    async fn get_block(&self, block: usize) -> Option<Bytes> {
+        match self.cache.get(&self.block_path(block)).await {
+            Ok(result) => result.bytes().await.ok(),
+            Err(_) => None,
+        }
+    }
+
+    /// The lock held while a block is fetched
+    fn block_lock(&self, block: usize) -> Arc<futures::lock::Mutex<()>> {
+        let mut fetching = self.fetching.lock().unwrap();
+        fetching.entry(block).or_default().clone()
+    }
+
+    /// Fetch consecutive blocks from the inner store with a single request, writing them to the
+    /// cache
+    async fn fetch_blocks(&self, blocks: Range<usize>) -> object_store::Result<Vec<Bytes>> {
+        let start = self.block_range(blocks.start).start;
+        let end = self.block_range(blocks.end - 1).end;
+        let bytes = self
+            .inner
+            .get_range(&self.meta.location, start..end)
+            .await?;
+
+        let mut result = Vec::with_capacity(blocks.len());
+        for block in blocks {
+            let range = self.block_range(block);
+            let block_bytes = bytes.slice(range.start - start..range.end - start);
+            let _ = self
+                .cache
+                .put(&self.block_path(block), block_bytes.clone().into())
+                .await;
+            result.push(block_bytes);
+        }
+        Ok(result)
+    }
+
+    /// Read a range of the cached file, fetching any blocks that are not cached yet
+    async fn get_cached_range(&self, range: Range<usize>) -> object_store::Result<Bytes> {
+        if range.is_empty() || range.end > self.meta.size {
+            return self.inner.get_range(&self.meta.location, range).await;
+        }
+
+        let first_block = range.start / self.block_size;
+        let last_block = (range.end - 1) / self.block_size;
+        let mut blocks = Vec::with_capacity(last_block - first_block + 1);
+        for block in first_block..=last_block {
+            blocks.push(self.get_block(block).await);
+        }
+
+        // Lock the missing blocks in order, then read again the blocks that a concurrent read
+        // fetched while waiting for their locks
+        let missing: Vec<_> = (first_block..=last_block)
+            .filter(|block| blocks[block - first_block].is_none())
+            .collect();
+        let locks: Vec<_> = missing
+            .iter()
+            .map(|&block| self.block_lock(block))
+            .collect();
+        let mut guards = Vec::with_capacity(locks.len());
+        for (&block, lock) in missing.iter().zip(&locks) {
+            guards.push(lock.lock().await);
+            blocks[block - first_block] = self.get_block(block).await;
+        }
+
+        // Fetch each run of missing blocks with a single request
+        let mut i = 0;
+        while i < blocks.len() {
+            if blocks[i].is_some() {
+                i += 1;
+                continue;
+            }
+            let end = (i..blocks.len())
+                .find(|&j| blocks[j].is_some())
+                .unwrap_or(blocks.len());
+            let fetched = self
+                .fetch_blocks(first_block + i..first_block + end)
+                .await?;
+            for (slot, bytes) in blocks[i..end].iter_mut().zip(fetched) {
+                *slot = Some(bytes);
+            }
+            i = end;
+        }
+        drop(guards);
+        let mut fetching = self.fetching.lock().unwrap();
+        for (block, lock) in missing.into_iter().zip(locks) {
+            // Only the map and this read hold the lock if no other read is waiting for it
+            if Arc::strong_count(&lock) == 2 {
+                fetching.remove(&block);
+            }
+        }
+        drop(fetching);
+        let blocks: Vec<Bytes> = blocks.into_iter().flatten().collect();
+
+        let offset = first_block * self.block_size;
+        if blocks.len() == 1 {
+            return Ok(blocks[0].slice(range.start - offset..range.end - offset));
+        }
+        let mut buf = Vec::with_capacity(range.len());
+        for block in blocks {
+            buf.extend_from_slice(&block);
+        }
+        Ok(Bytes::from(buf).slice(range.start - offset..range.end - offset))
+    }
+}
+
+impl Display for CachingStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CachingStore({}, {})", self.inner, self.cache)
+    }
+}
+
+impl_object_store!(CachingStore, {
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> object_store::Result<Bytes> {
+        if location == &self.meta.location {
+            self.get_cached_range(range).await
+        } else {
+            self.inner.get_range(location, range).await
+        }
+    }
+
+    async fn get_opts(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        self.inner.get_opts(location, options).await
+    }
+});
+
+/// A read-only [ObjectStore] serving a single local file at `location` with positional reads,
+/// for readers opened with [`crate::COGReader::from_file`].
+///
+/// Unlike a [LocalFileSystem](object_store::local::LocalFileSystem), the file is opened once and
+/// each read is a single `pread` of the requested range, without a blocking thread pool.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub(crate) struct FileStore {
+    file: std::fs::File,
+    meta: ObjectMeta,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileStore {
+    pub(crate) fn new(file: std::fs::File, location: Path) -> std::io::Result<Self> {
+        let metadata = file.metadata()?;
+        let meta = ObjectMeta {
+            location,
+            last_modified: metadata.modified()?.into(),
+            size: metadata.len() as usize,
+            e_tag: None,
+            version: None,
+        };
+        Ok(Self { file, meta })
+    }
+
+    pub(crate) fn meta(&self) -> &ObjectMeta {
+        &self.meta
+    }
+
+    fn read_at(&self, range: Range<usize>) -> std::io::Result<Bytes> {
+        let mut buf = vec![0; range.len()];
+        #[cfg(unix)]
+        std::os::unix::fs::FileExt::read_exact_at(&self.file, &mut buf, range.start as u64)?;
+        #[cfg(windows)]
+        {
+            let mut read = 0;
+            while read < buf.len() {
+                let offset = (range.start + read) as u64;
+                match std::os::windows::fs::FileExt::seek_read(
+                    &self.file,
+                    &mut buf[read..],
+                    offset,
+                )? {
+                    0 => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+                    n => read += n,
+                }
+            }
+        }
+        Ok(buf.into())
+    }
+
+    fn not_found(&self, location: &Path) -> object_store::Error {
+        object_store::Error::NotFound {
+            path: location.to_string(),
+            source: format!("only {} is served", self.meta.location).into(),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Display for FileStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FileStore({})", self.meta.location)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[async_trait]
+impl ObjectStore for FileStore {
+    async fn get_opts(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        if location != &self.meta.location {
+            return Err(self.not_found(location));
+        }
+        let size = self.meta.size;
+        let range = match options.range {
+            Some(GetRange::Bounded(range)) => range.start.min(size)..range.end.min(size),
+            Some(GetRange::Offset(offset)) => offset.min(size)..size,
+            Some(GetRange::Suffix(suffix)) => size.saturating_sub(suffix)..size,
+            None => 0..size,
+        };
+        let bytes = match options.head {
+            true => Bytes::new(),
+            false => self
+                .read_at(range.clone())
+                .map_err(|err| object_store::Error::Generic {
+                    store: "FileStore",
+                    source: Box::new(err),
+                })?,
+        };
+        Ok(GetResult {
+            payload: GetResultPayload::Stream(
+                futures::stream::once(async move { Ok(bytes) }).boxed(),
+            ),
+            meta: self.meta.clone(),
+            range,
+            attributes: Default::default(),
+        })
+    }
+
+    async fn put_opts(
+        &self,
+        _location: &Path,
+        _payload: PutPayload,
+        _opts: PutOptions,
+    ) -> object_store::Result<PutResult> {
+        Err(object_store::Error::NotImplemented)
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        _location: &Path,
+        _opts: PutMultipartOpts,
+    ) -> object_store::Result<Box<dyn MultipartUpload>> {
+        Err(object_store::Error::NotImplemented)
+    }
+
+    async fn delete(&self, _location: &Path) -> object_store::Result<()> {
+        Err(object_store::Error::NotImplemented)
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, object_store::Result<ObjectMeta>> {
+        let prefix = prefix.cloned().unwrap_or_default();
+        let meta = self.meta.clone();
+        let metas = meta.location.prefix_matches(&prefix).then_some(Ok(meta));
+        futures::stream::iter(metas).boxed()
+    }
+
+    async fn list_with_delimiter(
+        &self,
+        _prefix: Option<&Path>,
+    ) -> object_store::Result<ListResult> {
+        Err(object_store::Error::NotImplemented)
+    }
+
+    async fn copy(&self, _from: &Path, _to: &Path) -> object_store::Result<()> {
+        Err(object_store::Error::NotImplemented)
+    }
+
+    async fn copy_if_not_exists(&self, _from: &Path, _to: &Path) -> object_store::Result<()> {
+        Err(object_store::Error::NotImplemented)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recorder::RequestRecorder;
+    use object_store::memory::InMemory;
+
+    #[tokio::test]
+    async fn detects_replaced_object() {
+        let store = Arc::new(InMemory::new());
+        let path = Path::from("test.tif");
+        store
+            .put(&path, PutPayload::from_static(b"first"))
+            .await
+            .unwrap();
+
+        let meta = store.head(&path).await.unwrap();
+        let pinned = PinnedStore::new(store.clone(), meta);
+        assert_eq!(
+            pinned.get_range(&path, 0..5).await.unwrap().as_ref(),
+            b"first"
+        );
+
+        store
+            .put(&path, PutPayload::from_static(b"second"))
+            .await
+            .unwrap();
+        let err = pinned.get_range(&path, 0..5).await.unwrap_err();
+        assert!(matches!(err, object_store::Error::Precondition { .. }));
+    }
+
+    #[test]
+    fn evicts_oldest_headers() {
+        let meta = |name: &str, e_tag: Option<&str>| ObjectMeta {
+            location: Path::from(name),
+            last_modified: Default::default(),
+            size: 8,
+            e_tag: e_tag.map(str::to_string),
+            version: None,
+        };
+        let header = Bytes::from_static(b"II*\0\x08\0\0\0");
+        let cache = HeaderCache::new(2);
+        cache.insert("memory", meta("a.tif", Some("1")), header.clone());
+        cache.insert("memory", meta("b.tif", Some("1")), header.clone());
+        // Headers can't be revalidated without an ETag
+        cache.insert("memory", meta("c.tif", None), header.clone());
+        assert_eq!(cache.len(), 2);
+
+        cache.insert("memory", meta("d.tif", Some("1")), header.clone());
+        assert!(cache.get("memory", &Path::from("a.tif")).is_none());
+        assert!(cache.get("memory", &Path::from("d.tif")).is_some());
+        assert!(cache.get("other", &Path::from("d.tif")).is_none());
+
+        // Reading a header keeps it over headers inserted after it
+        assert!(cache.get("memory", &Path::from("b.tif")).is_some());
+        cache.insert("memory", meta("e.tif", Some("1")), header.clone());
+        assert!(cache.get("memory", &Path::from("b.tif")).is_some());
+        assert!(cache.get("memory", &Path::from("d.tif")).is_none());
+    }
+
+    #[tokio::test]
+    async fn serves_cached_blocks() {
+        let store = Arc::new(InMemory::new());
+        let cache = Arc::new(InMemory::new());
+        let path = Path::from("dir/test.tif");
+        store
+            .put(&path, PutPayload::from_static(b"hello world"))
+            .await
+            .unwrap();
+
+        let meta = store.head(&path).await.unwrap();
+        let caching = CachingStore::new(store.clone(), cache, "memory".to_string(), meta, 4);
+        assert_eq!(
+            caching.get_range(&path, 5..11).await.unwrap().as_ref(),
+            b" world"
+        );
+        assert_eq!(
+            caching.get_range(&path, 0..2).await.unwrap().as_ref(),
+            b"he"
+        );
+
+        // Later reads covered by cached blocks are served without touching the inner store,
+        // however they're split
+        store.delete(&path).await.unwrap();
+        assert_eq!(
+            caching.get_range(&path, 1..10).await.unwrap().as_ref(),
+            b"ello worl"
+        );
+        assert_eq!(caching.get_range(&path, 8..9).await.unwrap().as_ref(), b"r");
+    }
+
+    #[tokio::test]
+    async fn fetches_blocks_once() {
+        use crate::testing::MockStore;
+
+        /// A store whose reads wait for other tasks before being made, like network requests
+        #[derive(Debug)]
+        struct Yielding {
+            inner: Arc<MockStore>,
+        }
+        impl Display for Yielding {
+            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                write!(f, "Yielding")
+            }
+        }
+        impl_object_store!(Yielding, {
+            async fn get_opts(
+                &self,
+                location: &Path,
+                options: GetOptions,
+            ) -> object_store::Result<GetResult> {
+                tokio::task::yield_now().await;
+                self.inner.get_opts(location, options).await
+            }
+        });
+
+        let mock = Arc::new(MockStore::new());
+        let store = Arc::new(Yielding {
+            inner: mock.clone(),
+        });
+        let cache = Arc::new(InMemory::new());
+        let path = Path::from("test.tif");
+        store
+            .put(&path, PutPayload::from_static(b"hello world"))
+            .await
+            .unwrap();
+        let meta = store.head(&path).await.unwrap();
+        mock.clear();
+
+        // Concurrent reads of the same block wait for the first one to fetch it
+        let caching = CachingStore::new(store.clone(), cache, "memory".to_string(), meta, 8);
+        let other = caching.with_inner(store.clone());
+        let (first, second) =
+            futures::join!(caching.get_range(&path, 0..2), other.get_range(&path, 4..7),);
+        assert_eq!(first.unwrap().as_ref(), b"he");
+        assert_eq!(second.unwrap().as_ref(), b"o w");
+        mock.assert_request_count(1);
+        assert!(caching.fetching.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn refreshes_expired_credentials() {
+        use futures::FutureExt;
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+        /// A store whose credentials expire, failing every read after that
+        #[derive(Debug)]
+        struct Expiring {
+            inner: InMemory,
+            expired: AtomicBool,
+        }
+        impl Display for Expiring {
+            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                write!(f, "Expiring")
+            }
+        }
+        impl_object_store!(Expiring, {
+            async fn get_opts(
+                &self,
+                location: &Path,
+                options: GetOptions,
+            ) -> object_store::Result<GetResult> {
+                if self.expired.load(Ordering::SeqCst) {
+                    return Err(object_store::Error::PermissionDenied {
+                        path: location.to_string(),
+                        source: "the token expired".into(),
+                    });
+                }
+                self.inner.get_opts(location, options).await
+            }
+        });
+
+        let store = Arc::new(Expiring {
+            inner: InMemory::new(),
+            expired: AtomicBool::new(false),
+        });
+        let path = Path::from("test.tif");
+        let payload = PutPayload::from_static(b"hello world");
+        store.put(&path, payload.clone()).await.unwrap();
+        let renewed = Arc::new(InMemory::new());
+        let renewed_path = Path::from("renewed.tif");
+        renewed.put(&renewed_path, payload).await.unwrap();
+
+        let refreshes = Arc::new(AtomicUsize::new(0));
+        let refresher = {
+            let refreshes = refreshes.clone();
+            let (renewed, renewed_path) = (renewed.clone(), renewed_path.clone());
+            StoreRefresher::new(move || {
+                refreshes.fetch_add(1, Ordering::SeqCst);
+                let store: Arc<dyn ObjectStore> = renewed.clone();
+                let path = renewed_path.clone();
+                async move { Ok((store, path)) }.boxed()
+            })
+        };
+        let refreshing = RefreshingStore::new(store.clone(), path.clone(), refresher);
+        assert_eq!(
+            refreshing.get_range(&path, 0..5).await.unwrap().as_ref(),
+            b"hello"
+        );
+        assert_eq!(refreshes.load(Ordering::SeqCst), 0);
+
+        // Once the credentials expire, the store is refreshed once and reads are retried
+        store.expired.store(true, Ordering::SeqCst);
+        assert_eq!(
+            refreshing.get_range(&path, 6..11).await.unwrap().as_ref(),
+            b"world"
+        );
+        let result = refreshing.get(&path).await.unwrap();
+        assert_eq!(result.meta.location, path);
+        assert_eq!(refreshes.load(Ordering::SeqCst), 1);
+
+        // Other calls also use the refreshed store and path
+        let listed = refreshing.list(None).map(|meta| meta.unwrap().location);
+        assert_eq!(listed.collect::<Vec<_>>().await, vec![renewed_path.clone()]);
+        refreshing.delete(&path).await.unwrap();
+        assert!(renewed.head(&renewed_path).await.is_err());
+        assert!(store.inner.head(&path).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn records_requests() {
+        let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let path = Path::from("test.tif");
+        store
+            .put(&path, PutPayload::from_static(b"hello world"))
+            .await
+            .unwrap();
+
+        let recorder = RequestRecorder::new();
+        let hooks = vec![Arc::new(recorder.clone()) as Arc<dyn RequestHooks>];
+        let recording = recording_store(&store, hooks, RequestPurpose::Tile);
+        recording.get_range(&path, 6..11).await.unwrap();
+        recording.get(&path).await.unwrap();
+        recording.get_range(&path, 20..30).await.unwrap_err();
+
+        let requests = recorder.take();
+        let ranges: Vec<_> = requests.iter().map(|r| (r.offset, r.length)).collect();
+        assert_eq!(ranges, vec![(6, 5), (0, 11), (20, 10)]);
+        assert!(requests.iter().all(|r| r.purpose == RequestPurpose::Tile));
+        assert_eq!(recorder.total_bytes(), 0);
+
+        #[derive(Debug, Default)]
+        struct Starts(std::sync::Mutex<Vec<Option<Range<u64>>>>);
+        impl RequestHooks for Starts {
+            fn on_request_start(&self, _purpose: RequestPurpose, range: Option<Range<u64>>) {
+                self.0.lock().unwrap().push(range);
+            }
+        }
+        let starts = Arc::new(Starts::default());
+        let recording = recording_store(&store, vec![starts.clone()], RequestPurpose::Header);
+        recording.get(&path).await.unwrap();
+        recording.get_range(&path, 0..5).await.unwrap();
+        assert_eq!(*starts.0.lock().unwrap(), vec![None, Some(0..5)]);
+    }
+}
@@ -0,0 +1,105 @@
+//! Terrain operators for elevation models, computed with Horn's method over each pixel's 3x3
+//! neighborhood.
+//!
+//! Elevations and the units of the geotransform must match, so these are only meaningful for
+//! projected CRSs. Pixels on the edge of the array reuse their own values for missing neighbors;
+//! read a window padded by one pixel on each side to avoid edge effects.
+use ndarray::{Array2, ArrayView2};
+
+use crate::affine::AffineTransform;
+
+/// The east and north gradients of the elevation at every pixel
+fn gradients(elevation: ArrayView2<f64>, transform: &AffineTransform) -> Array2<(f64, f64)> {
+    let (height, width) = elevation.dim();
+    let x_res = transform.a().hypot(transform.d());
+    let y_res = transform.b().hypot(transform.e());
+    // Columns usually increase to the east and rows to the south
+    let east_sign = transform.a().signum();
+    let north_sign = -transform.e().signum();
+
+    let at = |row: isize, col: isize| {
+        let row = row.clamp(0, height as isize - 1) as usize;
+        let col = col.clamp(0, width as isize - 1) as usize;
+        elevation[[row, col]]
+    };
+    Array2::from_shape_fn((height, width), |(row, col)| {
+        let (r, c) = (row as isize, col as isize);
+        let (a, b, c_, d, f, g, h, i) = (
+            at(r - 1, c - 1),
+            at(r - 1, c),
+            at(r - 1, c + 1),
+            at(r, c - 1),
+            at(r, c + 1),
+            at(r + 1, c - 1),
+            at(r + 1, c),
+            at(r + 1, c + 1),
+        );
+        let dz_dcol = ((c_ + 2.0 * f + i) - (a + 2.0 * d + g)) / (8.0 * x_res);
+        let dz_drow = ((g + 2.0 * h + i) - (a + 2.0 * b + c_)) / (8.0 * y_res);
+        (east_sign * dz_dcol, -north_sign * dz_drow)
+    })
+}
+
+/// The slope of every pixel, in degrees from horizontal
+pub fn slope(elevation: ArrayView2<f64>, transform: &AffineTransform) -> Array2<f64> {
+    gradients(elevation, transform).mapv(|(east, north)| east.hypot(north).atan().to_degrees())
+}
+
+/// The direction every pixel faces, in degrees clockwise from north. Flat pixels are `NaN`.
+pub fn aspect(elevation: ArrayView2<f64>, transform: &AffineTransform) -> Array2<f64> {
+    gradients(elevation, transform).mapv(|(east, north)| {
+        if east == 0.0 && north == 0.0 {
+            f64::NAN
+        } else {
+            // Pixels face downhill, against the gradient
+            (-east).atan2(-north).to_degrees().rem_euclid(360.0)
+        }
+    })
+}
+
+/// Shade the terrain as lit by a sun at `azimuth` degrees clockwise from north and `altitude`
+/// degrees above the horizon, from 0 (unlit) to 255 (facing the sun).
+///
+/// Use an azimuth of 315 and an altitude of 45 for the conventional look.
+pub fn hillshade(
+    elevation: ArrayView2<f64>,
+    transform: &AffineTransform,
+    azimuth: f64,
+    altitude: f64,
+) -> Array2<u8> {
+    let zenith = (90.0 - altitude).to_radians();
+    let azimuth = azimuth.to_radians();
+    gradients(elevation, transform).mapv(|(east, north)| {
+        let slope = east.hypot(north).atan();
+        let aspect = (-east).atan2(-north);
+        let shade =
+            zenith.cos() * slope.cos() + zenith.sin() * slope.sin() * (azimuth - aspect).cos();
+        // NaN elevations saturate to 0
+        (shade.max(0.0) * 255.0).round() as u8
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn plane_rising_to_the_east() {
+        let transform = AffineTransform::new(1.0, 0.0, 0.0, 0.0, -1.0, 0.0);
+        let elevation = Array2::from_shape_fn((3, 3), |(_, col)| col as f64);
+
+        let slope = slope(elevation.view(), &transform);
+        assert!((slope[[1, 1]] - 45.0).abs() < 1e-9);
+        let aspect = aspect(elevation.view(), &transform);
+        assert!((aspect[[1, 1]] - 270.0).abs() < 1e-9);
+
+        assert_eq!(
+            hillshade(elevation.view(), &transform, 270.0, 45.0)[[1, 1]],
+            255
+        );
+        assert_eq!(
+            hillshade(elevation.view(), &transform, 90.0, 45.0)[[1, 1]],
+            0
+        );
+    }
+}
@@ -0,0 +1,926 @@
+//! Utilities for testing code that reads COGs without shipping fixture files: a builder of small
+//! synthetic COGs and an in-memory store that records the requests made to it.
+//!
+//! ```ignore
+//! let (reader, store) = CogBuilder::default().open().await?;
+//! reader.get_tile(0, 0, 0).await?;
+//! store.assert_request_count(1);
+//! ```
+
+use std::io::Write;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use flate2::write::ZlibEncoder;
+use futures::stream::BoxStream;
+use ndarray::{Array2, Array3};
+use object_store::memory::InMemory;
+use object_store::path::Path;
+use object_store::{
+    GetOptions, GetRange, GetResult, ListResult, MultipartUpload, ObjectMeta, ObjectStore,
+    PutMultipartOpts, PutOptions, PutPayload, PutResult,
+};
+use tiff::tags::{CompressionMethod, PlanarConfiguration, Predictor, Tag};
+
+use crate::array::RasterArray;
+use crate::cog::COGReader;
+use crate::enums::DataType;
+use crate::error::{AiocogeoError, Result};
+use crate::options::OpenOptions;
+use crate::store::impl_object_store;
+
+/// The path of the file written by [`CogBuilder::open`]
+pub const TEST_PATH: &str = "test.tif";
+
+/// Builds small tiled GeoTIFFs in memory, as GDAL would write a COG: the full resolution image
+/// and its mask, followed by each overview and its mask, then the tiles.
+///
+/// Pixels follow a fixed pattern that spans the range of the data type and differs between
+/// bands, so that reads can be compared against [`CogBuilder::expected`].
+#[derive(Debug, Clone)]
+pub struct CogBuilder {
+    /// The width of the full resolution image, in pixels
+    pub width: u32,
+    /// The height of the full resolution image, in pixels
+    pub height: u32,
+    /// The width of the tiles, in pixels, a multiple of 16
+    pub tile_width: u32,
+    /// The height of the tiles, in pixels, a multiple of 16
+    pub tile_height: u32,
+    /// The compression of the tiles: `None`, `Deflate`, `LZW` or `PackBits`
+    pub compression: CompressionMethod,
+    /// The type of the samples. Complex types are not supported.
+    pub data_type: DataType,
+    /// The number of bands
+    pub bands: u16,
+    /// Whether bands are interleaved in the same tiles (`Chunky`) or stored in separate tiles
+    /// (`Planar`)
+    pub planar_configuration: PlanarConfiguration,
+    /// The predictor applied to tiles before compression
+    pub predictor: Predictor,
+    /// Add a 1-bit transparency mask to the image and each overview, as GDAL writes internal
+    /// nodata masks
+    pub mask: bool,
+    /// Write a big endian (`MM`) file rather than a little endian (`II`) one
+    pub big_endian: bool,
+    /// The decimation factors of the overviews, e.g. `[2, 4]`
+    pub overviews: Vec<u32>,
+    /// The `(x, y)` coordinates of the top left corner and the size of a pixel, if the image is
+    /// georeferenced
+    pub origin: Option<(f64, f64, f64)>,
+    /// The EPSG code of the CRS, written as a GeoKey if the image is georeferenced
+    pub epsg: Option<u16>,
+    /// The nodata value, written as GDAL's `GDAL_NODATA` tag
+    pub nodata: Option<f64>,
+    /// The description of each band, written as `DESCRIPTION` items of GDAL's `GDAL_METADATA`
+    /// tag
+    pub band_descriptions: Vec<String>,
+    /// The indices in `TileOffsets` of full resolution tiles that aren't stored, written with an
+    /// offset and byte count of 0 as GDAL writes tiles that are entirely nodata
+    pub sparse_tiles: Vec<usize>,
+    /// Write the IFDs after the tiles at the end of the file, as streaming writers do, rather
+    /// than before them
+    pub trailing_ifds: bool,
+    /// Write each mask tile right after the image tile it masks, with GDAL's 4 byte block leader
+    /// and trailer around each tile, as GDAL's `MASK_INTERLEAVED_WITH_IMAGERY` COG layout
+    pub interleave_mask: bool,
+}
+
+impl Default for CogBuilder {
+    fn default() -> Self {
+        Self {
+            width: 64,
+            height: 48,
+            tile_width: 32,
+            tile_height: 32,
+            compression: CompressionMethod::None,
+            data_type: DataType::Uint8,
+            bands: 1,
+            planar_configuration: PlanarConfiguration::Chunky,
+            predictor: Predictor::None,
+            mask: false,
+            big_endian: false,
+            overviews: vec![],
+            origin: None,
+            epsg: None,
+            nodata: None,
+            band_descriptions: vec![],
+            sparse_tiles: vec![],
+            trailing_ifds: false,
+            interleave_mask: false,
+        }
+    }
+}
+
+/// The value of a tag, by TIFF type
+enum TagValue {
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Double(Vec<f64>),
+    Ascii(String),
+}
+
+/// Return the `size` low-order bytes of `value` in file byte order
+fn to_bytes(value: u64, size: usize, big_endian: bool) -> Vec<u8> {
+    if big_endian {
+        value.to_be_bytes()[8 - size..].to_vec()
+    } else {
+        value.to_le_bytes()[..size].to_vec()
+    }
+}
+
+impl TagValue {
+    /// The TIFF type code, value count and bytes of the value
+    fn encode(&self, big_endian: bool) -> (u16, u32, Vec<u8>) {
+        let encode = |values: Vec<u64>, size| -> Vec<u8> {
+            values
+                .into_iter()
+                .flat_map(|value| to_bytes(value, size, big_endian))
+                .collect()
+        };
+        match self {
+            Self::Short(v) => (
+                3,
+                v.len() as u32,
+                encode(v.iter().map(|&x| x as u64).collect(), 2),
+            ),
+            Self::Long(v) => (
+                4,
+                v.len() as u32,
+                encode(v.iter().map(|&x| x as u64).collect(), 4),
+            ),
+            Self::Double(v) => (
+                12,
+                v.len() as u32,
+                encode(v.iter().map(|x| x.to_bits()).collect(), 8),
+            ),
+            Self::Ascii(v) => {
+                let mut bytes = v.as_bytes().to_vec();
+                bytes.push(0);
+                (2, bytes.len() as u32, bytes)
+            }
+        }
+    }
+}
+
+/// An IFD to write, with its tiles. `TileOffsets` and `TileByteCounts` are added when writing.
+struct IfdToWrite {
+    tags: Vec<(Tag, TagValue)>,
+    tiles: Vec<Vec<u8>>,
+    mask: bool,
+}
+
+impl CogBuilder {
+    /// Return the `(width, height)` of overview level `z`, where level 0 is the full resolution
+    /// image
+    fn level_size(&self, z: usize) -> (u32, u32) {
+        let factor = self.level_factor(z);
+        (self.width.div_ceil(factor), self.height.div_ceil(factor))
+    }
+
+    fn level_factor(&self, z: usize) -> u32 {
+        match z {
+            0 => 1,
+            z => self.overviews[z - 1],
+        }
+    }
+
+    /// Return the bits of the samples of overview level `z`, with shape `(bands, height,
+    /// width)`. Overviews are decimated from the full resolution image by nearest neighbor.
+    fn sample_bits(&self, z: usize) -> Array3<u64> {
+        let factor = self.level_factor(z) as usize;
+        let (width, height) = self.level_size(z);
+        let shape = (self.bands as usize, height as usize, width as usize);
+        Array3::from_shape_fn(shape, |(band, row, col)| {
+            let (x, y) = (col * factor, row * factor);
+            let value = ((x + y * self.width as usize + 31 * band) % 251) as i64;
+            // Spread the pattern over the range of the type, with negative values if signed
+            let centered = value - 125;
+            match self.data_type {
+                DataType::Uint8 => value as u64,
+                DataType::Int8 => centered as i8 as u8 as u64,
+                DataType::Uint16 => (value * 0x101) as u64,
+                DataType::Int16 => (centered * 0x101) as i16 as u16 as u64,
+                DataType::Uint32 => (value * 0x0101_0101) as u64,
+                DataType::Int32 => (centered * 0x0101_0101) as i32 as u32 as u64,
+                DataType::Uint64 => value as u64 * 0x0101_0101_0101_0101,
+                DataType::Int64 => (centered * 0x0101_0101_0101_0101) as u64,
+                DataType::Float32 => (centered as f32 + 0.25).to_bits() as u64,
+                DataType::Float64 => (centered as f64 + 0.25).to_bits(),
+                _ => 0,
+            }
+        })
+    }
+
+    /// Return the pixels of overview level `z`, with shape `(bands, height, width)`, or an error
+    /// for complex data types, which aren't supported
+    pub fn expected(&self, z: usize) -> Result<RasterArray> {
+        let bits = self.sample_bits(z);
+        Ok(match self.data_type {
+            DataType::Uint8 => RasterArray::Uint8(bits.mapv(|v| v as u8)),
+            DataType::Int8 => RasterArray::Int8(bits.mapv(|v| v as u8 as i8)),
+            DataType::Uint16 => RasterArray::Uint16(bits.mapv(|v| v as u16)),
+            DataType::Int16 => RasterArray::Int16(bits.mapv(|v| v as u16 as i16)),
+            DataType::Uint32 => RasterArray::Uint32(bits.mapv(|v| v as u32)),
+            DataType::Int32 => RasterArray::Int32(bits.mapv(|v| v as u32 as i32)),
+            DataType::Uint64 => RasterArray::Uint64(bits),
+            DataType::Int64 => RasterArray::Int64(bits.mapv(|v| v as i64)),
+            DataType::Float32 => RasterArray::Float32(bits.mapv(|v| f32::from_bits(v as u32))),
+            DataType::Float64 => RasterArray::Float64(bits.mapv(f64::from_bits)),
+            DataType::CInt16 | DataType::CInt32 | DataType::CFloat32 | DataType::CFloat64 => {
+                return Err(self.unsupported_data_type())
+            }
+        })
+    }
+
+    fn unsupported_data_type(&self) -> AiocogeoError {
+        AiocogeoError::General(format!("cannot build {:?} images", self.data_type))
+    }
+
+    /// Return the mask of overview level `z`, with shape `(height, width)`, where `true` marks
+    /// valid pixels
+    pub fn expected_mask(&self, z: usize) -> Array2<bool> {
+        let factor = self.level_factor(z) as usize;
+        let (width, height) = self.level_size(z);
+        Array2::from_shape_fn((height as usize, width as usize), |(row, col)| {
+            !(col * factor + 2 * row * factor).is_multiple_of(7)
+        })
+    }
+
+    /// Return the `(x, y)` indices of the tiles of overview level `z`, in row-major order
+    fn tile_indices(&self, z: usize) -> Vec<(usize, usize)> {
+        let (width, height) = self.level_size(z);
+        let x_count = width.div_ceil(self.tile_width) as usize;
+        let y_count = height.div_ceil(self.tile_height) as usize;
+        (0..y_count)
+            .flat_map(|y| (0..x_count).map(move |x| (x, y)))
+            .collect()
+    }
+
+    /// Return the encoded, uncompressed tiles of overview level `z`, padded with zeros past the
+    /// edges of the image. Band-interleaved images store the tiles of each band in turn.
+    fn tiles(&self, z: usize) -> Vec<Vec<u8>> {
+        let bits = self.sample_bits(z);
+        let (tw, th) = (self.tile_width as usize, self.tile_height as usize);
+        let sample = |band, row, col| bits.get((band, row, col)).copied().unwrap_or(0);
+        let mut tiles = vec![];
+        match self.planar_configuration {
+            PlanarConfiguration::Chunky => {
+                for (tx, ty) in self.tile_indices(z) {
+                    let mut samples = vec![];
+                    for row in ty * th..(ty + 1) * th {
+                        for col in tx * tw..(tx + 1) * tw {
+                            samples.extend((0..self.bands as usize).map(|b| sample(b, row, col)));
+                        }
+                    }
+                    tiles.push(self.encode_samples(samples, tw * self.bands as usize));
+                }
+            }
+            _ => {
+                for band in 0..self.bands as usize {
+                    for (tx, ty) in self.tile_indices(z) {
+                        let mut samples = vec![];
+                        for row in ty * th..(ty + 1) * th {
+                            samples.extend((tx * tw..(tx + 1) * tw).map(|c| sample(band, row, c)));
+                        }
+                        tiles.push(self.encode_samples(samples, tw));
+                    }
+                }
+            }
+        }
+        tiles
+    }
+
+    /// Apply the predictor to the sample bits of a tile and write them in file byte order
+    fn encode_samples(&self, mut samples: Vec<u64>, samples_per_row: usize) -> Vec<u8> {
+        let size = self.data_type.size();
+        let stride = match self.planar_configuration {
+            PlanarConfiguration::Chunky => self.bands as usize,
+            _ => 1,
+        };
+        match self.predictor {
+            // Differences wrap around at the sample size once truncated to it
+            Predictor::Horizontal => {
+                for row in samples.chunks_exact_mut(samples_per_row) {
+                    for i in (stride..samples_per_row).rev() {
+                        row[i] = row[i].wrapping_sub(row[i - stride]);
+                    }
+                }
+            }
+            // Split the big endian bytes of each row into planes, then difference the bytes
+            Predictor::FloatingPoint => {
+                let mut out = vec![];
+                for row in samples.chunks_exact(samples_per_row) {
+                    let mut planes = vec![0u8; samples_per_row * size];
+                    for (i, &sample) in row.iter().enumerate() {
+                        for (byte, value) in to_bytes(sample, size, true).into_iter().enumerate() {
+                            planes[byte * samples_per_row + i] = value;
+                        }
+                    }
+                    for i in (stride..planes.len()).rev() {
+                        planes[i] = planes[i].wrapping_sub(planes[i - stride]);
+                    }
+                    out.extend(planes);
+                }
+                return out;
+            }
+            _ => {}
+        }
+        samples
+            .into_iter()
+            .flat_map(|sample| to_bytes(sample, size, self.big_endian))
+            .collect()
+    }
+
+    /// Return the uncompressed tiles of the mask of overview level `z`, with 1 bit per pixel
+    /// and rows padded to whole bytes
+    fn mask_tiles(&self, z: usize) -> Vec<Vec<u8>> {
+        let mask = self.expected_mask(z);
+        let (tw, th) = (self.tile_width as usize, self.tile_height as usize);
+        self.tile_indices(z)
+            .into_iter()
+            .map(|(tx, ty)| {
+                let mut tile = vec![0u8; tw.div_ceil(8) * th];
+                for row in 0..th {
+                    for col in 0..tw {
+                        let valid = mask.get((ty * th + row, tx * tw + col)) == Some(&true);
+                        if valid {
+                            tile[row * tw.div_ceil(8) + col / 8] |= 0x80 >> (col % 8);
+                        }
+                    }
+                }
+                tile
+            })
+            .collect()
+    }
+
+    fn compress(&self, tile: Vec<u8>) -> Result<Vec<u8>> {
+        match self.compression {
+            CompressionMethod::None => Ok(tile),
+            CompressionMethod::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&tile)?;
+                Ok(encoder.finish()?)
+            }
+            CompressionMethod::LZW => {
+                weezl::encode::Encoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8)
+                    .encode(&tile)
+                    .map_err(|err| AiocogeoError::General(err.to_string()))
+            }
+            // Literal runs of up to 128 bytes, each preceded by its length minus one
+            CompressionMethod::PackBits => Ok(tile
+                .chunks(128)
+                .flat_map(|run| std::iter::once(run.len() as u8 - 1).chain(run.iter().copied()))
+                .collect()),
+            method => Err(AiocogeoError::UnsupportedCompression(method.to_u16())),
+        }
+    }
+
+    /// Return the tags of overview level `z`, or of its mask, except the tile offsets and byte
+    /// counts
+    fn tags(&self, z: usize, mask: bool) -> Vec<(Tag, TagValue)> {
+        let (width, height) = self.level_size(z);
+        // Bit 0 marks reduced resolution images and bit 2 masks
+        let subfile_type = (z > 0) as u32 | if mask { 4 } else { 0 };
+        let (bands, bits, photometric, sample_format) = if mask {
+            (1, 1, 4, 1)
+        } else {
+            let sample_format = match self.data_type {
+                DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 => 2,
+                DataType::Float32 | DataType::Float64 => 3,
+                _ => 1,
+            };
+            let bits = self.data_type.size() as u16 * 8;
+            (self.bands, bits, 1, sample_format)
+        };
+        let planar_configuration = if mask {
+            PlanarConfiguration::Chunky
+        } else {
+            self.planar_configuration
+        };
+        let mut tags = vec![
+            (Tag::NewSubfileType, TagValue::Long(vec![subfile_type])),
+            (Tag::ImageWidth, TagValue::Long(vec![width])),
+            (Tag::ImageLength, TagValue::Long(vec![height])),
+            (
+                Tag::BitsPerSample,
+                TagValue::Short(vec![bits; bands as usize]),
+            ),
+            (
+                Tag::Compression,
+                TagValue::Short(vec![self.compression.to_u16()]),
+            ),
+            (
+                Tag::PhotometricInterpretation,
+                TagValue::Short(vec![photometric]),
+            ),
+            (Tag::SamplesPerPixel, TagValue::Short(vec![bands])),
+            (
+                Tag::PlanarConfiguration,
+                TagValue::Short(vec![planar_configuration.to_u16()]),
+            ),
+            (
+                Tag::TileWidth,
+                TagValue::Short(vec![self.tile_width as u16]),
+            ),
+            (
+                Tag::TileLength,
+                TagValue::Short(vec![self.tile_height as u16]),
+            ),
+            (
+                Tag::SampleFormat,
+                TagValue::Short(vec![sample_format; bands as usize]),
+            ),
+        ];
+        if let (false, Some(nodata)) = (mask, self.nodata) {
+            tags.push((Tag::Unknown(42113), TagValue::Ascii(nodata.to_string())));
+        }
+        if !mask && !self.band_descriptions.is_empty() {
+            let items: String = self
+                .band_descriptions
+                .iter()
+                .enumerate()
+                .map(|(band, description)| {
+                    format!(
+                        r#"<Item name="DESCRIPTION" sample="{band}" role="description">{description}</Item>"#
+                    )
+                })
+                .collect();
+            tags.push((
+                Tag::Unknown(42112),
+                TagValue::Ascii(format!("<GDALMetadata>{items}</GDALMetadata>")),
+            ));
+        }
+        if !mask && self.predictor != Predictor::None {
+            tags.push((
+                Tag::Predictor,
+                TagValue::Short(vec![self.predictor.to_u16()]),
+            ));
+        }
+        // Only the full resolution image is georeferenced, as GDAL writes it
+        if let (0, false, Some((x, y, res))) = (z, mask, self.origin) {
+            tags.push((
+                Tag::ModelPixelScaleTag,
+                TagValue::Double(vec![res, res, 0.0]),
+            ));
+            tags.push((
+                Tag::ModelTiepointTag,
+                TagValue::Double(vec![0.0, 0.0, 0.0, x, y, 0.0]),
+            ));
+            if let Some(epsg) = self.epsg {
+                // Geographic CRSs have their own GeoKey
+                let (model_type, key) = if epsg == 4326 { (2, 2048) } else { (1, 3072) };
+                tags.push((
+                    Tag::GeoKeyDirectoryTag,
+                    TagValue::Short(vec![1, 1, 0, 2, 1024, 0, 1, model_type, key, 0, 1, epsg]),
+                ));
+            }
+        }
+        tags
+    }
+
+    /// Build the file
+    pub fn build(&self) -> Result<Bytes> {
+        if self.data_type.is_complex() {
+            return Err(self.unsupported_data_type());
+        }
+        let mut ifds = vec![];
+        for z in 0..=self.overviews.len() {
+            let tiles = self.tiles(z);
+            let masks = if self.mask {
+                vec![(true, self.mask_tiles(z))]
+            } else {
+                vec![]
+            };
+            for (mask, tiles) in std::iter::once((false, tiles)).chain(masks) {
+                let mut tiles: Vec<_> = tiles
+                    .into_iter()
+                    .map(|tile| self.compress(tile))
+                    .collect::<Result<_>>()?;
+                if z == 0 && !mask {
+                    for &idx in &self.sparse_tiles {
+                        tiles[idx].clear();
+                    }
+                }
+                ifds.push(IfdToWrite {
+                    tags: self.tags(z, mask),
+                    tiles,
+                    mask,
+                });
+            }
+        }
+        let layout = TiffLayout {
+            big_endian: self.big_endian,
+            trailing_ifds: self.trailing_ifds,
+            interleave_mask: self.interleave_mask,
+        };
+        Ok(write_tiff(ifds, layout).into())
+    }
+
+    /// Build the file into a new [`MockStore`] at [`TEST_PATH`] and open it with the default
+    /// options, clearing the requests made while opening
+    pub async fn open(&self) -> Result<(COGReader, Arc<MockStore>)> {
+        self.open_with_options(&OpenOptions::default()).await
+    }
+
+    /// Build the file into a new [`MockStore`] and open it, as in [`CogBuilder::open`], with
+    /// custom options
+    pub async fn open_with_options(
+        &self,
+        options: &OpenOptions,
+    ) -> Result<(COGReader, Arc<MockStore>)> {
+        let store = Arc::new(MockStore::new());
+        let path = Path::from(TEST_PATH);
+        store.put(&path, self.build()?.into()).await?;
+        let reader = COGReader::try_open_with_options(store.clone(), path, options).await?;
+        store.clear();
+        Ok((reader, store))
+    }
+}
+
+/// Where [`write_tiff`] places IFDs and tiles
+struct TiffLayout {
+    big_endian: bool,
+    trailing_ifds: bool,
+    interleave_mask: bool,
+}
+
+/// Write a TIFF of the given IFDs, followed by their tiles
+fn write_tiff(ifds: Vec<IfdToWrite>, layout: TiffLayout) -> Vec<u8> {
+    let TiffLayout {
+        big_endian,
+        trailing_ifds,
+        interleave_mask,
+    } = layout;
+    // The `(ifd, tile)` indices of the tiles in file order, with each mask tile following the
+    // image tile it masks if interleaved, that of the last band for planar images
+    let mut order = vec![];
+    for (i, ifd) in ifds.iter().enumerate() {
+        let masks = match ifds.get(i + 1) {
+            Some(next) if interleave_mask && next.mask => next.tiles.len(),
+            _ => 0,
+        };
+        if interleave_mask && ifd.mask {
+            continue;
+        }
+        let last_band = ifd.tiles.len() - masks;
+        for tile in 0..ifd.tiles.len() {
+            order.push((i, tile));
+            if masks > 0 && tile >= last_band {
+                order.push((i + 1, tile - last_band));
+            }
+        }
+    }
+    // The tile data, and the offset of each stored tile within it
+    let mut data = vec![];
+    let mut data_offsets: Vec<Vec<Option<usize>>> =
+        ifds.iter().map(|ifd| vec![None; ifd.tiles.len()]).collect();
+    for (i, tile) in order {
+        // Empty tiles are sparse
+        let bytes = &ifds[i].tiles[tile];
+        if bytes.is_empty() {
+            continue;
+        }
+        if interleave_mask {
+            data.extend(to_bytes(bytes.len() as u64, 4, big_endian));
+        }
+        data_offsets[i][tile] = Some(data.len());
+        data.extend(bytes);
+        if interleave_mask {
+            data.extend(&bytes[bytes.len().saturating_sub(4)..]);
+        }
+    }
+
+    let mut ifds: Vec<_> = ifds
+        .into_iter()
+        .map(|ifd| {
+            let mut tags = ifd.tags;
+            let count = ifd.tiles.len();
+            tags.push((Tag::TileOffsets, TagValue::Long(vec![0; count])));
+            let byte_counts = ifd.tiles.iter().map(|tile| tile.len() as u32).collect();
+            tags.push((Tag::TileByteCounts, TagValue::Long(byte_counts)));
+            tags.sort_by_key(|(tag, _)| tag.to_u16());
+            tags
+        })
+        .collect();
+
+    // Each IFD is followed by the values that don't fit in its entries, so its size is known
+    // before the tile offsets are filled in
+    let ifd_size = |tags: &[(Tag, TagValue)]| {
+        let values: usize = tags
+            .iter()
+            .map(|(_, value)| value.encode(big_endian).2.len())
+            .filter(|&len| len > 4)
+            .sum();
+        2 + tags.len() * 12 + 4 + values
+    };
+    let mut offset = if trailing_ifds { 8 + data.len() } else { 8 };
+    let mut ifd_offsets = vec![];
+    for tags in &ifds {
+        ifd_offsets.push(offset);
+        offset += ifd_size(tags);
+    }
+    let data_start = if trailing_ifds { 8 } else { offset };
+    for (tags, data_offsets) in ifds.iter_mut().zip(data_offsets) {
+        let offsets = data_offsets
+            .iter()
+            .map(|offset| offset.map_or(0, |offset| (data_start + offset) as u32))
+            .collect();
+        for (tag, value) in tags.iter_mut() {
+            if *tag == Tag::TileOffsets {
+                *value = TagValue::Long(offsets);
+                break;
+            }
+        }
+    }
+
+    let short = |value: usize| to_bytes(value as u64, 2, big_endian);
+    let long = |value: usize| to_bytes(value as u64, 4, big_endian);
+    let mut out = if big_endian { b"MM" } else { b"II" }.to_vec();
+    out.extend(short(42));
+    out.extend(long(ifd_offsets[0]));
+    if trailing_ifds {
+        out.extend(&data);
+    }
+    for (i, tags) in ifds.iter().enumerate() {
+        let mut values = vec![];
+        let values_start = ifd_offsets[i] + 2 + tags.len() * 12 + 4;
+        out.extend(short(tags.len()));
+        for (tag, value) in tags {
+            let (type_code, count, mut bytes) = value.encode(big_endian);
+            out.extend(short(tag.to_u16() as usize));
+            out.extend(short(type_code as usize));
+            out.extend(long(count as usize));
+            if bytes.len() <= 4 {
+                bytes.resize(4, 0);
+                out.extend(bytes);
+            } else {
+                out.extend(long(values_start + values.len()));
+                values.extend(bytes);
+            }
+        }
+        out.extend(long(ifd_offsets.get(i + 1).copied().unwrap_or(0)));
+        out.extend(values);
+    }
+    if !trailing_ifds {
+        out.extend(data);
+    }
+    out
+}
+
+/// An in-memory [ObjectStore] that records the byte ranges read from it, to assert how many
+/// requests an operation makes
+#[derive(Debug, Default)]
+pub struct MockStore {
+    inner: InMemory,
+    requests: Mutex<Vec<Range<usize>>>,
+    head_requests: Mutex<usize>,
+    max_range_bytes: Option<usize>,
+    expired: AtomicBool,
+}
+
+impl MockStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject reads of ranges longer than `max` bytes with status 416, as some stores and CDNs
+    /// do. Rejected reads are still recorded.
+    pub fn with_max_range_bytes(self, max: usize) -> Self {
+        Self {
+            max_range_bytes: Some(max),
+            ..self
+        }
+    }
+
+    /// Fail every later read with a permission error, as stores do once their credentials
+    /// expire. Failed reads aren't recorded.
+    pub fn expire(&self) {
+        self.expired.store(true, Ordering::SeqCst);
+    }
+
+    /// Return the byte ranges read since the store was created or last cleared, in the order
+    /// they were requested
+    pub fn requests(&self) -> Vec<Range<usize>> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Return the number of reads since the store was created or last cleared
+    pub fn request_count(&self) -> usize {
+        self.requests.lock().unwrap().len()
+    }
+
+    /// Return the number of `HEAD` requests since the store was created or last cleared
+    pub fn head_request_count(&self) -> usize {
+        *self.head_requests.lock().unwrap()
+    }
+
+    /// Forget the recorded reads and `HEAD` requests
+    pub fn clear(&self) {
+        self.requests.lock().unwrap().clear();
+        *self.head_requests.lock().unwrap() = 0;
+    }
+
+    /// Panic unless exactly `expected` reads were made since the store was last cleared
+    #[track_caller]
+    pub fn assert_request_count(&self, expected: usize) {
+        let requests = self.requests();
+        assert_eq!(
+            requests.len(),
+            expected,
+            "expected {expected} requests, got {requests:?}"
+        );
+    }
+
+    /// Panic if more than `max` reads were made since the store was last cleared
+    #[track_caller]
+    pub fn assert_max_requests(&self, max: usize) {
+        let requests = self.requests();
+        assert!(
+            requests.len() <= max,
+            "expected at most {max} requests, got {requests:?}"
+        );
+    }
+}
+
+impl std::fmt::Display for MockStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MockStore")
+    }
+}
+
+impl_object_store!(MockStore, {
+    async fn get_opts(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        if self.expired.load(Ordering::SeqCst) {
+            return Err(object_store::Error::PermissionDenied {
+                path: location.to_string(),
+                source: "the credentials expired".into(),
+            });
+        }
+        if options.head {
+            *self.head_requests.lock().unwrap() += 1;
+            return self.inner.get_opts(location, options).await;
+        }
+        let result = self.inner.get_opts(location, options.clone()).await;
+        // Reads of the whole object are recorded with the range they returned
+        let range = match (&result, options.range) {
+            (_, Some(GetRange::Bounded(range))) => Some(range),
+            (Ok(result), _) => Some(result.range.clone()),
+            (Err(_), _) => None,
+        };
+        if let Some(range) = range {
+            self.requests.lock().unwrap().push(range.clone());
+            if self.max_range_bytes.is_some_and(|max| range.len() > max) {
+                return Err(object_store::Error::Generic {
+                    store: "MockStore",
+                    source: format!(
+                        "Client error with status 416 Range Not Satisfiable: range {range:?} is \
+                         too large"
+                    )
+                    .into(),
+                });
+            }
+        }
+        result
+    }
+});
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::enums::Orientation;
+    use crate::partial_reads::Window;
+    use crate::ReadOptions;
+
+    /// Check that every overview level and mask of the file built by `builder` reads back as
+    /// built
+    async fn assert_round_trip(builder: &CogBuilder) {
+        let (reader, store) = builder.open().await.unwrap();
+        assert_eq!(reader.ifds().len(), builder.overviews.len() + 1);
+        let masks = if builder.mask { reader.ifds().len() } else { 0 };
+        assert_eq!(reader.mask_ifds().len(), masks);
+        for z in 0..=builder.overviews.len() {
+            let expected = builder.expected(z).unwrap();
+            let (_, height, width) = expected.shape();
+            let window = Window::new(0, 0, width, height);
+            let read = reader
+                .read_window(window, z, &ReadOptions::default())
+                .await
+                .unwrap();
+            assert_eq!(read, expected, "level {z} of {builder:?}");
+
+            if !builder.mask {
+                continue;
+            }
+            let mask = &reader.mask_ifds()[z];
+            let path = Path::from(TEST_PATH);
+            let expected = builder.expected_mask(z);
+            for (x, y) in builder.tile_indices(z) {
+                let raw = mask.get_raw_tile(store.as_ref(), &path, x, y).await;
+                let tile = mask.decode(vec![raw.unwrap().bytes], Orientation::TopLeft, None, None);
+                let RasterArray::Uint8(tile) = tile.unwrap() else {
+                    panic!("unexpected mask data type")
+                };
+                for ((_, row, col), &valid) in tile.indexed_iter() {
+                    let (th, tw) = (tile.dim().1, tile.dim().2);
+                    if let Some(&expected) = expected.get((y * th + row, x * tw + col)) {
+                        assert_eq!(valid == 1, expected, "mask level {z} of {builder:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let data_types = [
+            DataType::Uint8,
+            DataType::Int8,
+            DataType::Uint16,
+            DataType::Int16,
+            DataType::Uint32,
+            DataType::Int32,
+            DataType::Uint64,
+            DataType::Int64,
+            DataType::Float32,
+            DataType::Float64,
+        ];
+        let compressions = [
+            CompressionMethod::None,
+            CompressionMethod::Deflate,
+            CompressionMethod::LZW,
+            CompressionMethod::PackBits,
+        ];
+        let mut combination = 0u32;
+        for data_type in data_types {
+            // GDAL only writes the floating point predictor for floating point data
+            let predictor = match data_type {
+                DataType::Float32 | DataType::Float64 => Predictor::FloatingPoint,
+                _ => Predictor::Horizontal,
+            };
+            for compression in compressions {
+                for predictor in [Predictor::None, predictor] {
+                    for planar_configuration in
+                        [PlanarConfiguration::Chunky, PlanarConfiguration::Planar]
+                    {
+                        for big_endian in [false, true] {
+                            combination += 1;
+                            let builder = CogBuilder {
+                                width: 40,
+                                height: 30,
+                                tile_width: 16,
+                                tile_height: 16,
+                                compression,
+                                data_type,
+                                bands: 3,
+                                planar_configuration,
+                                predictor,
+                                mask: combination.is_multiple_of(3),
+                                big_endian,
+                                overviews: vec![2],
+                                trailing_ifds: combination.is_multiple_of(5),
+                                interleave_mask: combination.is_multiple_of(2),
+                                ..Default::default()
+                            };
+                            assert_round_trip(&builder).await;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn request_counts() {
+        let builder = CogBuilder {
+            origin: Some((0.0, 0.0, 1.0)),
+            epsg: Some(3857),
+            ..Default::default()
+        };
+        let (reader, store) = builder.open().await.unwrap();
+        assert_eq!(reader.epsg(), Some(3857));
+        store.assert_request_count(0);
+        reader.get_tile(1, 0, 0).await.unwrap();
+        store.assert_request_count(1);
+        reader.get_tile(0, 1, 0).await.unwrap();
+        store.assert_max_requests(2);
+        assert_eq!(store.requests()[0].len(), 32 * 32);
+    }
+
+    #[test]
+    fn complex_data_types() {
+        let builder = CogBuilder {
+            data_type: DataType::CFloat32,
+            ..Default::default()
+        };
+        assert!(builder.expected(0).is_err());
+        assert!(builder.build().is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use crate::error::{AiocogeoError, Result};
+
+/// Half the width of the EPSG:3857 world, in meters
+const WEB_MERCATOR_EXTENT: f64 = 20037508.342789244;
+
+/// A tiling grid of square zoom levels, as defined by the OGC Two Dimensional Tile Matrix Set
+/// standard and morecantile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileMatrixSet {
+    /// The identifier of the tile matrix set, e.g. `WebMercatorQuad`
+    pub id: String,
+    /// The EPSG code of the CRS of the grid
+    pub epsg: u16,
+    /// The tile matrices, indexed by zoom level
+    pub matrices: Vec<TileMatrix>,
+}
+
+/// A single zoom level of a [`TileMatrixSet`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileMatrix {
+    /// The size of a pixel, in CRS units
+    pub cell_size: f64,
+    /// The `(x, y)` top left corner of the matrix, in CRS units
+    pub point_of_origin: (f64, f64),
+    /// The width of a tile, in pixels
+    pub tile_width: usize,
+    /// The height of a tile, in pixels
+    pub tile_height: usize,
+    /// The number of tiles across
+    pub matrix_width: usize,
+    /// The number of tiles down
+    pub matrix_height: usize,
+}
+
+impl TileMatrixSet {
+    /// The `WebMercatorQuad` grid of EPSG:3857 used by most web maps, with zoom levels 0 to 24
+    pub fn web_mercator_quad() -> Self {
+        let matrices = (0..=24)
+            .map(|z| TileMatrix {
+                cell_size: 2.0 * WEB_MERCATOR_EXTENT / 256.0 / 2f64.powi(z),
+                point_of_origin: (-WEB_MERCATOR_EXTENT, WEB_MERCATOR_EXTENT),
+                tile_width: 256,
+                tile_height: 256,
+                matrix_width: 1 << z,
+                matrix_height: 1 << z,
+            })
+            .collect();
+        Self {
+            id: "WebMercatorQuad".to_string(),
+            epsg: 3857,
+            matrices,
+        }
+    }
+
+    /// The `WorldCRS84Quad` grid of longitudes and latitudes, two tiles across at zoom level 0,
+    /// with zoom levels 0 to 17
+    pub fn world_crs84_quad() -> Self {
+        let matrices = (0..=17)
+            .map(|z| TileMatrix {
+                cell_size: 180.0 / 256.0 / 2f64.powi(z),
+                point_of_origin: (-180.0, 90.0),
+                tile_width: 256,
+                tile_height: 256,
+                matrix_width: 2 << z,
+                matrix_height: 1 << z,
+            })
+            .collect();
+        Self {
+            id: "WorldCRS84Quad".to_string(),
+            epsg: 4326,
+            matrices,
+        }
+    }
+
+    /// Return the tile matrix of zoom level `z`
+    pub fn matrix(&self, z: usize) -> Result<&TileMatrix> {
+        self.matrices
+            .get(z)
+            .ok_or_else(|| AiocogeoError::General(format!("{} has no zoom level {z}", self.id)))
+    }
+
+    /// Return the `(min_x, min_y, max_x, max_y)` bounds of tile `(x, y)` of zoom level `z`, in
+    /// the CRS of the grid
+    pub fn tile_bounds(&self, x: usize, y: usize, z: usize) -> Result<(f64, f64, f64, f64)> {
+        let matrix = self.matrix(z)?;
+        if x >= matrix.matrix_width || y >= matrix.matrix_height {
+            return Err(AiocogeoError::General(format!(
+                "tile ({x}, {y}) is outside zoom level {z} of {}",
+                self.id
+            )));
+        }
+        let tile_width = matrix.tile_width as f64 * matrix.cell_size;
+        let tile_height = matrix.tile_height as f64 * matrix.cell_size;
+        let (origin_x, origin_y) = matrix.point_of_origin;
+        let min_x = origin_x + x as f64 * tile_width;
+        let max_y = origin_y - y as f64 * tile_height;
+        Ok((min_x, max_y - tile_height, min_x + tile_width, max_y))
+    }
+
+    /// Return the highest zoom level whose pixels are at least `resolution` wide, as rio-tiler
+    /// does, or the lowest zoom level if there is none
+    pub fn zoom_for_resolution(&self, resolution: f64) -> usize {
+        self.matrices
+            .iter()
+            .rposition(|matrix| matrix.cell_size * (1.0 + 1e-9) >= resolution)
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tile_bounds() {
+        let tms = TileMatrixSet::web_mercator_quad();
+        let (min_x, min_y, max_x, max_y) = tms.tile_bounds(1, 0, 1).unwrap();
+        assert_eq!((min_x, max_x), (0.0, WEB_MERCATOR_EXTENT));
+        assert!((min_y - 0.0).abs() < 1e-6 && (max_y - WEB_MERCATOR_EXTENT).abs() < 1e-6);
+
+        let tms = TileMatrixSet::world_crs84_quad();
+        assert_eq!(tms.tile_bounds(1, 0, 0).unwrap(), (0.0, -90.0, 180.0, 90.0));
+        assert!(tms.tile_bounds(2, 0, 0).is_err());
+        assert!(tms.matrix(18).is_err());
+    }
+
+    #[test]
+    fn zoom_for_resolution() {
+        let tms = TileMatrixSet::web_mercator_quad();
+        assert_eq!(tms.zoom_for_resolution(tms.matrices[10].cell_size), 10);
+        assert_eq!(
+            tms.zoom_for_resolution(tms.matrices[10].cell_size * 0.9),
+            10
+        );
+        assert_eq!(tms.zoom_for_resolution(1e9), 0);
+        assert_eq!(tms.zoom_for_resolution(1e-9), 24);
+    }
+}
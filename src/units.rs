@@ -0,0 +1,138 @@
+/// User-defined units are marked with this code in the GeoKey directory, with the unit size
+/// stored in a separate key
+pub(crate) const USER_DEFINED: u16 = 32767;
+
+/// A linear unit of measure, as referenced by `ProjLinearUnitsGeoKey`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinearUnit {
+    /// EPSG:9001
+    Meter,
+    /// EPSG:9002, the international foot
+    Foot,
+    /// EPSG:9003
+    UsSurveyFoot,
+    /// EPSG:9036
+    Kilometer,
+    /// Any other unit, with its size in meters when known
+    Other {
+        code: u16,
+        meters_per_unit: Option<f64>,
+    },
+}
+
+impl LinearUnit {
+    /// Construct a unit from its EPSG code, using `unit_size` (in meters) for user-defined units
+    pub(crate) fn from_code(code: u16, unit_size: Option<f64>) -> Self {
+        match code {
+            9001 => Self::Meter,
+            9002 => Self::Foot,
+            9003 => Self::UsSurveyFoot,
+            9036 => Self::Kilometer,
+            USER_DEFINED => Self::Other {
+                code,
+                meters_per_unit: unit_size,
+            },
+            code => Self::Other {
+                code,
+                meters_per_unit: None,
+            },
+        }
+    }
+
+    /// The length of one unit in meters, if known
+    pub fn meters_per_unit(&self) -> Option<f64> {
+        match self {
+            Self::Meter => Some(1.0),
+            Self::Foot => Some(0.3048),
+            Self::UsSurveyFoot => Some(1200.0 / 3937.0),
+            Self::Kilometer => Some(1000.0),
+            Self::Other {
+                meters_per_unit, ..
+            } => *meters_per_unit,
+        }
+    }
+}
+
+/// An angular unit of measure, as referenced by `GeogAngularUnitsGeoKey`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AngularUnit {
+    /// EPSG:9101
+    Radian,
+    /// EPSG:9102
+    Degree,
+    /// EPSG:9103
+    ArcMinute,
+    /// EPSG:9104
+    ArcSecond,
+    /// EPSG:9105
+    Grad,
+    /// Any other unit, with its size in radians when known
+    Other {
+        code: u16,
+        radians_per_unit: Option<f64>,
+    },
+}
+
+impl AngularUnit {
+    /// Construct a unit from its EPSG code, using `unit_size` (in radians) for user-defined
+    /// units
+    pub(crate) fn from_code(code: u16, unit_size: Option<f64>) -> Self {
+        match code {
+            9101 => Self::Radian,
+            9102 | 9122 => Self::Degree,
+            9103 => Self::ArcMinute,
+            9104 => Self::ArcSecond,
+            9105 | 9106 => Self::Grad,
+            USER_DEFINED => Self::Other {
+                code,
+                radians_per_unit: unit_size,
+            },
+            code => Self::Other {
+                code,
+                radians_per_unit: None,
+            },
+        }
+    }
+
+    /// The size of one unit in radians, if known
+    pub fn radians_per_unit(&self) -> Option<f64> {
+        use std::f64::consts::PI;
+        match self {
+            Self::Radian => Some(1.0),
+            Self::Degree => Some(PI / 180.0),
+            Self::ArcMinute => Some(PI / 10_800.0),
+            Self::ArcSecond => Some(PI / 648_000.0),
+            Self::Grad => Some(PI / 200.0),
+            Self::Other {
+                radians_per_unit, ..
+            } => *radians_per_unit,
+        }
+    }
+}
+
+/// The units of an image's model coordinates, and therefore of its bounds and resolution
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Units {
+    Linear(LinearUnit),
+    Angular(AngularUnit),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn us_survey_foot() {
+        let unit = LinearUnit::from_code(9003, None);
+        assert_eq!(unit, LinearUnit::UsSurveyFoot);
+        let meters = unit.meters_per_unit().unwrap();
+        assert!((meters - 0.3048006096).abs() < 1e-9);
+    }
+
+    #[test]
+    fn user_defined_units() {
+        let unit = LinearUnit::from_code(USER_DEFINED, Some(2.0));
+        assert_eq!(unit.meters_per_unit(), Some(2.0));
+        assert_eq!(LinearUnit::from_code(9999, None).meters_per_unit(), None);
+    }
+}
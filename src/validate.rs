@@ -0,0 +1,324 @@
+//! Check that every tile of a file can be fetched and decoded, to detect truncated files and bit
+//! rot in archives, and that its overviews are complete.
+use std::fmt::{self, Display};
+use std::ops::Range;
+
+use bytes::Bytes;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use tiff::tags::PlanarConfiguration;
+
+use crate::dump::IfdKind;
+use crate::enums::Orientation;
+use crate::error::Result;
+use crate::ifd::{ImageFileDirectories, ImageFileDirectory};
+use crate::options::{ReadOptions, DEFAULT_CONCURRENCY};
+use crate::partial_reads::{get_range_exact, get_ranges_coalesced, TILE_BATCH_SIZE};
+
+/// A tile that failed validation or a read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileFailure {
+    /// Whether the tile belongs to the image, an overview or a mask
+    pub kind: IfdKind,
+    /// The overview level of the IFD, where level 0 is the full resolution image
+    pub level: usize,
+    /// The x index of the tile: in stored order for validation, and in visual orientation for
+    /// reads, as passed to [`crate::COGReader::get_tile`]
+    pub x: usize,
+    /// The y index of the tile, in the same order as `x`
+    pub y: usize,
+    /// The band of the tile, for images that store bands in separate tiles
+    pub band: Option<usize>,
+    /// The byte range of the tile in the file
+    pub range: Range<u64>,
+    /// Why the tile failed
+    pub reason: String,
+}
+
+impl Display for TileFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} tile ({}, {})",
+            self.kind, self.level, self.x, self.y
+        )?;
+        if let Some(band) = self.band {
+            write!(f, " of band {band}")?;
+        }
+        write!(
+            f,
+            " at bytes {}..{}: {}",
+            self.range.start, self.range.end, self.reason
+        )
+    }
+}
+
+/// A problem with the overviews of a file, which breaks clients that pick an overview level by
+/// position or assume GDAL's power of 2 pyramid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverviewIssue {
+    /// No overview has this decimation factor, although the level of half its factor is larger
+    /// than a tile. GDAL builds levels of factors 2, 4, 8 and so on until one fits in a tile.
+    Missing { factor: u32 },
+    /// The overviews are not stored from highest to lowest resolution in the IFD chain
+    Unsorted,
+}
+
+impl Display for OverviewIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing { factor } => write!(f, "missing overview of factor {factor}"),
+            Self::Unsorted => f.write_str("overviews are not sorted from highest resolution"),
+        }
+    }
+}
+
+/// The result of [`crate::COGReader::validate_tiles`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TileValidation {
+    /// The number of stored tiles checked
+    pub tiles: usize,
+    /// The number of sparse tiles, which aren't stored and read as nodata
+    pub sparse_tiles: usize,
+    /// The tiles that couldn't be fetched or decoded
+    pub failures: Vec<TileFailure>,
+    /// The problems with the overviews of the image
+    pub overview_issues: Vec<OverviewIssue>,
+}
+
+impl TileValidation {
+    /// Return whether every tile was fetched and decoded, and the overviews are complete and
+    /// sorted
+    pub fn is_valid(&self) -> bool {
+        self.failures.is_empty() && self.overview_issues.is_empty()
+    }
+}
+
+/// Check that the overviews of an image are stored from highest to lowest resolution, and that
+/// no level of GDAL's power of 2 pyramid is missing
+pub(crate) fn overview_issues(ifds: &ImageFileDirectories) -> Vec<OverviewIssue> {
+    let mut issues = vec![];
+    if !ifds.overviews_sorted() {
+        issues.push(OverviewIssue::Unsorted);
+    }
+    let primary = ifds.primary();
+    let (tile_width, tile_height) = primary.tile_size();
+    let factors = ifds.overview_factors();
+    let mut previous = 1;
+    while primary.width().div_ceil(previous) > tile_width
+        || primary.height().div_ceil(previous) > tile_height
+    {
+        let factor = previous * 2;
+        if !factors.contains(&factor) {
+            issues.push(OverviewIssue::Missing { factor });
+        }
+        previous = factor;
+    }
+    issues
+}
+
+/// A stored tile to check
+struct StoredTile {
+    x: usize,
+    y: usize,
+    band: Option<usize>,
+    range: Range<usize>,
+}
+
+/// Fetch and decode every stored tile of an IFD, adding the tiles that fail to `validation`.
+///
+/// Tiles extending past `file_size` fail without being fetched. If a coalesced request fails,
+/// e.g. because the file is shorter than its tiles, its tiles are fetched one by one so that the
+/// failure is reported against the right tiles.
+pub(crate) async fn validate_ifd(
+    store: &dyn ObjectStore,
+    path: &Path,
+    ifd: &ImageFileDirectory,
+    (kind, level): (IfdKind, usize),
+    file_size: Option<u64>,
+    options: &ReadOptions,
+    validation: &mut TileValidation,
+) -> Result<()> {
+    let (x_count, y_count) = ifd.tile_count();
+    let planar = ifd.planar_configuration != PlanarConfiguration::Chunky;
+    let mut tiles = vec![];
+    for (idx, (&offset, &byte_count)) in ifd
+        .tile_offsets
+        .iter()
+        .zip(&ifd.tile_byte_counts)
+        .enumerate()
+    {
+        let (plane, index) = (idx / (x_count * y_count), idx % (x_count * y_count));
+        let (x, y, band) = (index % x_count, index / x_count, planar.then_some(plane));
+        if offset == 0 || byte_count == 0 {
+            validation.sparse_tiles += 1;
+            continue;
+        }
+        validation.tiles += 1;
+        let failure = |reason: String| TileFailure {
+            kind,
+            level,
+            x,
+            y,
+            band,
+            range: offset..offset.saturating_add(byte_count),
+            reason,
+        };
+        let end = offset.checked_add(byte_count);
+        match (end, file_size) {
+            (Some(end), Some(size)) if end > size => validation.failures.push(failure(format!(
+                "the tile ends past the end of the file at byte {size}"
+            ))),
+            (Some(end), _) => match (usize::try_from(offset), usize::try_from(end)) {
+                (Ok(start), Ok(end)) => tiles.push(StoredTile {
+                    x,
+                    y,
+                    band,
+                    range: start..end,
+                }),
+                _ => validation.failures.push(failure(
+                    "the tile is not addressable on this platform".to_string(),
+                )),
+            },
+            (None, _) => validation
+                .failures
+                .push(failure("the tile's byte range overflows".to_string())),
+        }
+    }
+
+    let concurrency = options
+        .max_concurrent_requests
+        .unwrap_or(DEFAULT_CONCURRENCY);
+    for batch in tiles.chunks(TILE_BATCH_SIZE) {
+        let ranges: Vec<_> = batch.iter().map(|tile| tile.range.clone()).collect();
+        let fetched: Vec<Result<Bytes>> = match get_ranges_coalesced(
+            store,
+            path,
+            &ranges,
+            options.coalesce_gap_bytes,
+            options.max_range_bytes,
+            concurrency,
+        )
+        .await
+        {
+            Ok(fetched) => fetched.into_iter().map(Ok).collect(),
+            Err(_) => {
+                let mut fetched = Vec::with_capacity(ranges.len());
+                for range in ranges {
+                    fetched.push(get_range_exact(store, path, range).await);
+                }
+                fetched
+            }
+        };
+        for (tile, bytes) in batch.iter().zip(fetched) {
+            let bands = tile.band.map(|band| vec![band]);
+            let checked = bytes.and_then(|bytes| {
+                ifd.decode(vec![bytes], Orientation::TopLeft, bands.as_deref(), None)
+            });
+            if let Err(err) = checked {
+                validation.failures.push(TileFailure {
+                    kind,
+                    level,
+                    x: tile.x,
+                    y: tile.y,
+                    band: tile.band,
+                    range: tile.range.start as u64..tile.range.end as u64,
+                    reason: err.to_string(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{CogBuilder, TEST_PATH};
+    use crate::COGReader;
+    use object_store::memory::InMemory;
+    use std::sync::Arc;
+    use tiff::tags::CompressionMethod;
+
+    #[tokio::test]
+    async fn validate_tiles() {
+        let builder = CogBuilder {
+            compression: CompressionMethod::Deflate,
+            bands: 2,
+            planar_configuration: PlanarConfiguration::Planar,
+            overviews: vec![2],
+            mask: true,
+            ..Default::default()
+        };
+        let (reader, _) = builder.open().await.unwrap();
+        let validation = reader
+            .validate_tiles(&ReadOptions::default())
+            .await
+            .unwrap();
+        assert!(validation.is_valid());
+        // 2 bands of 4 tiles, 2 bands of 1 overview tile and their masks
+        assert_eq!(validation.tiles, 8 + 2 + 4 + 1);
+
+        // Corrupt the first tile and drop the end of the last one
+        let mut bytes = builder.build().unwrap().to_vec();
+        let first = reader.ifds()[0].tile_offsets[0] as usize;
+        bytes[first + 4] ^= 0xff;
+        bytes.truncate(bytes.len() - 1);
+        let store = Arc::new(InMemory::new());
+        let path = Path::from(TEST_PATH);
+        store.put(&path, bytes.into()).await.unwrap();
+        let reader = COGReader::try_open(store, path).await.unwrap();
+        let validation = reader
+            .validate_tiles(&ReadOptions::default())
+            .await
+            .unwrap();
+        let failures: Vec<_> = validation
+            .failures
+            .iter()
+            .map(|f| (f.kind, f.level, f.x, f.y, f.band))
+            .collect();
+        assert_eq!(
+            failures,
+            [
+                (IfdKind::Image, 0, 0, 0, Some(0)),
+                (IfdKind::Mask, 1, 0, 0, None)
+            ]
+        );
+        assert!(validation.failures[1]
+            .reason
+            .contains("past the end of the file"));
+    }
+
+    #[tokio::test]
+    async fn overview_issues() {
+        let builder = CogBuilder {
+            width: 128,
+            height: 96,
+            overviews: vec![4, 2],
+            ..Default::default()
+        };
+        let (reader, _) = builder.open().await.unwrap();
+        assert_eq!(reader.overview_factors(), [2, 4]);
+        let validation = reader
+            .validate_tiles(&ReadOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(validation.overview_issues, [OverviewIssue::Unsorted]);
+        assert!(!validation.is_valid());
+
+        // The overview of factor 2 is larger than a tile
+        let builder = CogBuilder {
+            overviews: vec![2],
+            ..builder
+        };
+        let (reader, _) = builder.open().await.unwrap();
+        let validation = reader
+            .validate_tiles(&ReadOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            validation.overview_issues,
+            [OverviewIssue::Missing { factor: 4 }]
+        );
+    }
+}